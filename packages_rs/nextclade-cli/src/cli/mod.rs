@@ -2,8 +2,12 @@ pub mod nextalign_cli;
 pub mod nextalign_loop;
 pub mod nextalign_ordered_writer;
 pub mod nextclade_cli;
+pub mod nextclade_dataset_build;
+pub mod nextclade_dataset_check;
+pub mod nextclade_dataset_diff;
 pub mod nextclade_dataset_get;
 pub mod nextclade_dataset_list;
 pub mod nextclade_loop;
+pub mod nextclade_loop_multi_dataset;
 pub mod nextclade_ordered_writer;
 pub mod verbosity;
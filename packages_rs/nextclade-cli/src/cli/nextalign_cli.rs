@@ -101,7 +101,9 @@ pub struct NextalignRunInputArgs {
   #[clap(value_hint = ValueHint::FilePath)]
   pub input_ref: PathBuf,
 
-  /// Path to a .gff file containing the gene map (genome annotation).
+  /// Path to a GFF3 or GenBank file containing the gene map (genome annotation).
+  ///
+  /// The format, GFF3 or GenBank, is detected from the file extension (`.gff`, `.gff3` or `.gb`, `.gbk`, `.gbff`, `.genbank`) or, if the extension is not known, from the file content. In GenBank files, genes are read from the `CDS` features and are named after their `gene`, `locus_tag` or `product` qualifier.
   ///
   /// Gene map (sometimes also called 'genome annotation') is used to find coding regions. If not supplied, coding regions will
   /// not be translated, amino acid sequences will not be output, and nucleotide sequence
@@ -112,6 +114,9 @@ pub struct NextalignRunInputArgs {
   /// Learn more about Generic Feature Format Version 3 (GFF3):
   /// https://github.com/The-Sequence-Ontology/Specifications/blob/master/gff3.md
   ///
+  /// Learn more about GenBank flat file format:
+  /// https://www.ncbi.nlm.nih.gov/genbank/samplerecord/
+  ///
   /// Supports the following compression formats: "gz", "bz2", "xz", "zstd". Use "-" to read uncompressed data from standard input (stdin).
   #[clap(long, short = 'm', alias = "genemap")]
   #[clap(value_hint = ValueHint::FilePath)]
@@ -6,11 +6,12 @@ use eyre::{Report, WrapErr};
 use log::info;
 use nextclade::align::gap_open::{get_gap_open_close_scores_codon_aware, get_gap_open_close_scores_flat};
 use nextclade::align::params::AlignPairwiseParams;
+use nextclade::align::seed_chain::MinimizerIndex;
 use nextclade::io::fasta::{read_one_fasta, FastaReader, FastaRecord};
-use nextclade::io::gene_map::{filter_gene_map, GeneMap};
-use nextclade::io::gff3::read_gff3_file;
+use nextclade::io::gene_map::{filter_gene_map, read_gene_map_file, GeneMap};
 use nextclade::io::nuc::{to_nuc_seq, to_nuc_seq_replacing};
 use nextclade::run::nextalign_run_one::nextalign_run_one;
+use nextclade::translate::genetic_code::GeneticCode;
 use nextclade::translate::translate_genes_ref::translate_genes_ref;
 use nextclade::types::outputs::NextalignOutputs;
 
@@ -60,16 +61,20 @@ pub fn nextalign_run(run_args: NextalignRunArgs) -> Result<(), Report> {
 
   let gene_map = match input_gene_map {
     Some(input_gene_map) => {
-      let gene_map = read_gff3_file(&input_gene_map)?;
+      let gene_map = read_gene_map_file(input_gene_map)?;
       filter_gene_map(Some(gene_map), &genes)?
     }
     None => GeneMap::new(),
   };
 
+  let minimizer_index = &MinimizerIndex::for_params(ref_seq, &alignment_params)?;
   let gap_open_close_nuc = &get_gap_open_close_scores_codon_aware(ref_seq, &gene_map, &alignment_params);
   let gap_open_close_aa = &get_gap_open_close_scores_flat(ref_seq, &alignment_params);
 
-  let ref_peptides = &translate_genes_ref(ref_seq, &gene_map, &alignment_params)?;
+  // Genes are translated using the standard genetic code, unless a different one is specified in the gene map
+  let genetic_code = GeneticCode::standard();
+
+  let ref_peptides = &translate_genes_ref(ref_seq, &gene_map, genetic_code, &alignment_params)?;
 
   std::thread::scope(|s| {
     const CHANNEL_SIZE: usize = 128;
@@ -120,8 +125,10 @@ pub fn nextalign_run(run_args: NextalignRunArgs) -> Result<(), Report> {
               ref_seq,
               ref_peptides,
               gene_map,
+              minimizer_index.as_ref(),
               gap_open_close_nuc,
               gap_open_close_aa,
+              genetic_code,
               alignment_params,
             )
           });
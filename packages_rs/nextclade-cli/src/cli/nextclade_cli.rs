@@ -1,8 +1,11 @@
+use crate::cli::nextclade_dataset_build::nextclade_dataset_build;
+use crate::cli::nextclade_dataset_check::nextclade_dataset_check;
+use crate::cli::nextclade_dataset_diff::nextclade_dataset_diff;
 use crate::cli::nextclade_dataset_get::nextclade_dataset_get;
 use crate::cli::nextclade_dataset_list::nextclade_dataset_list;
 use crate::cli::nextclade_loop::nextclade_run;
 use crate::cli::verbosity::{Verbosity, WarnLevel};
-use crate::io::http_client::ProxyConfig;
+use crate::io::http_client::{parse_server_url, ProxyConfig};
 use clap::{AppSettings, ArgEnum, ArgGroup, CommandFactory, Parser, Subcommand, ValueHint};
 use clap_complete::{generate, Generator, Shell};
 use clap_complete_fig::Fig;
@@ -11,6 +14,9 @@ use itertools::Itertools;
 use lazy_static::lazy_static;
 use nextclade::align::params::AlignPairwiseParamsOptional;
 use nextclade::io::fs::add_extension;
+use nextclade::io::isolates_csv::ISOLATE_NAME_REGEX_DEFAULT;
+use nextclade::sort::params::DatasetSketchParams;
+use nextclade::tree::params::TreePlacementParams;
 use nextclade::utils::global_init::setup_logger;
 use nextclade::{getenv, make_error};
 use std::fmt::Debug;
@@ -72,7 +78,7 @@ pub enum NextcladeCommands {
   /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade run --help`.
   Run(Box<NextcladeRunArgs>),
 
-  /// List and download available Nextclade datasets
+  /// List, download, build, check and compare Nextclade datasets
   ///
   /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade run --help`.
   Dataset(Box<NextcladeDatasetArgs>),
@@ -96,6 +102,21 @@ pub enum NextcladeDatasetCommands {
   ///
   /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade run --help`.
   Get(NextcladeDatasetGetArgs),
+
+  /// Build a custom dataset from a reference sequence, genome annotation and, optionally, a reference tree and other files
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade run --help`.
+  Build(NextcladeDatasetBuildArgs),
+
+  /// Check a dataset for errors: validate each of the dataset files and their consistency with each other
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade run --help`.
+  Check(NextcladeDatasetCheckArgs),
+
+  /// Compare two versions of a dataset and list what changed between them
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade run --help`.
+  Diff(NextcladeDatasetDiffArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -140,10 +161,22 @@ pub struct NextcladeDatasetListArgs {
   #[clap(long)]
   pub json: bool,
 
-  /// Use custom dataset server
+  /// List datasets stored in the local dataset cache (see `--cache-dir`), instead of the datasets available on the server. All cached versions are listed, as if `--include-old` was given.
+  #[clap(long)]
+  #[clap(requires = "cache-dir")]
+  pub local: bool,
+
+  /// Path to the local dataset cache directory. Can also be set with the environment variable `NEXTCLADE_DATASET_CACHE_DIR`.
+  #[clap(long)]
+  #[clap(env = "NEXTCLADE_DATASET_CACHE_DIR")]
+  #[clap(value_hint = ValueHint::DirPath)]
+  pub cache_dir: Option<PathBuf>,
+
+  /// Use custom dataset server. Can be a URL, including a `file://` URL, or a path to a local directory containing the dataset index.
   #[clap(long)]
   #[clap(value_hint = ValueHint::Url)]
   #[clap(default_value_t = Url::from_str(DATA_FULL_DOMAIN).expect("Invalid URL"))]
+  #[clap(parse(try_from_str = parse_server_url))]
   pub server: Url,
 
   #[clap(flatten)]
@@ -152,7 +185,7 @@ pub struct NextcladeDatasetListArgs {
 
 #[derive(Parser, Debug)]
 #[clap(verbatim_doc_comment)]
-#[clap(group(ArgGroup::new("outputs").required(true).multiple(false)))]
+#[clap(group(ArgGroup::new("outputs").required(true).multiple(true)))]
 pub struct NextcladeDatasetGetArgs {
   /// Name of the dataset to download. Equivalent to `--attribute='name=<value>'`. Use `dataset list` command to view available datasets.
   #[clap(long, short = 'n')]
@@ -184,10 +217,11 @@ pub struct NextcladeDatasetGetArgs {
   #[clap(value_hint = ValueHint::Other)]
   pub attribute: Vec<String>,
 
-  /// Use custom dataset server
+  /// Use custom dataset server. Can be a URL, including a `file://` URL, or a path to a local directory containing the dataset index.
   #[clap(long)]
   #[clap(value_hint = ValueHint::Url)]
   #[clap(default_value_t = Url::from_str(DATA_FULL_DOMAIN).expect("Invalid URL"))]
+  #[clap(parse(try_from_str = parse_server_url))]
   pub server: Url,
 
   /// Path to directory to write dataset files to.
@@ -198,7 +232,7 @@ pub struct NextcladeDatasetGetArgs {
   /// If the required directory tree does not exist, it will be created.
   #[clap(long, short = 'o')]
   #[clap(value_hint = ValueHint::DirPath)]
-  #[clap(group = "outputs")]
+  #[clap(group = "outputs", conflicts_with = "output-zip")]
   pub output_dir: Option<PathBuf>,
 
   /// Path to resulting dataset zip file.
@@ -212,10 +246,188 @@ pub struct NextcladeDatasetGetArgs {
   #[clap(group = "outputs")]
   pub output_zip: Option<PathBuf>,
 
+  /// Path to the local dataset cache directory, to store the dataset in. Datasets in the cache can be listed with `dataset list --local` and used with `run --dataset-name`, without internet access. Each dataset version is stored in a subdirectory `<name>/<reference>/<tag>`, along with a manifest file containing checksums of the dataset files, which are verified before use.
+  ///
+  /// Can also be set with the environment variable `NEXTCLADE_DATASET_CACHE_DIR`. Can be used instead of, or in addition to `--output-dir` or `--output-zip`.
+  #[clap(long)]
+  #[clap(env = "NEXTCLADE_DATASET_CACHE_DIR")]
+  #[clap(value_hint = ValueHint::DirPath)]
+  #[clap(group = "outputs")]
+  pub cache_dir: Option<PathBuf>,
+
+  /// Instead of downloading the dataset, check whether a newer compatible version of it is available, than the one already present at the output location (`--output-dir`, `--output-zip` or `--cache-dir`).
+  ///
+  /// The version tag of the local dataset is compared with the latest version in the dataset index of the server. Nothing is downloaded, apart from the index.
+  #[clap(long)]
+  pub check_updates: bool,
+
   #[clap(flatten)]
   pub proxy_config: ProxyConfig,
 }
 
+#[derive(Parser, Debug)]
+#[clap(verbatim_doc_comment)]
+#[clap(group(ArgGroup::new("outputs").required(true).multiple(false)))]
+pub struct NextcladeDatasetBuildArgs {
+  /// Path to a FASTA file containing reference sequence. This file should contain exactly 1 sequence.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, short = 'r', visible_alias("reference"))]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_ref: PathBuf,
+
+  /// Path to a GFF3 or GenBank file containing the gene map (genome annotation).
+  ///
+  /// The format, GFF3 or GenBank, is detected from the file extension (`.gff`, `.gff3` or `.gb`, `.gbk`, `.gbff`, `.genbank`) or, if the extension is not known, from the file content. In GenBank files, genes are read from the `CDS` features and are named after their `gene`, `locus_tag` or `product` qualifier.
+  ///
+  /// If not provided, the dataset will contain no genes. A GenBank file is converted to GFF3 when written into the dataset.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, short = 'm', alias = "genemap")]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_gene_map: Option<PathBuf>,
+
+  /// Path to reference tree: Auspice JSON v2, Newick (together with `--input-node-data` or `--input-tree-alignment`) or UShER mutation-annotated tree (`.pb`). See `nextclade run --help` for details.
+  ///
+  /// If not provided, the tree will consist of a single node: the reference sequence.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, short = 'a')]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_tree: Option<PathBuf>,
+
+  /// Path to one or multiple node data JSON files, as produced by augur, which contain mutations, clades and other attributes of the nodes of the reference tree in Newick format.
+  ///
+  /// This flag can occur multiple times, or accept a comma-separated list of paths.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, multiple_occurrences = true, use_value_delimiter = true)]
+  #[clap(value_hint = ValueHint::FilePath)]
+  #[clap(requires = "input-tree", conflicts_with = "input-tree-alignment")]
+  pub input_node_data: Vec<PathBuf>,
+
+  /// Path to a FASTA file containing sequences of the tips of the reference tree in Newick format, aligned to the reference sequence.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::FilePath)]
+  #[clap(requires = "input-tree")]
+  pub input_tree_alignment: Option<PathBuf>,
+
+  /// Path to a TSV file containing clade definitions, in the format of `augur clades` (columns `clade`, `gene`, `site`, `alt`).
+  ///
+  /// Clades are assigned to the nodes of the reference tree according to these definitions, replacing the clades the tree may already have.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::FilePath)]
+  #[clap(requires = "input-tree")]
+  pub input_clades: Option<PathBuf>,
+
+  /// Path to a JSON file containing configuration of Quality Control rules.
+  ///
+  /// If not provided, a configuration with all QC rules disabled is written, which can be used as a template.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, short = 'Q')]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_qc_config: Option<PathBuf>,
+
+  /// Path to a JSON file containing configuration and data specific to a pathogen.
+  ///
+  /// If not provided, a minimal file is written, such that default alignment parameters are used.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, short = 'R')]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_virus_properties: Option<PathBuf>,
+
+  /// Path to a CSV file containing a list of custom PCR primer sites.
+  ///
+  /// If not provided, the dataset will contain no PCR primers.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, short = 'p')]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_pcr_primers: Option<PathBuf>,
+
+  /// Path to a FASTA file containing example sequences, to be included into the dataset as `sequences.fasta`.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_example_sequences: Option<PathBuf>,
+
+  /// Name of the dataset, written into the dataset version information (`tag.json`).
+  #[clap(long, short = 'n')]
+  #[clap(value_hint = ValueHint::Other)]
+  pub name: String,
+
+  /// Version tag of the dataset, written into the dataset version information (`tag.json`).
+  #[clap(long, short = 't')]
+  #[clap(value_hint = ValueHint::Other)]
+  #[clap(default_value = "unreleased")]
+  pub tag: String,
+
+  /// Path to directory to write dataset files to.
+  ///
+  /// This flag is mutually exclusive with `--output-zip`, and provides the equivalent output, but in the form of
+  /// a directory with files, instead of a compressed zip archive.
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long, short = 'o')]
+  #[clap(value_hint = ValueHint::DirPath)]
+  #[clap(group = "outputs")]
+  pub output_dir: Option<PathBuf>,
+
+  /// Path to resulting dataset zip file.
+  ///
+  /// This flag is mutually exclusive with `--output-dir`, and provides the equivalent output, but in the form of
+  /// compressed zip archive instead of a directory with files.
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long, short = 'z')]
+  #[clap(value_hint = ValueHint::FilePath)]
+  #[clap(group = "outputs")]
+  pub output_zip: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+#[clap(verbatim_doc_comment)]
+pub struct NextcladeDatasetCheckArgs {
+  /// Path to a directory or a zip file containing the dataset to check.
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub input_dataset: PathBuf,
+
+  /// Print the report in JSON format.
+  #[clap(long)]
+  pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+#[clap(verbatim_doc_comment)]
+pub struct NextcladeDatasetDiffArgs {
+  /// Old version of the dataset: path to a directory or a zip file containing the dataset, or, if the dataset cache is configured (see `--cache-dir`), a name and a version tag of a cached dataset, in the form `<name>@<tag>`.
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub old_dataset: PathBuf,
+
+  /// New version of the dataset, in the same form as the old version.
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub new_dataset: PathBuf,
+
+  /// Path to the local dataset cache directory, to look up the datasets given in the form `<name>@<tag>`.
+  ///
+  /// Can also be set with the environment variable `NEXTCLADE_DATASET_CACHE_DIR`.
+  #[clap(long)]
+  #[clap(env = "NEXTCLADE_DATASET_CACHE_DIR")]
+  #[clap(value_hint = ValueHint::DirPath)]
+  pub cache_dir: Option<PathBuf>,
+
+  /// Print the changes in JSON format.
+  #[clap(long)]
+  pub json: bool,
+}
+
 #[derive(Copy, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum, EnumIter)]
 pub enum NextcladeOutputSelection {
   All,
@@ -225,6 +437,9 @@ pub enum NextcladeOutputSelection {
   Csv,
   Tsv,
   Tree,
+  TreeNwk,
+  TreeNexus,
+  TreePb,
   Translations,
   Insertions,
   Errors,
@@ -263,13 +478,34 @@ pub struct NextcladeRunInputArgs {
   #[clap(value_hint = ValueHint::AnyPath)]
   pub input_dataset: Option<PathBuf>,
 
+  /// Paths to multiple directories or zip files containing datasets. Enables multi-dataset mode.
+  ///
+  /// In this mode, each input sequence is compared to the reference sequence of every dataset, using a fast k-mer
+  /// similarity score, and is then analyzed with the dataset which matches best. Each dataset is named after its
+  /// directory or zip file (without extension) and these names should be unique.
+  ///
+  /// Outputs are written separately for each dataset, into subdirectories of the `--output-all` directory, named after
+  /// the datasets. The assignment of sequences to datasets and their scores are written into
+  /// `--output-dataset-assignment`. Sequences which do not match any of the datasets well enough (see
+  /// `--dataset-min-score`) are reported in `--output-errors`.
+  ///
+  /// This flag can occur multiple times, or accept a comma-separated list of paths. Requires `--output-all`.
+  ///
+  /// This flag is mutually exclusive with `--input-dataset`, `--dataset-name`, and with individual `--input-*` flags.
+  #[clap(long, multiple_occurrences = true, use_value_delimiter = true)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  #[clap(conflicts_with_all = &["input-dataset", "dataset-name"])]
+  pub input_datasets: Vec<PathBuf>,
+
   /// Name of the dataset to download and use during the run
   ///
   /// This is a convenience shortcut to first downloading a dataset and then immediately running with it. Providing this flag is equivalent to running 2 commands: `dataset get` followed by `run`, with the difference that the dataset files from the first command are not saved to disk and cannot be reused later. The default parameters are used for the dataset (e.g. default reference name and latest version tag).
   ///
+  /// A version tag can be pinned by appending it to the name after `@`, for example: `--dataset-name='sars-cov-2@2022-04-28T12:00:00Z'`.
+  ///
   /// See `dataset get --help` and `dataset list --help` for more details.
   ///
-  /// Note that when using this flag, the dataset will be downloaded on every run. If a new version of the dataset is released between two runs, they will use different versions of the dataset and may produce different results. For the most reproducible runs, and for more control, use the usual 2-step flow with `dataset get` followed by `run`.
+  /// Note that when using this flag, the dataset will be downloaded on every run, unless a dataset cache is used (see `--cache-dir`). If a new version of the dataset is released between two runs, they will use different versions of the dataset and may produce different results. For the most reproducible runs, pin the version tag, or use the usual 2-step flow with `dataset get` followed by `run`.
   ///
   /// This flag is mutually exclusive with `--input_dataset`
   #[clap(long, short = 'd')]
@@ -288,6 +524,10 @@ pub struct NextcladeRunInputArgs {
   ///
   /// See https://nextstrain.org/docs/bioinformatics/data-formats.
   ///
+  /// The tree can also be provided in Newick format. In this case, the mutations on its branches are taken from node data JSON files (`--input-node-data`) or reconstructed from the alignment of the sequences of its tips (`--input-tree-alignment`).
+  ///
+  /// Files with extension `.pb` (optionally followed by a compression extension, e.g. `.pb.gz`) are read as UShER mutation-annotated trees (MAT) in protobuf format. Clade annotations of the MAT become clades and clade-like node attributes of the tree.
+  ///
   /// Overrides path to `tree.json` in the dataset (`--input-dataset`).
   ///
   /// Supports the following compression formats: "gz", "bz2", "xz", "zstd". Use "-" to read uncompressed data from standard input (stdin).
@@ -295,6 +535,28 @@ pub struct NextcladeRunInputArgs {
   #[clap(value_hint = ValueHint::FilePath)]
   pub input_tree: Option<PathBuf>,
 
+  /// Path to one or multiple node data JSON files, as produced by augur (e.g. `augur ancestral`, `augur translate`, `augur clades`), which contain mutations, clades and other attributes of the nodes of the reference tree.
+  ///
+  /// Only used when the reference tree (`--input-tree`) is in Newick format. Data of the same node from multiple files is merged, in the same way as `augur export v2` does.
+  ///
+  /// This flag can occur multiple times, or accept a comma-separated list of paths.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long, multiple_occurrences = true, use_value_delimiter = true)]
+  #[clap(value_hint = ValueHint::FilePath)]
+  #[clap(requires = "input-tree", conflicts_with = "input-tree-alignment")]
+  pub input_node_data: Vec<PathBuf>,
+
+  /// Path to a FASTA file containing sequences of the tips of the reference tree, aligned to the reference sequence.
+  ///
+  /// Only used when the reference tree (`--input-tree`) is in Newick format. Mutations on the branches of the tree are reconstructed from these sequences using maximum parsimony. Clades are not assigned to the nodes of such a tree.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zstd".
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::FilePath)]
+  #[clap(requires = "input-tree")]
+  pub input_tree_alignment: Option<PathBuf>,
+
   /// Path to a JSON file containing configuration of Quality Control rules.
   ///
   /// Overrides path to `qc.json` in the dataset (`--input-dataset`).
@@ -322,7 +584,9 @@ pub struct NextcladeRunInputArgs {
   #[clap(value_hint = ValueHint::FilePath)]
   pub input_pcr_primers: Option<PathBuf>,
 
-  /// Path to a .gff file containing the gene map (genome annotation).
+  /// Path to a GFF3 or GenBank file containing the gene map (genome annotation).
+  ///
+  /// The format, GFF3 or GenBank, is detected from the file extension (`.gff`, `.gff3` or `.gb`, `.gbk`, `.gbff`, `.genbank`) or, if the extension is not known, from the file content. In GenBank files, genes are read from the `CDS` features and are named after their `gene`, `locus_tag` or `product` qualifier.
   ///
   /// Gene map (sometimes also called 'genome annotation') is used to find coding regions. If not supplied, coding regions will
   /// not be translated, amino acid sequences will not be output, amino acid mutations will not be detected and nucleotide sequence
@@ -335,6 +599,9 @@ pub struct NextcladeRunInputArgs {
   /// Learn more about Generic Feature Format Version 3 (GFF3):
   /// https://github.com/The-Sequence-Ontology/Specifications/blob/master/gff3.md
   ///
+  /// Learn more about GenBank flat file format:
+  /// https://www.ncbi.nlm.nih.gov/genbank/samplerecord/
+  ///
   /// Supports the following compression formats: "gz", "bz2", "xz", "zstd". Use "-" to read uncompressed data from standard input (stdin).
   #[clap(long, short = 'm', alias = "genemap")]
   #[clap(value_hint = ValueHint::FilePath)]
@@ -357,11 +624,20 @@ pub struct NextcladeRunInputArgs {
   #[clap(value_hint = ValueHint::FilePath)]
   pub genes: Option<Vec<String>>,
 
-  /// Use custom dataset server
+  /// Use custom dataset server. Can be a URL, including a `file://` URL, or a path to a local directory containing the dataset index.
   #[clap(long)]
   #[clap(value_hint = ValueHint::Url)]
   #[clap(default_value_t = Url::from_str(DATA_FULL_DOMAIN).expect("Invalid URL"))]
+  #[clap(parse(try_from_str = parse_server_url))]
   pub server: Url,
+
+  /// Path to the local dataset cache directory, used with `--dataset-name`. The dataset is looked up in the cache first: if the version tag is pinned (`<name>@<tag>`), this version is used, otherwise the latest compatible cached version is used. Only if the dataset is not found in the cache, it is downloaded, and then stored in the cache for later runs. Checksums of the cached files are verified before use.
+  ///
+  /// Can also be set with the environment variable `NEXTCLADE_DATASET_CACHE_DIR`. See `dataset get --help` for more details.
+  #[clap(long)]
+  #[clap(env = "NEXTCLADE_DATASET_CACHE_DIR")]
+  #[clap(value_hint = ValueHint::DirPath)]
+  pub cache_dir: Option<PathBuf>,
 }
 
 #[allow(clippy::struct_excessive_bools)]
@@ -380,7 +656,9 @@ pub struct NextcladeRunOutputArgs {
   ///
   /// If both the `--output-all` and individual `--output-*` flags are provided, each individual flag overrides the corresponding default output path.
   ///
-  /// At least one of the output flags is required: `--output-all`, `--output-fasta`, `--output-ndjson`, `--output-json`, `--output-csv`, `--output-tsv`, `--output-tree`, `--output-translations`, `--output-insertions`, `--output-errors`
+  /// For datasets of segmented genomes (with more than one sequence in the reference sequence file) the outputs of each segment are written into a subdirectory named after the segment. In this case `--output-all` is required, and individual `--output-*` file arguments cannot be used.
+  ///
+  /// At least one of the output flags is required: `--output-all`, `--output-fasta`, `--output-ndjson`, `--output-json`, `--output-csv`, `--output-tsv`, `--output-tree`, `--output-tree-nwk`, `--output-tree-nexus`, `--output-tree-pb`, `--output-translations`, `--output-insertions`, `--output-errors`
   ///
   /// If the required directory tree does not exist, it will be created.
   #[clap(long, short = 'O')]
@@ -503,7 +781,7 @@ pub struct NextcladeRunOutputArgs {
   ///
   /// Should contain a comma-separated list of individual column names and/or column category names to include into both CSV and TSV outputs.
   ///
-  /// If this flag is omitted, or if category 'all' is present in the list, then all other entries are ignored and all columns are written.
+  /// If this flag is omitted, or if category 'all' is present in the list, then all other categories are ignored and all columns are written, except for the columns which are not written by default. These columns are only written when listed individually, e.g. `--output-columns-selection=all,segment`. Columns not written by default: `segment`, `aaAmbiguities`, `mixedSites.sites`, `mixedSites.totalCoinfectionSites`, `mixedSites.coinfectionScore`, `mixedSites.coinfectionCladePairs`, `placement.cladeConfidence`, `placement.candidates`.
   ///
   /// Only valid together with one or multiple of flags: `--output-csv`, `--output-tsv`, `--output-all`.
   #[clap(
@@ -531,6 +809,51 @@ pub struct NextcladeRunOutputArgs {
   #[clap(value_hint = ValueHint::AnyPath)]
   pub output_tree: Option<PathBuf>,
 
+  /// Path to output phylogenetic tree with input sequences placed onto it, in Newick format.
+  ///
+  /// For file format description see: https://en.wikipedia.org/wiki/Newick_format
+  ///
+  /// Branch lengths are the differences in divergence between a node and its parent. Node attributes are not included. By contrast to Auspice JSON, the tree is written as it is traversed, without constructing the entire output in memory, so much bigger trees are feasible.
+  ///
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zstd", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_tree_nwk: Option<PathBuf>,
+
+  /// Path to output phylogenetic tree with input sequences placed onto it, in Nexus format.
+  ///
+  /// For file format description see: https://en.wikipedia.org/wiki/Nexus_file
+  ///
+  /// The tree is the same as in `--output-tree-nwk`, but the nodes are annotated with clade, QC status and branch mutations, in a form understood by common tree viewers, such as FigTree.
+  ///
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zstd", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_tree_nexus: Option<PathBuf>,
+
+  /// Path to output phylogenetic tree with input sequences placed onto it, as UShER mutation-annotated tree (MAT) in protobuf format.
+  ///
+  /// For file format description see: https://usher-wiki.readthedocs.io/en/latest/matUtils.html
+  ///
+  /// The output can be further processed with UShER and matUtils. Only substitutions between nucleotides A, C, G and T are included, because the format does not support other mutations. Clades and clade-like node attributes are written as clade annotations.
+  ///
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zstd", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_tree_pb: Option<PathBuf>,
+
   /// Path to output CSV file that contain insertions stripped from the reference alignment.
   ///
   /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
@@ -553,6 +876,52 @@ pub struct NextcladeRunOutputArgs {
   #[clap(value_hint = ValueHint::AnyPath)]
   pub output_errors: Option<PathBuf>,
 
+  /// Path to output TSV file with the assignment of sequences to datasets, in multi-dataset mode (see `--input-datasets`).
+  ///
+  /// Contains name of the dataset each sequence was assigned to and the k-mer similarity score of the match. Sequences which were not assigned to any dataset have an empty dataset name.
+  ///
+  /// Takes precedence over paths configured with `--output-all` and `--output-basename`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zstd", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_dataset_assignment: Option<PathBuf>,
+
+  /// Path to output CSV file with results joined per isolate, for datasets of segmented genomes and in multi-dataset mode.
+  ///
+  /// Sequences are grouped into isolates by their names (see `--isolate-name-regex`). Each row contains the main results for each of the segments (or datasets) of one isolate.
+  ///
+  /// Takes precedence over paths configured with `--output-all` and `--output-basename`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zstd", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_isolates_csv: Option<PathBuf>,
+
+  /// Path to output TSV file with results joined per isolate (delimiter: tab). Equivalent to `--output-isolates-csv`, except for the column delimiter.
+  ///
+  /// Takes precedence over paths configured with `--output-all` and `--output-basename`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zstd", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_isolates_tsv: Option<PathBuf>,
+
+  /// Regular expression which extracts isolate name from sequence name, for the outputs joined per isolate (see `--output-isolates-csv`).
+  ///
+  /// Isolate name is the capture group named `isolate` or, if there is no such group, the first capture group. Sequences with names not matching the expression are considered separate isolates.
+  ///
+  /// By default, isolate name is the part of the sequence name before the last `|` character, e.g. `A/Texas/50/2012` in `A/Texas/50/2012|HA`.
+  #[clap(long)]
+  #[clap(default_value = ISOLATE_NAME_REGEX_DEFAULT)]
+  pub isolate_name_regex: String,
+
   /// Whether to include aligned reference nucleotide sequence into output nucleotide sequence FASTA file and reference peptides into output peptide FASTA files.
   #[clap(long)]
   pub include_reference: bool,
@@ -581,6 +950,11 @@ pub struct NextcladeRunOutputArgs {
   /// The following characters are considered known:  '-', 'A', 'B', 'C', 'D', 'G', 'H', 'K', 'M', 'N', 'R', 'S', 'T', 'V', 'W', 'Y'
   #[clap(long)]
   pub replace_unknown: bool,
+
+  /// Whether any of the individual output files (e.g. `--output-fasta`) is provided by the user, as opposed to being
+  /// deduced from `--output-all`. Set before the default output filenames are deduced.
+  #[clap(skip)]
+  pub has_individual_output_args: bool,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -601,6 +975,12 @@ pub struct NextcladeRunArgs {
   #[clap(flatten, next_help_heading = "  Alignment parameters")]
   pub alignment_params: AlignPairwiseParamsOptional,
 
+  #[clap(flatten, next_help_heading = "  Tree placement")]
+  pub placement_params: TreePlacementParams,
+
+  #[clap(flatten, next_help_heading = "  Multi-dataset mode")]
+  pub sketch_params: DatasetSketchParams,
+
   #[clap(flatten, next_help_heading = "  Other")]
   pub other: NextcladeRunOtherArgs,
 }
@@ -636,6 +1016,7 @@ pub fn nextclade_get_output_filenames(run_args: &mut NextcladeRunArgs) -> Result
         input_virus_properties,
         input_pcr_primers,
         input_gene_map,
+        input_datasets,
         genes,
         ..
       },
@@ -651,14 +1032,18 @@ pub fn nextclade_get_output_filenames(run_args: &mut NextcladeRunArgs) -> Result
         output_csv,
         output_tsv,
         output_tree,
+        output_tree_nwk,
+        output_tree_nexus,
+        output_tree_pb,
         output_insertions,
         output_errors,
+        output_dataset_assignment,
         include_reference,
         in_order,
         ..
       },
     other: NextcladeRunOtherArgs { jobs },
-    alignment_params,
+    ..
   } = run_args;
 
   // If `--output-all` is provided, then we need to deduce default output filenames,
@@ -721,6 +1106,22 @@ pub fn nextclade_get_output_filenames(run_args: &mut NextcladeRunArgs) -> Result
     if output_selection.contains(&NextcladeOutputSelection::Tree) {
       output_tree.get_or_insert(add_extension(&default_output_file_path, "auspice.json"));
     }
+
+    if output_selection.contains(&NextcladeOutputSelection::TreeNwk) {
+      output_tree_nwk.get_or_insert(add_extension(&default_output_file_path, "nwk"));
+    }
+
+    if output_selection.contains(&NextcladeOutputSelection::TreeNexus) {
+      output_tree_nexus.get_or_insert(add_extension(&default_output_file_path, "nexus"));
+    }
+
+    if output_selection.contains(&NextcladeOutputSelection::TreePb) {
+      output_tree_pb.get_or_insert(add_extension(&default_output_file_path, "pb"));
+    }
+
+    if !input_datasets.is_empty() {
+      output_dataset_assignment.get_or_insert(add_extension(&default_output_file_path, "dataset_assignment.tsv"));
+    }
   }
 
   if let Some(output_translations) = output_translations {
@@ -749,6 +1150,9 @@ Example for bash shell:
     output_csv,
     output_tsv,
     output_tree,
+    output_tree_nwk,
+    output_tree_nexus,
+    output_tree_pb,
     output_insertions,
     output_errors,
   ]
@@ -768,15 +1172,41 @@ At least one of the following flags is required:
   --output-csv
   --output-tsv
   --output-tree
+  --output-tree-nwk
+  --output-tree-nexus
+  --output-tree-pb
   --output-translations
   --output-insertions
   --output-errors"#
     );
   }
 
+  if !input_datasets.is_empty() {
+    nextclade_get_isolates_output_filenames(&mut run_args.outputs);
+  }
+
   Ok(())
 }
 
+/// Deduces default paths of the outputs joined per isolate, if `--output-all` is provided. These outputs are only
+/// produced for datasets of segmented genomes and in multi-dataset mode.
+pub fn nextclade_get_isolates_output_filenames(outputs: &mut NextcladeRunOutputArgs) {
+  let NextcladeRunOutputArgs {
+    output_all,
+    output_basename,
+    output_isolates_csv,
+    output_isolates_tsv,
+    ..
+  } = outputs;
+
+  if let Some(output_all) = output_all {
+    let output_basename = output_basename.as_deref().unwrap_or("nextclade");
+    let default_output_file_path = output_all.join(output_basename);
+    output_isolates_csv.get_or_insert(add_extension(&default_output_file_path, "isolates.csv"));
+    output_isolates_tsv.get_or_insert(add_extension(&default_output_file_path, "isolates.tsv"));
+  }
+}
+
 const ERROR_MSG_INPUT_FASTA_REMOVED: &str = r#"The argument `--input-fasta` (alias: `--sequences`, `-i`) is removed in favor of positional arguments.
 
 Try:
@@ -833,6 +1263,98 @@ pub fn nextclade_check_column_config_args(run_args: &NextcladeRunArgs) -> Result
   Ok(())
 }
 
+/// Individual output file arguments, which cannot be used in multi-dataset mode and with datasets of segmented
+/// genomes, because each dataset (or segment) produces its own outputs
+pub const INDIVIDUAL_OUTPUT_ARGS: &str = "`--output-fasta`, `--output-translations`, `--output-ndjson`, `--output-json`, `--output-csv`, `--output-tsv`, `--output-tree`, `--output-tree-nwk`, `--output-tree-nexus`, `--output-tree-pb`, `--output-insertions`";
+
+fn has_individual_output_args(outputs: &NextcladeRunOutputArgs) -> bool {
+  let NextcladeRunOutputArgs {
+    output_fasta,
+    output_translations,
+    output_ndjson,
+    output_json,
+    output_csv,
+    output_tsv,
+    output_tree,
+    output_tree_nwk,
+    output_tree_nexus,
+    output_tree_pb,
+    output_insertions,
+    ..
+  } = outputs;
+
+  [
+    output_fasta,
+    output_ndjson,
+    output_json,
+    output_csv,
+    output_tsv,
+    output_tree,
+    output_tree_nwk,
+    output_tree_nexus,
+    output_tree_pb,
+    output_insertions,
+  ]
+  .iter()
+  .any(|arg| arg.is_some())
+    || output_translations.is_some()
+}
+
+pub fn nextclade_check_multi_dataset_args(run_args: &NextcladeRunArgs) -> Result<(), Report> {
+  let NextcladeRunInputArgs {
+    input_datasets,
+    input_ref,
+    input_tree,
+    input_node_data,
+    input_tree_alignment,
+    input_qc_config,
+    input_virus_properties,
+    input_pcr_primers,
+    input_gene_map,
+    ..
+  } = &run_args.inputs;
+
+  let NextcladeRunOutputArgs {
+    output_all,
+    output_dataset_assignment,
+    ..
+  } = &run_args.outputs;
+
+  if input_datasets.is_empty() {
+    if output_dataset_assignment.is_some() {
+      return make_error!("The `--output-dataset-assignment` argument can only be used in multi-dataset mode, i.e. together with `--input-datasets`.");
+    }
+    return Ok(());
+  }
+
+  if output_all.is_none() {
+    return make_error!("In multi-dataset mode (`--input-datasets`), the `--output-all` argument is required: outputs for each dataset are written into subdirectories of this directory.");
+  }
+
+  let input_overrides_are_present = [
+    input_ref,
+    input_tree,
+    input_qc_config,
+    input_virus_properties,
+    input_pcr_primers,
+    input_gene_map,
+  ]
+  .iter()
+  .any(|arg| arg.is_some())
+    || !input_node_data.is_empty()
+    || input_tree_alignment.is_some();
+
+  if input_overrides_are_present {
+    return make_error!("In multi-dataset mode (`--input-datasets`), individual input files (`--input-ref`, `--input-tree`, `--input-node-data`, `--input-tree-alignment`, `--input-qc-config`, `--input-virus-properties`, `--input-pcr-primers`, `--input-gene-map`) cannot be used. Each dataset should contain all of its files.");
+  }
+
+  if has_individual_output_args(&run_args.outputs) {
+    return make_error!("In multi-dataset mode (`--input-datasets`), individual output files ({INDIVIDUAL_OUTPUT_ARGS}) cannot be used, because each dataset produces its own outputs. Use `--output-all` together with `--output-selection` instead.");
+  }
+
+  Ok(())
+}
+
 pub fn nextclade_parse_cli_args() -> Result<(), Report> {
   let args = NextcladeArgs::parse();
 
@@ -845,12 +1367,17 @@ pub fn nextclade_parse_cli_args() -> Result<(), Report> {
     NextcladeCommands::Run(mut run_args) => {
       nextclade_check_removed_args(&run_args)?;
       nextclade_check_column_config_args(&run_args)?;
+      nextclade_check_multi_dataset_args(&run_args)?;
+      run_args.outputs.has_individual_output_args = has_individual_output_args(&run_args.outputs);
       nextclade_get_output_filenames(&mut run_args).wrap_err("When deducing output filenames")?;
       nextclade_run(*run_args)
     }
     NextcladeCommands::Dataset(dataset_command) => match dataset_command.command {
       NextcladeDatasetCommands::List(dataset_list_args) => nextclade_dataset_list(dataset_list_args),
       NextcladeDatasetCommands::Get(dataset_get_args) => nextclade_dataset_get(&dataset_get_args),
+      NextcladeDatasetCommands::Build(dataset_build_args) => nextclade_dataset_build(&dataset_build_args),
+      NextcladeDatasetCommands::Check(dataset_check_args) => nextclade_dataset_check(&dataset_check_args),
+      NextcladeDatasetCommands::Diff(dataset_diff_args) => nextclade_dataset_diff(&dataset_diff_args),
     },
   }
 }
@@ -0,0 +1,289 @@
+use crate::cli::nextclade_cli::NextcladeDatasetBuildArgs;
+use crate::dataset::dataset::{
+  Dataset, DatasetAttributeValue, DatasetAttributes, DatasetCompatibility, DatasetCompatibilityRange,
+};
+use crate::dataset::dataset_check::{dataset_check_files, find_genes_outside_of_ref, format_dataset_check_report};
+use crate::dataset::dataset_download::{get_segment_name, read_ref_tree_file};
+use eyre::{Report, WrapErr};
+use log::info;
+use nextclade::align::params::AlignPairwiseParams;
+use nextclade::analyze::pcr_primers::PcrPrimer;
+use nextclade::analyze::virus_properties::VirusProperties;
+use nextclade::io::fasta::{read_many_fasta_str, FastaRecord};
+use nextclade::io::fs::{absolute_path, read_file_to_string};
+use nextclade::io::gene_map::{read_gene_map_str, GeneMapFormat};
+use nextclade::io::gff3::write_gff3_str;
+use nextclade::io::json::json_stringify;
+use nextclade::io::nuc::to_nuc_seq;
+use nextclade::io::nwk_reader::NwkNode;
+use nextclade::qc::qc_config::QcConfig;
+use nextclade::translate::translate_genes_ref::translate_genes_ref;
+use nextclade::tree::tree::AuspiceTree;
+use nextclade::tree::tree_assign_clades::{tree_assign_clades_in_place, CladeDefinitions};
+use nextclade::tree::tree_from_nwk::tree_from_nwk_and_node_data;
+use nextclade::{getenv, make_error};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const THIS_VERSION: &str = getenv!("CARGO_PKG_VERSION");
+
+const GENE_MAP_EMPTY: &str = "##gff-version 3\n";
+
+const VIRUS_PROPERTIES_MINIMAL: &str = r#"{
+  "schemaVersion": "1.10.0",
+  "nucMutLabelMap": {}
+}
+"#;
+
+const PRIMERS_EMPTY: &str = "Country (Institute),Target,Oligonucleotide,Sequence\n";
+
+const QC_CONFIG_SCHEMA_VERSION: &str = "1.2.0";
+
+/// Builds a dataset from individual input files. All files are validated against each other in the same way as by
+/// `nextclade dataset check`, and the missing optional files are replaced with minimal defaults.
+pub fn nextclade_dataset_build(args: &NextcladeDatasetBuildArgs) -> Result<(), Report> {
+  let NextcladeDatasetBuildArgs {
+    input_ref,
+    input_gene_map,
+    input_tree,
+    input_node_data,
+    input_tree_alignment,
+    input_clades,
+    input_qc_config,
+    input_virus_properties,
+    input_pcr_primers,
+    input_example_sequences,
+    name,
+    tag,
+    output_dir,
+    output_zip,
+  } = args;
+
+  let ref_str = read_file_to_string(input_ref).wrap_err("When reading reference sequence")?;
+  let ref_record = match read_many_fasta_str(&ref_str)
+    .wrap_err("When reading reference sequence")?
+    .as_slice()
+  {
+    [ref_record] => ref_record.clone(),
+    [] => return make_error!("Reference sequence file contains no sequences"),
+    _ => {
+      return make_error!(
+        "Reference sequence file contains more than one sequence. Building datasets of segmented genomes is not supported. Please provide a file with exactly one reference sequence."
+      )
+    }
+  };
+  let ref_seq = to_nuc_seq(&ref_record.seq).wrap_err("When reading reference sequence")?;
+
+  let gene_map_str = match input_gene_map {
+    Some(input_gene_map) => read_file_to_string(input_gene_map)?,
+    None => GENE_MAP_EMPTY.to_owned(),
+  };
+  let gene_map_format = GeneMapFormat::guess(input_gene_map.as_deref(), &gene_map_str);
+  let gene_map = read_gene_map_str(&gene_map_str, gene_map_format).wrap_err("When reading gene map")?;
+  if let Some(message) = find_genes_outside_of_ref(&gene_map, ref_seq.len()).values().next() {
+    return make_error!("{message}");
+  }
+
+  let virus_properties_str = match input_virus_properties {
+    Some(input_virus_properties) => read_file_to_string(input_virus_properties)?,
+    None => VIRUS_PROPERTIES_MINIMAL.to_owned(),
+  };
+  let virus_properties = VirusProperties::from_str(&virus_properties_str).wrap_err("When reading virus properties")?;
+
+  let qc_config_str = match input_qc_config {
+    Some(input_qc_config) => read_file_to_string(input_qc_config)?,
+    None => json_stringify(&QcConfig {
+      schema_version: QC_CONFIG_SCHEMA_VERSION.to_owned(),
+      ..QcConfig::default()
+    })?,
+  };
+  QcConfig::from_str(&qc_config_str).wrap_err("When reading QC configuration")?;
+
+  let primers_str = match input_pcr_primers {
+    Some(input_pcr_primers) => read_file_to_string(input_pcr_primers)?,
+    None => PRIMERS_EMPTY.to_owned(),
+  };
+  PcrPrimer::from_str(&primers_str, &ref_record.seq).wrap_err("When reading PCR primers")?;
+
+  let mut tree = match input_tree {
+    Some(input_tree) => read_ref_tree_file(
+      input_tree,
+      input_node_data,
+      input_tree_alignment,
+      &ref_record,
+      &gene_map,
+      &virus_properties,
+    ),
+    None => tree_from_ref_record(&ref_record),
+  }
+  .wrap_err("When reading reference tree")?;
+
+  let mut alignment_params = AlignPairwiseParams::default();
+  if let Some(alignment_params_from_file) = &virus_properties.alignment_params {
+    alignment_params.merge_opt(alignment_params_from_file.clone());
+  }
+
+  let ref_peptides = translate_genes_ref(&ref_seq, &gene_map, virus_properties.genetic_code()?, &alignment_params)
+    .wrap_err("When translating reference genes")?;
+
+  if let Some(input_clades) = input_clades {
+    let clade_definitions = CladeDefinitions::from_path(input_clades)?;
+    tree_assign_clades_in_place(&mut tree, &clade_definitions, &ref_seq, &ref_peptides)
+      .wrap_err("When assigning clades to the reference tree")?;
+  }
+
+  let tree_str = tree.to_string_pretty()?;
+
+  let example_sequences_str = input_example_sequences
+    .as_ref()
+    .map(|input_example_sequences| -> Result<String, Report> {
+      let content = read_file_to_string(input_example_sequences)?;
+      read_many_fasta_str(&content).wrap_err("When reading example sequences")?;
+      Ok(content)
+    })
+    .transpose()?;
+
+  // Datasets always contain the genome annotation in GFF3 format
+  let gene_map_str = match gene_map_format {
+    GeneMapFormat::Gff3 => gene_map_str,
+    GeneMapFormat::GenBank => write_gff3_str(&gene_map, &get_segment_name(&ref_record.seq_name)),
+  };
+
+  let mut files = BTreeMap::from([
+    ("reference.fasta".to_owned(), ref_str),
+    ("genemap.gff".to_owned(), gene_map_str),
+    ("tree.json".to_owned(), tree_str),
+    ("qc.json".to_owned(), qc_config_str),
+    ("virus_properties.json".to_owned(), virus_properties_str),
+    ("primers.csv".to_owned(), primers_str),
+  ]);
+  if let Some(example_sequences_str) = example_sequences_str {
+    files.insert("sequences.fasta".to_owned(), example_sequences_str);
+  }
+
+  let dataset = create_dataset_tag(name, tag, &ref_record, files.keys());
+  files.insert("tag.json".to_owned(), json_stringify(&dataset)?);
+
+  // Cross-validate the files in the same way as `nextclade dataset check` does
+  let filenames = files.keys().cloned().collect();
+  let report = dataset_check_files(&filenames, |filename| Ok(files[filename].clone()));
+  if report.has_errors() {
+    return make_error!(
+      "The dataset files are not consistent with each other:\n{}",
+      format_dataset_check_report(&report)
+    );
+  }
+
+  if let Some(output_dir) = output_dir {
+    dataset_dir_write(&files, output_dir)?;
+    info!("Dataset '{name}' (tag '{tag}') is written to directory {output_dir:#?}");
+  } else if let Some(output_zip) = output_zip {
+    dataset_zip_write(&files, output_zip)?;
+    info!("Dataset '{name}' (tag '{tag}') is written to zip file {output_zip:#?}");
+  }
+
+  Ok(())
+}
+
+/// Creates a tree consisting of a single node: the reference sequence
+fn tree_from_ref_record(ref_record: &FastaRecord) -> Result<AuspiceTree, Report> {
+  let root = NwkNode {
+    name: ref_record.seq_name.clone(),
+    branch_length: None,
+    children: vec![],
+  };
+  tree_from_nwk_and_node_data(&root, &[])
+}
+
+/// Creates dataset version information (`tag.json`)
+fn create_dataset_tag<'a>(
+  name: &str,
+  tag: &str,
+  ref_record: &FastaRecord,
+  filenames: impl IntoIterator<Item = &'a String>,
+) -> Dataset {
+  let (accession, friendly_name) = match ref_record.seq_name.trim().split_once(char::is_whitespace) {
+    Some((accession, friendly_name)) => (accession.to_owned(), Some(friendly_name.trim().to_owned())),
+    None => (ref_record.seq_name.trim().to_owned(), None),
+  };
+
+  let files = filenames
+    .into_iter()
+    .chain([&"tag.json".to_owned()])
+    .map(|filename| (filename.clone(), filename.clone()))
+    .collect();
+
+  Dataset {
+    enabled: true,
+    attributes: DatasetAttributes {
+      name: DatasetAttributeValue {
+        is_default: true,
+        value: name.to_owned(),
+        value_friendly: None,
+      },
+      reference: DatasetAttributeValue {
+        is_default: true,
+        value: accession,
+        value_friendly: friendly_name,
+      },
+      tag: DatasetAttributeValue {
+        is_default: true,
+        value: tag.to_owned(),
+        value_friendly: None,
+      },
+      rest_attrs: BTreeMap::new(),
+    },
+    comment: String::new(),
+    compatibility: DatasetCompatibility {
+      nextclade_cli: DatasetCompatibilityRange {
+        min: Some(THIS_VERSION.to_owned()),
+        max: None,
+      },
+      nextclade_web: DatasetCompatibilityRange { min: None, max: None },
+    },
+    files,
+    params: None,
+    zip_bundle: String::new(),
+  }
+}
+
+fn dataset_dir_write(files: &BTreeMap<String, String>, output_dir: &Path) -> Result<(), Report> {
+  let output_dir = &absolute_path(output_dir)?;
+  fs::create_dir_all(output_dir).wrap_err_with(|| format!("When creating directory '{output_dir:#?}'"))?;
+
+  files.iter().try_for_each(|(filename, content)| -> Result<(), Report> {
+    let output_file_path = output_dir.join(filename);
+    fs::write(&output_file_path, content).wrap_err_with(|| format!("When writing dataset file {output_file_path:#?}"))
+  })
+}
+
+fn dataset_zip_write(files: &BTreeMap<String, String>, output_file_path: &Path) -> Result<(), Report> {
+  if let Some(parent_dir) = output_file_path.parent() {
+    let parent_dir = &absolute_path(parent_dir)?;
+    fs::create_dir_all(parent_dir)
+      .wrap_err_with(|| format!("When creating parent directory '{parent_dir:#?}' for file '{output_file_path:#?}'"))?;
+  }
+
+  let file =
+    File::create(output_file_path).wrap_err_with(|| format!("When creating dataset zip file {output_file_path:#?}"))?;
+  let mut zip = ZipWriter::new(file);
+  let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+  files
+    .iter()
+    .try_for_each(|(filename, content)| -> Result<(), Report> {
+      zip.start_file(filename, options)?;
+      zip.write_all(content.as_bytes())?;
+      Ok(())
+    })
+    .wrap_err_with(|| format!("When writing dataset zip file {output_file_path:#?}"))?;
+
+  zip.finish()?;
+  Ok(())
+}
@@ -0,0 +1,22 @@
+use crate::cli::nextclade_cli::NextcladeDatasetCheckArgs;
+use crate::dataset::dataset_check::{dataset_check_path, format_dataset_check_report};
+use eyre::Report;
+use nextclade::io::json::json_stringify;
+use nextclade::make_error;
+
+pub fn nextclade_dataset_check(args: &NextcladeDatasetCheckArgs) -> Result<(), Report> {
+  let report = dataset_check_path(&args.input_dataset)?;
+
+  if args.json {
+    println!("{}", json_stringify(&report)?);
+  } else {
+    println!("{}", format_dataset_check_report(&report));
+  }
+
+  // Non-zero exit code allows to use the check in CI
+  if report.has_errors() {
+    return make_error!("Dataset {:#?} has {} error(s)", args.input_dataset, report.num_errors());
+  }
+
+  Ok(())
+}
@@ -0,0 +1,60 @@
+use crate::cli::nextclade_cli::NextcladeDatasetDiffArgs;
+use crate::dataset::dataset_cache::{parse_dataset_name_with_tag, DatasetCache};
+use crate::dataset::dataset_diff::{dataset_diff_files, dataset_read_files_for_diff, format_dataset_diff};
+use eyre::{Report, WrapErr};
+use nextclade::io::json::json_stringify;
+use nextclade::make_error;
+use std::path::{Path, PathBuf};
+
+pub fn nextclade_dataset_diff(args: &NextcladeDatasetDiffArgs) -> Result<(), Report> {
+  let NextcladeDatasetDiffArgs {
+    old_dataset,
+    new_dataset,
+    cache_dir,
+    json,
+  } = args;
+
+  let old_path = resolve_dataset_path(old_dataset, cache_dir)?;
+  let new_path = resolve_dataset_path(new_dataset, cache_dir)?;
+
+  let old_files =
+    dataset_read_files_for_diff(&old_path).wrap_err_with(|| format!("When reading dataset {old_path:#?}"))?;
+  let new_files =
+    dataset_read_files_for_diff(&new_path).wrap_err_with(|| format!("When reading dataset {new_path:#?}"))?;
+
+  let diff = dataset_diff_files(&old_files, &new_files)?;
+
+  if *json {
+    println!("{}", json_stringify(&diff)?);
+  } else {
+    print!("{}", format_dataset_diff(&diff));
+  }
+
+  Ok(())
+}
+
+/// Finds the dataset either on disk or, if there is no such path, in the dataset cache
+fn resolve_dataset_path(dataset: &Path, cache_dir: &Option<PathBuf>) -> Result<PathBuf, Report> {
+  if dataset.exists() {
+    return Ok(dataset.to_owned());
+  }
+
+  let name_with_tag = dataset.to_string_lossy();
+  match cache_dir {
+    Some(cache_dir) => {
+      let (name, tag) = parse_dataset_name_with_tag(&name_with_tag);
+      match DatasetCache::new(cache_dir)?.find(name, tag)? {
+        Some(entry) => {
+          entry.verify()?;
+          Ok(entry.path)
+        }
+        None => make_error!(
+          "Dataset '{name_with_tag}' is not found: there is no such file or directory, and no such dataset in the cache directory {cache_dir:#?}"
+        ),
+      }
+    }
+    None => make_error!(
+      "Dataset '{name_with_tag}' is not found: there is no such file or directory. To use datasets from the cache, set `--cache-dir`."
+    ),
+  }
+}
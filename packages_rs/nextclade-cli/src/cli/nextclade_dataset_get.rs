@@ -1,13 +1,19 @@
 use crate::cli::nextclade_cli::NextcladeDatasetGetArgs;
 use crate::dataset::dataset::{Dataset, DatasetsIndexJson};
 use crate::dataset::dataset_attributes::{format_attribute_list, parse_dataset_attributes};
-use crate::dataset::dataset_download::{dataset_dir_download, dataset_zip_download};
+use crate::dataset::dataset_cache::DatasetCache;
+use crate::dataset::dataset_download::{dataset_dir_download, dataset_zip_download, zip_read_str};
 use crate::dataset::dataset_table::format_dataset_table;
 use crate::io::http_client::HttpClient;
 use eyre::{eyre, Report, WrapErr};
 use itertools::Itertools;
 use log::{info, LevelFilter};
+use nextclade::io::fs::read_file_to_string;
+use nextclade::io::json::json_parse;
 use nextclade::{getenv, make_error};
+use std::fs::File;
+use std::io::BufReader;
+use zip::ZipArchive;
 
 const THIS_VERSION: &str = getenv!("CARGO_PKG_VERSION");
 
@@ -116,6 +122,10 @@ pub fn nextclade_dataset_get(args: &NextcladeDatasetGetArgs) -> Result<(), Repor
   let verbose = log::max_level() > LevelFilter::Info;
   let mut http = HttpClient::new(&args.server, &args.proxy_config, verbose)?;
 
+  if args.check_updates {
+    return nextclade_dataset_check_updates(&mut http, args);
+  }
+
   let dataset = nextclade_dataset_http_get(
     &mut http,
     DatasetHttpGetParams {
@@ -134,9 +144,90 @@ pub fn nextclade_dataset_get(args: &NextcladeDatasetGetArgs) -> Result<(), Repor
     dataset_zip_download(&mut http, &dataset, output_zip)?;
   }
 
+  if let Some(cache_dir) = &args.cache_dir {
+    DatasetCache::new(cache_dir)?.download(&mut http, &dataset)?;
+  }
+
   Ok(())
 }
 
+/// Reports whether the latest compatible version of the dataset in the index is newer than the local one
+fn nextclade_dataset_check_updates(http: &mut HttpClient, args: &NextcladeDatasetGetArgs) -> Result<(), Report> {
+  if args.tag != "latest" {
+    return make_error!(
+      "`--check-updates` compares the local dataset with the latest version, so `--tag` cannot be used with it"
+    );
+  }
+
+  let latest = nextclade_dataset_http_get(
+    http,
+    DatasetHttpGetParams {
+      name: &args.name,
+      reference: &args.reference,
+      tag: &args.tag,
+    },
+    &args.attribute,
+  )?;
+  let name = &latest.attributes.name.value;
+  let latest_tag = &latest.attributes.tag.value;
+
+  let local = find_local_dataset(args)?;
+  match local {
+    None => println!("No local version of dataset '{name}' is found. The latest version is '{latest_tag}'."),
+    Some(local) => {
+      let local_tag = &local.attributes.tag.value;
+      if &local.attributes.name.value != name {
+        return make_error!(
+          "The local dataset is '{}', but dataset '{name}' is requested",
+          local.attributes.name.value
+        );
+      }
+      // Tags are timestamps, so they are ordered chronologically when compared as strings
+      if latest_tag > local_tag {
+        println!("A newer version of dataset '{name}' is available: '{latest_tag}' (local version: '{local_tag}'). To update, run the same command without `--check-updates`.");
+      } else {
+        println!("Dataset '{name}' is up to date (version '{local_tag}').");
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Reads version information of the dataset at the output location
+fn find_local_dataset(args: &NextcladeDatasetGetArgs) -> Result<Option<Dataset>, Report> {
+  if let Some(output_dir) = &args.output_dir {
+    let tag_path = output_dir.join("tag.json");
+    if tag_path.is_file() {
+      let content = read_file_to_string(&tag_path)?;
+      return json_parse(&content)
+        .wrap_err_with(|| format!("When parsing {tag_path:#?}"))
+        .map(Some);
+    }
+  }
+
+  if let Some(output_zip) = &args.output_zip {
+    if output_zip.is_file() {
+      let file = File::open(output_zip).wrap_err_with(|| format!("When opening dataset file {output_zip:#?}"))?;
+      let mut zip = ZipArchive::new(BufReader::new(file))
+        .wrap_err_with(|| format!("When reading dataset zip file {output_zip:#?}"))?;
+      let content =
+        zip_read_str(&mut zip, "tag.json").wrap_err_with(|| format!("When reading 'tag.json' from {output_zip:#?}"))?;
+      return json_parse(&content)
+        .wrap_err_with(|| format!("When parsing 'tag.json' from {output_zip:#?}"))
+        .map(Some);
+    }
+  }
+
+  if let Some(cache_dir) = &args.cache_dir {
+    if let Some(entry) = DatasetCache::new(cache_dir)?.find(&args.name, None)? {
+      return Ok(Some(entry.manifest.dataset));
+    }
+  }
+
+  Ok(None)
+}
+
 pub fn dataset_file_http_get(http: &mut HttpClient, dataset: &Dataset, filename: &str) -> Result<String, Report> {
   let url = dataset
     .files
@@ -1,6 +1,7 @@
 use crate::cli::nextclade_cli::NextcladeDatasetListArgs;
 use crate::dataset::dataset::DatasetsIndexJson;
 use crate::dataset::dataset_attributes::{format_attribute_list, parse_dataset_attributes};
+use crate::dataset::dataset_cache::DatasetCache;
 use crate::dataset::dataset_table::format_dataset_table;
 use crate::io::http_client::HttpClient;
 use eyre::Report;
@@ -18,15 +19,31 @@ pub fn nextclade_dataset_list(
     mut tag,
     attribute,
     include_incompatible,
-    include_old,
+    mut include_old,
     json,
+    local,
+    cache_dir,
     server,
     proxy_config,
   }: NextcladeDatasetListArgs,
 ) -> Result<(), Report> {
-  let verbose = log::max_level() > LevelFilter::Info;
-  let mut http = HttpClient::new(&server, &proxy_config, verbose)?;
-  let DatasetsIndexJson { datasets, .. } = DatasetsIndexJson::download(&mut http)?;
+  let datasets = match (&cache_dir, local) {
+    (Some(cache_dir), true) => {
+      // All cached versions are shown, because any of them can be used
+      include_old = true;
+      DatasetCache::new(cache_dir)?
+        .list()?
+        .into_iter()
+        .map(|entry| entry.manifest.dataset)
+        .collect_vec()
+    }
+    _ => {
+      let verbose = log::max_level() > LevelFilter::Info;
+      let mut http = HttpClient::new(&server, &proxy_config, verbose)?;
+      let DatasetsIndexJson { datasets, .. } = DatasetsIndexJson::download(&mut http)?;
+      datasets
+    }
+  };
 
   // Parse attribute key-value pairs
   let mut attributes = parse_dataset_attributes(&attribute)?;
@@ -1,6 +1,7 @@
 use crate::cli::nextclade_cli::{
   NextcladeRunArgs, NextcladeRunInputArgs, NextcladeRunOtherArgs, NextcladeRunOutputArgs,
 };
+use crate::cli::nextclade_loop_multi_dataset::{nextclade_run_multi_dataset, nextclade_run_segmented};
 use crate::cli::nextclade_ordered_writer::NextcladeOrderedWriter;
 use crate::dataset::dataset_download::{
   dataset_dir_load, dataset_individual_files_load, dataset_str_download_and_load, dataset_zip_load, DatasetFiles,
@@ -8,24 +9,35 @@ use crate::dataset::dataset_download::{
 use eyre::{Report, WrapErr};
 use itertools::Itertools;
 use log::info;
-use nextclade::align::gap_open::{get_gap_open_close_scores_codon_aware, get_gap_open_close_scores_flat};
-use nextclade::align::params::AlignPairwiseParams;
+use nextclade::align::gap_open::{get_gap_open_close_scores_codon_aware, get_gap_open_close_scores_flat, GapScoreMap};
+use nextclade::align::params::{AlignPairwiseParams, AlignPairwiseParamsOptional};
+use nextclade::align::seed_chain::MinimizerIndex;
 use nextclade::analyze::find_aa_motifs::find_aa_motifs;
+use nextclade::analyze::find_aa_motifs_changes::AaMotifsMap;
+use nextclade::analyze::pcr_primers::PcrPrimer;
 use nextclade::analyze::phenotype::get_phenotype_attr_descs;
+use nextclade::analyze::virus_properties::{PhenotypeAttrDesc, VirusProperties};
 use nextclade::io::fasta::{FastaReader, FastaRecord};
 use nextclade::io::fs::has_extension;
+use nextclade::io::gene_map::GeneMap;
 use nextclade::io::json::json_write;
 use nextclade::io::nextclade_csv::CsvColumnConfig;
 use nextclade::io::nuc::{to_nuc_seq, to_nuc_seq_replacing, Nuc};
+use nextclade::io::nwk_writer::{nexus_write_to_file, nwk_write_to_file};
+use nextclade::io::usher_mat::usher_mat_write_to_file;
+use nextclade::qc::qc_config::QcConfig;
 use nextclade::run::nextclade_run_one::nextclade_run_one;
-use nextclade::translate::translate_genes::Translation;
+use nextclade::translate::translate_genes::{Translation, TranslationMap};
 use nextclade::translate::translate_genes_ref::translate_genes_ref;
+use nextclade::tree::params::TreePlacementParams;
+use nextclade::tree::tree::{AuspiceTree, CladeNodeAttrKeyDesc};
 use nextclade::tree::tree_attach_new_nodes::tree_attach_new_nodes_in_place;
 use nextclade::tree::tree_preprocess::tree_preprocess_in_place;
+use nextclade::tree::tree_usher_mat::tree_to_usher_mat;
 use nextclade::types::outputs::NextcladeOutputs;
 use nextclade::utils::range::Range;
 use nextclade::{make_error, make_internal_report};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub struct NextcladeRecord {
   pub index: usize,
@@ -42,127 +54,277 @@ pub struct DatasetFilePaths {
   input_gene_map: PathBuf,
 }
 
-pub fn nextclade_get_inputs(run_args: &NextcladeRunArgs, genes: &Option<Vec<String>>) -> Result<DatasetFiles, Report> {
+/// Dataset files along with the data derived from them, which is required to analyze sequences using this dataset
+pub struct NextcladeDatasetState {
+  pub segment: Option<String>,
+  pub ref_record: FastaRecord,
+  pub ref_seq: Vec<Nuc>,
+  pub ref_peptides: TranslationMap,
+  pub aa_motifs_ref: AaMotifsMap,
+  pub gene_map: GeneMap,
+  pub tree: AuspiceTree,
+  pub clade_node_attrs: Vec<CladeNodeAttrKeyDesc>,
+  pub phenotype_attrs: Vec<PhenotypeAttrDesc>,
+  pub aa_motifs_keys: Vec<String>,
+  pub qc_config: QcConfig,
+  pub virus_properties: VirusProperties,
+  pub primers: Vec<PcrPrimer>,
+  pub alignment_params: AlignPairwiseParams,
+  pub minimizer_index: Option<MinimizerIndex>,
+  pub gap_open_close_nuc: GapScoreMap,
+  pub gap_open_close_aa: GapScoreMap,
+}
+
+impl NextcladeDatasetState {
+  pub fn new(
+    dataset_files: DatasetFiles,
+    alignment_params_from_cli: &AlignPairwiseParamsOptional,
+  ) -> Result<Self, Report> {
+    let DatasetFiles {
+      ref_record,
+      virus_properties,
+      mut tree,
+      gene_map,
+      qc_config,
+      primers,
+      segment,
+    } = dataset_files;
+
+    let ref_seq = to_nuc_seq(&ref_record.seq).wrap_err("When reading reference sequence")?;
+
+    let mut alignment_params = AlignPairwiseParams::default();
+
+    // Merge alignment params coming from virus_properties into alignment_params
+    if let Some(alignment_params_from_file) = &virus_properties.alignment_params {
+      alignment_params.merge_opt(alignment_params_from_file.clone());
+    }
+
+    // Merge alignment params coming from CLI arguments
+    alignment_params.merge_opt(alignment_params_from_cli.clone());
+
+    info!("Alignment parameters (final):\n{alignment_params:#?}");
+
+    let minimizer_index = MinimizerIndex::for_params(&ref_seq, &alignment_params)?;
+    let gap_open_close_nuc = get_gap_open_close_scores_codon_aware(&ref_seq, &gene_map, &alignment_params);
+    let gap_open_close_aa = get_gap_open_close_scores_flat(&ref_seq, &alignment_params);
+
+    let ref_peptides = {
+      let mut ref_peptides =
+        translate_genes_ref(&ref_seq, &gene_map, virus_properties.genetic_code()?, &alignment_params)
+          .wrap_err("When translating reference genes")?;
+
+      ref_peptides
+        .iter_mut()
+        .try_for_each(|(name, translation)| -> Result<(), Report> {
+          let gene = gene_map
+            .get(&translation.gene_name)
+            .ok_or_else(|| make_internal_report!("Gene not found in gene map: '{}'", &translation.gene_name))?;
+          translation.alignment_range = Range::new(0, gene.len_codon());
+
+          Ok(())
+        })?;
+
+      ref_peptides
+    };
+
+    let aa_motifs_ref = find_aa_motifs(
+      &virus_properties.aa_motifs,
+      &ref_peptides.values().cloned().collect_vec(),
+    )?;
+
+    tree_preprocess_in_place(&mut tree, &ref_seq, &ref_peptides).wrap_err("When preprocessing reference tree")?;
+    let clade_node_attrs = tree.clade_node_attr_descs().to_vec();
+
+    let phenotype_attrs = get_phenotype_attr_descs(&virus_properties);
+
+    let aa_motifs_keys = virus_properties
+      .aa_motifs
+      .iter()
+      .map(|desc| desc.name.clone())
+      .collect_vec();
+
+    Ok(Self {
+      segment,
+      ref_record,
+      ref_seq,
+      ref_peptides,
+      aa_motifs_ref,
+      gene_map,
+      tree,
+      clade_node_attrs,
+      phenotype_attrs,
+      aa_motifs_keys,
+      qc_config,
+      virus_properties,
+      primers,
+      alignment_params,
+      minimizer_index,
+      gap_open_close_nuc,
+      gap_open_close_aa,
+    })
+  }
+
+  /// Analyzes one query sequence using this dataset
+  pub fn run_one(
+    &self,
+    index: usize,
+    seq_name: &str,
+    qry_seq: &[Nuc],
+    include_nearest_node_info: bool,
+    placement_params: &TreePlacementParams,
+  ) -> Result<(Vec<Nuc>, Vec<Translation>, NextcladeOutputs), Report> {
+    let (qry_seq_aligned, translations, mut outputs) = nextclade_run_one(
+      index,
+      seq_name,
+      qry_seq,
+      &self.ref_seq,
+      &self.ref_peptides,
+      &self.aa_motifs_ref,
+      &self.gene_map,
+      &self.primers,
+      &self.tree,
+      &self.qc_config,
+      &self.virus_properties,
+      self.minimizer_index.as_ref(),
+      &self.gap_open_close_nuc,
+      &self.gap_open_close_aa,
+      &self.alignment_params,
+      include_nearest_node_info,
+      placement_params,
+    )?;
+
+    outputs.segment = self.segment.clone();
+
+    Ok((qry_seq_aligned, translations, outputs))
+  }
+
+  /// Creates writer of the output files requested for this dataset
+  pub fn create_writer(
+    &self,
+    outputs: &NextcladeRunOutputArgs,
+    csv_column_config: &CsvColumnConfig,
+    in_order: bool,
+  ) -> Result<NextcladeOrderedWriter<'_>, Report> {
+    let mut output_writer = NextcladeOrderedWriter::new(
+      &self.gene_map,
+      &self.clade_node_attrs,
+      &self.phenotype_attrs,
+      &self.aa_motifs_keys,
+      &outputs.output_fasta,
+      &outputs.output_json,
+      &outputs.output_ndjson,
+      &outputs.output_csv,
+      &outputs.output_tsv,
+      &outputs.output_insertions,
+      &outputs.output_errors,
+      &outputs.output_translations,
+      csv_column_config,
+      in_order,
+    )
+    .wrap_err("When creating output writer")?;
+
+    if outputs.include_reference {
+      output_writer
+        .write_ref(&self.ref_record, &self.ref_peptides)
+        .wrap_err("When writing output record for ref sequence")?;
+    }
+
+    Ok(output_writer)
+  }
+}
+
+/// Converts characters of a query sequence to nucleotides
+pub fn nextclade_read_qry_seq(
+  index: usize,
+  seq_name: &str,
+  seq: &str,
+  replace_unknown: bool,
+) -> Result<Vec<Nuc>, Report> {
+  if replace_unknown {
+    Ok(to_nuc_seq_replacing(seq))
+  } else {
+    to_nuc_seq(seq)
+  }
+  .wrap_err_with(|| format!("When processing sequence #{index} '{seq_name}'"))
+}
+
+/// Loads dataset files. Returns files for each of the segments for datasets of segmented genomes, or a single entry
+/// otherwise.
+pub fn nextclade_get_inputs(
+  run_args: &NextcladeRunArgs,
+  genes: &Option<Vec<String>>,
+) -> Result<Vec<DatasetFiles>, Report> {
   if let Some(dataset_name) = run_args.inputs.dataset_name.as_ref() {
     dataset_str_download_and_load(run_args, dataset_name, genes)
       .wrap_err_with(|| format!("When downloading dataset '{dataset_name}'"))
   } else if let Some(input_dataset) = run_args.inputs.input_dataset.as_ref() {
-    if input_dataset.is_file() && has_extension(input_dataset, "zip") {
-      dataset_zip_load(run_args, input_dataset, genes)
-    } else if input_dataset.is_dir() {
-      dataset_dir_load(run_args.clone(), input_dataset, genes)
-    } else {
-      make_error!(
-        "--input-dataset: path is invalid. \
-        Expected a directory path or a zip archive file path, but got: '{input_dataset:#?}'"
-      )
-    }
+    nextclade_load_dataset_path(run_args, input_dataset, genes)
   } else {
     dataset_individual_files_load(run_args, genes)
   }
 }
 
+/// Loads dataset from either a directory or a zip archive
+pub fn nextclade_load_dataset_path(
+  run_args: &NextcladeRunArgs,
+  input_dataset: &Path,
+  genes: &Option<Vec<String>>,
+) -> Result<Vec<DatasetFiles>, Report> {
+  if input_dataset.is_file() && has_extension(input_dataset, "zip") {
+    dataset_zip_load(run_args, input_dataset, genes)
+  } else if input_dataset.is_dir() {
+    dataset_dir_load(run_args, input_dataset, genes)
+  } else {
+    make_error!(
+      "--input-dataset: path is invalid. \
+      Expected a directory path or a zip archive file path, but got: '{input_dataset:#?}'"
+    )
+  }
+}
+
 pub fn nextclade_run(run_args: NextcladeRunArgs) -> Result<(), Report> {
   info!("Command-line arguments:\n{run_args:#?}");
 
+  run_args.placement_params.validate()?;
+
+  if !run_args.inputs.input_datasets.is_empty() {
+    return nextclade_run_multi_dataset(run_args);
+  }
+
+  let dataset_files = nextclade_get_inputs(&run_args, &run_args.inputs.genes)?;
+  if dataset_files.len() > 1 {
+    return nextclade_run_segmented(run_args, dataset_files);
+  }
+
   let NextcladeRunArgs {
-    inputs:
-      NextcladeRunInputArgs {
-        input_fastas,
-        input_dataset,
-        input_ref,
-        input_tree,
-        input_qc_config,
-        input_virus_properties,
-        input_pcr_primers,
-        input_gene_map,
-        genes,
-        ..
-      },
-    outputs:
-      NextcladeRunOutputArgs {
-        output_all,
-        output_basename,
-        output_selection,
-        output_fasta,
-        output_translations,
-        output_ndjson,
-        output_json,
-        output_csv,
-        output_tsv,
-        output_columns_selection,
-        output_tree,
-        output_insertions,
-        output_errors,
-        include_reference,
-        include_nearest_node_info,
-        in_order,
-        replace_unknown,
-        ..
-      },
+    inputs: NextcladeRunInputArgs { input_fastas, .. },
+    outputs,
     other: NextcladeRunOtherArgs { jobs },
     alignment_params,
+    placement_params,
+    ..
   } = run_args.clone();
 
-  let DatasetFiles {
-    ref_record,
-    virus_properties,
-    mut tree,
-    ref gene_map,
-    qc_config,
-    primers,
-  } = nextclade_get_inputs(&run_args, &genes)?;
-
-  let ref_seq = &to_nuc_seq(&ref_record.seq).wrap_err("When reading reference sequence")?;
-
-  let mut alignment_params = AlignPairwiseParams::default();
+  let NextcladeRunOutputArgs {
+    output_columns_selection,
+    include_nearest_node_info,
+    in_order,
+    replace_unknown,
+    ..
+  } = outputs.clone();
 
-  // Merge alignment params coming from virus_properties into alignment_params
-  if let Some(alignment_params_from_file) = &virus_properties.alignment_params {
-    alignment_params.merge_opt(alignment_params_from_file.clone());
+  if outputs.output_isolates_csv.is_some() || outputs.output_isolates_tsv.is_some() {
+    return make_error!("The `--output-isolates-csv` and `--output-isolates-tsv` arguments can only be used with datasets of segmented genomes or in multi-dataset mode (`--input-datasets`).");
   }
 
-  // Merge alignment params coming from CLI arguments
-  alignment_params.merge_opt(run_args.alignment_params);
+  let dataset_files = dataset_files
+    .into_iter()
+    .next()
+    .ok_or_else(|| make_internal_report!("Dataset files are expected to be present"))?;
+  let mut dataset = NextcladeDatasetState::new(dataset_files, &alignment_params)?;
 
-  info!("Alignment parameters (final):\n{alignment_params:#?}");
-
-  let gap_open_close_nuc = &get_gap_open_close_scores_codon_aware(ref_seq, gene_map, &alignment_params);
-  let gap_open_close_aa = &get_gap_open_close_scores_flat(ref_seq, &alignment_params);
-
-  let ref_peptides = &{
-    let mut ref_peptides =
-      translate_genes_ref(ref_seq, gene_map, &alignment_params).wrap_err("When translating reference genes")?;
-
-    ref_peptides
-      .iter_mut()
-      .try_for_each(|(name, translation)| -> Result<(), Report> {
-        let gene = gene_map
-          .get(&translation.gene_name)
-          .ok_or_else(|| make_internal_report!("Gene not found in gene map: '{}'", &translation.gene_name))?;
-        translation.alignment_range = Range::new(0, gene.len_codon());
-
-        Ok(())
-      })?;
-
-    ref_peptides
-  };
-
-  let aa_motifs_ref = &find_aa_motifs(
-    &virus_properties.aa_motifs,
-    &ref_peptides.values().cloned().collect_vec(),
-  )?;
-
-  let should_keep_outputs = output_tree.is_some();
-  let mut outputs = Vec::<NextcladeOutputs>::new();
-
-  let phenotype_attrs = &get_phenotype_attr_descs(&virus_properties);
-
-  let aa_motifs_keys = &virus_properties
-    .aa_motifs
-    .iter()
-    .map(|desc| desc.name.clone())
-    .collect_vec();
+  let should_keep_outputs = has_tree_outputs(&outputs);
+  let mut outputs_kept = Vec::<NextcladeOutputs>::new();
 
   let csv_column_config = CsvColumnConfig::new(&output_columns_selection)?;
 
@@ -171,10 +333,11 @@ pub fn nextclade_run(run_args: NextcladeRunArgs) -> Result<(), Report> {
     let (fasta_sender, fasta_receiver) = crossbeam_channel::bounded::<FastaRecord>(CHANNEL_SIZE);
     let (result_sender, result_receiver) = crossbeam_channel::bounded::<NextcladeRecord>(CHANNEL_SIZE);
 
-    tree_preprocess_in_place(&mut tree, ref_seq, ref_peptides).unwrap();
-    let clade_node_attrs = tree.clade_node_attr_descs();
-
-    let outputs = &mut outputs;
+    let dataset = &dataset;
+    let outputs = &outputs;
+    let csv_column_config = &csv_column_config;
+    let placement_params = &placement_params;
+    let outputs_kept = &mut outputs_kept;
 
     s.spawn(|| {
       let mut reader = FastaReader::from_paths(&input_fastas).unwrap();
@@ -195,13 +358,6 @@ pub fn nextclade_run(run_args: NextcladeRunArgs) -> Result<(), Report> {
     for _ in 0..jobs {
       let fasta_receiver = fasta_receiver.clone();
       let result_sender = result_sender.clone();
-      let gap_open_close_nuc = &gap_open_close_nuc;
-      let gap_open_close_aa = &gap_open_close_aa;
-      let alignment_params = &alignment_params;
-      let primers = &primers;
-      let tree = &tree;
-      let qc_config = &qc_config;
-      let virus_properties = &virus_properties;
 
       s.spawn(move || {
         let result_sender = result_sender.clone();
@@ -209,30 +365,8 @@ pub fn nextclade_run(run_args: NextcladeRunArgs) -> Result<(), Report> {
         for FastaRecord { seq_name, seq, index } in &fasta_receiver {
           info!("Processing sequence '{seq_name}'");
 
-          let outputs_or_err = if replace_unknown {
-            Ok(to_nuc_seq_replacing(&seq))
-          } else {
-            to_nuc_seq(&seq)
-          }
-          .wrap_err_with(|| format!("When processing sequence #{index} '{seq_name}'"))
-          .and_then(|qry_seq| {
-            nextclade_run_one(
-              index,
-              &seq_name,
-              &qry_seq,
-              ref_seq,
-              ref_peptides,
-              aa_motifs_ref,
-              gene_map,
-              primers,
-              tree,
-              qc_config,
-              virus_properties,
-              gap_open_close_nuc,
-              gap_open_close_aa,
-              alignment_params,
-              include_nearest_node_info,
-            )
+          let outputs_or_err = nextclade_read_qry_seq(index, &seq_name, &seq, replace_unknown).and_then(|qry_seq| {
+            dataset.run_one(index, &seq_name, &qry_seq, include_nearest_node_info, placement_params)
           });
 
           let record = NextcladeRecord {
@@ -256,36 +390,12 @@ pub fn nextclade_run(run_args: NextcladeRunArgs) -> Result<(), Report> {
     }
 
     let writer = s.spawn(move || {
-      let mut output_writer = NextcladeOrderedWriter::new(
-        gene_map,
-        clade_node_attrs,
-        phenotype_attrs,
-        aa_motifs_keys,
-        &output_fasta,
-        &output_json,
-        &output_ndjson,
-        &output_csv,
-        &output_tsv,
-        &output_insertions,
-        &output_errors,
-        &output_translations,
-        &csv_column_config,
-        in_order,
-      )
-      .wrap_err("When creating output writer")
-      .unwrap();
-
-      if include_reference {
-        output_writer
-          .write_ref(&ref_record, ref_peptides)
-          .wrap_err("When writing output record for ref sequence")
-          .unwrap();
-      }
+      let mut output_writer = dataset.create_writer(outputs, csv_column_config, in_order).unwrap();
 
       for record in result_receiver {
         if should_keep_outputs {
           if let Ok((_, _, nextclade_outputs)) = &record.outputs_or_err {
-            outputs.push(nextclade_outputs.clone());
+            outputs_kept.push(nextclade_outputs.clone());
           }
         }
 
@@ -297,9 +407,47 @@ pub fn nextclade_run(run_args: NextcladeRunArgs) -> Result<(), Report> {
     });
   });
 
-  if let Some(output_tree) = output_tree {
-    tree_attach_new_nodes_in_place(&mut tree, &outputs);
-    json_write(output_tree, &tree)?;
+  if has_tree_outputs(&outputs) {
+    tree_attach_new_nodes_in_place(&mut dataset.tree, &outputs_kept);
+    write_tree_outputs(&dataset, &outputs)?;
+  }
+
+  Ok(())
+}
+
+/// Whether any of the outputs requires the reference tree with the new nodes attached
+pub fn has_tree_outputs(outputs: &NextcladeRunOutputArgs) -> bool {
+  outputs.output_tree.is_some()
+    || outputs.output_tree_nwk.is_some()
+    || outputs.output_tree_nexus.is_some()
+    || outputs.output_tree_pb.is_some()
+}
+
+/// Writes the reference tree with the new nodes attached in all requested formats
+pub fn write_tree_outputs(dataset: &NextcladeDatasetState, outputs: &NextcladeRunOutputArgs) -> Result<(), Report> {
+  let tree = &dataset.tree;
+
+  if let Some(output_tree) = &outputs.output_tree {
+    json_write(output_tree, tree)?;
+  }
+
+  if let Some(output_tree_nwk) = &outputs.output_tree_nwk {
+    nwk_write_to_file(output_tree_nwk, tree)?;
+  }
+
+  if let Some(output_tree_nexus) = &outputs.output_tree_nexus {
+    nexus_write_to_file(output_tree_nexus, tree)?;
+  }
+
+  if let Some(output_tree_pb) = &outputs.output_tree_pb {
+    let ref_name = dataset
+      .ref_record
+      .seq_name
+      .split_whitespace()
+      .next()
+      .unwrap_or_default();
+    let mat = tree_to_usher_mat(tree, &dataset.ref_seq, ref_name)?;
+    usher_mat_write_to_file(output_tree_pb, &mat)?;
   }
 
   Ok(())
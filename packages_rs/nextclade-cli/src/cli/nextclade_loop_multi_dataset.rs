@@ -0,0 +1,492 @@
+use crate::cli::nextclade_cli::{
+  nextclade_get_isolates_output_filenames, nextclade_get_output_filenames, NextcladeRunArgs, NextcladeRunInputArgs,
+  NextcladeRunOtherArgs, NextcladeRunOutputArgs, INDIVIDUAL_OUTPUT_ARGS,
+};
+use crate::cli::nextclade_loop::{
+  has_tree_outputs, nextclade_load_dataset_path, nextclade_read_qry_seq, write_tree_outputs, NextcladeDatasetState,
+  NextcladeRecord,
+};
+use crate::cli::nextclade_ordered_writer::NextcladeOrderedWriter;
+use crate::dataset::dataset_download::DatasetFiles;
+use eyre::{eyre, Report, WrapErr};
+use itertools::Itertools;
+use log::{info, warn};
+use nextclade::io::dataset_assignment_csv::DatasetAssignmentCsvWriter;
+use nextclade::io::errors_csv::ErrorsCsvWriter;
+use nextclade::io::fasta::{FastaReader, FastaRecord};
+use nextclade::io::fs::{absolute_path, basename_maybe, filename_maybe, has_extension};
+use nextclade::io::gene_map::GeneMap;
+use nextclade::io::isolates_csv::IsolatesTable;
+use nextclade::io::nextclade_csv::CsvColumnConfig;
+use nextclade::sort::kmer_sketch::{sketch_find_best_match, DatasetMatch, KmerSketch};
+use nextclade::tree::tree_attach_new_nodes::tree_attach_new_nodes_in_place;
+use nextclade::types::outputs::NextcladeOutputs;
+use nextclade::utils::error::report_to_string;
+use nextclade::utils::option::OptionMapRefFallible;
+use nextclade::{make_error, make_internal_report};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Output record along with the dataset the sequence was assigned to
+pub struct NextcladeMultiDatasetRecord {
+  pub dataset_index: Option<usize>,
+  pub score: f64,
+  pub record: NextcladeRecord,
+}
+
+/// Dataset name is the name of its directory or of its zip file without extension
+fn get_dataset_name(input_dataset: &Path) -> Result<String, Report> {
+  let path = absolute_path(input_dataset)?;
+  let name = if has_extension(&path, "zip") {
+    basename_maybe(&path)
+  } else {
+    filename_maybe(&path)
+  };
+  name.ok_or_else(|| eyre!("Unable to deduce dataset name from path {input_dataset:#?}"))
+}
+
+/// Output paths for one dataset: same outputs as requested with `--output-all`, but in a subdirectory
+fn get_dataset_output_args(run_args: &NextcladeRunArgs, dataset_name: &str) -> Result<NextcladeRunOutputArgs, Report> {
+  let mut dataset_run_args = run_args.clone();
+  dataset_run_args.inputs.input_datasets = vec![];
+
+  let outputs = &mut dataset_run_args.outputs;
+  outputs.output_all = run_args
+    .outputs
+    .output_all
+    .as_ref()
+    .map(|output_all| output_all.join(dataset_name));
+  outputs.output_fasta = None;
+  outputs.output_translations = None;
+  outputs.output_ndjson = None;
+  outputs.output_json = None;
+  outputs.output_csv = None;
+  outputs.output_tsv = None;
+  outputs.output_tree = None;
+  outputs.output_tree_nwk = None;
+  outputs.output_tree_nexus = None;
+  outputs.output_tree_pb = None;
+  outputs.output_insertions = None;
+  outputs.output_errors = None;
+  outputs.output_dataset_assignment = None;
+  outputs.output_isolates_csv = None;
+  outputs.output_isolates_tsv = None;
+
+  nextclade_get_output_filenames(&mut dataset_run_args)
+    .wrap_err_with(|| format!("When deducing output filenames for dataset '{dataset_name}'"))?;
+
+  Ok(dataset_run_args.outputs)
+}
+
+/// Writes output records into the outputs of the datasets they were assigned to. Writes sequences which were not
+/// assigned to any dataset into the errors file. Optionally, preserves the initial order of records.
+pub struct NextcladeMultiDatasetWriter<'a> {
+  dataset_names: &'a [String],
+  dataset_writers: Vec<NextcladeOrderedWriter<'a>>,
+  errors_csv_writer: Option<ErrorsCsvWriter<'a>>,
+  dataset_assignment_writer: Option<DatasetAssignmentCsvWriter>,
+  isolates_table: Option<IsolatesTable>,
+  output_isolates_csv: &'a Option<PathBuf>,
+  output_isolates_tsv: &'a Option<PathBuf>,
+  should_keep_outputs: Vec<bool>,
+  outputs_kept: Vec<Vec<NextcladeOutputs>>,
+  expected_index: usize,
+  queue: HashMap<usize, NextcladeMultiDatasetRecord>,
+  in_order: bool,
+}
+
+impl<'a> NextcladeMultiDatasetWriter<'a> {
+  pub fn new(
+    datasets: &'a [NextcladeDatasetState],
+    dataset_names: &'a [String],
+    dataset_outputs: &[NextcladeRunOutputArgs],
+    csv_column_config: &CsvColumnConfig,
+    output_errors: &Option<PathBuf>,
+    output_dataset_assignment: &Option<PathBuf>,
+    output_isolates_csv: &'a Option<PathBuf>,
+    output_isolates_tsv: &'a Option<PathBuf>,
+    isolate_name_regex: &str,
+    gene_map_empty: &'a GeneMap,
+    in_order: bool,
+  ) -> Result<Self, Report> {
+    // Records are ordered here, before they are dispatched, so the writers of individual datasets don't need to
+    let dataset_writers = datasets
+      .iter()
+      .zip(dataset_outputs)
+      .map(|(dataset, outputs)| dataset.create_writer(outputs, csv_column_config, false))
+      .collect::<Result<Vec<NextcladeOrderedWriter<'a>>, Report>>()?;
+
+    let errors_csv_writer =
+      output_errors.map_ref_fallible(|output_errors| ErrorsCsvWriter::new(gene_map_empty, output_errors))?;
+
+    let dataset_assignment_writer = output_dataset_assignment.map_ref_fallible(|output_dataset_assignment| {
+      DatasetAssignmentCsvWriter::new(output_dataset_assignment, b'\t')
+    })?;
+
+    let isolates_table = if output_isolates_csv.is_some() || output_isolates_tsv.is_some() {
+      Some(IsolatesTable::new(dataset_names, isolate_name_regex)?)
+    } else {
+      None
+    };
+
+    let should_keep_outputs = dataset_outputs.iter().map(has_tree_outputs).collect_vec();
+
+    Ok(Self {
+      dataset_names,
+      dataset_writers,
+      errors_csv_writer,
+      dataset_assignment_writer,
+      isolates_table,
+      output_isolates_csv,
+      output_isolates_tsv,
+      should_keep_outputs,
+      outputs_kept: vec![vec![]; datasets.len()],
+      expected_index: 0,
+      queue: HashMap::<usize, NextcladeMultiDatasetRecord>::new(),
+      in_order,
+    })
+  }
+
+  fn write_impl(&mut self, record: NextcladeMultiDatasetRecord) -> Result<(), Report> {
+    let NextcladeMultiDatasetRecord {
+      dataset_index,
+      score,
+      record,
+    } = record;
+
+    if let Some(dataset_assignment_writer) = &mut self.dataset_assignment_writer {
+      let dataset_name = dataset_index.map(|dataset_index| self.dataset_names[dataset_index].as_str());
+      dataset_assignment_writer.write(record.index, &record.seq_name, dataset_name, score)?;
+    }
+
+    match dataset_index {
+      Some(dataset_index) => {
+        if let Some(isolates_table) = &mut self.isolates_table {
+          match &record.outputs_or_err {
+            Ok((_, _, nextclade_outputs)) => isolates_table.add_outputs(dataset_index, nextclade_outputs),
+            Err(report) => isolates_table.add_error(dataset_index, &record.seq_name, &report_to_string(report)),
+          }
+        }
+        if self.should_keep_outputs[dataset_index] {
+          if let Ok((_, _, nextclade_outputs)) = &record.outputs_or_err {
+            self.outputs_kept[dataset_index].push(nextclade_outputs.clone());
+          }
+        }
+        self.dataset_writers[dataset_index].write_record(record)?;
+      }
+      None => {
+        if let Err(report) = &record.outputs_or_err {
+          let cause = report_to_string(report);
+          warn!(
+            "In sequence #{} '{}': {cause}. Note that this sequence will not be included in the results.",
+            record.index, record.seq_name
+          );
+          if let Some(errors_csv_writer) = &mut self.errors_csv_writer {
+            errors_csv_writer.write_nuc_error(&record.seq_name, &cause)?;
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn write_queued_records(&mut self) -> Result<(), Report> {
+    while let Some(record) = self.queue.remove(&self.expected_index) {
+      self.write_impl(record)?;
+      self.expected_index += 1;
+    }
+    Ok(())
+  }
+
+  /// Writes a record. In in-order mode, queues the record if some of the preceding records are not written yet.
+  pub fn write_record(&mut self, record: NextcladeMultiDatasetRecord) -> Result<(), Report> {
+    if !self.in_order {
+      self.write_impl(record)?;
+    } else {
+      if record.record.index == self.expected_index {
+        self.write_impl(record)?;
+        self.expected_index += 1;
+      } else {
+        self.queue.insert(record.record.index, record);
+      }
+      self.write_queued_records()?;
+    }
+    Ok(())
+  }
+
+  /// Writes all queued records and the isolates table, and returns the outputs kept for the datasets which require
+  /// output tree
+  pub fn finish(mut self) -> Result<Vec<Vec<NextcladeOutputs>>, Report> {
+    self.write_queued_records()?;
+
+    if let Some(isolates_table) = &self.isolates_table {
+      if let Some(output_isolates_csv) = self.output_isolates_csv {
+        isolates_table.write(output_isolates_csv, b',')?;
+      }
+      if let Some(output_isolates_tsv) = self.output_isolates_tsv {
+        isolates_table.write(output_isolates_tsv, b'\t')?;
+      }
+    }
+
+    Ok(self.outputs_kept)
+  }
+}
+
+/// Runs analysis in multi-dataset mode: each sequence is analyzed using the dataset which matches it best. Segments of
+/// datasets of segmented genomes are treated as separate datasets, named `<dataset>/<segment>`.
+pub fn nextclade_run_multi_dataset(run_args: NextcladeRunArgs) -> Result<(), Report> {
+  let NextcladeRunArgs {
+    inputs: NextcladeRunInputArgs {
+      input_datasets, genes, ..
+    },
+    alignment_params,
+    ..
+  } = &run_args;
+
+  let input_dataset_names = input_datasets
+    .iter()
+    .map(|input_dataset| get_dataset_name(input_dataset))
+    .collect::<Result<Vec<String>, Report>>()?;
+
+  if let Some(duplicate) = input_dataset_names.iter().duplicates().next() {
+    return make_error!(
+      "In multi-dataset mode, datasets are named after their directories or zip files and these names should be unique, but the name '{duplicate}' occurs more than once"
+    );
+  }
+
+  let mut dataset_names = vec![];
+  let mut datasets = vec![];
+  for (input_dataset, input_dataset_name) in input_datasets.iter().zip(&input_dataset_names) {
+    info!("Loading dataset '{input_dataset_name}' from {input_dataset:#?}");
+    let dataset_files = nextclade_load_dataset_path(&run_args, input_dataset, genes)
+      .wrap_err_with(|| format!("When loading dataset '{input_dataset_name}' from {input_dataset:#?}"))?;
+
+    for dataset_files in dataset_files {
+      let dataset_name = match &dataset_files.segment {
+        Some(segment) => format!("{input_dataset_name}/{segment}"),
+        None => input_dataset_name.clone(),
+      };
+      let dataset = NextcladeDatasetState::new(dataset_files, alignment_params)
+        .wrap_err_with(|| format!("When loading dataset '{dataset_name}' from {input_dataset:#?}"))?;
+      dataset_names.push(dataset_name);
+      datasets.push(dataset);
+    }
+  }
+
+  nextclade_run_with_dataset_assignment(run_args, dataset_names, datasets)
+}
+
+/// Runs analysis using a dataset of a segmented genome: each sequence is analyzed using the segment which matches it
+/// best. Outputs of each segment are written into a subdirectory of `--output-all` named after the segment.
+pub fn nextclade_run_segmented(mut run_args: NextcladeRunArgs, dataset_files: Vec<DatasetFiles>) -> Result<(), Report> {
+  if run_args.outputs.output_all.is_none() {
+    return make_error!("For datasets of segmented genomes, the `--output-all` argument is required: outputs for each segment are written into subdirectories of this directory.");
+  }
+
+  if run_args.outputs.has_individual_output_args {
+    return make_error!("For datasets of segmented genomes, individual output files ({INDIVIDUAL_OUTPUT_ARGS}) cannot be used, because each segment produces its own outputs. Use `--output-all` together with `--output-selection` instead.");
+  }
+
+  nextclade_get_isolates_output_filenames(&mut run_args.outputs);
+
+  let mut dataset_names = vec![];
+  let mut datasets = vec![];
+  for dataset_files in dataset_files {
+    let segment = dataset_files
+      .segment
+      .clone()
+      .ok_or_else(|| make_internal_report!("Segment name is expected to be present in a segmented dataset"))?;
+    info!("Loading segment '{segment}'");
+    let dataset = NextcladeDatasetState::new(dataset_files, &run_args.alignment_params)
+      .wrap_err_with(|| format!("When loading segment '{segment}'"))?;
+    dataset_names.push(segment);
+    datasets.push(dataset);
+  }
+
+  nextclade_run_with_dataset_assignment(run_args, dataset_names, datasets)
+}
+
+/// Analyzes each sequence using the dataset which matches it best, according to the k-mer similarity of the sequence
+/// to the reference sequences of the datasets
+fn nextclade_run_with_dataset_assignment(
+  run_args: NextcladeRunArgs,
+  dataset_names: Vec<String>,
+  mut datasets: Vec<NextcladeDatasetState>,
+) -> Result<(), Report> {
+  let NextcladeRunArgs {
+    inputs: NextcladeRunInputArgs { input_fastas, .. },
+    outputs:
+      NextcladeRunOutputArgs {
+        output_columns_selection,
+        output_errors,
+        output_dataset_assignment,
+        output_isolates_csv,
+        output_isolates_tsv,
+        isolate_name_regex,
+        include_nearest_node_info,
+        in_order,
+        replace_unknown,
+        ..
+      },
+    sketch_params,
+    placement_params,
+    other: NextcladeRunOtherArgs { jobs },
+    ..
+  } = run_args.clone();
+
+  let sketches = datasets
+    .iter()
+    .zip(&dataset_names)
+    .map(|(dataset, dataset_name)| {
+      KmerSketch::new(&dataset.ref_seq, sketch_params.dataset_kmer_length)
+        .wrap_err_with(|| format!("When creating k-mer sketch of dataset '{dataset_name}'"))
+    })
+    .collect::<Result<Vec<KmerSketch>, Report>>()?;
+
+  let dataset_outputs = dataset_names
+    .iter()
+    .map(|dataset_name| get_dataset_output_args(&run_args, dataset_name))
+    .collect::<Result<Vec<NextcladeRunOutputArgs>, Report>>()?;
+
+  let csv_column_config = CsvColumnConfig::new(&output_columns_selection)?;
+
+  let gene_map_empty = GeneMap::new();
+
+  let outputs_kept = std::thread::scope(|s| {
+    const CHANNEL_SIZE: usize = 128;
+    let (fasta_sender, fasta_receiver) = crossbeam_channel::bounded::<FastaRecord>(CHANNEL_SIZE);
+    let (result_sender, result_receiver) = crossbeam_channel::bounded::<NextcladeMultiDatasetRecord>(CHANNEL_SIZE);
+
+    let datasets = &datasets;
+    let dataset_names = &dataset_names;
+    let dataset_outputs = &dataset_outputs;
+    let sketches = &sketches;
+    let sketch_params = &sketch_params;
+    let placement_params = &placement_params;
+    let csv_column_config = &csv_column_config;
+    let output_errors = &output_errors;
+    let output_dataset_assignment = &output_dataset_assignment;
+    let output_isolates_csv = &output_isolates_csv;
+    let output_isolates_tsv = &output_isolates_tsv;
+    let isolate_name_regex = &isolate_name_regex;
+    let gene_map_empty = &gene_map_empty;
+
+    s.spawn(|| {
+      let mut reader = FastaReader::from_paths(&input_fastas).unwrap();
+      loop {
+        let mut record = FastaRecord::default();
+        reader.read(&mut record).unwrap();
+        if record.is_empty() {
+          break;
+        }
+        fasta_sender
+          .send(record)
+          .wrap_err("When sending a FastaRecord")
+          .unwrap();
+      }
+      drop(fasta_sender);
+    });
+
+    for _ in 0..jobs {
+      let fasta_receiver = fasta_receiver.clone();
+      let result_sender = result_sender.clone();
+
+      s.spawn(move || {
+        for FastaRecord { seq_name, seq, index } in &fasta_receiver {
+          info!("Processing sequence '{seq_name}'");
+
+          let (dataset_index, score, outputs_or_err) =
+            match nextclade_read_qry_seq(index, &seq_name, &seq, replace_unknown) {
+              Err(report) => (None, 0.0, Err(report)),
+              Ok(qry_seq) => match sketch_find_best_match(sketches, &qry_seq, sketch_params.dataset_max_kmers) {
+                Some(DatasetMatch { dataset_index, score }) if score >= sketch_params.dataset_min_score => {
+                  info!(
+                    "Sequence '{seq_name}' is assigned to dataset '{}' (score: {score:.6})",
+                    dataset_names[dataset_index]
+                  );
+                  let outputs_or_err = datasets[dataset_index].run_one(
+                    index,
+                    &seq_name,
+                    &qry_seq,
+                    include_nearest_node_info,
+                    placement_params,
+                  );
+                  (Some(dataset_index), score, outputs_or_err)
+                }
+                best_match => {
+                  let best_score = best_match.map_or(0.0, |best_match| best_match.score);
+                  let min_score = sketch_params.dataset_min_score;
+                  let report = eyre!(
+                    "Sequence does not match any of the datasets: the best k-mer similarity score is {best_score:.6}, \
+                    which is below the minimum score {min_score:.6} required for dataset assignment (see `--dataset-min-score`)"
+                  );
+                  (None, best_score, Err(report))
+                }
+              },
+            };
+
+          // Important: **all** records should be sent into this channel, without skipping (see `nextclade_run()`)
+          result_sender
+            .send(NextcladeMultiDatasetRecord {
+              dataset_index,
+              score,
+              record: NextcladeRecord {
+                index,
+                seq_name,
+                outputs_or_err,
+              },
+            })
+            .wrap_err("When sending NextcladeMultiDatasetRecord")
+            .unwrap();
+        }
+
+        drop(result_sender);
+      });
+    }
+
+    // Writer thread stops when all senders are dropped, including this one
+    drop(result_sender);
+
+    let writer = s.spawn(move || {
+      let mut output_writer = NextcladeMultiDatasetWriter::new(
+        datasets,
+        dataset_names,
+        dataset_outputs,
+        csv_column_config,
+        output_errors,
+        output_dataset_assignment,
+        output_isolates_csv,
+        output_isolates_tsv,
+        isolate_name_regex,
+        gene_map_empty,
+        in_order,
+      )
+      .wrap_err("When creating output writer")
+      .unwrap();
+
+      for record in result_receiver {
+        output_writer
+          .write_record(record)
+          .wrap_err("When writing output record")
+          .unwrap();
+      }
+
+      output_writer
+        .finish()
+        .wrap_err("When finalizing output writer")
+        .unwrap()
+    });
+
+    writer.join().unwrap()
+  });
+
+  for ((dataset, outputs_kept), outputs) in datasets.iter_mut().zip(outputs_kept).zip(&dataset_outputs) {
+    if has_tree_outputs(outputs) {
+      tree_attach_new_nodes_in_place(&mut dataset.tree, &outputs_kept);
+      write_tree_outputs(dataset, outputs)?;
+    }
+  }
+
+  Ok(())
+}
@@ -0,0 +1,330 @@
+use crate::dataset::dataset::Dataset;
+use crate::io::http_client::HttpClient;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use log::{info, warn};
+use nextclade::io::fs::absolute_path;
+use nextclade::io::json::{json_parse, json_stringify};
+use nextclade::{getenv, make_error};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const THIS_VERSION: &str = getenv!("CARGO_PKG_VERSION");
+
+/// Name of the file which describes a cached dataset and contains checksums of its files
+const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// Suffix of the temporary directories, into which datasets are written before they are moved into the cache
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Description of a dataset stored in the cache
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetCacheManifest {
+  /// Entry of the dataset in the index of the server it was downloaded from
+  pub dataset: Dataset,
+
+  /// SHA-256 checksums of the dataset files, as lowercase hex strings
+  pub checksums: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DatasetCacheEntry {
+  /// Directory containing the dataset files
+  pub path: PathBuf,
+  pub manifest: DatasetCacheManifest,
+}
+
+impl DatasetCacheEntry {
+  pub const fn dataset(&self) -> &Dataset {
+    &self.manifest.dataset
+  }
+
+  /// Verifies integrity of the dataset files, by comparing their checksums with the ones recorded in the manifest
+  pub fn verify(&self) -> Result<(), Report> {
+    for (filename, expected) in &self.manifest.checksums {
+      let filepath = self.path.join(ensure_plain_filename(filename)?);
+      let content = fs::read(&filepath).wrap_err_with(|| format!("When reading cached dataset file {filepath:#?}"))?;
+      let actual = sha256(&content);
+      if &actual != expected {
+        return make_error!(
+          "Cached dataset file {filepath:#?} is corrupted: its checksum is '{actual}', but '{expected}' is expected. Remove the directory {:#?} and download the dataset again.",
+          self.path
+        );
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Local store of downloaded datasets, such that they can be used without internet access. Each dataset is stored in
+/// a directory `<name>/<reference>/<tag>`, along with a manifest which contains checksums of the dataset files.
+pub struct DatasetCache {
+  root: PathBuf,
+}
+
+impl DatasetCache {
+  pub fn new(root: impl AsRef<Path>) -> Result<Self, Report> {
+    Ok(Self {
+      root: absolute_path(root)?,
+    })
+  }
+
+  /// Lists all datasets in the cache. Entries which cannot be read are skipped with a warning.
+  pub fn list(&self) -> Result<Vec<DatasetCacheEntry>, Report> {
+    if !self.root.exists() {
+      return Ok(vec![]);
+    }
+
+    let mut manifest_paths = vec![];
+    find_manifests(&self.root, &mut manifest_paths)
+      .wrap_err_with(|| format!("When searching dataset cache directory {:#?}", self.root))?;
+
+    let entries = manifest_paths
+      .into_iter()
+      .filter_map(|manifest_path| match read_entry(&manifest_path) {
+        Ok(entry) => Some(entry),
+        Err(report) => {
+          warn!("Skipping unreadable entry of the dataset cache: {report:#}");
+          None
+        }
+      })
+      .sorted_by_key(|entry| entry.path.clone())
+      .collect_vec();
+
+    Ok(entries)
+  }
+
+  /// Finds a dataset in the cache, given its name and, optionally, its version tag. If the tag is not given, the
+  /// latest of the cached versions compatible with this version of Nextclade is returned. Only datasets based on the
+  /// default reference sequence are considered.
+  pub fn find(&self, name: &str, tag: Option<&str>) -> Result<Option<DatasetCacheEntry>, Report> {
+    let entry = self
+      .list()?
+      .into_iter()
+      .filter(|entry| {
+        let attributes = &entry.dataset().attributes;
+        attributes.name.value == name
+          && attributes.reference.is_default
+          && tag.map_or_else(
+            || entry.dataset().is_compatible(THIS_VERSION),
+            |tag| attributes.tag.value == tag,
+          )
+      })
+      .max_by(|a, b| a.dataset().attributes.tag.value.cmp(&b.dataset().attributes.tag.value));
+    Ok(entry)
+  }
+
+  /// Downloads dataset files and stores them in the cache, replacing the previously cached files of the same dataset
+  pub fn download(&self, http: &mut HttpClient, dataset: &Dataset) -> Result<DatasetCacheEntry, Report> {
+    let files = dataset
+      .files
+      .iter()
+      .map(|(filename, url)| -> Result<(String, Vec<u8>), Report> {
+        let content = http
+          .get_checked(url)
+          .wrap_err_with(|| format!("Dataset file download failed: '{url}'"))?;
+        Ok((filename.clone(), content))
+      })
+      .collect::<Result<BTreeMap<String, Vec<u8>>, Report>>()?;
+
+    self.store(dataset, &files)
+  }
+
+  /// Stores dataset files in the cache, replacing the previously cached files of the same dataset. Files are first
+  /// written into a temporary directory, so that an interrupted write does not leave an incomplete dataset behind.
+  pub fn store(&self, dataset: &Dataset, files: &BTreeMap<String, Vec<u8>>) -> Result<DatasetCacheEntry, Report> {
+    let path = self.entry_path(dataset);
+    let tmp_path = {
+      let mut tmp_path = path.clone().into_os_string();
+      tmp_path.push(PARTIAL_SUFFIX);
+      PathBuf::from(tmp_path)
+    };
+
+    // Filenames come from the server index, so make sure they cannot point outside of the dataset directory
+    for filename in files.keys() {
+      ensure_plain_filename(filename)?;
+    }
+
+    if tmp_path.exists() {
+      fs::remove_dir_all(&tmp_path).wrap_err_with(|| format!("When removing directory {tmp_path:#?}"))?;
+    }
+    fs::create_dir_all(&tmp_path).wrap_err_with(|| format!("When creating directory {tmp_path:#?}"))?;
+
+    let mut checksums = BTreeMap::new();
+    for (filename, content) in files {
+      let filepath = tmp_path.join(filename);
+      fs::write(&filepath, content).wrap_err_with(|| format!("When writing file {filepath:#?}"))?;
+      checksums.insert(filename.clone(), sha256(content));
+    }
+
+    let manifest = DatasetCacheManifest {
+      dataset: dataset.clone(),
+      checksums,
+    };
+    let manifest_path = tmp_path.join(MANIFEST_FILENAME);
+    fs::write(&manifest_path, json_stringify(&manifest)?)
+      .wrap_err_with(|| format!("When writing file {manifest_path:#?}"))?;
+
+    if path.exists() {
+      fs::remove_dir_all(&path).wrap_err_with(|| format!("When removing directory {path:#?}"))?;
+    }
+    fs::rename(&tmp_path, &path).wrap_err_with(|| format!("When moving directory {tmp_path:#?} to {path:#?}"))?;
+
+    info!("Dataset is stored in the cache directory {path:#?}");
+    Ok(DatasetCacheEntry { path, manifest })
+  }
+
+  fn entry_path(&self, dataset: &Dataset) -> PathBuf {
+    let attributes = &dataset.attributes;
+    self
+      .root
+      .join(sanitize_path_component(&attributes.name.value))
+      .join(sanitize_path_component(&attributes.reference.value))
+      .join(sanitize_path_component(&attributes.tag.value))
+  }
+}
+
+/// Splits dataset name in the form `<name>@<tag>` into the name and the version tag
+pub fn parse_dataset_name_with_tag(name_with_tag: &str) -> (&str, Option<&str>) {
+  match name_with_tag.rsplit_once('@') {
+    Some((name, tag)) if !tag.is_empty() => (name, Some(tag)),
+    _ => (name_with_tag.trim_end_matches('@'), None),
+  }
+}
+
+fn read_entry(manifest_path: &Path) -> Result<DatasetCacheEntry, Report> {
+  let content = fs::read_to_string(manifest_path).wrap_err_with(|| format!("When reading {manifest_path:#?}"))?;
+  let manifest: DatasetCacheManifest =
+    json_parse(&content).wrap_err_with(|| format!("When parsing {manifest_path:#?}"))?;
+  let path = manifest_path.parent().map(Path::to_path_buf).unwrap_or_default();
+  Ok(DatasetCacheEntry { path, manifest })
+}
+
+fn find_manifests(dir: &Path, manifest_paths: &mut Vec<PathBuf>) -> Result<(), Report> {
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      if !path.to_string_lossy().ends_with(PARTIAL_SUFFIX) {
+        find_manifests(&path, manifest_paths)?;
+      }
+    } else if path.file_name().map_or(false, |filename| filename == MANIFEST_FILENAME) {
+      manifest_paths.push(path);
+    }
+  }
+  Ok(())
+}
+
+/// Checks that a dataset filename is a plain file name, i.e. that it does not contain directories and is not a special
+/// path component (e.g. `..`), such that it cannot point outside of the directory it is joined with
+fn ensure_plain_filename(filename: &str) -> Result<&str, Report> {
+  let mut components = Path::new(filename).components();
+  match (components.next(), components.next()) {
+    (Some(Component::Normal(component)), None) if component == filename => Ok(filename),
+    _ => make_error!(
+      "Dataset filename is not allowed: '{filename}'. Only plain file names, without directories, are supported."
+    ),
+  }
+}
+
+/// Replaces characters which are not allowed in file names on some of the platforms (e.g. `:` in version tags)
+fn sanitize_path_component(s: &str) -> String {
+  s.chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+        c
+      } else {
+        '_'
+      }
+    })
+    .collect()
+}
+
+fn sha256(content: &[u8]) -> String {
+  format!("{:x}", Sha256::digest(content))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dataset::dataset::{
+    DatasetAttributeValue, DatasetAttributes, DatasetCompatibility, DatasetCompatibilityRange,
+  };
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use tempfile::TempDir;
+
+  fn attr(value: &str) -> DatasetAttributeValue {
+    DatasetAttributeValue {
+      is_default: true,
+      value: value.to_owned(),
+      value_friendly: None,
+    }
+  }
+
+  fn dataset(tag: &str) -> Dataset {
+    Dataset {
+      enabled: true,
+      attributes: DatasetAttributes {
+        name: attr("flu"),
+        reference: attr("CY034116"),
+        tag: attr(tag),
+        rest_attrs: BTreeMap::new(),
+      },
+      comment: String::new(),
+      compatibility: DatasetCompatibility {
+        nextclade_cli: DatasetCompatibilityRange { min: None, max: None },
+        nextclade_web: DatasetCompatibilityRange { min: None, max: None },
+      },
+      files: BTreeMap::new(),
+      params: None,
+      zip_bundle: String::new(),
+    }
+  }
+
+  #[rstest]
+  #[case("sars-cov-2", ("sars-cov-2", None))]
+  #[case("sars-cov-2@", ("sars-cov-2", None))]
+  #[case("sars-cov-2@2022-04-28T12:00:00Z", ("sars-cov-2", Some("2022-04-28T12:00:00Z")))]
+  fn parses_dataset_name_with_tag(#[case] input: &str, #[case] expected: (&str, Option<&str>)) {
+    assert_eq!(parse_dataset_name_with_tag(input), expected);
+  }
+
+  #[rstest]
+  #[case("reference.fasta", true)]
+  #[case("../reference.fasta", false)]
+  #[case("..", false)]
+  #[case("data/reference.fasta", false)]
+  #[case("/etc/passwd", false)]
+  #[case("", false)]
+  fn accepts_only_plain_filenames(#[case] filename: &str, #[case] is_allowed: bool) {
+    assert_eq!(ensure_plain_filename(filename).is_ok(), is_allowed);
+  }
+
+  #[rstest]
+  fn stores_finds_and_verifies_datasets() -> Result<(), Report> {
+    let temp_dir = TempDir::new()?;
+    let root = temp_dir.path();
+    let cache = DatasetCache::new(root)?;
+
+    let files = BTreeMap::from([("reference.fasta".to_owned(), b">ref\nACGT\n".to_vec())]);
+    cache.store(&dataset("2022-01-01T00:00:00Z"), &files)?;
+    let newer = cache.store(&dataset("2023-01-01T00:00:00Z"), &files)?;
+
+    let latest = cache.find("flu", None)?.map(|entry| entry.path);
+    let pinned = cache.find("flu", Some("2022-01-01T00:00:00Z"))?.map(|entry| entry.path);
+    let missing = cache.find("flu", Some("2021-01-01T00:00:00Z"))?.map(|entry| entry.path);
+
+    assert_eq!(latest, Some(newer.path.clone()));
+    assert_eq!(pinned, Some(root.join("flu/CY034116/2022-01-01T00_00_00Z")));
+    assert_eq!(missing, None);
+
+    newer.verify()?;
+    fs::write(newer.path.join("reference.fasta"), ">ref\nACGG\n")?;
+    assert!(newer.verify().is_err());
+    Ok(())
+  }
+}
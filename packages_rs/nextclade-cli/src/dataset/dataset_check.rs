@@ -0,0 +1,775 @@
+use crate::dataset::dataset::Dataset;
+use crate::dataset::dataset_download::{get_segment_name, DatasetFilesReader};
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use nextclade::align::params::AlignPairwiseParams;
+use nextclade::analyze::aa_sub::AaSubMinimal;
+use nextclade::analyze::nuc_sub::NucSub;
+use nextclade::analyze::pcr_primers::{convert_pcr_primer, PcrPrimerCsvRow};
+use nextclade::analyze::virus_properties::VirusProperties;
+use nextclade::io::aa::{from_aa, Aa};
+use nextclade::io::csv::parse_csv;
+use nextclade::io::fasta::{read_many_fasta_str, FastaRecord};
+use nextclade::io::gene_map::GeneMap;
+use nextclade::io::gff3::{read_gff3_str, read_gff3_str_segmented};
+use nextclade::io::json::json_parse;
+use nextclade::io::nuc::{from_nuc, to_nuc_seq, Nuc};
+use nextclade::qc::qc_config::QcConfig;
+use nextclade::translate::genetic_code::{GeneticCode, GENETIC_CODE_STANDARD};
+use nextclade::translate::translate_genes::TranslationMap;
+use nextclade::translate::translate_genes_ref::translate_genes_ref;
+use nextclade::tree::tree::{AuspiceTree, AuspiceTreeNode};
+use nextclade::tree::tree_preprocess::tree_preprocess_in_place;
+use nextclade::utils::error::report_to_string;
+use nextclade::utils::range::Range;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DatasetCheckSeverity {
+  Warning,
+  Error,
+}
+
+impl Display for DatasetCheckSeverity {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      DatasetCheckSeverity::Warning => write!(f, "warning"),
+      DatasetCheckSeverity::Error => write!(f, "error"),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetCheckIssue {
+  pub severity: DatasetCheckSeverity,
+  pub file: String,
+  pub message: String,
+}
+
+/// Result of a dataset check: the list of files which were checked and the problems found in them
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetCheckReport {
+  pub files_checked: Vec<String>,
+  pub issues: Vec<DatasetCheckIssue>,
+}
+
+impl DatasetCheckReport {
+  pub fn num_errors(&self) -> usize {
+    self.count(DatasetCheckSeverity::Error)
+  }
+
+  pub fn num_warnings(&self) -> usize {
+    self.count(DatasetCheckSeverity::Warning)
+  }
+
+  pub fn has_errors(&self) -> bool {
+    self.num_errors() > 0
+  }
+
+  fn count(&self, severity: DatasetCheckSeverity) -> usize {
+    self.issues.iter().filter(|issue| issue.severity == severity).count()
+  }
+
+  fn error(&mut self, file: &str, message: impl Into<String>) {
+    self.add(DatasetCheckSeverity::Error, file, message);
+  }
+
+  fn warning(&mut self, file: &str, message: impl Into<String>) {
+    self.add(DatasetCheckSeverity::Warning, file, message);
+  }
+
+  fn add(&mut self, severity: DatasetCheckSeverity, file: &str, message: impl Into<String>) {
+    self.issues.push(DatasetCheckIssue {
+      severity,
+      file: file.to_owned(),
+      message: message.into(),
+    });
+  }
+
+  /// Records an error if the result is an error, and converts the result into an option
+  fn check<T>(&mut self, file: &str, result: Result<T, Report>) -> Option<T> {
+    match result {
+      Ok(value) => Some(value),
+      Err(report) => {
+        self.error(file, report_to_string(&report));
+        None
+      }
+    }
+  }
+}
+
+/// Formats the report as human-readable text, one line per issue
+pub fn format_dataset_check_report(report: &DatasetCheckReport) -> String {
+  let summary = format!(
+    "Checked {} file(s): {} error(s), {} warning(s)",
+    report.files_checked.len(),
+    report.num_errors(),
+    report.num_warnings()
+  );
+  report
+    .issues
+    .iter()
+    .map(
+      |DatasetCheckIssue {
+         severity,
+         file,
+         message,
+       }| format!("{severity}: {file}: {message}"),
+    )
+    .chain([summary])
+    .join("\n")
+}
+
+/// Checks dataset in a directory or in a zip archive
+pub fn dataset_check_path(input_dataset: impl AsRef<Path>) -> Result<DatasetCheckReport, Report> {
+  let mut reader = DatasetFilesReader::open(input_dataset)?;
+  let filenames = reader.filenames()?;
+  Ok(dataset_check_files(&filenames, |filename| reader.read_str(filename)))
+}
+
+/// Checks dataset files: each file is parsed and the files are validated against each other, such that the problems
+/// which would otherwise only be found in the middle of a `nextclade run` are reported upfront. Unlike when loading
+/// the dataset, the check does not stop on the first problem, but collects all of them into the report.
+pub fn dataset_check_files(
+  filenames: &BTreeSet<String>,
+  mut read_dataset_file: impl FnMut(&str) -> Result<String, Report>,
+) -> DatasetCheckReport {
+  let mut report = DatasetCheckReport::default();
+
+  let mut read_file = |report: &mut DatasetCheckReport, filename: &str| -> Option<String> {
+    if !filenames.contains(filename) {
+      report.error(filename, "Required file is missing");
+      return None;
+    }
+    report.files_checked.push(filename.to_owned());
+    let content = read_dataset_file(filename).wrap_err("When reading file");
+    report.check(filename, content)
+  };
+
+  if filenames.contains("tag.json") {
+    if let Some(content) = read_file(&mut report, "tag.json") {
+      report.check("tag.json", json_parse::<Dataset>(&content));
+    }
+  } else {
+    report.warning("tag.json", "Dataset version information is missing");
+  }
+
+  let virus_properties = read_file(&mut report, "virus_properties.json")
+    .and_then(|content| report.check("virus_properties.json", VirusProperties::from_str(&content)));
+
+  let qc_config =
+    read_file(&mut report, "qc.json").and_then(|content| report.check("qc.json", QcConfig::from_str(&content)));
+
+  if filenames.contains("sequences.fasta") {
+    if let Some(content) = read_file(&mut report, "sequences.fasta") {
+      report.check("sequences.fasta", read_many_fasta_str(&content));
+    }
+  }
+
+  // Other files can only be checked against the reference sequence
+  let ref_records = match read_file(&mut report, "reference.fasta")
+    .and_then(|content| report.check("reference.fasta", read_many_fasta_str(&content)))
+  {
+    Some(ref_records) => ref_records,
+    None => return report,
+  };
+
+  if ref_records.is_empty() {
+    report.error("reference.fasta", "No reference sequences found");
+    return report;
+  }
+
+  let is_segmented = ref_records.len() > 1;
+  let segments = ref_records
+    .iter()
+    .map(|ref_record| is_segmented.then(|| get_segment_name(&ref_record.seq_name)))
+    .collect_vec();
+
+  if let Some(duplicate) = segments.iter().flatten().duplicates().next() {
+    report.error("reference.fasta", format!("More than one segment named '{duplicate}'"));
+  }
+
+  let gene_maps = read_file(&mut report, "genemap.gff").and_then(|content| {
+    if is_segmented {
+      let mut gene_maps = report.check("genemap.gff", read_gff3_str_segmented(&content))?;
+      for seqid in gene_maps.keys() {
+        if !segments.contains(&Some(seqid.clone())) {
+          report.error(
+            "genemap.gff",
+            format!("Genes of sequence '{seqid}' are found, but there is no reference segment with this name"),
+          );
+        }
+      }
+      Some(
+        segments
+          .iter()
+          .flatten()
+          .map(|segment| gene_maps.remove(segment).unwrap_or_default())
+          .collect_vec(),
+      )
+    } else {
+      report
+        .check("genemap.gff", read_gff3_str(&content))
+        .map(|gene_map| vec![gene_map])
+    }
+  });
+
+  let genetic_code = virus_properties.as_ref().map_or_else(
+    || GeneticCode::from_id(GENETIC_CODE_STANDARD),
+    VirusProperties::genetic_code,
+  );
+  let genetic_code = report.check("virus_properties.json", genetic_code);
+
+  let mut alignment_params = AlignPairwiseParams::default();
+  if let Some(alignment_params_from_file) = virus_properties.as_ref().and_then(|vp| vp.alignment_params.clone()) {
+    alignment_params.merge_opt(alignment_params_from_file);
+  }
+
+  // Lengths of all genes, in codons
+  let mut gene_lengths = BTreeMap::<String, usize>::new();
+
+  for (i, (ref_record, segment)) in ref_records.iter().zip(&segments).enumerate() {
+    let tree_filename = segment
+      .as_ref()
+      .map_or_else(|| "tree.json".to_owned(), |segment| format!("tree_{segment}.json"));
+
+    let ref_seq = match report.check("reference.fasta", to_nuc_seq(&ref_record.seq)) {
+      Some(ref_seq) => ref_seq,
+      None => continue,
+    };
+
+    let gene_map = gene_maps
+      .as_ref()
+      .map(|gene_maps| check_gene_map_bounds(&mut report, &gene_maps[i], ref_seq.len()));
+
+    let tree = read_file(&mut report, &tree_filename)
+      .and_then(|content| report.check(&tree_filename, AuspiceTree::from_str(&content)));
+
+    if let Some(gene_map) = &gene_map {
+      gene_lengths.extend(
+        gene_map
+          .iter()
+          .map(|(gene_name, gene)| (gene_name.clone(), gene.len_codon())),
+      );
+    }
+
+    // The tree can only be checked when the reference peptides are known
+    if let (Some(mut tree), Some(gene_map), Some(genetic_code)) = (tree, gene_map, genetic_code) {
+      let ref_peptides = report.check(
+        "genemap.gff",
+        translate_genes_ref(&ref_seq, &gene_map, genetic_code, &alignment_params)
+          .wrap_err("When translating reference genes"),
+      );
+
+      if let Some(ref_peptides) = ref_peptides {
+        let num_errors = report.num_errors();
+        check_tree_mutations(&mut report, &tree_filename, &tree, &ref_seq, &ref_peptides);
+
+        // Preprocessing does not expect inconsistent mutations, so it is only attempted when none are found
+        if report.num_errors() == num_errors {
+          let result =
+            tree_preprocess_in_place(&mut tree, &ref_seq, &ref_peptides).wrap_err("When preprocessing reference tree");
+          report.check(&tree_filename, result);
+        }
+      }
+    }
+  }
+
+  let ref_len_max = ref_records
+    .iter()
+    .map(|ref_record| ref_record.seq.len())
+    .max()
+    .unwrap_or_default();
+
+  if let Some(primers_str) = read_file(&mut report, "primers.csv") {
+    check_primers(&mut report, &primers_str, &ref_records);
+  }
+
+  if let Some(virus_properties) = &virus_properties {
+    check_virus_properties(
+      &mut report,
+      virus_properties,
+      gene_maps.is_some().then_some(&gene_lengths),
+      ref_len_max,
+    );
+  }
+
+  if let (Some(qc_config), Some(_)) = (&qc_config, &gene_maps) {
+    check_qc_config(&mut report, qc_config, &gene_lengths);
+  }
+
+  report
+}
+
+/// Checks that all genes fit into the reference sequence. Returns the gene map without the genes which don't.
+fn check_gene_map_bounds(report: &mut DatasetCheckReport, gene_map: &GeneMap, ref_len: usize) -> GeneMap {
+  let genes_outside = find_genes_outside_of_ref(gene_map, ref_len);
+  for message in genes_outside.values() {
+    report.error("genemap.gff", message);
+  }
+  gene_map
+    .iter()
+    .filter(|(gene_name, _)| !genes_outside.contains_key(*gene_name))
+    .map(|(gene_name, gene)| (gene_name.clone(), gene.clone()))
+    .collect()
+}
+
+/// Finds genes which don't fit into the reference sequence. Returns an error message for each of them.
+pub fn find_genes_outside_of_ref(gene_map: &GeneMap, ref_len: usize) -> BTreeMap<String, String> {
+  gene_map
+    .iter()
+    .filter_map(|(gene_name, gene)| {
+      let end = gene
+        .cds_segments
+        .iter()
+        .map(|segment| segment.end)
+        .chain([gene.end])
+        .max()
+        .unwrap_or_default();
+      (end > ref_len).then(|| {
+        let message = format!(
+          "Gene '{gene_name}' ends at position {end}, which is outside of the reference sequence of length {ref_len}"
+        );
+        (gene_name.clone(), message)
+      })
+    })
+    .collect()
+}
+
+/// Checks that the mutations on the branches of the tree are consistent with the reference sequence and peptides: the
+/// reference character of each mutation should be the character the parent node has at this position. At most
+/// `MAX_TREE_ISSUES` inconsistencies are reported.
+fn check_tree_mutations(
+  report: &mut DatasetCheckReport,
+  file: &str,
+  tree: &AuspiceTree,
+  ref_seq: &[Nuc],
+  ref_peptides: &TranslationMap,
+) {
+  let mut issues = vec![];
+
+  // Characters of the current node which differ from the reference. Nodes are visited in pre-order: the mutations of
+  // a node are applied when entering it and reverted when moving to a node which is not its descendant.
+  let mut nucs = BTreeMap::<usize, Nuc>::new();
+  let mut aas = BTreeMap::<(String, usize), Aa>::new();
+  let mut path: Vec<NodeChanges> = vec![];
+
+  for (depth, node) in tree.iter_depth_first_preorder() {
+    while path.len() > depth {
+      let changes = path.pop().expect("Path is expected to be non-empty");
+      for (pos, nuc) in changes.nucs.into_iter().rev() {
+        restore(&mut nucs, pos, nuc);
+      }
+      for (key, aa) in changes.aas.into_iter().rev() {
+        restore(&mut aas, key, aa);
+      }
+    }
+
+    let changes = check_node_mutations(node, ref_seq, ref_peptides, &mut nucs, &mut aas, &mut issues);
+    path.push(changes);
+  }
+
+  let num_issues = issues.len();
+  for issue in issues.into_iter().take(MAX_TREE_ISSUES) {
+    report.error(file, issue);
+  }
+  if num_issues > MAX_TREE_ISSUES {
+    report.error(
+      file,
+      format!(
+        "{} more inconsistent mutations are found on the reference tree",
+        num_issues - MAX_TREE_ISSUES
+      ),
+    );
+  }
+}
+
+const MAX_TREE_ISSUES: usize = 20;
+
+/// Characters which a node has overwritten, as they were before the node's mutations were applied
+#[derive(Default)]
+struct NodeChanges {
+  nucs: Vec<(usize, Option<Nuc>)>,
+  aas: Vec<((String, usize), Option<Aa>)>,
+}
+
+fn restore<K: Ord, V>(map: &mut BTreeMap<K, V>, key: K, previous: Option<V>) {
+  match previous {
+    Some(value) => map.insert(key, value),
+    None => map.remove(&key),
+  };
+}
+
+/// Checks mutations of a single node against the characters of its parent and applies them
+fn check_node_mutations(
+  node: &AuspiceTreeNode,
+  ref_seq: &[Nuc],
+  ref_peptides: &TranslationMap,
+  nucs: &mut BTreeMap<usize, Nuc>,
+  aas: &mut BTreeMap<(String, usize), Aa>,
+  issues: &mut Vec<String>,
+) -> NodeChanges {
+  let mut changes = NodeChanges::default();
+
+  for (gene_name, mutations) in &node.branch_attrs.mutations {
+    for mutation_str in mutations {
+      if gene_name == "nuc" {
+        let mutation = match NucSub::from_str(mutation_str) {
+          Ok(mutation) => mutation,
+          Err(report) => {
+            issues.push(format!("Node '{}': {}", node.name, report_to_string(&report)));
+            continue;
+          }
+        };
+        match nucs.get(&mutation.pos).or_else(|| ref_seq.get(mutation.pos)) {
+          None => issues.push(format!(
+            "Node '{}': nucleotide mutation '{mutation_str}' is outside of the reference sequence of length {}",
+            node.name,
+            ref_seq.len()
+          )),
+          Some(&parent_nuc) if parent_nuc != mutation.reff => issues.push(format!(
+            "Node '{}': nucleotide mutation '{mutation_str}' does not match the parent node, which has '{}' at this position",
+            node.name,
+            from_nuc(parent_nuc)
+          )),
+          Some(_) => {}
+        }
+        let previous = nucs.insert(mutation.pos, mutation.qry);
+        changes.nucs.push((mutation.pos, previous));
+      } else {
+        let mutation = match AaSubMinimal::from_str(mutation_str) {
+          Ok(mutation) => mutation,
+          Err(report) => {
+            issues.push(format!("Node '{}': {}", node.name, report_to_string(&report)));
+            continue;
+          }
+        };
+        let ref_peptide = match ref_peptides.get(gene_name) {
+          Some(ref_peptide) => ref_peptide,
+          None => {
+            issues.push(format!(
+              "Node '{}': aminoacid mutation '{mutation_str}' is in gene '{gene_name}', which is not found in the gene map",
+              node.name
+            ));
+            continue;
+          }
+        };
+        let key = (gene_name.clone(), mutation.pos);
+        match aas.get(&key).or_else(|| ref_peptide.seq.get(mutation.pos)) {
+          None => issues.push(format!(
+            "Node '{}': aminoacid mutation '{mutation_str}' is outside of gene '{gene_name}' of length {}",
+            node.name,
+            ref_peptide.seq.len()
+          )),
+          Some(&parent_aa) if parent_aa != mutation.reff => issues.push(format!(
+            "Node '{}': aminoacid mutation '{gene_name}:{mutation_str}' does not match the parent node, which has '{}' at this position",
+            node.name,
+            from_aa(parent_aa)
+          )),
+          Some(_) => {}
+        }
+        let previous = aas.insert(key.clone(), mutation.qry);
+        changes.aas.push((key, previous));
+      }
+    }
+  }
+
+  changes
+}
+
+/// Checks that every primer is found in the reference sequence (or in at least one of the segments)
+fn check_primers(report: &mut DatasetCheckReport, primers_str: &str, ref_records: &[FastaRecord]) {
+  let rows = match report.check("primers.csv", parse_csv::<PcrPrimerCsvRow>(primers_str)) {
+    Some(rows) => rows,
+    None => return,
+  };
+
+  for row in rows {
+    if let Err(report_primer) = to_nuc_seq(&row.primer_oligonuc) {
+      report.error(
+        "primers.csv",
+        format!("PCR primer '{}': {}", row.name, report_to_string(&report_primer)),
+      );
+      continue;
+    }
+
+    let is_found = ref_records
+      .iter()
+      .any(|ref_record| convert_pcr_primer(row.clone(), &ref_record.seq).is_ok());
+
+    if !is_found {
+      report.error(
+        "primers.csv",
+        format!(
+          "PCR primer '{}' (oligonucleotide '{}') is not found in the reference sequence",
+          row.name, row.primer_oligonuc
+        ),
+      );
+    }
+  }
+}
+
+/// Checks that genes and ranges referred to in virus properties exist. Gene references are only checked if the gene
+/// map could be read.
+fn check_virus_properties(
+  report: &mut DatasetCheckReport,
+  virus_properties: &VirusProperties,
+  gene_lengths: Option<&BTreeMap<String, usize>>,
+  ref_len: usize,
+) {
+  const FILE: &str = "virus_properties.json";
+
+  for range in &virus_properties.placement_mask_ranges {
+    if range.is_empty() {
+      report.warning(FILE, format!("Placement mask range {} is empty", format_range(range)));
+    } else if range.end > ref_len {
+      report.error(
+        FILE,
+        format!(
+          "Placement mask range {} is outside of the reference sequence of length {ref_len}",
+          format_range(range)
+        ),
+      );
+    }
+  }
+
+  let gene_lengths = match gene_lengths {
+    Some(gene_lengths) => gene_lengths,
+    None => return,
+  };
+
+  for motifs_desc in &virus_properties.aa_motifs {
+    for gene_desc in &motifs_desc.include_genes {
+      let context = format!("Aminoacid motifs '{}'", motifs_desc.name);
+      check_gene_ranges(report, FILE, &context, gene_lengths, &gene_desc.gene, &gene_desc.ranges);
+    }
+  }
+
+  for phenotype_data in virus_properties.phenotype_data.iter().flatten() {
+    let context = format!("Phenotype '{}'", phenotype_data.name);
+    check_gene_ranges(
+      report,
+      FILE,
+      &context,
+      gene_lengths,
+      &phenotype_data.gene,
+      &[phenotype_data.aa_range.clone()],
+    );
+  }
+}
+
+/// Checks that genes referred to in the lists of ignored frame shifts and stop codons exist
+fn check_qc_config(report: &mut DatasetCheckReport, qc_config: &QcConfig, gene_lengths: &BTreeMap<String, usize>) {
+  const FILE: &str = "qc.json";
+
+  for frame_shift in &qc_config.frame_shifts.ignored_frame_shifts {
+    check_gene_ranges(
+      report,
+      FILE,
+      "Ignored frame shift",
+      gene_lengths,
+      &frame_shift.gene_name,
+      &[frame_shift.codon_range.clone()],
+    );
+  }
+
+  for stop_codon in &qc_config.stop_codons.ignored_stop_codons {
+    check_gene_ranges(
+      report,
+      FILE,
+      "Ignored stop codon",
+      gene_lengths,
+      &stop_codon.gene_name,
+      &[Range::new(stop_codon.codon, stop_codon.codon + 1)],
+    );
+  }
+}
+
+/// Checks that the gene exists and that the codon ranges are inside of it
+fn check_gene_ranges(
+  report: &mut DatasetCheckReport,
+  file: &str,
+  context: &str,
+  gene_lengths: &BTreeMap<String, usize>,
+  gene_name: &str,
+  ranges: &[Range],
+) {
+  let gene_len = match gene_lengths.get(gene_name) {
+    Some(gene_len) => *gene_len,
+    None => {
+      report.error(
+        file,
+        format!("{context}: gene '{gene_name}' is not found in the gene map"),
+      );
+      return;
+    }
+  };
+
+  for range in ranges {
+    if range.end > gene_len {
+      report.error(
+        file,
+        format!(
+          "{context}: codon range {} is outside of gene '{gene_name}' of length {gene_len}",
+          format_range(range)
+        ),
+      );
+    }
+  }
+}
+
+/// Formats 0-based, end-exclusive range as 1-based, end-inclusive, as displayed to users elsewhere
+fn format_range(range: &Range) -> String {
+  format!("{}-{}", range.begin + 1, range.end)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rstest::rstest;
+
+  const TREE: &str = r#"{
+    "meta": { "display_defaults": {} },
+    "tree": {
+      "name": "root",
+      "branch_attrs": { "mutations": {} },
+      "node_attrs": { "clade_membership": { "value": "A" } },
+      "children": [
+        {
+          "name": "child",
+          "branch_attrs": { "mutations": { "nuc": ["{MUTATION}"] } },
+          "node_attrs": { "clade_membership": { "value": "A" } }
+        }
+      ]
+    }
+  }"#;
+
+  fn check(files: &[(&str, &str)]) -> DatasetCheckReport {
+    let files: BTreeMap<String, String> = files
+      .iter()
+      .map(|(filename, content)| ((*filename).to_owned(), (*content).to_owned()))
+      .collect();
+    let filenames = files.keys().cloned().collect();
+    dataset_check_files(&filenames, |filename| Ok(files[filename].clone()))
+  }
+
+  fn issue_files(report: &DatasetCheckReport) -> Vec<(DatasetCheckSeverity, String)> {
+    report
+      .issues
+      .iter()
+      .map(|issue| (issue.severity, issue.file.clone()))
+      .collect()
+  }
+
+  #[rstest]
+  fn reports_problems_in_dataset_files() {
+    let report = check(&[
+      (
+        "virus_properties.json",
+        r#"{
+          "schemaVersion": "1.10.0",
+          "nucMutLabelMap": {},
+          "placementMaskRanges": [{ "begin": 0, "end": 100 }],
+          "aaMotifs": [{
+            "name": "m", "nameShort": "m", "nameFriendly": "m", "description": "", "motifs": ["M"],
+            "includeGenes": [{ "gene": "nope", "ranges": [] }]
+          }]
+        }"#,
+      ),
+      (
+        "qc.json",
+        r#"{ "schemaVersion": "1.2.0", "stopCodons": { "ignoredStopCodons": [{ "geneName": "g", "codon": 5 }] } }"#,
+      ),
+      ("reference.fasta", ">ref\nATGTAA\n"),
+      (
+        "genemap.gff",
+        "##gff-version 3\nref\t.\tgene\t1\t6\t.\t+\t.\tgene_name=g\n",
+      ),
+      ("tree.json", &TREE.replace("{MUTATION}", "A2C")),
+      (
+        "primers.csv",
+        "Country (Institute),Target,Oligonucleotide,Sequence\nX,Y,p_F,GGGGGG\n",
+      ),
+    ]);
+
+    assert_eq!(
+      issue_files(&report),
+      vec![
+        (DatasetCheckSeverity::Warning, "tag.json".to_owned()),
+        (DatasetCheckSeverity::Error, "tree.json".to_owned()),
+        (DatasetCheckSeverity::Error, "primers.csv".to_owned()),
+        (DatasetCheckSeverity::Error, "virus_properties.json".to_owned()),
+        (DatasetCheckSeverity::Error, "virus_properties.json".to_owned()),
+        (DatasetCheckSeverity::Error, "qc.json".to_owned()),
+      ]
+    );
+  }
+
+  #[rstest]
+  fn accepts_consistent_dataset_files() {
+    let report = check(&[
+      (
+        "virus_properties.json",
+        r#"{ "schemaVersion": "1.10.0", "nucMutLabelMap": {} }"#,
+      ),
+      ("qc.json", r#"{ "schemaVersion": "1.2.0" }"#),
+      ("reference.fasta", ">ref\nATGTAA\n"),
+      (
+        "genemap.gff",
+        "##gff-version 3\nref\t.\tgene\t1\t6\t.\t+\t.\tgene_name=g\n",
+      ),
+      ("tree.json", &TREE.replace("{MUTATION}", "T2C")),
+      ("primers.csv", "Country (Institute),Target,Oligonucleotide,Sequence\n"),
+    ]);
+
+    assert_eq!(
+      issue_files(&report),
+      vec![(DatasetCheckSeverity::Warning, "tag.json".to_owned())]
+    );
+    assert!(!report.has_errors());
+  }
+
+  #[rstest]
+  fn reverts_mutations_when_leaving_branch() -> Result<(), Report> {
+    let tree = AuspiceTree::from_str(
+      r#"{
+        "meta": { "display_defaults": {} },
+        "tree": {
+          "name": "root",
+          "branch_attrs": { "mutations": {} },
+          "node_attrs": { "clade_membership": { "value": "A" } },
+          "children": [
+            {
+              "name": "a",
+              "branch_attrs": { "mutations": { "nuc": ["T2C"] } },
+              "node_attrs": { "clade_membership": { "value": "A" } },
+              "children": [
+                { "name": "a1", "branch_attrs": { "mutations": { "nuc": ["C2G"] } }, "node_attrs": { "clade_membership": { "value": "A" } } }
+              ]
+            },
+            { "name": "b", "branch_attrs": { "mutations": { "nuc": ["T2A"] } }, "node_attrs": { "clade_membership": { "value": "A" } } },
+            { "name": "c", "branch_attrs": { "mutations": { "nuc": ["C2A"] } }, "node_attrs": { "clade_membership": { "value": "A" } } }
+          ]
+        }
+      }"#,
+    )?;
+    let ref_seq = to_nuc_seq("ATGTAA")?;
+
+    let mut report = DatasetCheckReport::default();
+    check_tree_mutations(&mut report, "tree.json", &tree, &ref_seq, &TranslationMap::new());
+
+    let messages = report.issues.iter().map(|issue| issue.message.as_str()).collect_vec();
+    assert_eq!(
+      messages,
+      vec!["Node 'c': nucleotide mutation 'C2A' does not match the parent node, which has 'T' at this position"]
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,525 @@
+use crate::dataset::dataset::Dataset;
+use crate::dataset::dataset_download::{get_segment_name, DatasetFilesReader};
+use eyre::{eyre, Report, WrapErr};
+use itertools::Itertools;
+use nextclade::align::params::AlignPairwiseParams;
+use nextclade::analyze::virus_properties::VirusProperties;
+use nextclade::gene::gene::Gene;
+use nextclade::io::fasta::{read_many_fasta_str, FastaRecord};
+use nextclade::io::gff3::{read_gff3_str, read_gff3_str_segmented};
+use nextclade::io::json::json_parse;
+use nextclade::qc::qc_config::QcConfig;
+use nextclade::tree::tree::{AuspiceTree, AUSPICE_UNKNOWN_VALUE};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Summary of a reference sequence (or of a segment of a segmented reference)
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetReferenceSummary {
+  pub name: String,
+  pub length: usize,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetReferenceChange {
+  /// `None` if the sequence is only present in the new version
+  pub old: Option<DatasetReferenceSummary>,
+  /// `None` if the sequence is only present in the old version
+  pub new: Option<DatasetReferenceSummary>,
+  /// Number of positions at which the old and the new sequences differ. Only known if they are of the same length.
+  pub num_nuc_differences: Option<usize>,
+}
+
+/// Change of a single value in one of the dataset's JSON configuration files. The key is the dot-separated path to the
+/// value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetValueChange {
+  pub key: String,
+  pub old: Option<Value>,
+  pub new: Option<Value>,
+}
+
+/// Differences between two versions of a dataset
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetDiff {
+  pub old_tag: Option<String>,
+  pub new_tag: Option<String>,
+  pub reference: Vec<DatasetReferenceChange>,
+  pub genes_added: Vec<String>,
+  pub genes_removed: Vec<String>,
+  /// Genes which are present in both versions, but have different coordinates, strand or coding segments
+  pub genes_changed: Vec<String>,
+  pub clades_added: Vec<String>,
+  pub clades_removed: Vec<String>,
+  pub qc_config: Vec<DatasetValueChange>,
+  pub alignment_params: Vec<DatasetValueChange>,
+  pub label_maps: Vec<DatasetValueChange>,
+}
+
+impl DatasetDiff {
+  pub fn is_empty(&self) -> bool {
+    self.reference.is_empty()
+      && self.genes_added.is_empty()
+      && self.genes_removed.is_empty()
+      && self.genes_changed.is_empty()
+      && self.clades_added.is_empty()
+      && self.clades_removed.is_empty()
+      && self.qc_config.is_empty()
+      && self.alignment_params.is_empty()
+      && self.label_maps.is_empty()
+  }
+}
+
+/// Dataset files which are relevant for the comparison, already parsed
+struct DatasetContents {
+  tag: Option<String>,
+  ref_records: Vec<FastaRecord>,
+  genes: BTreeMap<String, Gene>,
+  clades: BTreeSet<String>,
+  qc_config: Value,
+  alignment_params: Value,
+  label_maps: Value,
+}
+
+impl DatasetContents {
+  fn from_files(files: &BTreeMap<String, String>) -> Result<Self, Report> {
+    let read_file = |filename: &str| -> Result<&str, Report> {
+      files
+        .get(filename)
+        .map(String::as_str)
+        .ok_or_else(|| eyre!("Required file '{filename}' is missing"))
+    };
+
+    let tag = files
+      .get("tag.json")
+      .map(|content| json_parse::<Dataset>(content).wrap_err("When parsing 'tag.json'"))
+      .transpose()?
+      .map(|dataset| dataset.attributes.tag.value);
+
+    let ref_records = read_many_fasta_str(read_file("reference.fasta")?).wrap_err("When parsing 'reference.fasta'")?;
+
+    let gene_map_str = read_file("genemap.gff")?;
+    let genes = if ref_records.len() > 1 {
+      read_gff3_str_segmented(gene_map_str)
+        .wrap_err("When parsing 'genemap.gff'")?
+        .into_values()
+        .flatten()
+        .collect()
+    } else {
+      read_gff3_str(gene_map_str).wrap_err("When parsing 'genemap.gff'")?
+    };
+
+    // Trees of segmented datasets are in separate files, one per segment
+    let mut clades = BTreeSet::new();
+    for (filename, content) in files {
+      if filename == "tree.json" || (filename.starts_with("tree_") && filename.ends_with(".json")) {
+        let tree = AuspiceTree::from_str(content).wrap_err_with(|| format!("When parsing '{filename}'"))?;
+        clades.extend(
+          tree
+            .iter_depth_first_preorder()
+            .map(|(_, node)| node.clade())
+            .filter(|clade| !clade.is_empty() && clade != AUSPICE_UNKNOWN_VALUE),
+        );
+      }
+    }
+
+    let qc_config = QcConfig::from_str(read_file("qc.json")?).wrap_err("When parsing 'qc.json'")?;
+
+    let virus_properties_str = read_file("virus_properties.json")?;
+    let virus_properties =
+      VirusProperties::from_str(virus_properties_str).wrap_err("When parsing 'virus_properties.json'")?;
+
+    // Alignment parameters are compared after applying the defaults, because this is what the alignment will use
+    let mut alignment_params = AlignPairwiseParams::default();
+    if let Some(alignment_params_from_file) = virus_properties.alignment_params {
+      alignment_params.merge_opt(alignment_params_from_file);
+    }
+
+    // Label maps are compared in their original form, because the parsed form is keyed by genotype and is harder to read
+    let virus_properties_json: Value =
+      json_parse(virus_properties_str).wrap_err("When parsing 'virus_properties.json'")?;
+
+    Ok(Self {
+      tag,
+      ref_records,
+      genes,
+      clades,
+      qc_config: serde_json::to_value(qc_config)?,
+      alignment_params: serde_json::to_value(alignment_params)?,
+      label_maps: virus_properties_json
+        .get("nucMutLabelMap")
+        .cloned()
+        .unwrap_or(Value::Null),
+    })
+  }
+}
+
+/// Reads files of a dataset in a directory or in a zip archive, which are needed for the comparison
+pub fn dataset_read_files_for_diff(input_dataset: impl AsRef<Path>) -> Result<BTreeMap<String, String>, Report> {
+  let is_needed =
+    |filename: &str| filename.ends_with(".json") || filename == "reference.fasta" || filename == "genemap.gff";
+
+  let mut reader = DatasetFilesReader::open(input_dataset)?;
+  reader
+    .filenames()?
+    .into_iter()
+    .filter(|filename| is_needed(filename))
+    .map(|filename| {
+      let content = reader.read_str(&filename)?;
+      Ok((filename, content))
+    })
+    .collect()
+}
+
+/// Compares two versions of a dataset, given their files
+pub fn dataset_diff_files(
+  old_files: &BTreeMap<String, String>,
+  new_files: &BTreeMap<String, String>,
+) -> Result<DatasetDiff, Report> {
+  let old = DatasetContents::from_files(old_files).wrap_err("When reading old version of the dataset")?;
+  let new = DatasetContents::from_files(new_files).wrap_err("When reading new version of the dataset")?;
+
+  let mut diff = DatasetDiff {
+    old_tag: old.tag,
+    new_tag: new.tag,
+    reference: diff_ref_records(&old.ref_records, &new.ref_records),
+    genes_added: new
+      .genes
+      .keys()
+      .filter(|gene| !old.genes.contains_key(*gene))
+      .cloned()
+      .collect(),
+    genes_removed: old
+      .genes
+      .keys()
+      .filter(|gene| !new.genes.contains_key(*gene))
+      .cloned()
+      .collect(),
+    genes_changed: old
+      .genes
+      .iter()
+      .filter(|(gene_name, old_gene)| {
+        new
+          .genes
+          .get(*gene_name)
+          .map_or(false, |new_gene| !is_same_gene(old_gene, new_gene))
+      })
+      .map(|(gene_name, _)| gene_name.clone())
+      .collect(),
+    clades_added: new.clades.difference(&old.clades).cloned().collect(),
+    clades_removed: old.clades.difference(&new.clades).cloned().collect(),
+    ..DatasetDiff::default()
+  };
+
+  diff_json("", &old.qc_config, &new.qc_config, &mut diff.qc_config);
+  diff_json(
+    "",
+    &old.alignment_params,
+    &new.alignment_params,
+    &mut diff.alignment_params,
+  );
+  diff_json("", &old.label_maps, &new.label_maps, &mut diff.label_maps);
+
+  Ok(diff)
+}
+
+fn is_same_gene(old: &Gene, new: &Gene) -> bool {
+  old.start == new.start && old.end == new.end && old.strand == new.strand && old.cds_segments == new.cds_segments
+}
+
+/// Pairs reference sequences by segment name (or, for non-segmented datasets, the only sequences with each other) and
+/// lists those which changed
+fn diff_ref_records(old: &[FastaRecord], new: &[FastaRecord]) -> Vec<DatasetReferenceChange> {
+  let segment_key = |records: &[FastaRecord], record: &FastaRecord| -> String {
+    if records.len() > 1 {
+      get_segment_name(&record.seq_name)
+    } else {
+      String::new()
+    }
+  };
+
+  let old = old
+    .iter()
+    .map(|record| (segment_key(old, record), record))
+    .collect::<BTreeMap<_, _>>();
+  let new = new
+    .iter()
+    .map(|record| (segment_key(new, record), record))
+    .collect::<BTreeMap<_, _>>();
+
+  old
+    .keys()
+    .chain(new.keys())
+    .unique()
+    .filter_map(|key| {
+      let old = old.get(key);
+      let new = new.get(key);
+      if let (Some(old), Some(new)) = (old, new) {
+        if old.seq_name == new.seq_name && old.seq == new.seq {
+          return None;
+        }
+      }
+
+      let num_nuc_differences = match (old, new) {
+        (Some(old), Some(new)) if old.seq.len() == new.seq.len() => {
+          Some(old.seq.bytes().zip(new.seq.bytes()).filter(|(a, b)| a != b).count())
+        }
+        _ => None,
+      };
+
+      let summary = |record: &&FastaRecord| DatasetReferenceSummary {
+        name: record.seq_name.clone(),
+        length: record.seq.len(),
+      };
+
+      Some(DatasetReferenceChange {
+        old: old.map(summary),
+        new: new.map(summary),
+        num_nuc_differences,
+      })
+    })
+    .collect()
+}
+
+/// Recursively compares JSON objects and lists the values which were added, removed or changed. Arrays are compared
+/// as a whole.
+fn diff_json(prefix: &str, old: &Value, new: &Value, changes: &mut Vec<DatasetValueChange>) {
+  let join_key = |key: &str| {
+    if prefix.is_empty() {
+      key.to_owned()
+    } else {
+      format!("{prefix}.{key}")
+    }
+  };
+
+  match (old, new) {
+    (Value::Object(old), Value::Object(new)) => {
+      for key in old.keys().chain(new.keys()).unique() {
+        match (old.get(key), new.get(key)) {
+          (Some(old), Some(new)) => diff_json(&join_key(key), old, new, changes),
+          (old, new) => changes.push(DatasetValueChange {
+            key: join_key(key),
+            old: old.cloned(),
+            new: new.cloned(),
+          }),
+        }
+      }
+    }
+    (old, new) => {
+      if old != new {
+        changes.push(DatasetValueChange {
+          key: prefix.to_owned(),
+          old: Some(old.clone()),
+          new: Some(new.clone()),
+        });
+      }
+    }
+  }
+}
+
+/// Formats the differences as a human-readable changelog
+pub fn format_dataset_diff(diff: &DatasetDiff) -> String {
+  let tag = |tag: &Option<String>| tag.as_deref().unwrap_or("unknown").to_owned();
+  let mut s = format!(
+    "Changes between dataset versions '{}' and '{}':\n",
+    tag(&diff.old_tag),
+    tag(&diff.new_tag)
+  );
+
+  if diff.is_empty() {
+    s += "\nNo changes found\n";
+    return s;
+  }
+
+  let reference = diff
+    .reference
+    .iter()
+    .map(|change| {
+      let summary = |summary: &Option<DatasetReferenceSummary>| {
+        summary
+          .as_ref()
+          .map_or_else(|| "none".to_owned(), |s| format!("'{}' ({} nt)", s.name, s.length))
+      };
+      let differences = change
+        .num_nuc_differences
+        .map(|n| format!(", {n} nucleotide difference(s)"))
+        .unwrap_or_default();
+      format!("  ~ {} -> {}{differences}", summary(&change.old), summary(&change.new))
+    })
+    .collect_vec();
+
+  let genes = diff
+    .genes_added
+    .iter()
+    .map(|gene| format!("  + {gene}"))
+    .chain(diff.genes_removed.iter().map(|gene| format!("  - {gene}")))
+    .chain(
+      diff
+        .genes_changed
+        .iter()
+        .map(|gene| format!("  ~ {gene} (coordinates changed)")),
+    )
+    .collect_vec();
+
+  let clades = diff
+    .clades_added
+    .iter()
+    .map(|clade| format!("  + {clade}"))
+    .chain(diff.clades_removed.iter().map(|clade| format!("  - {clade}")))
+    .collect_vec();
+
+  let sections = [
+    ("Reference sequence", reference),
+    ("Genes", genes),
+    ("Clades", clades),
+    ("QC configuration", format_value_changes(&diff.qc_config)),
+    ("Alignment parameters", format_value_changes(&diff.alignment_params)),
+    ("Mutation label maps", format_value_changes(&diff.label_maps)),
+  ];
+
+  for (title, lines) in sections {
+    if !lines.is_empty() {
+      writeln!(s, "\n{title}:\n{}", lines.join("\n")).unwrap();
+    }
+  }
+
+  s
+}
+
+fn format_value_changes(changes: &[DatasetValueChange]) -> Vec<String> {
+  let value = |value: &Value| serde_json::to_string(value).unwrap_or_default();
+  changes
+    .iter()
+    .map(|DatasetValueChange { key, old, new }| match (old, new) {
+      (Some(old), Some(new)) => format!("  ~ {key}: {} -> {}", value(old), value(new)),
+      (None, Some(new)) => format!("  + {key}: {}", value(new)),
+      (Some(old), None) => format!("  - {key}: {}", value(old)),
+      (None, None) => format!("  ~ {key}"),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use serde_json::json;
+
+  const TREE: &str = r#"{
+    "meta": { "display_defaults": {} },
+    "tree": {
+      "name": "root",
+      "branch_attrs": { "mutations": {} },
+      "node_attrs": { "clade_membership": { "value": "A" } },
+      "children": [
+        {
+          "name": "child",
+          "branch_attrs": { "mutations": {} },
+          "node_attrs": { "clade_membership": { "value": "{CLADE}" } }
+        }
+      ]
+    }
+  }"#;
+
+  fn files(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+    entries
+      .iter()
+      .map(|(filename, content)| ((*filename).to_owned(), (*content).to_owned()))
+      .collect()
+  }
+
+  #[rstest]
+  fn finds_differences_between_dataset_versions() -> Result<(), Report> {
+    let old = files(&[
+      ("reference.fasta", ">ref\nATGTAAATG\n"),
+      (
+        "genemap.gff",
+        "##gff-version 3\nref\t.\tgene\t1\t6\t.\t+\t.\tgene_name=g\n",
+      ),
+      ("tree.json", &TREE.replace("{CLADE}", "B")),
+      (
+        "qc.json",
+        r#"{ "schemaVersion": "1.2.0", "privateMutations": { "cutoff": 24.0 } }"#,
+      ),
+      (
+        "virus_properties.json",
+        r#"{ "schemaVersion": "1.10.0", "nucMutLabelMap": { "A3G": ["X"], "T4C": ["Y"] } }"#,
+      ),
+    ]);
+
+    let new = files(&[
+      ("reference.fasta", ">ref\nATGTAAATC\n"),
+      (
+        "genemap.gff",
+        "##gff-version 3\nref\t.\tgene\t1\t9\t.\t+\t.\tgene_name=g\nref\t.\tgene\t4\t9\t.\t+\t.\tgene_name=h\n",
+      ),
+      ("tree.json", &TREE.replace("{CLADE}", "C")),
+      (
+        "qc.json",
+        r#"{ "schemaVersion": "1.2.0", "privateMutations": { "cutoff": 30.0 } }"#,
+      ),
+      (
+        "virus_properties.json",
+        r#"{ "schemaVersion": "1.10.0", "nucMutLabelMap": { "A3G": ["X", "Z"] } }"#,
+      ),
+    ]);
+
+    let diff = dataset_diff_files(&old, &new)?;
+
+    assert_eq!(
+      diff.reference,
+      vec![DatasetReferenceChange {
+        old: Some(DatasetReferenceSummary {
+          name: "ref".to_owned(),
+          length: 9
+        }),
+        new: Some(DatasetReferenceSummary {
+          name: "ref".to_owned(),
+          length: 9
+        }),
+        num_nuc_differences: Some(1),
+      }]
+    );
+    assert_eq!(diff.genes_added, vec!["h"]);
+    assert_eq!(diff.genes_removed, Vec::<String>::new());
+    assert_eq!(diff.genes_changed, vec!["g"]);
+    assert_eq!(diff.clades_added, vec!["C"]);
+    assert_eq!(diff.clades_removed, vec!["B"]);
+    assert_eq!(
+      diff.qc_config,
+      vec![DatasetValueChange {
+        key: "privateMutations.cutoff".to_owned(),
+        old: Some(json!(24.0)),
+        new: Some(json!(30.0)),
+      }]
+    );
+    assert!(diff.alignment_params.is_empty());
+    assert_eq!(
+      diff.label_maps,
+      vec![
+        DatasetValueChange {
+          key: "A3G".to_owned(),
+          old: Some(json!(["X"])),
+          new: Some(json!(["X", "Z"])),
+        },
+        DatasetValueChange {
+          key: "T4C".to_owned(),
+          old: Some(json!(["Y"])),
+          new: None,
+        },
+      ]
+    );
+
+    assert!(dataset_diff_files(&old, &old)?.is_empty());
+    Ok(())
+  }
+}
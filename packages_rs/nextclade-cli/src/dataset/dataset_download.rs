@@ -1,24 +1,30 @@
 use crate::cli::nextclade_cli::NextcladeRunArgs;
 use crate::cli::nextclade_dataset_get::{dataset_file_http_get, nextclade_dataset_http_get, DatasetHttpGetParams};
 use crate::dataset::dataset::Dataset;
+use crate::dataset::dataset_cache::{parse_dataset_name_with_tag, DatasetCache};
 use crate::io::http_client::{HttpClient, ProxyConfig};
 use eyre::{Report, WrapErr};
 use itertools::Itertools;
-use log::LevelFilter;
+use log::{info, warn, LevelFilter};
 use nextclade::analyze::pcr_primers::PcrPrimer;
 use nextclade::analyze::virus_properties::VirusProperties;
-use nextclade::io::fasta::{read_one_fasta, read_one_fasta_str, FastaRecord};
-use nextclade::io::fs::absolute_path;
-use nextclade::io::gene_map::{filter_gene_map, GeneMap};
-use nextclade::io::gff3::{read_gff3_file, read_gff3_str};
+use nextclade::io::fasta::{read_many_fasta_str, FastaRecord};
+use nextclade::io::fs::{absolute_path, read_file_to_string};
+use nextclade::io::gene_map::{
+  filter_gene_map, read_gene_map_str, read_gene_map_str_segmented, GeneMap, GeneMapFormat,
+};
+use nextclade::io::nuc::to_nuc_seq;
+use nextclade::io::nwk_reader::nwk_parse;
+use nextclade::io::usher_mat::is_usher_mat_filepath;
 use nextclade::make_error;
 use nextclade::qc::qc_config::QcConfig;
 use nextclade::tree::tree::AuspiceTree;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::collections::BTreeSet;
 use std::fs;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use zip::ZipArchive;
 
@@ -58,6 +64,50 @@ pub struct DatasetFiles {
   pub gene_map: GeneMap,
   pub qc_config: QcConfig,
   pub primers: Vec<PcrPrimer>,
+  /// Name of the genome segment these files belong to. Only present for datasets of segmented genomes.
+  pub segment: Option<String>,
+}
+
+/// Reads files of a dataset stored either in a directory or in a zip archive
+pub enum DatasetFilesReader {
+  Dir(PathBuf),
+  Zip(ZipArchive<BufReader<File>>),
+}
+
+impl DatasetFilesReader {
+  pub fn open(input_dataset: impl AsRef<Path>) -> Result<Self, Report> {
+    let input_dataset = input_dataset.as_ref();
+    if input_dataset.is_dir() {
+      return Ok(Self::Dir(input_dataset.to_owned()));
+    }
+
+    let file = File::open(input_dataset).wrap_err_with(|| format!("When opening dataset file {input_dataset:#?}"))?;
+    let zip = ZipArchive::new(BufReader::new(file))
+      .wrap_err_with(|| format!("When reading dataset zip file {input_dataset:#?}"))?;
+    Ok(Self::Zip(zip))
+  }
+
+  /// Lists names of all files of the dataset
+  pub fn filenames(&self) -> Result<BTreeSet<String>, Report> {
+    match self {
+      Self::Dir(dir) => Ok(
+        fs::read_dir(dir)
+          .wrap_err_with(|| format!("When reading dataset directory {dir:#?}"))?
+          .filter_map(Result::ok)
+          .filter(|entry| entry.path().is_file())
+          .map(|entry| entry.file_name().to_string_lossy().to_string())
+          .collect(),
+      ),
+      Self::Zip(zip) => Ok(zip.file_names().map(str::to_owned).collect()),
+    }
+  }
+
+  pub fn read_str(&mut self, filename: &str) -> Result<String, Report> {
+    match self {
+      Self::Dir(dir) => read_file_to_string(dir.join(filename)),
+      Self::Zip(zip) => zip_read_str(zip, filename),
+    }
+  }
 }
 
 pub fn zip_read_str<R: Read + Seek>(zip: &mut ZipArchive<R>, name: &str) -> Result<String, Report> {
@@ -70,72 +120,29 @@ pub fn dataset_zip_load(
   run_args: &NextcladeRunArgs,
   dataset_zip: impl AsRef<Path>,
   genes: &Option<Vec<String>>,
-) -> Result<DatasetFiles, Report> {
+) -> Result<Vec<DatasetFiles>, Report> {
   let file = File::open(dataset_zip)?;
   let buf_file = BufReader::new(file);
   let mut zip = ZipArchive::new(buf_file)?;
 
-  let ref_record = run_args.inputs.input_ref.as_ref().map_or_else(
-    || read_one_fasta_str(&zip_read_str(&mut zip, "reference.fasta")?),
-    read_one_fasta,
-  )?;
-
-  let tree = run_args.inputs.input_tree.as_ref().map_or_else(
-    || AuspiceTree::from_str(&zip_read_str(&mut zip, "tree.json")?),
-    AuspiceTree::from_path,
-  )?;
-
-  let qc_config = run_args.inputs.input_qc_config.as_ref().map_or_else(
-    || QcConfig::from_str(&zip_read_str(&mut zip, "qc.json")?),
-    QcConfig::from_path,
-  )?;
-
-  let virus_properties = run_args.inputs.input_virus_properties.as_ref().map_or_else(
-    || VirusProperties::from_str(&zip_read_str(&mut zip, "virus_properties.json")?),
-    VirusProperties::from_path,
-  )?;
-
-  let primers = run_args.inputs.input_pcr_primers.as_ref().map_or_else(
-    || PcrPrimer::from_str(&zip_read_str(&mut zip, "primers.csv")?, &ref_record.seq),
-    |input_pcr_primers| PcrPrimer::from_path(input_pcr_primers, &ref_record.seq),
-  )?;
-
-  let gene_map = run_args.inputs.input_gene_map.as_ref().map_or_else(
-    || filter_gene_map(Some(read_gff3_str(&zip_read_str(&mut zip, "genemap.gff")?)?), genes),
-    |input_gene_map| filter_gene_map(Some(read_gff3_file(&input_gene_map)?), genes),
-  )?;
-
-  Ok(DatasetFiles {
-    ref_record,
-    virus_properties,
-    tree,
-    gene_map,
-    qc_config,
-    primers,
-  })
+  dataset_load_files(run_args, genes, |filename| zip_read_str(&mut zip, filename))
 }
 
-#[rustfmt::skip]
 pub fn dataset_dir_load(
-  run_args: NextcladeRunArgs,
+  run_args: &NextcladeRunArgs,
   dataset_dir: impl AsRef<Path>,
   genes: &Option<Vec<String>>,
-) -> Result<DatasetFiles, Report> {
+) -> Result<Vec<DatasetFiles>, Report> {
   let input_dataset = dataset_dir.as_ref();
-  dataset_load_files(DatasetFilePaths {
-    input_ref: &run_args.inputs.input_ref.unwrap_or_else(|| input_dataset.join("reference.fasta")),
-    input_tree: &run_args.inputs.input_tree.unwrap_or_else(|| input_dataset.join("tree.json")),
-    input_qc_config: &run_args.inputs.input_qc_config.unwrap_or_else(|| input_dataset.join("qc.json")),
-    input_virus_properties: &run_args.inputs.input_virus_properties.unwrap_or_else(|| input_dataset.join("virus_properties.json")),
-    input_pcr_primers: &run_args.inputs.input_pcr_primers.unwrap_or_else(|| input_dataset.join("primers.csv")),
-    input_gene_map: &run_args.inputs.input_gene_map.unwrap_or_else(|| input_dataset.join("genemap.gff")),
-  }, genes)
+  dataset_load_files(run_args, genes, |filename| {
+    read_file_to_string(input_dataset.join(filename))
+  })
 }
 
 pub fn dataset_individual_files_load(
   run_args: &NextcladeRunArgs,
   genes: &Option<Vec<String>>,
-) -> Result<DatasetFiles, Report> {
+) -> Result<Vec<DatasetFiles>, Report> {
   #[rustfmt::skip]
   let required_args = &[
     (String::from("--input-ref"), &run_args.inputs.input_ref),
@@ -146,148 +153,281 @@ pub fn dataset_individual_files_load(
     (String::from("--input-virus-properties"), &run_args.inputs.input_virus_properties),
   ];
 
-  #[allow(clippy::single_match_else)]
-  match required_args {
-    #[rustfmt::skip]
-    [
-      (_, Some(input_ref)),
-      (_, Some(input_tree)),
-      (_, Some(input_gene_map)),
-      (_, Some(input_qc_config)),
-      (_, Some(input_pcr_primers)),
-      (_, Some(input_virus_properties)),
-    ] => {
-      dataset_load_files(DatasetFilePaths {
-        input_ref,
-        input_tree,
-        input_qc_config,
-        input_virus_properties,
-        input_pcr_primers,
-        input_gene_map,
-      }, genes)
-    },
+  let missing_args = required_args
+    .iter()
+    .filter_map(|(key, val)| match val {
+      None => Some(key),
+      Some(_) => None,
+    })
+    .cloned()
+    .join("  \n");
+
+  if !missing_args.is_empty() {
+    return make_error!(
+      "When `--input-dataset` is not specified, the following arguments are required:\n{missing_args}"
+    );
+  }
+
+  dataset_load_files(run_args, genes, |filename| {
+    make_error!("Dataset file '{filename}' is required, but `--input-dataset` is not specified")
+  })
+}
+
+/// Loads dataset files. Each file is read from the path given in the corresponding `--input-*` argument, if present,
+/// or otherwise from the dataset itself, using the provided function which reads a dataset file by its name.
+///
+/// If the reference sequence file contains more than one sequence, then the dataset is a dataset of a segmented genome.
+/// Each of the reference sequences is then a separate segment, named after the first word of the sequence name, and the
+/// result contains files for each segment. Genes of a segment are the GFF3 records with `seqid` equal to the segment
+/// name, and the reference tree of a segment is read from file `tree_<segment>.json`.
+pub fn dataset_load_files(
+  run_args: &NextcladeRunArgs,
+  genes: &Option<Vec<String>>,
+  mut read_dataset_file: impl FnMut(&str) -> Result<String, Report>,
+) -> Result<Vec<DatasetFiles>, Report> {
+  let inputs = &run_args.inputs;
+
+  let mut read_file = |input: &Option<PathBuf>, filename: &str| -> Result<String, Report> {
+    match input {
+      Some(input) => read_file_to_string(input),
+      None => read_dataset_file(filename).wrap_err_with(|| format!("When reading dataset file '{filename}'")),
+    }
+  };
+
+  let ref_records = read_many_fasta_str(&read_file(&inputs.input_ref, "reference.fasta")?)
+    .wrap_err("When reading reference sequence")?;
+
+  let virus_properties =
+    VirusProperties::from_str(&read_file(&inputs.input_virus_properties, "virus_properties.json")?)
+      .wrap_err("When reading virus properties")?;
+
+  let qc_config =
+    QcConfig::from_str(&read_file(&inputs.input_qc_config, "qc.json")?).wrap_err("When reading QC configuration")?;
+
+  let primers_str = read_file(&inputs.input_pcr_primers, "primers.csv")?;
+  let gene_map_str = read_file(&inputs.input_gene_map, "genemap.gff")?;
+  let gene_map_format = GeneMapFormat::guess(inputs.input_gene_map.as_deref(), &gene_map_str);
+
+  match ref_records.as_slice() {
+    [] => make_error!("Reference sequence file contains no sequences"),
+    [ref_record] => {
+      let gene_map = filter_gene_map(Some(read_gene_map_str(&gene_map_str, gene_map_format)?), genes)?;
+
+      let tree = match &inputs.input_tree {
+        Some(input_tree) => read_ref_tree_file(
+          input_tree,
+          &inputs.input_node_data,
+          &inputs.input_tree_alignment,
+          ref_record,
+          &gene_map,
+          &virus_properties,
+        ),
+        None => read_ref_tree(
+          &read_file(&None, "tree.json")?,
+          &inputs.input_node_data,
+          &inputs.input_tree_alignment,
+          ref_record,
+          &gene_map,
+          &virus_properties,
+        ),
+      }
+      .wrap_err("When reading reference tree")?;
+
+      let primers = PcrPrimer::from_str(&primers_str, &ref_record.seq).wrap_err("When reading PCR primers")?;
+
+      Ok(vec![DatasetFiles {
+        ref_record: ref_record.clone(),
+        virus_properties,
+        tree,
+        gene_map,
+        qc_config,
+        primers,
+        segment: None,
+      }])
+    }
     _ => {
-      let missing_args = required_args
+      if inputs.input_tree.is_some() || !inputs.input_node_data.is_empty() || inputs.input_tree_alignment.is_some() {
+        return make_error!(
+          "The `--input-tree`, `--input-node-data` and `--input-tree-alignment` arguments cannot be used with datasets of segmented genomes, because each segment has its own reference tree. Trees of segments are read from dataset files `tree_<segment>.json`."
+        );
+      }
+
+      let segments = ref_records
+        .iter()
+        .map(|ref_record| get_segment_name(&ref_record.seq_name))
+        .collect_vec();
+
+      if let Some(duplicate) = segments.iter().duplicates().next() {
+        return make_error!("Reference sequence file contains more than one segment named '{duplicate}'");
+      }
+
+      let mut gene_maps = read_gene_map_str_segmented(&gene_map_str, gene_map_format)?;
+      if let Some(unknown) = gene_maps.keys().find(|seqid| !segments.contains(seqid)) {
+        return make_error!(
+          "Genome annotation contains genes of sequence '{unknown}', but there is no reference segment with this name. The `seqid` column of the genome annotation (for GenBank files, the `VERSION` of the record) should contain one of the segment names: {}",
+          segments.join(", ")
+        );
+      }
+
+      if let Some(genes) = genes {
+        let genes_not_found = genes
+          .iter()
+          .filter(|gene| gene_maps.values().all(|gene_map| !gene_map.contains_key(*gene)))
+          .join("`, `");
+        if !genes_not_found.is_empty() {
+          warn!(
+            "The following genes were requested through `--genes` but not found in the gene map: `{genes_not_found}`"
+          );
+        }
+      }
+
+      let ref_seqs = ref_records
+        .iter()
+        .map(|ref_record| ref_record.seq.as_str())
+        .collect_vec();
+      let primers = PcrPrimer::from_str_segmented(&primers_str, &ref_seqs).wrap_err("When reading PCR primers")?;
+
+      ref_records
         .iter()
-        .filter_map(|(key, val)| match val {
-          None => Some(key),
-          Some(_) => None,
+        .zip(segments)
+        .zip(primers)
+        .map(|((ref_record, segment), primers)| {
+          let tree = AuspiceTree::from_str(&read_file(&None, &format!("tree_{segment}.json"))?)
+            .wrap_err_with(|| format!("When reading reference tree of segment '{segment}'"))?;
+
+          let gene_map: GeneMap = gene_maps
+            .remove(&segment)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(gene_name, _)| genes.as_ref().map_or(true, |genes| genes.contains(gene_name)))
+            .collect();
+
+          Ok(DatasetFiles {
+            ref_record: ref_record.clone(),
+            virus_properties: virus_properties.clone(),
+            tree,
+            gene_map,
+            qc_config: qc_config.clone(),
+            primers,
+            segment: Some(segment),
+          })
         })
-        .cloned()
-        .join("  \n");
+        .collect()
+    }
+  }
+}
+
+/// Reads reference tree, either in Auspice JSON or in Newick format. A Newick tree is converted into Auspice tree using
+/// node data JSON files (`--input-node-data`), or using the alignment of its tips (`--input-tree-alignment`), from
+/// which the mutations on the branches are reconstructed.
+pub fn read_ref_tree(
+  tree_str: &str,
+  input_node_data: &[PathBuf],
+  input_tree_alignment: &Option<PathBuf>,
+  ref_record: &FastaRecord,
+  gene_map: &GeneMap,
+  virus_properties: &VirusProperties,
+) -> Result<AuspiceTree, Report> {
+  // Auspice JSON is an object, while Newick always starts with either a parenthesis or a node name
+  if tree_str.trim_start().starts_with('{') {
+    if !input_node_data.is_empty() || input_tree_alignment.is_some() {
+      return make_error!("The `--input-node-data` and `--input-tree-alignment` arguments can only be used with a reference tree in Newick format, but the reference tree is in Auspice JSON format.");
+    }
+    return AuspiceTree::from_str(tree_str);
+  }
 
-      make_error!("When `--input-dataset` is not specified, the following arguments are required:\n{missing_args}")
+  let nwk = nwk_parse(tree_str).wrap_err("When parsing reference tree in Newick format")?;
+
+  match (input_node_data.as_slice(), input_tree_alignment) {
+    ([], None) => make_error!("The reference tree is in Newick format, which contains no mutations. Either node data JSON files (`--input-node-data`) or an alignment of the sequences of the tree tips (`--input-tree-alignment`) are required to build the reference tree from it."),
+    (node_data_paths, None) => AuspiceTree::from_nwk_and_node_data_paths(&nwk, node_data_paths),
+    ([], Some(tree_alignment)) => {
+      let ref_seq = to_nuc_seq(&ref_record.seq).wrap_err("When reading reference sequence")?;
+      AuspiceTree::from_nwk_and_alignment_path(
+        &nwk,
+        tree_alignment,
+        &ref_seq,
+        gene_map,
+        virus_properties.genetic_code()?,
+      )
     }
+    _ => make_error!("The `--input-node-data` and `--input-tree-alignment` arguments are mutually exclusive."),
   }
 }
 
-pub struct DatasetFilePaths<'a> {
-  input_ref: &'a Path,
-  input_tree: &'a Path,
-  input_qc_config: &'a Path,
-  input_virus_properties: &'a Path,
-  input_pcr_primers: &'a Path,
-  input_gene_map: &'a Path,
+/// Reads reference tree from a file. Files with `.pb` extension are read as UShER mutation-annotated trees (MAT), other
+/// files are read as described in `read_ref_tree`.
+pub fn read_ref_tree_file(
+  filepath: &Path,
+  input_node_data: &[PathBuf],
+  input_tree_alignment: &Option<PathBuf>,
+  ref_record: &FastaRecord,
+  gene_map: &GeneMap,
+  virus_properties: &VirusProperties,
+) -> Result<AuspiceTree, Report> {
+  if !is_usher_mat_filepath(filepath) {
+    return read_ref_tree(
+      &read_file_to_string(filepath)?,
+      input_node_data,
+      input_tree_alignment,
+      ref_record,
+      gene_map,
+      virus_properties,
+    );
+  }
+
+  if !input_node_data.is_empty() || input_tree_alignment.is_some() {
+    return make_error!("The `--input-node-data` and `--input-tree-alignment` arguments can only be used with a reference tree in Newick format, but the reference tree is an UShER mutation-annotated tree, which already contains mutations.");
+  }
+  let ref_seq = to_nuc_seq(&ref_record.seq).wrap_err("When reading reference sequence")?;
+  AuspiceTree::from_usher_mat_path(filepath, &ref_seq, gene_map, virus_properties.genetic_code()?)
 }
 
-pub fn dataset_load_files(
-  DatasetFilePaths {
-    input_ref,
-    input_tree,
-    input_qc_config,
-    input_virus_properties,
-    input_pcr_primers,
-    input_gene_map,
-  }: DatasetFilePaths,
-  genes: &Option<Vec<String>>,
-) -> Result<DatasetFiles, Report> {
-  let ref_record = read_one_fasta(input_ref)?;
-  let primers = PcrPrimer::from_path(input_pcr_primers, &ref_record.seq)?;
-
-  Ok(DatasetFiles {
-    ref_record,
-    virus_properties: VirusProperties::from_path(input_virus_properties)?,
-    gene_map: filter_gene_map(Some(read_gff3_file(&input_gene_map)?), genes)?,
-    tree: AuspiceTree::from_path(input_tree)?,
-    qc_config: QcConfig::from_path(input_qc_config)?,
-    primers,
-  })
+/// Segment name is the first word of the name of the segment's reference sequence
+pub fn get_segment_name(seq_name: &str) -> String {
+  seq_name.split_whitespace().next().unwrap_or_default().to_owned()
 }
 
 pub fn dataset_str_download_and_load(
   run_args: &NextcladeRunArgs,
   dataset_name: &str,
   genes: &Option<Vec<String>>,
-) -> Result<DatasetFiles, Report> {
+) -> Result<Vec<DatasetFiles>, Report> {
+  let (name, tag) = parse_dataset_name_with_tag(dataset_name);
+
+  let cache = run_args.inputs.cache_dir.as_ref().map(DatasetCache::new).transpose()?;
+  if let Some(cache) = &cache {
+    if let Some(entry) = cache.find(name, tag)? {
+      info!(
+        "Using dataset '{name}' (tag '{}') from the cache directory {:#?}",
+        entry.dataset().attributes.tag.value,
+        entry.path
+      );
+      entry.verify()?;
+      return dataset_dir_load(run_args, &entry.path, genes);
+    }
+  }
+
   let verbose = log::max_level() > LevelFilter::Info;
   let mut http = HttpClient::new(&run_args.inputs.server, &ProxyConfig::default(), verbose)?;
 
-  let name = run_args
-    .inputs
-    .dataset_name
-    .as_ref()
-    .expect("Dataset name is expected, but got 'None'");
-
   let dataset = nextclade_dataset_http_get(
     &mut http,
     DatasetHttpGetParams {
       name,
       reference: "default",
-      tag: "latest",
+      tag: tag.unwrap_or("latest"),
     },
     &[],
   )?;
 
-  let ref_record = run_args.inputs.input_ref.as_ref().map_or_else(
-    || read_one_fasta_str(&dataset_file_http_get(&mut http, &dataset, "reference.fasta")?),
-    read_one_fasta,
-  )?;
-
-  let tree = run_args.inputs.input_tree.as_ref().map_or_else(
-    || AuspiceTree::from_str(&dataset_file_http_get(&mut http, &dataset, "tree.json")?),
-    AuspiceTree::from_path,
-  )?;
-
-  let qc_config = run_args.inputs.input_qc_config.as_ref().map_or_else(
-    || QcConfig::from_str(&dataset_file_http_get(&mut http, &dataset, "qc.json")?),
-    QcConfig::from_path,
-  )?;
-
-  let virus_properties = run_args.inputs.input_virus_properties.as_ref().map_or_else(
-    || VirusProperties::from_str(&dataset_file_http_get(&mut http, &dataset, "virus_properties.json")?),
-    VirusProperties::from_path,
-  )?;
-
-  let primers = run_args.inputs.input_pcr_primers.as_ref().map_or_else(
-    || {
-      PcrPrimer::from_str(
-        &dataset_file_http_get(&mut http, &dataset, "primers.csv")?,
-        &ref_record.seq,
-      )
-    },
-    |input_pcr_primers| PcrPrimer::from_path(input_pcr_primers, &ref_record.seq),
-  )?;
-
-  let gene_map = run_args.inputs.input_gene_map.as_ref().map_or_else(
-    || {
-      filter_gene_map(
-        Some(read_gff3_str(&dataset_file_http_get(
-          &mut http,
-          &dataset,
-          "genemap.gff",
-        )?)?),
-        genes,
-      )
-    },
-    |input_gene_map| filter_gene_map(Some(read_gff3_file(&input_gene_map)?), genes),
-  )?;
+  // Store the downloaded dataset in the cache, so that the next runs don't need to download it again
+  if let Some(cache) = &cache {
+    let entry = cache.download(&mut http, &dataset)?;
+    return dataset_dir_load(run_args, &entry.path, genes);
+  }
 
-  Ok(DatasetFiles {
-    ref_record,
-    virus_properties,
-    tree,
-    gene_map,
-    qc_config,
-    primers,
+  dataset_load_files(run_args, genes, |filename| {
+    dataset_file_http_get(&mut http, &dataset, filename)
   })
 }
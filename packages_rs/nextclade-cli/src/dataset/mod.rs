@@ -1,4 +1,7 @@
 pub mod dataset;
 pub mod dataset_attributes;
+pub mod dataset_cache;
+pub mod dataset_check;
+pub mod dataset_diff;
 pub mod dataset_download;
 pub mod dataset_table;
@@ -1,9 +1,11 @@
 use clap::{Parser, ValueHint};
-use eyre::Report;
+use eyre::{eyre, Report, WrapErr};
 use log::info;
+use nextclade::io::fs::absolute_path;
 use nextclade::{getenv, make_internal_error};
 use reqwest::blocking::Client;
 use reqwest::{IntoUrl, Method, Proxy};
+use std::fs;
 use url::Url;
 
 #[derive(Parser, Debug, Default)]
@@ -90,10 +92,60 @@ impl HttpClient {
     self.request(Method::HEAD, url)
   }
 
+  /// Same as `get()`, but fails if the server responds with an error status, instead of returning the error page.
+  /// Used where the response is persisted, e.g. when storing datasets in the cache.
+  pub fn get_checked<U: IntoUrl + ?Sized>(&self, url: &U) -> Result<Vec<u8>, Report> {
+    self.request_impl(Method::GET, url, true)
+  }
+
   pub fn request<U: IntoUrl + ?Sized>(&self, method: Method, url: &U) -> Result<Vec<u8>, Report> {
-    let abs_url = self.root.join(url.as_str())?;
+    self.request_impl(method, url, false)
+  }
+
+  fn request_impl<U: IntoUrl + ?Sized>(&self, method: Method, url: &U, check_status: bool) -> Result<Vec<u8>, Report> {
+    let abs_url = self.resolve_url(url)?;
+
+    // Datasets can be served from a local directory, for example on machines without internet access
+    if abs_url.scheme() == "file" {
+      info!("Reading local file '{abs_url}'");
+      let filepath = abs_url
+        .to_file_path()
+        .map_err(|_| eyre!("Unable to convert URL to a file path: '{abs_url}'"))?;
+      return fs::read(&filepath).wrap_err_with(|| format!("When reading file {filepath:#?}"));
+    }
+
     info!("HTTP '{method}' request to '{abs_url}'");
-    let content = self.client.request(method, abs_url).send()?.bytes()?.to_vec();
+    let response = self.client.request(method, abs_url).send()?;
+    let response = if check_status {
+      response.error_for_status()?
+    } else {
+      response
+    };
+    let content = response.bytes()?.to_vec();
     Ok(content)
   }
+
+  /// Resolves URL relative to the root URL. If the root is a local directory (a `file://` URL), absolute paths (e.g.
+  /// `/index_v2.json`) are resolved relative to this directory too, as they are relative to the root of a dataset
+  /// server.
+  fn resolve_url<U: IntoUrl + ?Sized>(&self, url: &U) -> Result<Url, Report> {
+    if self.root.scheme() != "file" {
+      return Ok(self.root.join(url.as_str())?);
+    }
+
+    let mut root = self.root.clone();
+    if !root.path().ends_with('/') {
+      root.set_path(&format!("{}/", root.path()));
+    }
+    Ok(root.join(url.as_str().trim_start_matches('/'))?)
+  }
+}
+
+/// Parses address of a dataset server: either a URL (including a `file://` URL) or a path to a local directory
+pub fn parse_server_url(s: &str) -> Result<Url, Report> {
+  if let Ok(url) = Url::parse(s) {
+    return Ok(url);
+  }
+  let path = absolute_path(s)?;
+  Url::from_directory_path(&path).map_err(|_| eyre!("Unable to convert path to a URL: {path:#?}"))
 }
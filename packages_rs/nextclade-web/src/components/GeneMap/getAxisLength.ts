@@ -1,5 +1,5 @@
 import type { Gene } from 'src/types'
-import { geneLength } from 'src/types'
+import { geneCodingLength } from 'src/types'
 import { GENE_OPTION_NUC_SEQUENCE } from 'src/constants'
 
 /** Retrieves length of the axis to draw: Genome size in case of nuc sequence, or gene length on case of gene */
@@ -8,7 +8,7 @@ export function getAxisLength(genomeSize: number, viewedGene: string, geneMap: G
   if (viewedGene !== GENE_OPTION_NUC_SEQUENCE) {
     const gene = geneMap?.find((gene) => gene.geneName === viewedGene)
     if (gene) {
-      length = Math.round(geneLength(gene) / 3)
+      length = Math.round(geneCodingLength(gene) / 3)
     }
   }
   return length
@@ -17,7 +17,8 @@ export function ColumnQCStatus({ analysisResult }: ColumnQCStatusProps) {
   const onMouseLeave = useCallback(() => setShowTooltip(false), [])
 
   const { index, seqName, qc } = analysisResult
-  const { missingData, privateMutations, mixedSites, snpClusters, frameShifts, stopCodons } = qc
+  const { missingData, privateMutations, mixedSites, snpClusters, frameShifts, stopCodons, recombinants, inversions } =
+    qc
 
   const id = getSafeId('qc-label', { index, seqName })
 
@@ -28,6 +29,8 @@ export function ColumnQCStatus({ analysisResult }: ColumnQCStatusProps) {
     { value: snpClusters, name: 'C' },
     { value: frameShifts, name: 'F' },
     { value: stopCodons, name: 'S' },
+    { value: recombinants, name: 'R' },
+    { value: inversions, name: 'I' },
   ].filter((value) => notUndefined(value))
 
   const icons = rules.map(({ name, value }, i) => {
@@ -7,6 +7,7 @@ import { get } from 'lodash'
 
 import { geneMapAtom } from 'src/state/results.state'
 import type { AnalysisResult, Gene, PeptideWarning, Range } from 'src/types'
+import { geneCodingLength } from 'src/types'
 import { useTranslationSafe } from 'src/helpers/useTranslationSafe'
 import { getSafeId } from 'src/helpers/getSafeId'
 import { WarningIcon } from 'src/components/Results/getStatusIconAndText'
@@ -102,7 +103,7 @@ export function PeptideViewUnsized({ width, sequence, warnings, viewedGene }: Pe
   }
 
   const { index, seqName, unknownAaRanges, frameShifts, aaChangesGroups, aaInsertions, aaAlignmentRanges } = sequence
-  const geneLength = (gene.end - gene.start) / 3
+  const geneLength = geneCodingLength(gene) / 3
   const pixelsPerAa = width / Math.round(geneLength)
   const groups = aaChangesGroups.filter((group) => group.gene === viewedGene)
 
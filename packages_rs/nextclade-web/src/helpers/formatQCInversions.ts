@@ -0,0 +1,25 @@
+import type { QcResultInversions } from 'src/types'
+import type { TFunctionInterface } from 'src/helpers/TFunctionInterface'
+import { QcStatus } from 'src/types'
+
+export function formatQCInversions<TFunction extends TFunctionInterface>(
+  t: TFunction,
+  qcInversions?: QcResultInversions,
+) {
+  if (!qcInversions || qcInversions.status === QcStatus.good) {
+    return undefined
+  }
+
+  const { score, inversions, totalInversions } = qcInversions
+
+  const inversionList = inversions.map(({ refRange }) => `${refRange.begin + 1}-${refRange.end}`).join(', ')
+
+  return t(
+    '{{totalInversions}} inverted region(s) detected: {{inversionList}}. These regions were reverse-complemented before alignment. QC score: {{score}}',
+    {
+      totalInversions,
+      inversionList,
+      score,
+    },
+  )
+}
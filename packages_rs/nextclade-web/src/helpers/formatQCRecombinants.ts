@@ -0,0 +1,29 @@
+import type { QcResultRecombinants } from 'src/types'
+import type { TFunctionInterface } from 'src/helpers/TFunctionInterface'
+import { QcStatus } from 'src/types'
+
+export function formatQCRecombinants<TFunction extends TFunctionInterface>(
+  t: TFunction,
+  qcRecombinants?: QcResultRecombinants,
+) {
+  if (!qcRecombinants || qcRecombinants.status === QcStatus.good) {
+    return undefined
+  }
+
+  const { score, breakpoints, totalBreakpoints, parentalClades } = qcRecombinants
+
+  const cladeList = parentalClades.join(', ')
+  const breakpointList = breakpoints
+    .map(({ leftClade, rightClade, range }) => `${leftClade}/${rightClade}: ${range.begin + 1}-${range.end}`)
+    .join(', ')
+
+  return t(
+    'Possible recombinant of clades {{cladeList}}. {{totalBreakpoints}} breakpoint(s) detected: {{breakpointList}}. QC score: {{score}}',
+    {
+      cladeList,
+      totalBreakpoints,
+      breakpointList,
+      score,
+    },
+  )
+}
@@ -51,6 +51,40 @@ export interface AminoacidInsertion {
   ins: string
 }
 
+export interface AminoacidAmbiguity {
+  gene: string
+  refAA: Aminoacid
+  codon: number
+  queryAAs: Aminoacid[]
+  queryCodon: string
+  codonNucRange: Range
+}
+
+export interface MixedSite {
+  refNuc: Nucleotide
+  pos: number
+  queryNuc: Nucleotide
+  alleles: Nucleotide[]
+  nearestNodeNuc: Nucleotide
+  otherClades: string[]
+}
+
+export interface CoinfectionCladePair {
+  clade: string
+  otherClade: string
+  supportingSites: number
+  informativeSites: number
+  score: number
+}
+
+export interface MixedSites {
+  sites: MixedSite[]
+  totalMixedSites: number
+  totalCoinfectionSites: number
+  coinfectionScore: number
+  coinfectionCladePairs: CoinfectionCladePair[]
+}
+
 export interface NucleotideMissing extends Range {}
 
 export interface CharacterRange<Letter> extends Range {
@@ -261,6 +295,62 @@ export interface QcResultStopCodons {
   totalStopCodonsIgnored: number
 }
 
+export interface RecombinationSegment {
+  clade: string
+  nearestNodeName: string
+  range: Range
+}
+
+export interface RecombinationBreakpoint {
+  leftClade: string
+  rightClade: string
+  range: Range
+}
+
+export interface Recombination {
+  segments: RecombinationSegment[]
+  breakpoints: RecombinationBreakpoint[]
+  parentalClades: string[]
+}
+
+export interface PlacementCandidate {
+  nodeName: string
+  clade: string
+  distance: number
+  posterior: number
+}
+
+export interface CladePosterior {
+  clade: string
+  posterior: number
+}
+
+export interface PlacementPosteriors {
+  candidates: PlacementCandidate[]
+  clades: CladePosterior[]
+  cladeConfidence: number
+}
+
+export interface QcResultRecombinants {
+  score: number
+  status: QcStatus
+  breakpoints: RecombinationBreakpoint[]
+  totalBreakpoints: number
+  parentalClades: string[]
+}
+
+export interface Inversion {
+  qryRange: Range
+  refRange: Range
+}
+
+export interface QcResultInversions {
+  score: number
+  status: QcStatus
+  inversions: Inversion[]
+  totalInversions: number
+}
+
 export interface QcResult {
   missingData?: QcResultMissingData
   mixedSites?: QcResultMixedSites
@@ -268,6 +358,8 @@ export interface QcResult {
   snpClusters?: QcResultSnpClusters
   frameShifts?: QcResultFrameShifts
   stopCodons?: QcResultStopCodons
+  recombinants?: QcResultRecombinants
+  inversions?: QcResultInversions
   overallScore: number
   overallStatus: QcStatus
 }
@@ -377,6 +469,7 @@ export interface AaMotifChanges {
 export interface AnalysisResult {
   index: number
   seqName: string
+  segment?: string
   substitutions: NucleotideSubstitution[]
   totalSubstitutions: number
   insertions: NucleotideInsertion[]
@@ -389,12 +482,14 @@ export interface AnalysisResult {
   totalMissing: number
   nonACGTNs: NucleotideRange[]
   totalNonACGTNs: number
+  mixedSites: MixedSites
   aaSubstitutions: AminoacidSubstitution[]
   totalAminoacidSubstitutions: number
   aaDeletions: AminoacidDeletion[]
   totalAminoacidDeletions: number
   aaInsertions: AminoacidInsertion[]
   totalAminoacidInsertions: number
+  aaAmbiguities: AminoacidAmbiguity[]
   unknownAaRanges: GeneAminoacidRange[]
   totalUnknownAa: number
   aaChangesGroups: AminoacidChangesGroup[]
@@ -407,11 +502,14 @@ export interface AnalysisResult {
   pcrPrimerChanges: PcrPrimerChange[]
   totalPcrPrimerChanges: number
   clade: string
+  placement?: PlacementPosteriors
   privateNucMutations: PrivateMutations
   privateAaMutations: Record<string, PrivateMutations>
   coverage: number
   phenotypeValues?: PhenotypeValue[]
   qc: QcResult
+  recombination?: Recombination
+  inversions?: Inversion[]
   customNodeAttributes: Record<string, string>
   warnings: PeptideWarning[]
   missingGenes: string[]
@@ -448,12 +546,19 @@ export interface Gene {
   end: number
   frame: number
   strand: string
+  cdsSegments: Range[]
+  geneticCode?: number
 }
 
 export function geneLength(gene: Gene) {
   return gene.end - gene.start
 }
 
+/** Length of the coding sequence of a gene, i.e. total length of its coding segments */
+export function geneCodingLength(gene: Gene) {
+  return gene.cdsSegments.reduce((length, segment) => length + segment.end - segment.begin, 0)
+}
+
 export interface FastaRecordId {
   seqName: string
   index: number
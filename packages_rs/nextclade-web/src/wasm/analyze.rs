@@ -3,6 +3,7 @@ use eyre::{Report, WrapErr};
 use itertools::Itertools;
 use nextclade::align::gap_open::{get_gap_open_close_scores_codon_aware, get_gap_open_close_scores_flat};
 use nextclade::align::params::AlignPairwiseParams;
+use nextclade::align::seed_chain::MinimizerIndex;
 use nextclade::analyze::find_aa_motifs::find_aa_motifs;
 use nextclade::analyze::find_aa_motifs_changes::AaMotifsMap;
 use nextclade::analyze::pcr_primers::PcrPrimer;
@@ -21,6 +22,7 @@ use nextclade::run::nextclade_run_one::nextclade_run_one;
 use nextclade::translate::aa_alignment_ranges::calculate_aa_alignment_range_in_place;
 use nextclade::translate::translate_genes::TranslationMap;
 use nextclade::translate::translate_genes_ref::translate_genes_ref;
+use nextclade::tree::params::TreePlacementParams;
 use nextclade::tree::tree::{AuspiceTree, CladeNodeAttrKeyDesc};
 use nextclade::tree::tree_attach_new_nodes::tree_attach_new_nodes_in_place;
 use nextclade::tree::tree_preprocess::tree_preprocess_in_place;
@@ -160,6 +162,7 @@ pub struct Nextclade {
   tree: AuspiceTree,
   qc_config: QcConfig,
   virus_properties: VirusProperties,
+  minimizer_index: Option<MinimizerIndex>,
   gap_open_close_nuc: Vec<i32>,
   gap_open_close_aa: Vec<i32>,
   clade_node_attr_key_descs: Vec<CladeNodeAttrKeyDesc>,
@@ -167,6 +170,7 @@ pub struct Nextclade {
   aa_motifs_descs: Vec<AaMotifsDesc>,
   aln_params: AlignPairwiseParams,
   include_nearest_node_info: bool,
+  placement_params: TreePlacementParams,
 }
 
 impl Nextclade {
@@ -195,13 +199,16 @@ impl Nextclade {
 
     let gene_map = read_gff3_str(gene_map_str).wrap_err("When parsing gene map")?;
 
+    let minimizer_index = MinimizerIndex::for_params(&ref_seq, &alignment_params)?;
+
     let gap_open_close_nuc = get_gap_open_close_scores_codon_aware(&ref_seq, &gene_map, &alignment_params);
 
     let gap_open_close_aa = get_gap_open_close_scores_flat(&ref_seq, &alignment_params);
 
     let ref_peptides = {
       let mut ref_peptides =
-        translate_genes_ref(&ref_seq, &gene_map, &alignment_params).wrap_err("When translating reference genes")?;
+        translate_genes_ref(&ref_seq, &gene_map, virus_properties.genetic_code()?, &alignment_params)
+          .wrap_err("When translating reference genes")?;
 
       ref_peptides
         .iter_mut()
@@ -243,6 +250,7 @@ impl Nextclade {
       tree,
       qc_config,
       virus_properties,
+      minimizer_index,
       gap_open_close_nuc,
       gap_open_close_aa,
       clade_node_attr_key_descs,
@@ -250,6 +258,7 @@ impl Nextclade {
       aa_motifs_descs,
       aln_params: alignment_params,
       include_nearest_node_info: false, // Never emit nearest node info in web, to reduce output size
+      placement_params: TreePlacementParams::default(),
     })
   }
 
@@ -286,10 +295,12 @@ impl Nextclade {
       &self.tree,
       &self.qc_config,
       &self.virus_properties,
+      self.minimizer_index.as_ref(),
       &self.gap_open_close_nuc,
       &self.gap_open_close_aa,
       &self.aln_params,
       self.include_nearest_node_info,
+      &self.placement_params,
     ) {
       Ok((qry_seq_aligned_stripped, translations, nextclade_outputs)) => {
         let nextclade_outputs_str =
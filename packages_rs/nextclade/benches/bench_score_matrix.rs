@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use nextclade::align::band_2d::simple_stripes;
+use nextclade::align::gap_open::get_gap_open_close_scores_flat;
+use nextclade::align::params::AlignPairwiseParams;
+use nextclade::align::score_matrix::score_matrix_scalar;
+use nextclade::align::score_matrix_simd::score_matrix_simd;
+use nextclade::align::seed_alignment::seed_alignment;
+use nextclade::io::aa::to_aa_seq;
+use nextclade::io::nuc::to_nuc_seq;
+
+const SPIKE_N_TERMINAL: &str = "MFVFLVLLPLVSSQCVNLTTRTQLPPAYTNSFTRGVYYPDKVFRSSVLHSTQDLFLPFFSNVTWFHAIHVSGTNGTKRFDNPVLPFNDGVYFASTEKSNIIRGWIFGTTLDSKTQSLLIVNNATNVVIKVCEFQFCNDPFLGVYYHKNNKSWMESEFRVYSSANNCTFEYVSQPFLMDLEGKQGNFKNLREFVFKNIDGYFKIYSKHTPINLVRDLPQGFSALEPLVDLPIGINITRFQTLLALHRSYLTPGDSSSGWTAGAAAYYVGYLQPRTFLLKYNENGTITDAVDCALDPLSETKCTLKSFTVEKGIYQTSNFRVQPTESIVRFPNITNLCPFGEVFNATRFASVYAWNRKRISNCVADYSVLYNSASFSTFKCYGVSPTKLNDLCFTNVYADSFVIRGDEVRQIAPGQTGKIADYNYKLPDDFTGCVIAWNSNNLDSKVGGNYNYLYRLFRKSNLKPFERDISTEIYQAGSTPCNGVEGFNCYFPLQSYGFQPTNGVGYQPYRVVVLSFELLHAPATVCGPKKSTNLVKNKCVNF";
+
+pub fn bench_score_matrix_nuc(c: &mut Criterion) {
+  let params = AlignPairwiseParams::default();
+
+  let test_data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data");
+  let ref_seq = sequence_from_path(test_data_dir.join("reference.fasta"));
+  let qry_seq = sequence_from_path(test_data_dir.join("Hangzhou_ZJU_07_2020.fasta"));
+
+  let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &params);
+  let stripes = seed_alignment(&qry_seq, &ref_seq, &params).unwrap();
+
+  let mut group = c.benchmark_group("score_matrix_nuc");
+  group.throughput(Throughput::Elements(ref_seq.len() as u64));
+  group.bench_function("scalar", |b| {
+    b.iter(|| score_matrix_scalar(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params));
+  });
+  group.bench_function("simd", |b| {
+    b.iter(|| score_matrix_simd(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params));
+  });
+  group.finish();
+}
+
+pub fn bench_score_matrix_aa(c: &mut Criterion) {
+  let params = AlignPairwiseParams::default();
+
+  let ref_seq = black_box(to_aa_seq(SPIKE_N_TERMINAL).unwrap());
+  let qry_seq =
+    black_box(to_aa_seq(&SPIKE_N_TERMINAL.replace("HAIHVSGT", "HAIHGT").replace("NNCTF", "NNKTF")).unwrap());
+
+  let gap_open_close = vec![params.penalty_gap_open; ref_seq.len() + 2];
+  let stripes = simple_stripes(0, 32, ref_seq.len(), qry_seq.len());
+
+  let mut group = c.benchmark_group("score_matrix_aa");
+  group.throughput(Throughput::Elements(ref_seq.len() as u64));
+  group.bench_function("scalar", |b| {
+    b.iter(|| score_matrix_scalar(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params));
+  });
+  group.bench_function("simd", |b| {
+    b.iter(|| score_matrix_simd(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params));
+  });
+  group.finish();
+}
+
+fn sequence_from_path(path: PathBuf) -> Vec<nextclade::io::nuc::Nuc> {
+  black_box(to_nuc_seq(fs::read_to_string(path).unwrap().trim()).unwrap())
+}
+
+criterion_group!(benches, bench_score_matrix_nuc, bench_score_matrix_aa);
+criterion_main!(benches);
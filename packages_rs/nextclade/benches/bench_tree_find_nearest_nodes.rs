@@ -0,0 +1,105 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nextclade::analyze::nuc_sub::NucSub;
+use nextclade::io::nuc::{to_nuc_seq, Nuc};
+use nextclade::tree::tree::AuspiceTree;
+use nextclade::tree::tree_find_nearest_node::{tree_find_nearest_nodes, tree_find_nearest_nodes_exhaustive};
+use nextclade::tree::tree_preprocess::tree_preprocess_in_place;
+use nextclade::utils::range::Range;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+const GENOME_LENGTH: usize = 30_000;
+
+/// Simple deterministic pseudo-random number generator, so that the benchmark is reproducible
+struct Lcg(u64);
+
+impl Lcg {
+  fn next(&mut self, max: usize) -> usize {
+    self.0 = self
+      .0
+      .wrapping_mul(6_364_136_223_846_793_005)
+      .wrapping_add(1_442_695_040_888_963_407);
+    ((self.0 >> 33) as usize) % max
+  }
+}
+
+/// Generates a random bifurcating tree with a given number of nodes, each branch carrying a few mutations
+fn make_tree_json(rng: &mut Lcg, n_nodes: usize) -> Value {
+  fn make_node(rng: &mut Lcg, n_nodes: usize, name: &str) -> Value {
+    let mutations = (0..=rng.next(3))
+      .map(|_| format!("A{}{}", rng.next(GENOME_LENGTH) + 1, ["C", "G", "T"][rng.next(3)]))
+      .collect::<Vec<_>>();
+
+    let n_children_nodes = n_nodes - 1;
+    let children = if n_children_nodes == 0 {
+      vec![]
+    } else if n_children_nodes == 1 {
+      vec![make_node(rng, 1, &format!("{name}.0"))]
+    } else {
+      let n_left = 1 + rng.next(n_children_nodes - 1);
+      vec![
+        make_node(rng, n_left, &format!("{name}.0")),
+        make_node(rng, n_children_nodes - n_left, &format!("{name}.1")),
+      ]
+    };
+
+    json!({
+      "name": name,
+      "branch_attrs": { "mutations": { "nuc": mutations } },
+      "node_attrs": { "clade_membership": { "value": name } },
+      "children": children,
+    })
+  }
+
+  json!({
+    "meta": { "display_defaults": {} },
+    "tree": make_node(rng, n_nodes, "root"),
+  })
+}
+
+pub fn bench_tree_find_nearest_nodes(c: &mut Criterion) {
+  let mut rng = Lcg(42);
+  let ref_seq = to_nuc_seq(&"A".repeat(GENOME_LENGTH)).unwrap();
+  let aln_range = Range::new(0, GENOME_LENGTH);
+
+  let mut group = c.benchmark_group("tree_find_nearest_nodes");
+  for n_nodes in [1_000, 10_000] {
+    let mut tree = AuspiceTree::from_str(&make_tree_json(&mut rng, n_nodes).to_string()).unwrap();
+    tree_preprocess_in_place(&mut tree, &ref_seq, &BTreeMap::new()).unwrap();
+
+    // Query is a descendant of a random node, with a few private mutations
+    let (_, node) = tree.iter_depth_first_preorder().nth(rng.next(n_nodes)).unwrap();
+    let qry_nuc_subs = node
+      .tmp
+      .mutations
+      .substitutions()
+      .into_iter()
+      .map(|(pos, qry)| NucSub { reff: Nuc::A, pos, qry })
+      .chain((0..5).map(|_| NucSub {
+        reff: Nuc::A,
+        pos: rng.next(GENOME_LENGTH),
+        qry: Nuc::T,
+      }))
+      .map(|sub| (sub.pos, sub))
+      .collect::<BTreeMap<usize, NucSub>>()
+      .into_values()
+      .collect::<Vec<_>>();
+
+    let tree = black_box(&tree);
+    let qry_nuc_subs = black_box(&qry_nuc_subs);
+    let aln_range = black_box(&aln_range);
+
+    group.bench_function(BenchmarkId::new("exhaustive", n_nodes), |b| {
+      b.iter(|| tree_find_nearest_nodes_exhaustive(tree, qry_nuc_subs, &[], aln_range, &[]));
+    });
+
+    group.bench_function(BenchmarkId::new("indexed", n_nodes), |b| {
+      b.iter(|| tree_find_nearest_nodes(tree, qry_nuc_subs, &[], aln_range, &[]));
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_tree_find_nearest_nodes);
+criterion_main!(benches);
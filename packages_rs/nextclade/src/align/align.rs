@@ -1,16 +1,22 @@
+use crate::align::align_checkpointed::align_checkpointed;
 use crate::align::backtrace::{backtrace, AlignmentOutput};
+use crate::align::band_2d::band_area;
 use crate::align::band_2d::simple_stripes;
 use crate::align::band_2d::Stripe;
-use crate::align::params::AlignPairwiseParams;
+use crate::align::inversions::{find_inversions, reverse_complement_inversions_in_place, Inversion};
+use crate::align::params::{AlignPairwiseParams, SeedMethod};
 use crate::align::score_matrix::{score_matrix, ScoreMatrixResult};
 use crate::align::seed_alignment::seed_alignment;
+use crate::align::seed_chain::{seed_chain_alignment, MinimizerIndex, SeedChainStats};
 use crate::io::aa::Aa;
 use crate::io::letter::Letter;
 use crate::io::nuc::Nuc;
-use crate::make_error;
 use crate::translate::complement::reverse_complement_in_place;
+use crate::{make_error, make_internal_report};
 use eyre::Report;
+use itertools::Itertools;
 use log::{info, trace, warn};
+use std::borrow::Cow;
 
 fn align_pairwise<T: Letter<T>>(
   qry_seq: &[T],
@@ -21,19 +27,51 @@ fn align_pairwise<T: Letter<T>>(
 ) -> AlignmentOutput<T> {
   trace!("Align pairwise: started. Params: {params:?}");
 
-  let max_indel = params.max_indel;
+  let band_area = band_area(stripes);
+  if band_area > params.max_band_area {
+    trace!(
+      "Align pairwise: band area {band_area} exceeds maximum of {}. Using memory-efficient alignment.",
+      params.max_band_area
+    );
+    return align_checkpointed(qry_seq, ref_seq, gap_open_close, stripes, params);
+  }
 
   let ScoreMatrixResult { scores, paths } = score_matrix(qry_seq, ref_seq, gap_open_close, stripes, params);
 
   backtrace(qry_seq, ref_seq, &scores, &paths)
 }
 
+fn require_minimizer_index(minimizer_index: Option<&MinimizerIndex>) -> Result<&MinimizerIndex, Report> {
+  minimizer_index.ok_or_else(|| {
+    make_internal_report!(
+      "Minimizer index of the reference sequence is required by the alignment parameters, but was not built"
+    )
+  })
+}
+
+/// Find the band of the alignment using the seed method chosen in the parameters
+fn find_stripes(
+  qry_seq: &[Nuc],
+  ref_seq: &[Nuc],
+  minimizer_index: Option<&MinimizerIndex>,
+  params: &AlignPairwiseParams,
+) -> Result<(Vec<Stripe>, Option<SeedChainStats>), Report> {
+  match params.seed_method {
+    SeedMethod::FixedSpacing => Ok((seed_alignment(qry_seq, ref_seq, params)?, None)),
+    SeedMethod::Minimizer => seed_chain_alignment(qry_seq, ref_seq, require_minimizer_index(minimizer_index)?, params),
+  }
+}
+
 /// align nucleotide sequences via seed alignment and banded smith watermann without penalizing terminal gaps
+///
+/// The minimizer index of the reference sequence is required for minimizer seed chaining and for detection of
+/// inversions (see `MinimizerIndex::for_params()`).
 pub fn align_nuc(
   index: usize,
   seq_name: &str,
   qry_seq: &[Nuc],
   ref_seq: &[Nuc],
+  minimizer_index: Option<&MinimizerIndex>,
   gap_open_close: &[i32],
   params: &AlignPairwiseParams,
 ) -> Result<AlignmentOutput<Nuc>, Report> {
@@ -45,17 +83,39 @@ pub fn align_nuc(
     );
   }
 
+  let inversions = if params.detect_inversions {
+    find_inversions(qry_seq, ref_seq, require_minimizer_index(minimizer_index)?, params)
+  } else {
+    vec![]
+  };
+
+  // Align inverted regions in the orientation of the reference
+  let mut qry_seq_oriented = Cow::Borrowed(qry_seq);
+  if !inversions.is_empty() {
+    warn!(
+      "When processing sequence #{index} '{seq_name}': Detected inverted regions (in reference coordinates): {}. These regions will be reverse-complemented before alignment. Outputs will be derived from the sequence with these regions in the orientation of the reference.",
+      inversions.iter().map(Inversion::to_string).join(", ")
+    );
+    reverse_complement_inversions_in_place(qry_seq_oriented.to_mut(), &inversions);
+  }
+
   #[allow(clippy::map_err_ignore)]
-  match seed_alignment(qry_seq, ref_seq, params) {
-    Ok(stripes) => Ok(align_pairwise(qry_seq, ref_seq, gap_open_close, params, &stripes)),
+  match find_stripes(&qry_seq_oriented, ref_seq, minimizer_index, params) {
+    Ok((stripes, seed_chain)) => {
+      let mut result = align_pairwise(&qry_seq_oriented, ref_seq, gap_open_close, params, &stripes);
+      result.seed_chain = seed_chain;
+      result.inversions = inversions;
+      Ok(result)
+    }
     Err(report) => {
       if params.retry_reverse_complement {
         info!("When processing sequence #{index} '{seq_name}': Seed matching failed. Retrying reverse complement");
         let mut qry_seq = qry_seq.to_owned();
         reverse_complement_in_place(&mut qry_seq);
-        let stripes = seed_alignment(&qry_seq, ref_seq, params).map_err(|_| report)?;
+        let (stripes, seed_chain) = find_stripes(&qry_seq, ref_seq, minimizer_index, params).map_err(|_| report)?;
         let mut result = align_pairwise(&qry_seq, ref_seq, gap_open_close, params, &stripes);
         result.is_reverse_complement = true;
+        result.seed_chain = seed_chain;
         warn!("When processing sequence #{index} '{seq_name}': Sequence is reverse-complemented: Seed matching failed for the original sequence, but succeeded for its reverse complement. Outputs will be derived from the reverse complement and 'reverse complement' suffix will be added to the fasta header in the nucleotide alignment.");
         Ok(result)
       } else {
@@ -114,15 +174,19 @@ mod tests {
     Context { params, gap_open_close }
   }
 
+  fn read_reference() -> Vec<Nuc> {
+    let mut ref_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    ref_path.push("test_data");
+    ref_path.push("reference.fasta");
+    to_nuc_seq(fs::read_to_string(ref_path).unwrap().trim()).unwrap()
+  }
+
   #[fixture]
   fn more_realistic_ctx() -> Context {
     let params = AlignPairwiseParams::default();
     let gene_map = GeneMap::new();
 
-    let mut ref_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    ref_path.push("test_data");
-    ref_path.push("reference.fasta");
-    let ref_seq = to_nuc_seq(fs::read_to_string(ref_path).unwrap().trim()).unwrap();
+    let ref_seq = read_reference();
 
     let gap_open_close = get_gap_open_close_scores_codon_aware(&ref_seq, &gene_map, &params);
 
@@ -134,7 +198,7 @@ mod tests {
     let qry_seq = to_nuc_seq("ACGCTCGCT")?;
     let ref_seq = to_nuc_seq("ACGCTCGCT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_seq), from_nuc_seq(&result.qry_seq));
@@ -148,7 +212,7 @@ mod tests {
     let ref_seq = to_nuc_seq("ACGCTCGCT")?;
     let qry_aln = to_nuc_seq("-CGCTCGCT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -162,7 +226,7 @@ mod tests {
     let ref_seq = to_nuc_seq("ACGCTCGCT")?;
     let qry_aln = to_nuc_seq("---CTCGCT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -177,7 +241,7 @@ mod tests {
     let qry_aln = to_nuc_seq("-----TCCAATCA")?;
     //                                  ^
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -192,7 +256,7 @@ mod tests {
     let qry_aln = to_nuc_seq("-----TGTTACCTGCGC")?;
     //                              ^^
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -206,7 +270,7 @@ mod tests {
     let ref_seq = to_nuc_seq("ACGCTCGCT")?;
     let qry_aln = to_nuc_seq("ACGCTC---")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -221,7 +285,7 @@ mod tests {
     let qry_aln = to_nuc_seq("CCAATCAT-----")?;
     //                             ^
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -236,7 +300,7 @@ mod tests {
     let qry_aln = to_nuc_seq("CCGATCAT-----")?;
     //                            ^  ^
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -250,7 +314,7 @@ mod tests {
     let ref_seq = to_nuc_seq("GCCACGCTCGCT")?;
     let qry_aln = to_nuc_seq("---ACGCTC---")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -264,7 +328,7 @@ mod tests {
     let ref_seq = to_nuc_seq("ACGCTC")?;
     let ref_aln = to_nuc_seq("---ACGCTC---")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_aln), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_seq), from_nuc_seq(&result.qry_seq));
@@ -278,7 +342,7 @@ mod tests {
     let ref_seq = to_nuc_seq("GCCACGCTCGCT")?;
     let qry_aln = to_nuc_seq("GCCA--CTCCCT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     // assert_eq!(18, result.alignment_score);
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
@@ -293,7 +357,7 @@ mod tests {
     let ref_seq = to_nuc_seq("GCCACTCGCT")?;
     let ref_aln = to_nuc_seq("GCCA--CTCGCT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_aln), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_seq), from_nuc_seq(&result.qry_seq));
@@ -307,7 +371,7 @@ mod tests {
     let ref_seq = to_nuc_seq("ACATATACTTC")?;
     let qry_aln = to_nuc_seq("ACAT---CTTC")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -321,7 +385,7 @@ mod tests {
     let ref_seq = to_nuc_seq("ACATCTTG")?;
     let ref_aln = to_nuc_seq("ACAT---CTTG")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_aln), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_seq), from_nuc_seq(&result.qry_seq));
@@ -336,7 +400,7 @@ mod tests {
     let qry_aln = to_nuc_seq("AAAAAAAAAAAA----------")?;
     let ref_aln = to_nuc_seq("---------AAATTTTTTTTTT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_aln), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -351,7 +415,7 @@ mod tests {
     let ref_aln = to_nuc_seq("AAAAAAAAAAAA----------")?;
     let qry_aln = to_nuc_seq("---------AAATTTTTTTTTT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_aln), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
@@ -366,10 +430,76 @@ mod tests {
     let ref_aln = to_nuc_seq("CTTGGAGGTTCCGTGGCT----AGATAACAGAACATTCTTGGAATGCTGATCTTTATAAGCTCATGCGACACTTCGCATGGTG---AGCCTTTGT")?;
     let qry_aln = to_nuc_seq("CTTGGAGGTTCCGTGGCTATAAAGATAACAGAACATTCTTGGAATGCTGATC-----AAGCTCATGGGACANNNNNCATGGTGGACAGCCTTTGT")?;
 
-    let result = align_nuc(0, "", &qry_seq, &ref_seq, &ctx.gap_open_close, &ctx.params)?;
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, None, &ctx.gap_open_close, &ctx.params)?;
 
     assert_eq!(from_nuc_seq(&ref_aln), from_nuc_seq(&result.ref_seq));
     assert_eq!(from_nuc_seq(&qry_aln), from_nuc_seq(&result.qry_seq));
     Ok(())
   }
+
+  #[rstest]
+  fn aligns_inverted_region_in_reference_orientation(more_realistic_ctx: Context) -> Result<(), Report> {
+    let ref_seq = read_reference();
+    let mut qry_seq = ref_seq.clone();
+    reverse_complement_in_place(&mut qry_seq[10000..11000]);
+
+    let params = AlignPairwiseParams {
+      detect_inversions: true,
+      ..more_realistic_ctx.params
+    };
+
+    let minimizer_index = MinimizerIndex::for_params(&ref_seq, &params)?;
+    let result = align_nuc(
+      0,
+      "",
+      &qry_seq,
+      &ref_seq,
+      minimizer_index.as_ref(),
+      &more_realistic_ctx.gap_open_close,
+      &params,
+    )?;
+
+    assert_eq!(result.inversions.len(), 1);
+    assert!(result.inversions[0].ref_range.len() > 900);
+
+    // Only the nucleotides near boundaries of the inverted region, not covered by seed matches, remain mismatched
+    let num_mismatches = result
+      .qry_seq
+      .iter()
+      .zip(&result.ref_seq)
+      .filter(|(qry, rf)| qry != rf)
+      .count();
+    assert!(num_mismatches < 100, "{num_mismatches}");
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn aligns_long_deletion_with_increased_max_indel(more_realistic_ctx: Context) -> Result<(), Report> {
+    let ref_seq = read_reference();
+    let mut qry_seq = ref_seq.clone();
+    qry_seq.drain(25000..27000);
+
+    // The band is wider than the default `max_band_area` allows, so the memory-efficient alignment is used
+    let params = AlignPairwiseParams {
+      max_indel: 10_000,
+      ..more_realistic_ctx.params
+    };
+
+    let result = align_nuc(
+      0,
+      "",
+      &qry_seq,
+      &ref_seq,
+      None,
+      &more_realistic_ctx.gap_open_close,
+      &params,
+    )?;
+
+    assert_eq!(result.ref_seq, ref_seq);
+    let num_gaps = result.qry_seq.iter().filter(|nuc| nuc.is_gap()).count();
+    assert_eq!(num_gaps, 2000);
+
+    Ok(())
+  }
 }
@@ -0,0 +1,249 @@
+use crate::align::backtrace::{backtrace_paths, AlignmentOutput};
+use crate::align::band_2d::{Band2d, Stripe};
+use crate::align::params::AlignPairwiseParams;
+use crate::align::score_matrix::{score_matrix_first_row, score_matrix_row, NO_ALIGN};
+use crate::align::score_matrix_custom::MatchScores;
+use crate::io::letter::Letter;
+use log::trace;
+
+/// State of the score matrix computation after a given row
+struct Checkpoint {
+  /// Scores in the row, indexed by `qpos - stripe.begin`
+  scores: Vec<i32>,
+  /// Query gap scores in the row, indexed by `qpos - stripe.begin`
+  qry_gaps: Vec<i32>,
+}
+
+/// Aligns sequences within the band defined by `stripes`, using memory proportional to the square root of the number
+/// of cells in the band, rather than to the number of cells.
+///
+/// The score matrix is computed twice. During the first pass, only the current and the preceding rows are kept, along
+/// with a checkpoint every `block_size` rows. During backtrace, paths are recomputed block by block, starting from the
+/// saved checkpoints. The result is identical to the result of the regular alignment (`score_matrix()` followed by
+/// `backtrace()`), at the cost of roughly doubling the computation time.
+pub fn align_checkpointed<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+) -> AlignmentOutput<T> {
+  let n_rows = ref_seq.len() + 1;
+  let block_size = (n_rows as f64).sqrt().ceil() as usize;
+  align_checkpointed_with_block_size(qry_seq, ref_seq, gap_open_close, stripes, params, block_size)
+}
+
+fn align_checkpointed_with_block_size<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+  block_size: usize,
+) -> AlignmentOutput<T> {
+  let query_size = qry_seq.len();
+  let ref_len = ref_seq.len();
+  let n_rows = ref_len + 1;
+  let n_cols = query_size + 1;
+
+  trace!(
+    "Checkpointed alignment: started: query_size={query_size}, ref_len={ref_len}, n_rows={n_rows}, n_cols={n_cols}, block_size={block_size}"
+  );
+
+  let match_scores = MatchScores::<T>::new(params);
+
+  // First pass: compute scores row by row and save a checkpoint before each block, except for the first one
+  let mut checkpoints = Vec::<Checkpoint>::with_capacity(n_rows / block_size + 1);
+  let mut qry_gaps = vec![NO_ALIGN; n_cols];
+  let mut prev_row_scores = vec![0; stripes[0].len()];
+  let mut row_scores = vec![];
+  let mut row_paths = vec![0; stripes[0].len()];
+
+  score_matrix_first_row(
+    gap_open_close,
+    &stripes[0],
+    params,
+    &mut prev_row_scores,
+    &mut row_paths,
+  );
+
+  for ri in 1..n_rows {
+    if ri % block_size == 0 {
+      let prev_stripe = &stripes[ri - 1];
+      checkpoints.push(Checkpoint {
+        scores: prev_row_scores.clone(),
+        qry_gaps: qry_gaps[prev_stripe.begin..prev_stripe.end].to_vec(),
+      });
+    }
+
+    row_scores.resize(stripes[ri].len(), 0);
+    row_paths.resize(stripes[ri].len(), 0);
+    score_matrix_row(
+      ri,
+      qry_seq,
+      ref_seq,
+      gap_open_close,
+      stripes,
+      params,
+      &match_scores,
+      &prev_row_scores,
+      &mut qry_gaps,
+      &mut row_scores,
+      &mut row_paths,
+    );
+    std::mem::swap(&mut prev_row_scores, &mut row_scores);
+  }
+
+  let alignment_score = prev_row_scores[query_size - stripes[ref_len].begin];
+
+  trace!("Checkpointed alignment: saved {} checkpoints", checkpoints.len());
+
+  // Second pass: backtrace, recomputing paths of one block at a time. Backtrace proceeds from the last row to the
+  // first, so each block is only computed once.
+  let mut block_first_row = usize::MAX;
+  let mut block_paths = Band2d::<i8>::new(&[]);
+  let (aln_qry, aln_ref) = backtrace_paths(qry_seq, ref_seq, n_rows, n_cols, |r_pos, q_pos| {
+    let first_row = (r_pos / block_size) * block_size;
+    if first_row != block_first_row {
+      let last_row = (first_row + block_size).min(n_rows);
+      let checkpoint = (first_row / block_size).checked_sub(1).map(|i| &checkpoints[i]);
+      block_paths = compute_block_paths(
+        qry_seq,
+        ref_seq,
+        gap_open_close,
+        stripes,
+        params,
+        &match_scores,
+        first_row,
+        last_row,
+        checkpoint,
+      );
+      block_first_row = first_row;
+    }
+    block_paths[(r_pos - first_row, q_pos)]
+  });
+
+  AlignmentOutput {
+    qry_seq: aln_qry,
+    ref_seq: aln_ref,
+    alignment_score,
+    is_reverse_complement: false,
+    seed_chain: None,
+    inversions: vec![],
+  }
+}
+
+/// Computes paths in rows `first_row..last_row`, starting from the state saved in the checkpoint.
+/// The checkpoint is absent for the block starting at the first row.
+fn compute_block_paths<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+  match_scores: &MatchScores<T>,
+  first_row: usize,
+  last_row: usize,
+  checkpoint: Option<&Checkpoint>,
+) -> Band2d<i8> {
+  let mut paths = Band2d::<i8>::new(&stripes[first_row..last_row]);
+  let mut qry_gaps = vec![NO_ALIGN; qry_seq.len() + 1];
+  let mut prev_row_scores = vec![];
+  let mut row_scores = vec![];
+
+  let mut rows = first_row..last_row;
+  match checkpoint {
+    Some(checkpoint) => {
+      let prev_stripe = &stripes[first_row - 1];
+      prev_row_scores.clone_from(&checkpoint.scores);
+      qry_gaps[prev_stripe.begin..prev_stripe.end].copy_from_slice(&checkpoint.qry_gaps);
+    }
+    None => {
+      prev_row_scores.resize(stripes[0].len(), 0);
+      score_matrix_first_row(
+        gap_open_close,
+        &stripes[0],
+        params,
+        &mut prev_row_scores,
+        paths.row_mut(0),
+      );
+      rows.next();
+    }
+  }
+
+  for ri in rows {
+    row_scores.resize(stripes[ri].len(), 0);
+    score_matrix_row(
+      ri,
+      qry_seq,
+      ref_seq,
+      gap_open_close,
+      stripes,
+      params,
+      match_scores,
+      &prev_row_scores,
+      &mut qry_gaps,
+      &mut row_scores,
+      paths.row_mut(ri - first_row),
+    );
+    std::mem::swap(&mut prev_row_scores, &mut row_scores);
+  }
+
+  paths
+}
+
+#[cfg(test)]
+mod tests {
+  #![allow(clippy::needless_pass_by_value)] // rstest fixtures are passed by value
+  use super::*;
+  use crate::align::backtrace::backtrace;
+  use crate::align::band_2d::{full_matrix, simple_stripes};
+  use crate::align::params::GapAlignmentSide;
+  use crate::align::score_matrix::{score_matrix, ScoreMatrixResult};
+  use crate::io::nuc::{from_nuc_seq, to_nuc_seq};
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("CTCGCT", "ACGCTCGCT")]
+  #[case("ACGCTCGCT", "CTCGCT")]
+  #[case("ACGTTTACGGTAACCGTAGCTCGACTG", "ACGTTTACGGGTAACCGTAGCTAGCATCGACTG")]
+  #[case("ACGTTTACGNNNNNNCGTAGCTAGCATCGACTGTTTGGCA", "ACGTTTACGGGTAACCGTAGCTAGCATCGACTG")]
+  #[case("ACGTTTACGGGTAAGCATCGACTG", "ACGTTTACGGGTAACCGTAGCTAGCATTACGCATCGACTG")]
+  fn aligns_same_as_regular_alignment(#[case] qry: &str, #[case] rf: &str) -> Result<(), Report> {
+    let qry_seq = to_nuc_seq(qry)?;
+    let ref_seq = to_nuc_seq(rf)?;
+    let gap_open_close: Vec<i32> = (0..ref_seq.len() + 2).map(|i| [6, 8, 7][i % 3]).collect();
+
+    for gap_alignment_side in [GapAlignmentSide::Left, GapAlignmentSide::Right] {
+      for terminal_gaps_free in [false, true] {
+        let params = AlignPairwiseParams {
+          gap_alignment_side,
+          left_terminal_gaps_free: terminal_gaps_free,
+          right_terminal_gaps_free: terminal_gaps_free,
+          ..AlignPairwiseParams::default()
+        };
+
+        for stripes in [
+          full_matrix(ref_seq.len(), qry_seq.len()),
+          simple_stripes(0, 8, ref_seq.len(), qry_seq.len()),
+        ] {
+          let ScoreMatrixResult { scores, paths } =
+            score_matrix(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params);
+          let expected = backtrace(&qry_seq, &ref_seq, &scores, &paths);
+
+          for block_size in [1, 2, 3, 7, 100] {
+            let actual =
+              align_checkpointed_with_block_size(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params, block_size);
+            assert_eq!(from_nuc_seq(&expected.qry_seq), from_nuc_seq(&actual.qry_seq));
+            assert_eq!(from_nuc_seq(&expected.ref_seq), from_nuc_seq(&actual.ref_seq));
+            assert_eq!(expected.alignment_score, actual.alignment_score);
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
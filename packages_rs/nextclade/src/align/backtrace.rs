@@ -1,5 +1,7 @@
 use crate::align::band_2d::Band2d;
+use crate::align::inversions::Inversion;
 use crate::align::score_matrix::{MATCH, QRY_GAP_EXTEND, QRY_GAP_MATRIX, REF_GAP_EXTEND, REF_GAP_MATRIX};
+use crate::align::seed_chain::SeedChainStats;
 use crate::io::letter::Letter;
 use crate::utils::vec2d::Vec2d;
 use serde::{Deserialize, Serialize};
@@ -15,6 +17,10 @@ pub struct AlignmentOutput<T> {
   pub ref_seq: Vec<T>,
   pub alignment_score: i32,
   pub is_reverse_complement: bool,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub seed_chain: Option<SeedChainStats>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub inversions: Vec<Inversion>,
 }
 
 pub fn backtrace<T: Letter<T>>(
@@ -25,11 +31,37 @@ pub fn backtrace<T: Letter<T>>(
 ) -> AlignmentOutput<T> {
   let num_cols = scores.num_cols();
   let num_rows = scores.num_rows();
-  let qry_len = qry_seq.len() as i32;
-  let ref_len = ref_seq.len() as i32;
 
+  let (aln_qry, aln_ref) = backtrace_paths(qry_seq, ref_seq, num_rows, num_cols, |r_pos, q_pos| {
+    paths[(r_pos, q_pos)]
+  });
+
+  AlignmentOutput {
+    qry_seq: aln_qry,
+    ref_seq: aln_ref,
+    alignment_score: scores[(num_rows - 1, num_cols - 1)],
+    is_reverse_complement: false,
+    seed_chain: None,
+    inversions: vec![],
+  }
+}
+
+/// Follows the paths from the bottom right to the top left corner of the matrix and builds the aligned sequences.
+///
+/// Paths are retrieved with `path_at(r_pos, q_pos)`, in the order of non-increasing `r_pos`.
+pub fn backtrace_paths<T, F>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  num_rows: usize,
+  num_cols: usize,
+  mut path_at: F,
+) -> (Vec<T>, Vec<T>)
+where
+  T: Letter<T>,
+  F: FnMut(usize, usize) -> i8,
+{
   // max length of the alignment is the sum of query and reference length
-  let aln_capacity = scores.num_cols() + scores.num_rows();
+  let aln_capacity = num_cols + num_rows;
   let mut aln_ref = Vec::<T>::with_capacity(aln_capacity);
   let mut aln_qry = Vec::<T>::with_capacity(aln_capacity);
 
@@ -42,7 +74,7 @@ pub fn backtrace<T: Letter<T>>(
 
   // Do backtrace in the aligned region
   while r_pos > 0 || q_pos > 0 {
-    origin = paths[(r_pos, q_pos)];
+    origin = path_at(r_pos, q_pos);
 
     if (origin & MATCH) != 0 && (current_matrix == 0) {
       // Match -- decrement both strands and add match to alignment
@@ -86,12 +118,7 @@ pub fn backtrace<T: Letter<T>>(
   aln_qry.reverse();
   aln_ref.reverse();
 
-  AlignmentOutput {
-    qry_seq: aln_qry,
-    ref_seq: aln_ref,
-    alignment_score: scores[(num_rows - 1, num_cols - 1)],
-    is_reverse_complement: false,
-  }
+  (aln_qry, aln_ref)
 }
 
 #[cfg(test)]
@@ -164,6 +191,8 @@ mod tests {
       ref_seq: to_nuc_seq("ACGCTCGCT")?,
       alignment_score: 18,
       is_reverse_complement: false,
+      seed_chain: None,
+      inversions: vec![],
     };
 
     let output = backtrace(&qry_seq, &ref_seq, &scores, &paths);
@@ -63,6 +63,11 @@ pub fn full_matrix(ref_len: usize, qry_len: usize) -> Vec<Stripe> {
   stripes
 }
 
+/// Number of cells in the band defined by the given stripes
+pub fn band_area(stripes: &[Stripe]) -> usize {
+  stripes.iter().map(Stripe::len).sum()
+}
+
 /// Represents a diagonal band in a matrix.
 ///
 /// The underlying storage is sparse - the row storage consists of `Stripe`s, each of a given size (`stripe.length`)
@@ -118,6 +123,27 @@ where
     self.data.len()
   }
 
+  /// Underlying sparse storage, stripe after stripe, in row order
+  #[inline]
+  pub fn data_mut(&mut self) -> &mut [T] {
+    &mut self.data
+  }
+
+  /// Storage of a given row, indexed by `col - stripe.begin`
+  #[inline]
+  pub fn row_mut(&mut self, row: usize) -> &mut [T] {
+    &mut self.data[self.row_start_points[row]..self.row_start_points[row + 1]]
+  }
+
+  /// Storage of a given row, along with the storage of the preceding row
+  #[inline]
+  pub fn row_with_previous_mut(&mut self, row: usize) -> (&[T], &mut [T]) {
+    let (head, tail) = self.data.split_at_mut(self.row_start_points[row]);
+    let prev_row = &head[self.row_start_points[row - 1]..];
+    let row = &mut tail[..(self.row_start_points[row + 1] - self.row_start_points[row])];
+    (prev_row, row)
+  }
+
   #[inline]
   fn get_index<I: NumCast + Copy, J: NumCast + Copy>(&self, index2d: (I, J)) -> usize {
     let row = index2d.0.to_usize().unwrap();
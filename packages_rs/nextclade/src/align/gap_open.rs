@@ -1,4 +1,5 @@
 use crate::align::params::AlignPairwiseParams;
+use crate::gene::gene::GeneStrand;
 use crate::io::gene_map::GeneMap;
 use crate::io::nuc::Nuc;
 
@@ -17,10 +18,16 @@ pub fn get_gap_open_close_scores_codon_aware(
 ) -> GapScoreMap {
   let mut gap_open_close = get_gap_open_close_scores_flat(ref_seq, params);
   for (_, gene) in gene_map.iter() {
-    for i in (gene.start..gene.end).step_by(3) {
-      gap_open_close[i] = params.penalty_gap_open_in_frame;
-      gap_open_close[i + 1] = params.penalty_gap_open_out_of_frame;
-      gap_open_close[i + 2] = params.penalty_gap_open_out_of_frame;
+    // Gaps are cheaper to open at the codon boundaries, i.e. before the leftmost nucleotide of each codon. Coding
+    // segments of a gene are concatenated, so codons can span segment boundaries.
+    let leftmost_codon_nuc = if gene.strand == GeneStrand::Reverse { 2 } else { 0 };
+    for nuc_rel in 0..gene.len() {
+      let nuc_abs = gene.nuc_rel_to_abs(nuc_rel);
+      gap_open_close[nuc_abs] = if nuc_rel % 3 == leftmost_codon_nuc {
+        params.penalty_gap_open_in_frame
+      } else {
+        params.penalty_gap_open_out_of_frame
+      };
     }
   }
   gap_open_close
@@ -0,0 +1,207 @@
+use crate::align::params::AlignPairwiseParams;
+use crate::align::seed_chain::{chain_anchors, find_reverse_complement_chains, MinimizerIndex};
+use crate::io::nuc::Nuc;
+use crate::translate::complement::reverse_complement_in_place;
+use crate::utils::range::{have_intersection, Range};
+use serde::{Deserialize, Serialize};
+
+/// Region of the query sequence which matches the reference in reverse-complement orientation
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inversion {
+  /// Range of the query sequence, as provided (before reverse complementing the region)
+  pub qry_range: Range,
+  /// Range of the reference sequence, which the reverse complement of the query region matches
+  pub ref_range: Range,
+}
+
+impl ToString for Inversion {
+  fn to_string(&self) -> String {
+    // NOTE: by convention, in bioinformatics, nucleotides are numbered starting from 1, however our arrays are 0-based
+    format!("{}-{}", self.ref_range.begin + 1, self.ref_range.end)
+  }
+}
+
+/// Finds regions of the query which are reverse-complemented relative to the reference, while the rest of the query is
+/// not (assembly artefacts or genuine inversions).
+///
+/// Candidate regions are chains of seed matches between the reverse complement of the query and the reference (see
+/// `find_reverse_complement_chains()`). A candidate is considered a local inversion if it contains no seed matches in
+/// forward orientation, and if it matches the reference roughly where the surrounding forward seed matches suggest,
+/// i.e. if the region has been inverted in place. Boundaries of inversions are approximate: they are given by the
+/// outermost seed matches of the chain.
+pub fn find_inversions(
+  qry_seq: &[Nuc],
+  ref_seq: &[Nuc],
+  index: &MinimizerIndex,
+  params: &AlignPairwiseParams,
+) -> Vec<Inversion> {
+  let kmer_length = index.kmer_length();
+
+  let (_, anchors) = index.find_anchors(qry_seq);
+  let forward_chain = match chain_anchors(&anchors, kmer_length, params.max_indel) {
+    Some(chain) if chain.anchors.len() >= 2 => chain,
+    // Sequence does not match the reference in forward orientation. Reverse complement of the whole sequence is
+    // handled by `--retry-reverse-complement`.
+    _ => return vec![],
+  };
+
+  let mut inversions = Vec::<Inversion>::new();
+  for candidate in find_reverse_complement_chains(index, qry_seq, params.max_indel) {
+    let Range { begin, end } = candidate.qry_range;
+
+    let contains_forward_anchors = forward_chain
+      .anchors
+      .iter()
+      .any(|anchor| begin <= anchor.qry_pos && anchor.qry_pos + kmer_length <= end);
+    if contains_forward_anchors {
+      continue;
+    }
+
+    // Offset between reference and query positions of the sequence surrounding the candidate
+    let forward_shift = forward_chain
+      .anchors
+      .iter()
+      .min_by_key(|anchor| anchor.qry_pos.abs_diff(begin).min(anchor.qry_pos.abs_diff(end)))
+      .map_or(0, |anchor| anchor.ref_pos as i64 - anchor.qry_pos as i64);
+
+    // If query region `A..B` is inverted in place, the reverse complement of its part `begin..end` matches the
+    // reference starting at `A + B - end + forward_shift`. Extend the range to be symmetric around the center of the
+    // inverted region, such that after reverse complementing it is in the same frame as the surrounding sequence.
+    let sum = candidate.ref_range.begin as i64 + end as i64 - forward_shift;
+    let qry_begin = (begin as i64).min(sum - end as i64).clamp(0, qry_seq.len() as i64);
+    let qry_end = (end as i64).max(sum - begin as i64).clamp(0, qry_seq.len() as i64);
+    let ref_begin = (qry_begin + forward_shift).clamp(0, ref_seq.len() as i64);
+    let ref_end = (qry_end + forward_shift).clamp(0, ref_seq.len() as i64);
+
+    // If the region is inverted in place, the symmetric range stays within the inverted region. If the region has been
+    // moved elsewhere as well, the range extends towards its original location and covers forward seed matches.
+    let extends_over_forward_anchors = forward_chain
+      .anchors
+      .iter()
+      .any(|anchor| qry_begin <= anchor.qry_pos as i64 && anchor.qry_pos as i64 + kmer_length as i64 <= qry_end);
+    if extends_over_forward_anchors {
+      continue;
+    }
+
+    let inversion = Inversion {
+      qry_range: Range::new(qry_begin as usize, qry_end as usize),
+      ref_range: Range::new(ref_begin as usize, ref_end as usize),
+    };
+
+    let overlaps_other_inversion = inversions
+      .iter()
+      .any(|other| have_intersection(&other.qry_range, &inversion.qry_range));
+    if !overlaps_other_inversion {
+      inversions.push(inversion);
+    }
+  }
+
+  inversions.sort_by_key(|inversion| inversion.qry_range.begin);
+  inversions
+}
+
+/// Reverse-complements inverted regions of the query, so that the whole query is in the orientation of the reference
+pub fn reverse_complement_inversions_in_place(qry_seq: &mut [Nuc], inversions: &[Inversion]) {
+  for Inversion { qry_range, .. } in inversions {
+    reverse_complement_in_place(&mut qry_seq[qry_range.begin..qry_range.end]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  /// Pseudo-random sequence, which contains no repeats long enough to produce spurious seed matches
+  fn random_seq(len: usize) -> Vec<Nuc> {
+    let mut state: u64 = 42;
+    (0..len)
+      .map(|_| {
+        state = state
+          .wrapping_mul(6_364_136_223_846_793_005)
+          .wrapping_add(1_442_695_040_888_963_407);
+        [Nuc::A, Nuc::C, Nuc::G, Nuc::T][(state >> 62) as usize]
+      })
+      .collect()
+  }
+
+  fn index(ref_seq: &[Nuc], params: &AlignPairwiseParams) -> MinimizerIndex {
+    MinimizerIndex::new(ref_seq, params.seed_length, params.minimizer_window).unwrap()
+  }
+
+  fn invert(seq: &[Nuc], range: &Range) -> Vec<Nuc> {
+    let mut seq = seq.to_vec();
+    reverse_complement_in_place(&mut seq[range.begin..range.end]);
+    seq
+  }
+
+  #[rstest]
+  fn finds_inversion_and_restores_orientation() {
+    let params = AlignPairwiseParams::default();
+    let ref_seq = random_seq(4000);
+    let qry_seq = invert(&ref_seq, &Range::new(1500, 2100));
+
+    let inversions = find_inversions(&qry_seq, &ref_seq, &index(&ref_seq, &params), &params);
+
+    assert_eq!(inversions.len(), 1);
+    let Inversion { qry_range, ref_range } = &inversions[0];
+    assert_eq!(qry_range, ref_range);
+
+    // Boundaries are approximate, within the span of a minimizer window, but the range is symmetric around the center
+    // of the inverted region
+    let Range { begin, end } = *qry_range;
+    let tolerance = params.seed_length + params.minimizer_window;
+    assert!(begin.abs_diff(1500) <= tolerance, "{qry_range:?}");
+    assert!(end.abs_diff(2100) <= tolerance, "{qry_range:?}");
+    assert_eq!(begin + end, 1500 + 2100);
+
+    // After reverse complementing the detected region, the query matches the reference, except for the slivers between
+    // the detected and the actual boundaries
+    let mut restored = qry_seq.clone();
+    reverse_complement_inversions_in_place(&mut restored, &inversions);
+    let is_boundary =
+      |i: usize| (begin.min(1500)..begin.max(1500)).contains(&i) || (end.min(2100)..end.max(2100)).contains(&i);
+    for (i, (qry, rf)) in restored.iter().zip(&ref_seq).enumerate() {
+      if !is_boundary(i) {
+        assert_eq!(qry, rf, "at position {i}");
+      }
+    }
+  }
+
+  #[rstest]
+  fn finds_no_inversions_in_forward_sequence() {
+    let params = AlignPairwiseParams::default();
+    let ref_seq = random_seq(4000);
+    let qry_seq = [&ref_seq[..1000], &ref_seq[1100..]].concat();
+    assert_eq!(
+      find_inversions(&qry_seq, &ref_seq, &index(&ref_seq, &params), &params),
+      vec![]
+    );
+  }
+
+  #[rstest]
+  fn ignores_reverse_complement_of_whole_sequence() {
+    let params = AlignPairwiseParams::default();
+    let ref_seq = random_seq(4000);
+    let qry_seq = invert(&ref_seq, &Range::new(0, 4000));
+    assert_eq!(
+      find_inversions(&qry_seq, &ref_seq, &index(&ref_seq, &params), &params),
+      vec![]
+    );
+  }
+
+  #[rstest]
+  fn ignores_inverted_region_moved_elsewhere() {
+    let params = AlignPairwiseParams::default();
+    let ref_seq = random_seq(4000);
+    let mut moved = ref_seq[1000..1600].to_vec();
+    reverse_complement_in_place(&mut moved);
+    let qry_seq = [&ref_seq[..1000], &ref_seq[1600..3000], &moved, &ref_seq[3000..]].concat();
+    assert_eq!(
+      find_inversions(&qry_seq, &ref_seq, &index(&ref_seq, &params), &params),
+      vec![]
+    );
+  }
+}
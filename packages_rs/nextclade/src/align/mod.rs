@@ -1,12 +1,17 @@
 pub mod align;
+pub mod align_checkpointed;
 pub mod backtrace;
 pub mod band_2d;
 pub mod gap_open;
 pub mod insertions_strip;
+pub mod inversions;
 pub mod params;
 pub mod remove_gaps;
 pub mod score_matrix;
 pub mod score_matrix_aa;
+pub mod score_matrix_custom;
 pub mod score_matrix_nuc;
+pub mod score_matrix_simd;
 pub mod seed_alignment;
+pub mod seed_chain;
 pub mod seed_match;
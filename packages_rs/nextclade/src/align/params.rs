@@ -1,3 +1,6 @@
+use crate::align::score_matrix_custom::ScoringMatrix;
+use crate::io::aa::Aa;
+use crate::io::nuc::Nuc;
 use clap::{ArgEnum, Parser};
 use optfield::optfield;
 use serde::{Deserialize, Serialize};
@@ -9,6 +12,13 @@ pub enum GapAlignmentSide {
   Right,
 }
 
+#[derive(ArgEnum, Copy, Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeedMethod {
+  FixedSpacing,
+  Minimizer,
+}
+
 // NOTE: The `optfield` attribute creates a struct that have the same fields, but which are wrapped into `Option`,
 // as well as adds a method `.merge_opt(&opt)` to the original struct, which merges values from the optional counterpart
 // into self (mutably).
@@ -47,14 +57,34 @@ pub struct AlignPairwiseParams {
   #[clap(long)]
   pub score_match: i32,
 
-  /// Maximum length of insertions or deletions allowed to proceed with alignment. Alignments with long indels are slow to compute and require substantial memory in the current implementation. Alignment of sequences with indels longer that this value, will not be attempted and a warning will be emitted.
+  /// Path to a JSON file with a custom scoring matrix for nucleotide alignment, for example, with different scores for transitions and transversions. The file should contain an object with the `alphabet` string, listing every nucleotide character except the gap, and the `scores` array, containing one row of scores for each of the characters. The matrix must be symmetric. If provided, replaces `--score-match` and `--penalty-mismatch` in nucleotide alignment.
+  #[clap(long, parse(try_from_str = ScoringMatrix::<Nuc>::from_path))]
+  pub scoring_matrix_nuc: Option<ScoringMatrix<Nuc>>,
+
+  /// As `--scoring-matrix-nuc`, but for amino acid alignment, for example, a BLOSUM or PAM matrix. The alphabet should list every amino acid character, including the stop codon `*`, except the gap.
+  #[clap(long, parse(try_from_str = ScoringMatrix::<Aa>::from_path))]
+  pub scoring_matrix_aa: Option<ScoringMatrix<Aa>>,
+
+  /// Maximum length of insertions or deletions allowed to proceed with alignment. Alignments with long indels are slow to compute. Alignment of sequences with indels longer that this value, will not be attempted and a warning will be emitted. Memory usage of alignments with long indels is bounded by `--max-band-area`.
   #[clap(long)]
   pub max_indel: usize,
 
+  /// Maximum number of cells in the alignment band (each cell takes 5 bytes of memory). If the band of a sequence is larger, a memory-efficient alignment algorithm is used instead, which is roughly 2 times slower, but needs only a small fraction of memory. Large bands are usually the consequence of long insertions or deletions, and of increased `--max-indel`.
+  #[clap(long)]
+  pub max_band_area: usize,
+
   /// k-mer length to determine approximate alignments between query and reference and determine the bandwidth of the banded alignment.
   #[clap(long)]
   pub seed_length: usize,
 
+  /// Method to find seeds, which determine the band of the banded alignment. `fixed-spacing` matches k-mers of the query, taken at regular intervals, against the reference, allowing for a few mismatches. `minimizer` matches query minimizers against an index of reference minimizers, and chains the matches, which is more robust for divergent sequences and for sequences with long indels.
+  #[clap(long, arg_enum)]
+  pub seed_method: SeedMethod,
+
+  /// Number of consecutive k-mers from which one minimizer is chosen, when using `--seed-method=minimizer`. Smaller windows result in more seeds, at the cost of speed.
+  #[clap(long)]
+  pub minimizer_window: usize,
+
   /// Maximum number of mismatching nucleotides allowed for a seed to be considered a match.
   #[clap(long)]
   pub mismatches_allowed: usize,
@@ -76,6 +106,11 @@ pub struct AlignPairwiseParams {
   #[clap(takes_value = false, forbid_empty_values = false, default_missing_value = "true")]
   pub retry_reverse_complement: bool,
 
+  /// Detect regions of the query sequence which are reverse-complemented relative to the reference, while the rest of the sequence is not (inversions), and align these regions in the orientation of the reference. Detection uses minimizer seed matches, controlled by `--seed-length` and `--minimizer-window`. Detected inversions are reported in the outputs.
+  #[clap(long)]
+  #[clap(takes_value = false, forbid_empty_values = false, default_missing_value = "true")]
+  pub detect_inversions: bool,
+
   /// If this flag is present, the amino acid sequences will be truncated at the first stop codon, if mutations or sequencing errors cause premature stop codons to be present. No amino acid mutations in the truncated region will be recorded.
   #[clap(long)]
   #[clap(takes_value = false, forbid_empty_values = false, default_missing_value = "true")]
@@ -113,13 +148,19 @@ impl Default for AlignPairwiseParams {
       penalty_gap_open_out_of_frame: 8,
       penalty_mismatch: 1,
       score_match: 3,
+      scoring_matrix_nuc: None,
+      scoring_matrix_aa: None,
       max_indel: 400,
+      max_band_area: 50_000_000,
       seed_length: 21,
+      seed_method: SeedMethod::FixedSpacing,
+      minimizer_window: 10,
       min_seeds: 10,
       min_match_rate: 0.3,
       seed_spacing: 100,
       mismatches_allowed: 3,
       retry_reverse_complement: false,
+      detect_inversions: false,
       no_translate_past_stop: false,
       left_terminal_gaps_free: true,
       right_terminal_gaps_free: true,
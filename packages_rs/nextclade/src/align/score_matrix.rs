@@ -1,5 +1,7 @@
 use crate::align::band_2d::{Band2d, Stripe};
 use crate::align::params::{AlignPairwiseParams, GapAlignmentSide};
+use crate::align::score_matrix_custom::MatchScores;
+use crate::align::score_matrix_simd::score_matrix_simd;
 use crate::io::letter::Letter;
 use log::trace;
 
@@ -12,19 +14,42 @@ pub const QRY_GAP_MATRIX: i8 = 1 << 2;
 pub const REF_GAP_EXTEND: i8 = 1 << 3;
 pub const QRY_GAP_EXTEND: i8 = 1 << 4;
 
-const NO_ALIGN: i32 = -1_000_000_000; //very negative to be able to process unalignable seqs
+pub(crate) const NO_ALIGN: i32 = -1_000_000_000; //very negative to be able to process unalignable seqs
 
 pub struct ScoreMatrixResult {
   pub scores: Band2d<i32>,
   pub paths: Band2d<i8>,
 }
 
+/// Computes alignment scores and backtrace paths in the band defined by `stripes`.
+///
+/// Uses the vectorized implementation where available, and the scalar implementation otherwise (e.g. in WebAssembly).
+/// Both produce identical results.
 pub fn score_matrix<T: Letter<T>>(
   qry_seq: &[T],
   ref_seq: &[T],
   gap_open_close: &[i32],
   stripes: &[Stripe],
   params: &AlignPairwiseParams,
+) -> ScoreMatrixResult {
+  #[cfg(not(target_arch = "wasm32"))]
+  {
+    score_matrix_simd(qry_seq, ref_seq, gap_open_close, stripes, params)
+  }
+
+  #[cfg(target_arch = "wasm32")]
+  {
+    score_matrix_scalar(qry_seq, ref_seq, gap_open_close, stripes, params)
+  }
+}
+
+/// Computes the score matrix row by row, cell by cell
+pub fn score_matrix_scalar<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
 ) -> ScoreMatrixResult {
   let query_size = qry_seq.len();
   let ref_len = ref_seq.len();
@@ -39,11 +64,6 @@ pub fn score_matrix<T: Letter<T>>(
 
   trace!("Score matrix: allocated alignment band of size={band_size}");
 
-  let left_align = match params.gap_alignment_side {
-    GapAlignmentSide::Left => 1,
-    GapAlignmentSide::Right => 0,
-  };
-
   // fill scores with alignment scores
   // if the colon marks the position in the sequence before rPos,qPos
   // R: ...ACT:X
@@ -55,139 +75,194 @@ pub fn score_matrix<T: Letter<T>>(
   // 2) if X is a base and Y is '-', rPos advances the same and the shift increases
   //    -> diagonal step in the matrix from (ri,si-1) to (ri+1,si)
 
-  paths[(0, 0)] = 0;
-  scores[(0, 0)] = 0;
+  score_matrix_first_row(gap_open_close, &stripes[0], params, scores.row_mut(0), paths.row_mut(0));
+
+  let match_scores = MatchScores::<T>::new(params);
+  let mut qry_gaps = vec![NO_ALIGN; n_cols];
+
+  // Iterate over rows
+  for ri in 1..=ref_len {
+    let (prev_row_scores, row_scores) = scores.row_with_previous_mut(ri);
+    score_matrix_row(
+      ri,
+      qry_seq,
+      ref_seq,
+      gap_open_close,
+      stripes,
+      params,
+      &match_scores,
+      prev_row_scores,
+      &mut qry_gaps,
+      row_scores,
+      paths.row_mut(ri),
+    );
+  }
+
+  ScoreMatrixResult { scores, paths }
+}
+
+/// Initializes the first row of the score matrix.
+///
+/// Row storage is indexed by `qpos - stripe.begin`, as in `Band2d`.
+pub(crate) fn score_matrix_first_row(
+  gap_open_close: &[i32],
+  stripe: &Stripe,
+  params: &AlignPairwiseParams,
+  scores: &mut [i32],
+  paths: &mut [i8],
+) {
+  paths[0] = 0;
+  scores[0] = 0;
 
   // Initialize first row (start at + 1 since [(0,0)] is already set)
-  for qpos in (stripes[0].begin + 1)..stripes[0].end {
-    paths[(0, qpos)] = REF_GAP_EXTEND + REF_GAP_MATRIX;
+  for qpos in (stripe.begin + 1)..stripe.end {
+    let i = qpos - stripe.begin;
+    paths[i] = REF_GAP_EXTEND + REF_GAP_MATRIX;
     if params.left_terminal_gaps_free {
       // Left terminal qry insertion  is free
-      scores[(0, qpos)] = 0;
+      scores[i] = 0;
     } else {
       // Left terminal qry insertion is not free
       // TODO: Consider whether qry insertion should ever be free, not only qry deletion!
       if qpos == 1 {
-        scores[(0, 1)] = -gap_open_close[0];
+        scores[i] = -gap_open_close[0];
       } else {
-        scores[(0, qpos)] = scores[(0, qpos - 1)] - params.penalty_gap_extend;
+        scores[i] = scores[i - 1] - params.penalty_gap_extend;
       }
     }
   }
-  let mut qry_gaps = vec![NO_ALIGN; n_cols];
+}
 
-  // Iterate over rows
-  for ri in 1..=ref_len {
-    let mut ref_gaps = NO_ALIGN;
-
-    for qpos in stripes[ri].begin..stripes[ri].end {
-      let mut tmp_path = 0;
-      let mut score = NO_ALIGN; // Needs to be very negative so that one path is always the best
-      let mut origin = 0;
-      let q_gap_extend: i32;
-      let r_gap_extend: i32;
-      let r_gap_open: i32;
-      let q_gap_open: i32;
-      let tmp_match: i32;
-      let mut tmp_score: i32;
-
-      if qpos == 0 {
-        // Initialize first column
-        // precedes query sequence -- no score, origin is query gap
-        tmp_path = QRY_GAP_EXTEND;
-        origin = QRY_GAP_MATRIX;
-        if params.left_terminal_gaps_free {
-          // Left terminal qry gap is free
-          score = 0;
-        } else {
-          // Left terminal qry gap is not free
-          if ri == 1 {
-            score = -gap_open_close[0];
-          } else {
-            score = scores[(ri - 1, 0)] - params.penalty_gap_extend;
-          }
-        }
+/// Computes row `ri > 0` of the score matrix, given the scores in the preceding row.
+///
+/// Row storage is indexed by `qpos - stripe.begin`, as in `Band2d`. Query gap scores `qry_gaps` are indexed by
+/// `qpos` and are carried over from row to row.
+pub(crate) fn score_matrix_row<T: Letter<T>>(
+  ri: usize,
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+  match_scores: &MatchScores<T>,
+  prev_row_scores: &[i32],
+  qry_gaps: &mut [i32],
+  scores: &mut [i32],
+  paths: &mut [i8],
+) {
+  let query_size = qry_seq.len();
+  let ref_len = ref_seq.len();
+
+  let left_align = match params.gap_alignment_side {
+    GapAlignmentSide::Left => 1,
+    GapAlignmentSide::Right => 0,
+  };
+
+  let begin = stripes[ri].begin;
+  let prev_begin = stripes[ri - 1].begin;
+
+  let mut ref_gaps = NO_ALIGN;
+
+  for qpos in stripes[ri].begin..stripes[ri].end {
+    let mut tmp_path = 0;
+    let mut score = NO_ALIGN; // Needs to be very negative so that one path is always the best
+    let mut origin = 0;
+    let q_gap_extend: i32;
+    let r_gap_extend: i32;
+    let r_gap_open: i32;
+    let q_gap_open: i32;
+    let mut tmp_score: i32;
+
+    if qpos == 0 {
+      // Initialize first column
+      // precedes query sequence -- no score, origin is query gap
+      tmp_path = QRY_GAP_EXTEND;
+      origin = QRY_GAP_MATRIX;
+      if params.left_terminal_gaps_free {
+        // Left terminal qry gap is free
+        score = 0;
       } else {
-        // if the position is within the query sequence
-        // no gap -- match case
-
-        // TODO: Double bounds check -> wasteful, make better
-        if qpos > stripes[ri - 1].begin && qpos - 1 < stripes[ri - 1].end {
-          // ^ If stripes allow to move up diagonally to upper left
-          if T::lookup_match_score(qry_seq[qpos - 1], ref_seq[ri - 1]) > 0 {
-            score = scores[(ri - 1, qpos - 1)] + params.score_match;
-          } else {
-            score = scores[(ri - 1, qpos - 1)] - params.penalty_mismatch;
-          };
-          origin = MATCH;
+        // Left terminal qry gap is not free
+        if ri == 1 {
+          score = -gap_open_close[0];
+        } else {
+          score = prev_row_scores[0] - params.penalty_gap_extend;
         }
+      }
+    } else {
+      // if the position is within the query sequence
+      // no gap -- match case
+
+      // TODO: Double bounds check -> wasteful, make better
+      if qpos > stripes[ri - 1].begin && qpos - 1 < stripes[ri - 1].end {
+        // ^ If stripes allow to move up diagonally to upper left
+        score = prev_row_scores[qpos - 1 - prev_begin] + match_scores.get(qry_seq[qpos - 1], ref_seq[ri - 1]);
+        origin = MATCH;
+      }
 
-        // check the scores of a reference gap
-        // if qpos == stripes.begin: ref gap not allowed
-        // thus path skipped
-        if qpos > stripes[ri].begin {
-          if ri != ref_len || !params.right_terminal_gaps_free {
-            //normal case, not at end of ref sequence
-            r_gap_extend = ref_gaps - params.penalty_gap_extend;
-            r_gap_open = scores[(ri, qpos - 1)] - gap_open_close[ri];
-          } else {
-            // at end of ref sequence if right terminal gaps are free
-            // TODO: Consider whether qry insertion should ever be free, not only qry deletion!
-            r_gap_extend = ref_gaps;
-            r_gap_open = scores[(ri, qpos - 1)];
-          }
-          if r_gap_extend >= r_gap_open && qpos > stripes[ri].begin + 1 {
-            // extension better than opening (and ^ extension allowed positionally)
-            tmp_score = r_gap_extend;
-            tmp_path = REF_GAP_EXTEND;
-          } else {
-            // opening better than extension
-            tmp_score = r_gap_open;
-          }
-          // could factor out tmp_score, replacing with ref_gaps but maybe less readable
-          ref_gaps = tmp_score;
-          if score + left_align < tmp_score {
-            score = tmp_score;
-            origin = REF_GAP_MATRIX;
-          }
+      // check the scores of a reference gap
+      // if qpos == stripes.begin: ref gap not allowed
+      // thus path skipped
+      if qpos > stripes[ri].begin {
+        if ri != ref_len || !params.right_terminal_gaps_free {
+          //normal case, not at end of ref sequence
+          r_gap_extend = ref_gaps - params.penalty_gap_extend;
+          r_gap_open = scores[qpos - 1 - begin] - gap_open_close[ri];
+        } else {
+          // at end of ref sequence if right terminal gaps are free
+          // TODO: Consider whether qry insertion should ever be free, not only qry deletion!
+          r_gap_extend = ref_gaps;
+          r_gap_open = scores[qpos - 1 - begin];
         }
-
-        // check the scores of a query gap
-        if qpos < stripes[ri - 1].end {
-          // need stripe above to move from, otherwise no scores[(ri-1, qpos)] not existing
-          if qpos != query_size || !params.right_terminal_gaps_free {
-            //normal case, not at end of query sequence
-            q_gap_extend = qry_gaps[qpos] - params.penalty_gap_extend;
-            q_gap_open = scores[(ri - 1, qpos)] - gap_open_close[ri - 1];
-          } else {
-            //end of query sequence make right terminal gap free
-            q_gap_extend = qry_gaps[qpos];
-            q_gap_open = scores[(ri - 1, qpos)];
-          }
-          if q_gap_extend >= q_gap_open && qpos < stripes[ri - 2].end {
-            // extension better than opening (and ^ extension allowed positionally)
-            tmp_score = q_gap_extend;
-            tmp_path += QRY_GAP_EXTEND;
-          } else {
-            tmp_score = q_gap_open;
-          }
-          qry_gaps[qpos] = tmp_score;
-          if score + left_align < tmp_score {
-            score = tmp_score;
-            origin = QRY_GAP_MATRIX;
-          }
+        if r_gap_extend >= r_gap_open && qpos > stripes[ri].begin + 1 {
+          // extension better than opening (and ^ extension allowed positionally)
+          tmp_score = r_gap_extend;
+          tmp_path = REF_GAP_EXTEND;
         } else {
-          qry_gaps[qpos] = NO_ALIGN;
+          // opening better than extension
+          tmp_score = r_gap_open;
+        }
+        // could factor out tmp_score, replacing with ref_gaps but maybe less readable
+        ref_gaps = tmp_score;
+        if score + left_align < tmp_score {
+          score = tmp_score;
+          origin = REF_GAP_MATRIX;
         }
       }
 
-      tmp_path += origin;
-      paths[(ri, qpos)] = tmp_path;
-      scores[(ri, qpos)] = score;
+      // check the scores of a query gap
+      if qpos < stripes[ri - 1].end {
+        // need stripe above to move from, otherwise no scores[(ri-1, qpos)] not existing
+        if qpos != query_size || !params.right_terminal_gaps_free {
+          //normal case, not at end of query sequence
+          q_gap_extend = qry_gaps[qpos] - params.penalty_gap_extend;
+          q_gap_open = prev_row_scores[qpos - prev_begin] - gap_open_close[ri - 1];
+        } else {
+          //end of query sequence make right terminal gap free
+          q_gap_extend = qry_gaps[qpos];
+          q_gap_open = prev_row_scores[qpos - prev_begin];
+        }
+        if q_gap_extend >= q_gap_open && qpos < stripes[ri - 2].end {
+          // extension better than opening (and ^ extension allowed positionally)
+          tmp_score = q_gap_extend;
+          tmp_path += QRY_GAP_EXTEND;
+        } else {
+          tmp_score = q_gap_open;
+        }
+        qry_gaps[qpos] = tmp_score;
+        if score + left_align < tmp_score {
+          score = tmp_score;
+          origin = QRY_GAP_MATRIX;
+        }
+      } else {
+        qry_gaps[qpos] = NO_ALIGN;
+      }
     }
-  }
 
-  ScoreMatrixResult { scores, paths }
+    tmp_path += origin;
+    paths[qpos - begin] = tmp_path;
+    scores[qpos - begin] = score;
+  }
 }
 
 #[cfg(test)]
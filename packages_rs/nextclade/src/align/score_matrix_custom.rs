@@ -0,0 +1,268 @@
+use crate::align::params::AlignPairwiseParams;
+use crate::io::fs::read_file_to_string;
+use crate::io::json::json_parse;
+use crate::io::letter::Letter;
+use crate::make_error;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Raw JSON version of the `ScoringMatrix` struct
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoringMatrixRaw {
+  /// Letters corresponding to rows and columns of the matrix, in order
+  pub alphabet: String,
+  pub scores: Vec<Vec<i32>>,
+}
+
+/// Scoring matrix for nucleotide or amino acid alignment, provided by the user.
+///
+/// Contains scores for every pair of letters of the alphabet, except for gaps. Scores involving gaps, if not
+/// provided, are the same as in the default scoring (`--score-match` and `--penalty-mismatch`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ScoringMatrixRaw", into = "ScoringMatrixRaw", bound = "L: Letter<L>")]
+pub struct ScoringMatrix<L> {
+  alphabet: Vec<L>,
+  scores: Vec<Vec<i32>>,
+  /// Scores indexed by `L::alphabet_index()` of both letters
+  lookup: Vec<Option<i32>>,
+}
+
+impl<L: Letter<L>> ScoringMatrix<L> {
+  pub fn from_path(filepath: impl AsRef<Path>) -> Result<Self, Report> {
+    let filepath = filepath.as_ref();
+    let data =
+      read_file_to_string(filepath).wrap_err_with(|| format!("When reading scoring matrix file {filepath:#?}"))?;
+    Self::from_str(&data).wrap_err_with(|| format!("When parsing scoring matrix file {filepath:#?}"))
+  }
+
+  #[inline]
+  pub fn get(&self, x: L, y: L) -> Option<i32> {
+    self.lookup[L::alphabet_index(x) * L::ALPHABET.len() + L::alphabet_index(y)]
+  }
+}
+
+impl<L: Letter<L>> FromStr for ScoringMatrix<L> {
+  type Err = Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::try_from(json_parse::<ScoringMatrixRaw>(s)?)
+  }
+}
+
+impl<L: Letter<L>> TryFrom<ScoringMatrixRaw> for ScoringMatrix<L> {
+  type Error = Report;
+
+  fn try_from(raw: ScoringMatrixRaw) -> Result<Self, Self::Error> {
+    let ScoringMatrixRaw { alphabet, scores } = raw;
+
+    let alphabet = alphabet
+      .chars()
+      .map(|c| L::from_string(&c.to_string()))
+      .collect::<Result<Vec<L>, Report>>()
+      .wrap_err("When parsing scoring matrix alphabet")?;
+
+    for (i, letter) in alphabet.iter().enumerate() {
+      if alphabet[..i].contains(letter) {
+        return make_error!(
+          "Scoring matrix alphabet contains letter '{}' more than once",
+          L::from_seq(&[*letter])
+        );
+      }
+    }
+
+    let missing = L::ALPHABET
+      .iter()
+      .filter(|letter| !letter.is_gap() && !alphabet.contains(letter))
+      .copied()
+      .collect_vec();
+    if !missing.is_empty() {
+      return make_error!(
+        "Scoring matrix alphabet is incomplete: letters '{}' are missing. Scores are required for all letters, except for the gap.",
+        L::from_seq(&missing)
+      );
+    }
+
+    if scores.len() != alphabet.len() {
+      return make_error!(
+        "Scoring matrix has {} rows, but the alphabet has {} letters. Expected one row per letter.",
+        scores.len(),
+        alphabet.len()
+      );
+    }
+
+    for (i, row) in scores.iter().enumerate() {
+      if row.len() != alphabet.len() {
+        return make_error!(
+          "Scoring matrix row for letter '{}' has {} columns, but the alphabet has {} letters. Expected one column per letter.",
+          L::from_seq(&[alphabet[i]]),
+          row.len(),
+          alphabet.len()
+        );
+      }
+    }
+
+    for (i, j) in (0..alphabet.len()).tuple_combinations() {
+      if scores[i][j] != scores[j][i] {
+        return make_error!(
+          "Scoring matrix is not symmetric: score of '{x}' against '{y}' is {}, but score of '{y}' against '{x}' is {}",
+          scores[i][j],
+          scores[j][i],
+          x = L::from_seq(&[alphabet[i]]),
+          y = L::from_seq(&[alphabet[j]]),
+        );
+      }
+    }
+
+    let num_letters = L::ALPHABET.len();
+    let mut lookup = vec![None; num_letters * num_letters];
+    for (i, &x) in alphabet.iter().enumerate() {
+      for (j, &y) in alphabet.iter().enumerate() {
+        lookup[L::alphabet_index(x) * num_letters + L::alphabet_index(y)] = Some(scores[i][j]);
+      }
+    }
+
+    Ok(Self {
+      alphabet,
+      scores,
+      lookup,
+    })
+  }
+}
+
+impl<L: Letter<L>> From<ScoringMatrix<L>> for ScoringMatrixRaw {
+  fn from(matrix: ScoringMatrix<L>) -> Self {
+    Self {
+      alphabet: L::from_seq(&matrix.alphabet),
+      scores: matrix.scores,
+    }
+  }
+}
+
+/// Scores of aligning every pair of letters, as used when computing the score matrix
+pub struct MatchScores<T> {
+  scores: Vec<i32>,
+  _letter: PhantomData<T>,
+}
+
+impl<T: Letter<T>> MatchScores<T> {
+  /// Takes scores from the custom scoring matrix, if one is provided in the parameters. Otherwise, and for pairs the
+  /// custom matrix does not contain, the score is `score_match` for matching letters and `-penalty_mismatch` for
+  /// mismatching letters.
+  pub fn new(params: &AlignPairwiseParams) -> Self {
+    let custom = T::custom_scoring_matrix(params);
+    let scores = T::ALPHABET
+      .iter()
+      .cartesian_product(T::ALPHABET.iter())
+      .map(|(&x, &y)| {
+        custom.and_then(|matrix| matrix.get(x, y)).unwrap_or_else(|| {
+          if T::lookup_match_score(x, y) > 0 {
+            params.score_match
+          } else {
+            -params.penalty_mismatch
+          }
+        })
+      })
+      .collect();
+
+    Self {
+      scores,
+      _letter: PhantomData,
+    }
+  }
+
+  #[inline]
+  pub fn get(&self, x: T, y: T) -> i32 {
+    self.scores[T::alphabet_index(x) * T::ALPHABET.len() + T::alphabet_index(y)]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::aa::Aa;
+  use crate::io::nuc::Nuc;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use serde_json::json;
+
+  const ALPHABET_NUC: &str = "ACGTRYSWKMBDHVN";
+
+  fn matrix_json(alphabet: &str, scores: &[Vec<i32>]) -> String {
+    json!({ "alphabet": alphabet, "scores": scores }).to_string()
+  }
+
+  fn transition_transversion_matrix() -> String {
+    // Transitions (A<->G, C<->T) are penalized less than transversions, ambiguous nucleotides are neutral
+    let scores = ALPHABET_NUC
+      .chars()
+      .map(|x| {
+        ALPHABET_NUC
+          .chars()
+          .map(|y| match (x, y) {
+            _ if !"ACGT".contains(x) || !"ACGT".contains(y) => 0,
+            _ if x == y => 5,
+            ('A', 'G') | ('G', 'A') | ('C', 'T') | ('T', 'C') => -1,
+            _ => -4,
+          })
+          .collect_vec()
+      })
+      .collect_vec();
+    matrix_json(ALPHABET_NUC, &scores)
+  }
+
+  #[rstest]
+  fn parses_nuc_matrix() -> Result<(), Report> {
+    let matrix = ScoringMatrix::<Nuc>::from_str(&transition_transversion_matrix())?;
+    assert_eq!(matrix.get(Nuc::A, Nuc::A), Some(5));
+    assert_eq!(matrix.get(Nuc::A, Nuc::G), Some(-1));
+    assert_eq!(matrix.get(Nuc::T, Nuc::G), Some(-4));
+    assert_eq!(matrix.get(Nuc::N, Nuc::G), Some(0));
+    assert_eq!(matrix.get(Nuc::Gap, Nuc::G), None);
+    Ok(())
+  }
+
+  #[rstest]
+  fn uses_custom_scores_and_defaults_for_gaps() -> Result<(), Report> {
+    let params = AlignPairwiseParams {
+      scoring_matrix_nuc: Some(ScoringMatrix::from_str(&transition_transversion_matrix())?),
+      ..AlignPairwiseParams::default()
+    };
+    let match_scores = MatchScores::<Nuc>::new(&params);
+    assert_eq!(match_scores.get(Nuc::C, Nuc::T), -1);
+    assert_eq!(match_scores.get(Nuc::Gap, Nuc::Gap), params.score_match);
+    assert_eq!(match_scores.get(Nuc::Gap, Nuc::T), -params.penalty_mismatch);
+
+    // Amino acid scores are not affected by the nucleotide matrix
+    let match_scores = MatchScores::<Aa>::new(&params);
+    assert_eq!(match_scores.get(Aa::L, Aa::L), params.score_match);
+    assert_eq!(match_scores.get(Aa::L, Aa::I), -params.penalty_mismatch);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case::incomplete("ACGT", vec![vec![0; 4]; 4], "letters 'WYMHKRDSBVN' are missing")]
+  #[case::duplicate("ACGTRYSWKMBDHVNA", vec![vec![0; 16]; 16], "more than once")]
+  #[case::unknown_letter("ACGTRYSWKMBDHVNZ", vec![vec![0; 16]; 16], "alphabet")]
+  #[case::wrong_num_rows(ALPHABET_NUC, vec![vec![0; 15]; 14], "14 rows")]
+  #[case::wrong_num_cols(ALPHABET_NUC, vec![vec![0; 14]; 15], "14 columns")]
+  fn rejects_invalid_matrix(#[case] alphabet: &str, #[case] scores: Vec<Vec<i32>>, #[case] message: &str) {
+    let error = ScoringMatrix::<Nuc>::from_str(&matrix_json(alphabet, &scores)).unwrap_err();
+    assert!(format!("{error:?}").contains(message), "{error:?}");
+  }
+
+  #[rstest]
+  fn rejects_asymmetric_matrix() {
+    let mut scores = vec![vec![0; 15]; 15];
+    scores[0][1] = 1;
+    let error = ScoringMatrix::<Nuc>::from_str(&matrix_json(ALPHABET_NUC, &scores)).unwrap_err();
+    assert!(
+      format!("{error:?}").contains("score of 'A' against 'C' is 1, but score of 'C' against 'A' is 0"),
+      "{error:?}"
+    );
+  }
+}
@@ -0,0 +1,457 @@
+use crate::align::band_2d::{Band2d, Stripe};
+use crate::align::params::{AlignPairwiseParams, GapAlignmentSide};
+use crate::align::score_matrix::{
+  score_matrix_first_row, ScoreMatrixResult, MATCH, NO_ALIGN, QRY_GAP_EXTEND, QRY_GAP_MATRIX, REF_GAP_EXTEND,
+  REF_GAP_MATRIX,
+};
+use crate::align::score_matrix_custom::MatchScores;
+use crate::io::letter::Letter;
+use log::trace;
+
+/// Computes the same score matrix as `score_matrix_scalar()`, but fills it along anti-diagonals.
+///
+/// Cells on the same anti-diagonal (`ri + qpos == d`) only depend on cells of the two preceding anti-diagonals, so
+/// they can be computed independently of each other, in SIMD lanes. Cells of an anti-diagonal are indexed by row and
+/// are stored contiguously in per-diagonal buffers, so that the inner loop consists of plain element-wise operations
+/// on slices, which the compiler vectorizes. On x86 the kernel is additionally compiled for AVX2, and this version is
+/// selected at runtime if the CPU supports it.
+///
+/// The result (scores and paths) is bit-identical to the result of `score_matrix_scalar()`.
+pub fn score_matrix_simd<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+) -> ScoreMatrixResult {
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+  {
+    if is_x86_feature_detected!("avx2") {
+      // SAFETY: the CPU supports AVX2, as checked above
+      return unsafe { score_matrix_simd_avx2(qry_seq, ref_seq, gap_open_close, stripes, params) };
+    }
+  }
+
+  score_matrix_antidiagonal(qry_seq, ref_seq, gap_open_close, stripes, params)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn score_matrix_simd_avx2<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+) -> ScoreMatrixResult {
+  score_matrix_antidiagonal(qry_seq, ref_seq, gap_open_close, stripes, params)
+}
+
+/// Per-row values needed to compute a cell, laid out contiguously by row,
+/// so that they can be loaded into vector lanes directly.
+struct RowConstants {
+  row: Vec<i32>,
+  begin: Vec<i32>,
+  prev_begin: Vec<i32>,
+  prev_end: Vec<i32>,
+  prev2_end: Vec<i32>,
+  ref_gap_open: Vec<i32>,
+  ref_gap_extend: Vec<i32>,
+  qry_gap_open: Vec<i32>,
+}
+
+impl RowConstants {
+  fn new(stripes: &[Stripe], gap_open_close: &[i32], ref_len: usize, params: &AlignPairwiseParams) -> Self {
+    let n_rows = ref_len + 1;
+    let mut rows = Self {
+      row: (0..n_rows).map(|ri| ri as i32).collect(),
+      begin: stripes.iter().map(|stripe| stripe.begin as i32).collect(),
+      prev_begin: vec![0; n_rows],
+      prev_end: vec![0; n_rows],
+      // Query gap extension is never allowed in the first row: there is no row `ri - 2`
+      prev2_end: vec![0; n_rows],
+      ref_gap_open: vec![0; n_rows],
+      ref_gap_extend: vec![0; n_rows],
+      qry_gap_open: vec![0; n_rows],
+    };
+
+    for ri in 1..n_rows {
+      rows.prev_begin[ri] = stripes[ri - 1].begin as i32;
+      rows.prev_end[ri] = stripes[ri - 1].end as i32;
+      if ri >= 2 {
+        rows.prev2_end[ri] = stripes[ri - 2].end as i32;
+      }
+      if ri != ref_len || !params.right_terminal_gaps_free {
+        rows.ref_gap_open[ri] = gap_open_close[ri];
+        rows.ref_gap_extend[ri] = params.penalty_gap_extend;
+      }
+      rows.qry_gap_open[ri] = gap_open_close[ri - 1];
+    }
+
+    rows
+  }
+}
+
+#[inline(always)]
+#[allow(clippy::inline_always)] // must be inlined into the `#[target_feature]` wrapper to be compiled for it
+fn score_matrix_antidiagonal<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+) -> ScoreMatrixResult {
+  let query_size = qry_seq.len();
+  let ref_len = ref_seq.len();
+  let n_rows = ref_len + 1;
+  let n_cols = query_size + 1;
+
+  trace!("Score matrix (SIMD): started: query_size={query_size}, ref_len={ref_len}, n_rows={n_rows}, n_cols={n_cols}");
+
+  let mut paths = Band2d::<i8>::new(stripes);
+  let mut scores = Band2d::<i32>::new(stripes);
+
+  trace!(
+    "Score matrix (SIMD): allocated alignment band of size={}",
+    paths.data_len()
+  );
+
+  let left_align = match params.gap_alignment_side {
+    GapAlignmentSide::Left => 1,
+    GapAlignmentSide::Right => 0,
+  };
+
+  score_matrix_first_row(gap_open_close, &stripes[0], params, scores.row_mut(0), paths.row_mut(0));
+
+  let rows = RowConstants::new(stripes, gap_open_close, ref_len, params);
+
+  // Cell `(ri, d - ri)` is stored at `band_offsets[ri] + d` in the sparse storage of the band, if `d < diag_ends[ri]`
+  let mut band_offsets = vec![0_usize; n_rows];
+  let mut diag_ends = vec![0_usize; n_rows];
+  let mut row_start = 0_usize;
+  for (ri, stripe) in stripes.iter().enumerate() {
+    band_offsets[ri] = row_start.wrapping_sub(stripe.begin).wrapping_sub(ri);
+    diag_ends[ri] = ri + stripe.end;
+    row_start += stripe.len();
+  }
+
+  // Rows `0..=ri` are all finished before anti-diagonal `max_end[ri]`
+  let mut max_end = vec![0_usize; n_rows];
+  for ri in 0..n_rows {
+    max_end[ri] = if ri == 0 {
+      diag_ends[ri]
+    } else {
+      max_end[ri - 1].max(diag_ends[ri])
+    };
+  }
+
+  // Along an anti-diagonal, query position decreases as ref position increases
+  let qry_rev: Vec<T> = qry_seq.iter().rev().copied().collect();
+
+  // Scores, ref gap and query gap scores on the current and on the preceding anti-diagonals, indexed by row
+  let mut scores_cur = vec![NO_ALIGN; n_rows];
+  let mut scores_prev = vec![NO_ALIGN; n_rows];
+  let mut scores_prev2 = vec![NO_ALIGN; n_rows];
+  let mut ref_gaps_cur = vec![NO_ALIGN; n_rows];
+  let mut ref_gaps_prev = vec![NO_ALIGN; n_rows];
+  let mut qry_gaps_cur = vec![NO_ALIGN; n_rows];
+  let mut qry_gaps_prev = vec![NO_ALIGN; n_rows];
+
+  // Cell (0, 0) is the only cell on the anti-diagonal 0
+  scores_prev[0] = 0;
+
+  let letter_scores = MatchScores::<T>::new(params);
+  let mut match_scores = vec![0_i32; n_rows];
+  let mut lane_paths = vec![0_i8; n_rows];
+
+  // Range of rows `lo..=hi` crossed by the current anti-diagonal
+  let mut lo = 0_usize;
+  let mut hi = 0_usize;
+
+  for d in 1..=(ref_len + query_size) {
+    while hi < ref_len && hi + 1 + stripes[hi + 1].begin <= d {
+      hi += 1;
+    }
+    while lo <= hi && max_end[lo] <= d {
+      lo += 1;
+    }
+
+    // First row is already initialized. It never has query gaps.
+    if lo == 0 {
+      scores_cur[0] = scores[(0, d)];
+      qry_gaps_cur[0] = NO_ALIGN;
+    }
+
+    // First column: precedes query sequence -- no score, origin is query gap
+    if hi == d && stripes[d].end > 0 {
+      let score = if params.left_terminal_gaps_free {
+        0
+      } else if d == 1 {
+        -gap_open_close[0]
+      } else {
+        scores_prev[d - 1] - params.penalty_gap_extend
+      };
+      paths[(d, 0)] = QRY_GAP_EXTEND + QRY_GAP_MATRIX;
+      scores[(d, 0)] = score;
+      scores_cur[d] = score;
+      ref_gaps_cur[d] = NO_ALIGN;
+      qry_gaps_cur[d] = NO_ALIGN;
+    }
+
+    // The remaining cells are computed in vector lanes
+    let lanes_begin = lo.max(1);
+    let lanes_end = (hi + 1).min(d);
+    if lanes_begin < lanes_end {
+      let n = lanes_end - lanes_begin;
+
+      // Match scores depend on sequence letters, which are looked up cell by cell.
+      // Note that `1 <= qpos <= query_size` in all of the lanes, including the ones outside of the band.
+      let qry_begin = query_size + lanes_begin - d;
+      let qry_letters = &qry_rev[qry_begin..(qry_begin + n)];
+      let ref_letters = &ref_seq[(lanes_begin - 1)..(lanes_end - 1)];
+      for ((match_score, &qry), &rf) in match_scores[..n].iter_mut().zip(qry_letters).zip(ref_letters) {
+        *match_score = letter_scores.get(qry, rf);
+      }
+
+      fill_lanes(
+        d as i32,
+        query_size as i32,
+        params.right_terminal_gaps_free,
+        params.penalty_gap_extend,
+        left_align,
+        &rows,
+        lanes_begin,
+        n,
+        &match_scores[..n],
+        &scores_prev2[(lanes_begin - 1)..(lanes_end - 1)],
+        &scores_prev[(lanes_begin - 1)..lanes_end],
+        &ref_gaps_prev[lanes_begin..lanes_end],
+        &qry_gaps_prev[(lanes_begin - 1)..(lanes_end - 1)],
+        &mut scores_cur[lanes_begin..lanes_end],
+        &mut ref_gaps_cur[lanes_begin..lanes_end],
+        &mut qry_gaps_cur[lanes_begin..lanes_end],
+        &mut lane_paths[..n],
+      );
+
+      // Scatter the results into the band, skipping rows whose stripes have already ended
+      let paths_data = paths.data_mut();
+      for ri in lanes_begin..lanes_end {
+        if d < diag_ends[ri] {
+          paths_data[band_offsets[ri].wrapping_add(d)] = lane_paths[ri - lanes_begin];
+        }
+      }
+      let scores_data = scores.data_mut();
+      for ri in lanes_begin..lanes_end {
+        if d < diag_ends[ri] {
+          scores_data[band_offsets[ri].wrapping_add(d)] = scores_cur[ri];
+        }
+      }
+    }
+
+    // Advance to the next anti-diagonal
+    std::mem::swap(&mut scores_prev2, &mut scores_prev);
+    std::mem::swap(&mut scores_prev, &mut scores_cur);
+    std::mem::swap(&mut ref_gaps_prev, &mut ref_gaps_cur);
+    std::mem::swap(&mut qry_gaps_prev, &mut qry_gaps_cur);
+  }
+
+  ScoreMatrixResult { scores, paths }
+}
+
+/// Computes `n` cells of the anti-diagonal `d`, in rows starting from `row_begin`, excluding the first row and the
+/// first column.
+///
+/// This is the same recurrence as in `score_matrix_scalar()`, with branches replaced by selects, so that the loop can
+/// be vectorized. Inputs are the cells of the preceding anti-diagonals:
+///  - `scores_diag`: `(ri - 1, qpos - 1)`, i.e. 2 anti-diagonals back, one row up
+///  - `scores_prev`: starting from the row above, so that `scores_prev[i]` is `(ri - 1, qpos)`
+///    and `scores_prev[i + 1]` is `(ri, qpos - 1)`
+///  - `ref_gaps_left`: `(ri, qpos - 1)`
+///  - `qry_gaps_up`: `(ri - 1, qpos)`
+#[inline(always)]
+#[allow(clippy::inline_always)] // must be inlined into the `#[target_feature]` wrapper to be compiled for it
+fn fill_lanes(
+  d: i32,
+  query_size: i32,
+  right_terminal_gaps_free: bool,
+  penalty_gap_extend: i32,
+  left_align: i32,
+  rows: &RowConstants,
+  row_begin: usize,
+  n: usize,
+  match_scores: &[i32],
+  scores_diag: &[i32],
+  scores_prev: &[i32],
+  ref_gaps_left: &[i32],
+  qry_gaps_up: &[i32],
+  scores_out: &mut [i32],
+  ref_gaps_out: &mut [i32],
+  qry_gaps_out: &mut [i32],
+  paths_out: &mut [i8],
+) {
+  let row_end = row_begin + n;
+  let row = &rows.row[row_begin..row_end];
+  let begin = &rows.begin[row_begin..row_end];
+  let prev_begin = &rows.prev_begin[row_begin..row_end];
+  let prev_end = &rows.prev_end[row_begin..row_end];
+  let prev2_end = &rows.prev2_end[row_begin..row_end];
+  let ref_gap_open = &rows.ref_gap_open[row_begin..row_end];
+  let ref_gap_extend = &rows.ref_gap_extend[row_begin..row_end];
+  let qry_gap_open = &rows.qry_gap_open[row_begin..row_end];
+
+  // Equal lengths allow the compiler to elide bounds checks in the loop below
+  let match_scores = &match_scores[..n];
+  let scores_diag = &scores_diag[..n];
+  let scores_up = &scores_prev[..n];
+  let scores_left = &scores_prev[1..=n];
+  let ref_gaps_left = &ref_gaps_left[..n];
+  let qry_gaps_up = &qry_gaps_up[..n];
+  let scores_out = &mut scores_out[..n];
+  let ref_gaps_out = &mut ref_gaps_out[..n];
+  let qry_gaps_out = &mut qry_gaps_out[..n];
+  let paths_out = &mut paths_out[..n];
+
+  for i in 0..n {
+    let qpos = d - row[i];
+
+    // Match: if stripes allow to move up diagonally to upper left
+    let can_match = qpos > prev_begin[i] && qpos <= prev_end[i];
+    let mut score = if can_match {
+      scores_diag[i] + match_scores[i]
+    } else {
+      NO_ALIGN
+    };
+    let mut origin = if can_match { MATCH } else { 0 };
+
+    // Ref gap: not allowed at the beginning of the stripe
+    let can_ref_gap = qpos > begin[i];
+    let r_gap_extend = ref_gaps_left[i] - ref_gap_extend[i];
+    let r_gap_open = scores_left[i] - ref_gap_open[i];
+    let r_extend = r_gap_extend >= r_gap_open && qpos > begin[i] + 1;
+    let r_score = if r_extend { r_gap_extend } else { r_gap_open };
+    ref_gaps_out[i] = if can_ref_gap { r_score } else { NO_ALIGN };
+    let mut tmp_path = if can_ref_gap && r_extend { REF_GAP_EXTEND } else { 0 };
+    let take_ref_gap = can_ref_gap && score + left_align < r_score;
+    score = if take_ref_gap { r_score } else { score };
+    origin = if take_ref_gap { REF_GAP_MATRIX } else { origin };
+
+    // Query gap: need stripe above to move from
+    let can_qry_gap = qpos < prev_end[i];
+    let qry_gap_free = right_terminal_gaps_free && qpos == query_size;
+    let q_gap_extend = qry_gaps_up[i] - if qry_gap_free { 0 } else { penalty_gap_extend };
+    let q_gap_open = scores_up[i] - if qry_gap_free { 0 } else { qry_gap_open[i] };
+    let q_extend = q_gap_extend >= q_gap_open && qpos < prev2_end[i];
+    let q_score = if q_extend { q_gap_extend } else { q_gap_open };
+    qry_gaps_out[i] = if can_qry_gap { q_score } else { NO_ALIGN };
+    tmp_path += if can_qry_gap && q_extend { QRY_GAP_EXTEND } else { 0 };
+    let take_qry_gap = can_qry_gap && score + left_align < q_score;
+    score = if take_qry_gap { q_score } else { score };
+    origin = if take_qry_gap { QRY_GAP_MATRIX } else { origin };
+
+    scores_out[i] = score;
+    paths_out[i] = tmp_path + origin;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  #![allow(clippy::needless_pass_by_value)] // rstest fixtures are passed by value
+  use super::*;
+  use crate::align::band_2d::{full_matrix, simple_stripes};
+  use crate::align::score_matrix::score_matrix_scalar;
+  use crate::align::score_matrix_custom::{ScoringMatrix, ScoringMatrixRaw};
+  use crate::io::aa::to_aa_seq;
+  use crate::io::nuc::to_nuc_seq;
+  use eyre::Report;
+  use itertools::Itertools;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn all_params() -> Vec<AlignPairwiseParams> {
+    let mut all_params = vec![];
+    for gap_alignment_side in [GapAlignmentSide::Left, GapAlignmentSide::Right] {
+      for left_terminal_gaps_free in [false, true] {
+        for right_terminal_gaps_free in [false, true] {
+          all_params.push(AlignPairwiseParams {
+            gap_alignment_side,
+            left_terminal_gaps_free,
+            right_terminal_gaps_free,
+            ..AlignPairwiseParams::default()
+          });
+        }
+      }
+    }
+    all_params.push(AlignPairwiseParams {
+      scoring_matrix_nuc: Some(custom_scoring_matrix("TAWCYMHGKRDSBVN")),
+      scoring_matrix_aa: Some(custom_scoring_matrix("ABCDEFGHIJKLMNOPQRSTUVWYZX*")),
+      ..AlignPairwiseParams::default()
+    });
+    all_params
+  }
+
+  fn custom_scoring_matrix<L: Letter<L>>(alphabet: &str) -> ScoringMatrix<L> {
+    let n = alphabet.len();
+    let scores = (0..n)
+      .map(|i| {
+        (0..n)
+          .map(|j| {
+            if i == j {
+              4 + (i % 3) as i32
+            } else {
+              -(((i + j) % 4) as i32)
+            }
+          })
+          .collect_vec()
+      })
+      .collect_vec();
+    ScoringMatrix::try_from(ScoringMatrixRaw {
+      alphabet: alphabet.to_owned(),
+      scores,
+    })
+    .unwrap()
+  }
+
+  fn all_stripes(ref_len: usize, qry_len: usize) -> Vec<Vec<Stripe>> {
+    let mut all_stripes = vec![full_matrix(ref_len, qry_len)];
+    for mean_shift in [-3, 0, 2, 5] {
+      for band_width in [1, 3, 8] {
+        all_stripes.push(simple_stripes(mean_shift, band_width, ref_len, qry_len));
+      }
+    }
+    all_stripes
+  }
+
+  fn assert_same_as_scalar<T: Letter<T>>(qry_seq: &[T], ref_seq: &[T]) {
+    let gap_open_close: Vec<i32> = (0..ref_seq.len() + 2).map(|i| [6, 8, 7][i % 3]).collect();
+    for params in &all_params() {
+      for stripes in &all_stripes(ref_seq.len(), qry_seq.len()) {
+        let expected = score_matrix_scalar(qry_seq, ref_seq, &gap_open_close, stripes, params);
+        let actual = score_matrix_simd(qry_seq, ref_seq, &gap_open_close, stripes, params);
+        assert_eq!(expected.scores, actual.scores);
+        assert_eq!(expected.paths, actual.paths);
+      }
+    }
+  }
+
+  #[rstest]
+  #[case("CTCGCT", "ACGCTCGCT")]
+  #[case("ACGCTCGCT", "CTCGCT")]
+  #[case("ACGTTTACGGGTAACCGTAGCTAGCATCGACTG", "ACGTTTACGGGTAACCGTAGCTAGCATCGACTG")]
+  #[case("ACGTTTACGGTAACCGTAGCTCGACTG", "ACGTTTACGGGTAACCGTAGCTAGCATCGACTG")]
+  #[case("ACGTTTACGNNNNNNCGTAGCTAGCATCGACTGTTTGGCA", "ACGTTTACGGGTAACCGTAGCTAGCATCGACTG")]
+  #[case("TTGCAAGGCTRYACGT", "GCATTACGCATTGCAAGG")]
+  fn computes_same_nuc_scores_as_scalar(#[case] qry: &str, #[case] rf: &str) -> Result<(), Report> {
+    assert_same_as_scalar(&to_nuc_seq(qry)?, &to_nuc_seq(rf)?);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("MFVFLVLLPLVSSQCVNLT", "MFVFLVLLPLVSSQCVNLT")]
+  #[case("MFVFLVLLPLVSSQ", "MFVFLVLLPLVSSQCVNLTTRTQLPPAYTN")]
+  #[case("MFVFLVLPLVSSQCVNLTTRTQLXXXYTNSFTRGVYYPDK", "MFVFLVLLPLVSSQCVNLTTRTQLPPAYTN")]
+  #[case("QCVNLTTRT", "MFVFLVLLPLVSSQCVNLTTRTQLPPAYTN")]
+  fn computes_same_aa_scores_as_scalar(#[case] qry: &str, #[case] rf: &str) -> Result<(), Report> {
+    assert_same_as_scalar(&to_aa_seq(qry)?, &to_aa_seq(rf)?);
+    Ok(())
+  }
+}
@@ -0,0 +1,472 @@
+use crate::align::band_2d::{full_matrix, Stripe};
+use crate::align::inversions::Inversion;
+use crate::align::params::{AlignPairwiseParams, SeedMethod};
+use crate::align::seed_alignment::{create_stripes, SeedMatch};
+use crate::align::seed_match::{encode_kmers, MAX_KMER_LENGTH};
+use crate::io::letter::Letter;
+use crate::io::nuc::Nuc;
+use crate::make_error;
+use crate::translate::complement::reverse_complement_in_place;
+use crate::utils::range::Range;
+use eyre::Report;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Minimizers occurring in the reference more often than this are not informative (repeats, low complexity regions)
+/// and are not used as anchors
+const MAX_MINIMIZER_OCCURRENCES: usize = 8;
+
+/// Maximum number of preceding anchors considered as predecessors of an anchor during chaining
+const MAX_CHAIN_LOOKBACK: usize = 50;
+
+/// Minimum number of anchors in a chain of the reverse-complemented query to consider it an inversion
+const MIN_INVERSION_ANCHORS: usize = 3;
+
+/// Maximum number of inversions to report
+const MAX_INVERSIONS: usize = 8;
+
+/// A k-mer which has the smallest hash among the k-mers in a window of consecutive k-mers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Minimizer {
+  pub hash: u64,
+  pub pos: usize,
+}
+
+/// Scrambles the 2-bit encoding of a k-mer, so that the order of hashes, which determines minimizers, is not
+/// lexicographic (which would favour poly-A k-mers). The function (finalizer of MurmurHash3) is invertible, so distinct
+/// k-mers never collide, and it does not depend on the platform or on the version of the standard library.
+#[inline]
+const fn kmer_hash(kmer: u64) -> u64 {
+  let mut hash = kmer;
+  hash ^= hash >> 33;
+  hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+  hash ^= hash >> 33;
+  hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+  hash ^= hash >> 33;
+  hash
+}
+
+/// Finds minimizers of a sequence: for every window of `window_size` consecutive k-mers, the k-mer with the smallest
+/// hash. K-mers containing letters other than A, C, G, T are skipped.
+pub fn find_minimizers(seq: &[Nuc], kmer_length: usize, window_size: usize) -> Vec<Minimizer> {
+  if kmer_length == 0 || seq.len() < kmer_length {
+    return vec![];
+  }
+
+  let mut hashes: Vec<Option<u64>> = vec![None; seq.len() - kmer_length + 1];
+  for kmer in encode_kmers(seq, kmer_length) {
+    // The encoding skips gaps, so a k-mer which ends at `end` starts at `end - kmer_length` only if it has no gaps
+    let pos = kmer.end - kmer_length;
+    if !seq[pos..kmer.end].iter().any(Letter::is_gap) {
+      hashes[pos] = Some(kmer_hash(kmer.fwd));
+    }
+  }
+
+  let window_size = window_size.clamp(1, hashes.len());
+  let mut minimizers = Vec::<Minimizer>::with_capacity(2 * hashes.len() / window_size + 1);
+  for (window_start, window) in hashes.windows(window_size).enumerate() {
+    // Leftmost k-mer with the smallest hash
+    let best = window
+      .iter()
+      .enumerate()
+      .filter_map(|(i, hash)| {
+        hash.map(|hash| Minimizer {
+          hash,
+          pos: window_start + i,
+        })
+      })
+      .min_by_key(|minimizer| minimizer.hash);
+
+    if let Some(best) = best {
+      if minimizers.last().map_or(true, |last| last.pos != best.pos) {
+        minimizers.push(best);
+      }
+    }
+  }
+
+  minimizers
+}
+
+/// Exact match of a k-mer between query and reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+  pub qry_pos: usize,
+  pub ref_pos: usize,
+}
+
+/// Positions of minimizers of the reference sequence, by minimizer hash
+pub struct MinimizerIndex {
+  kmer_length: usize,
+  window_size: usize,
+  positions: HashMap<u64, Vec<usize>>,
+}
+
+impl MinimizerIndex {
+  pub fn new(ref_seq: &[Nuc], kmer_length: usize, window_size: usize) -> Result<Self, Report> {
+    if kmer_length == 0 || kmer_length > MAX_KMER_LENGTH {
+      return make_error!(
+        "Seed length for minimizer seed chaining should be between 1 and {MAX_KMER_LENGTH}, but got {kmer_length}"
+      );
+    }
+
+    let mut positions = HashMap::<u64, Vec<usize>>::new();
+    for Minimizer { hash, pos } in find_minimizers(ref_seq, kmer_length, window_size) {
+      positions.entry(hash).or_default().push(pos);
+    }
+    Ok(Self {
+      kmer_length,
+      window_size,
+      positions,
+    })
+  }
+
+  /// Builds the index of the reference sequence, if the alignment parameters require it: for minimizer seed chaining
+  /// or for detection of inversions. The index only depends on the reference sequence and on the parameters, so it is
+  /// built once and then used for all query sequences.
+  pub fn for_params(ref_seq: &[Nuc], params: &AlignPairwiseParams) -> Result<Option<Self>, Report> {
+    if matches!(params.seed_method, SeedMethod::Minimizer) || params.detect_inversions {
+      Self::new(ref_seq, params.seed_length, params.minimizer_window).map(Some)
+    } else {
+      Ok(None)
+    }
+  }
+
+  pub const fn kmer_length(&self) -> usize {
+    self.kmer_length
+  }
+
+  /// Finds anchors between the query and the reference, using query minimizers.
+  /// Returns the number of query minimizers and the anchors.
+  pub fn find_anchors(&self, qry_seq: &[Nuc]) -> (usize, Vec<Anchor>) {
+    let minimizers = find_minimizers(qry_seq, self.kmer_length, self.window_size);
+
+    let anchors = minimizers
+      .iter()
+      .filter_map(|minimizer| {
+        self
+          .positions
+          .get(&minimizer.hash)
+          .filter(|ref_positions| ref_positions.len() <= MAX_MINIMIZER_OCCURRENCES)
+          .map(|ref_positions| (minimizer.pos, ref_positions))
+      })
+      .flat_map(|(qry_pos, ref_positions)| ref_positions.iter().map(move |&ref_pos| Anchor { qry_pos, ref_pos }))
+      .collect();
+
+    (minimizers.len(), anchors)
+  }
+}
+
+/// Collinear chain of anchors: both query and reference positions are strictly increasing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chain {
+  pub anchors: Vec<Anchor>,
+  pub score: i64,
+}
+
+/// Penalty for the difference in distances between successive anchors in the query and in the reference
+fn chain_gap_cost(indel: usize, kmer_length: usize) -> i64 {
+  if indel == 0 {
+    0
+  } else {
+    ((indel * kmer_length + 99) / 100 + (indel.ilog2() as usize) / 2) as i64
+  }
+}
+
+/// Finds the highest scoring collinear chain of anchors.
+///
+/// Each anchor contributes the number of bases it adds to the chain. Successive anchors further apart in the query
+/// than in the reference (or vice versa) are penalized, and not chained at all if the difference exceeds `max_indel`.
+pub fn chain_anchors(anchors: &[Anchor], kmer_length: usize, max_indel: usize) -> Option<Chain> {
+  let mut anchors = anchors.to_vec();
+  anchors.sort_unstable_by_key(|anchor| (anchor.ref_pos, anchor.qry_pos));
+  anchors.dedup();
+
+  let mut scores = vec![0_i64; anchors.len()];
+  let mut predecessors = vec![None; anchors.len()];
+  for (i, anchor) in anchors.iter().enumerate() {
+    scores[i] = kmer_length as i64;
+    for j in (i.saturating_sub(MAX_CHAIN_LOOKBACK)..i).rev() {
+      let prev = &anchors[j];
+      if prev.qry_pos >= anchor.qry_pos || prev.ref_pos >= anchor.ref_pos {
+        continue;
+      }
+      let qry_dist = anchor.qry_pos - prev.qry_pos;
+      let ref_dist = anchor.ref_pos - prev.ref_pos;
+      let indel = qry_dist.abs_diff(ref_dist);
+      if indel > max_indel {
+        continue;
+      }
+      let gain = qry_dist.min(ref_dist).min(kmer_length) as i64;
+      let score = scores[j] + gain - chain_gap_cost(indel, kmer_length);
+      if score > scores[i] {
+        scores[i] = score;
+        predecessors[i] = Some(j);
+      }
+    }
+  }
+
+  let (mut i, &score) = scores
+    .iter()
+    .enumerate()
+    .max_by_key(|(i, score)| (**score, usize::MAX - i))?;
+
+  let mut chain = vec![anchors[i]];
+  while let Some(j) = predecessors[i] {
+    chain.push(anchors[j]);
+    i = j;
+  }
+  chain.reverse();
+
+  Some(Chain { anchors: chain, score })
+}
+
+/// Finds up to `max_chains` non-overlapping chains, best first, each consisting of at least `min_anchors` anchors
+pub fn find_chains(
+  anchors: &[Anchor],
+  kmer_length: usize,
+  max_indel: usize,
+  min_anchors: usize,
+  max_chains: usize,
+) -> Vec<Chain> {
+  let mut anchors = anchors.to_vec();
+  let mut chains = vec![];
+  while chains.len() < max_chains {
+    match chain_anchors(&anchors, kmer_length, max_indel) {
+      Some(chain) if chain.anchors.len() >= min_anchors => {
+        anchors.retain(|anchor| !chain.anchors.contains(anchor));
+        chains.push(chain);
+      }
+      _ => break,
+    }
+  }
+  chains
+}
+
+/// Statistics of seed chaining, useful for diagnosing failed or poor alignments
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedChainStats {
+  /// Number of minimizers in the query sequence
+  pub num_minimizers: usize,
+  /// Number of query minimizers found in the reference sequence
+  pub num_anchors: usize,
+  /// Number of anchors in the best chain
+  pub num_chain_anchors: usize,
+  pub chain_score: i64,
+  /// Query region spanned by the best chain
+  pub qry_range: Range,
+  /// Reference region spanned by the best chain
+  pub ref_range: Range,
+}
+
+/// Determine rough positioning of qry to reference sequence by chaining of minimizer anchors.
+/// Returns vector of stripes, that is a band within which the alignment is expected to lie,
+/// and statistics of the chaining.
+pub fn seed_chain_alignment(
+  qry_seq: &[Nuc],
+  ref_seq: &[Nuc],
+  index: &MinimizerIndex,
+  params: &AlignPairwiseParams,
+) -> Result<(Vec<Stripe>, Option<SeedChainStats>), Report> {
+  let qry_len = qry_seq.len();
+  let ref_len = ref_seq.len();
+  let kmer_length = index.kmer_length;
+
+  // for very short sequences, use full square
+  if ref_len + qry_len < (5 * kmer_length) {
+    trace!("Band construction: Short qry&ref sequence (< 5*seed_length), thus using full matrix");
+    return Ok((full_matrix(ref_len, qry_len), None));
+  };
+
+  let (num_minimizers, anchors) = index.find_anchors(qry_seq);
+  let chain = chain_anchors(&anchors, kmer_length, params.max_indel).unwrap_or(Chain {
+    anchors: vec![],
+    score: 0,
+  });
+
+  let (qry_range, ref_range) = if chain.anchors.is_empty() {
+    (Range::default(), Range::default())
+  } else {
+    let (first, last) = chain_bounds(&chain);
+    (
+      Range::new(first.qry_pos, last.qry_pos + kmer_length),
+      Range::new(first.ref_pos, last.ref_pos + kmer_length),
+    )
+  };
+
+  let stats = SeedChainStats {
+    num_minimizers,
+    num_anchors: anchors.len(),
+    num_chain_anchors: chain.anchors.len(),
+    chain_score: chain.score,
+    qry_range,
+    ref_range,
+  };
+
+  trace!("Seed chaining: {stats:?}");
+
+  if stats.num_chain_anchors < 2 {
+    return make_error!(
+      "Unable to align: not enough anchors in the seed chain. Details: number of query minimizers: {}, number of anchors: {}, number of anchors in the best chain: {}. This is likely due to a low quality of the provided sequence, or due to using incorrect reference sequence.",
+      stats.num_minimizers,
+      stats.num_anchors,
+      stats.num_chain_anchors
+    );
+  }
+
+  let seed_matches: Vec<SeedMatch> = chain
+    .anchors
+    .iter()
+    .map(|anchor| SeedMatch {
+      qry_pos: anchor.qry_pos,
+      ref_pos: anchor.ref_pos,
+      score: kmer_length,
+    })
+    .collect();
+
+  let stripes = create_stripes(
+    &seed_matches,
+    qry_len as i32,
+    ref_len as i32,
+    params.terminal_bandwidth,
+    params.excess_bandwidth,
+    params.max_indel,
+  )?;
+
+  Ok((stripes, Some(stats)))
+}
+
+/// Finds chains of anchors between the reverse complement of the query and the reference, that is, regions of the
+/// query which match the reference in reverse-complement orientation. Query ranges are in coordinates of the original
+/// query.
+pub fn find_reverse_complement_chains(index: &MinimizerIndex, qry_seq: &[Nuc], max_indel: usize) -> Vec<Inversion> {
+  let qry_len = qry_seq.len();
+  let kmer_length = index.kmer_length;
+
+  let mut qry_seq_rev = qry_seq.to_vec();
+  reverse_complement_in_place(&mut qry_seq_rev);
+  let (_, anchors_rev) = index.find_anchors(&qry_seq_rev);
+
+  find_chains(
+    &anchors_rev,
+    kmer_length,
+    max_indel,
+    MIN_INVERSION_ANCHORS,
+    MAX_INVERSIONS,
+  )
+  .iter()
+  .map(|chain| {
+    let (first, last) = chain_bounds(chain);
+    Inversion {
+      // Convert k-mer positions in the reverse complement back to the original query coordinates
+      qry_range: Range::new(qry_len - last.qry_pos - kmer_length, qry_len - first.qry_pos),
+      ref_range: Range::new(first.ref_pos, last.ref_pos + kmer_length),
+    }
+  })
+  .collect()
+}
+
+fn chain_bounds(chain: &Chain) -> (&Anchor, &Anchor) {
+  (&chain.anchors[0], &chain.anchors[chain.anchors.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::to_nuc_seq;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  const REF: &str = "ATGTTTGTTTTTCTTGTTTTATTGCCACTAGTCTCTAGTCAGTGTGTTAATCTTACAACCAGAACTCAATTACCCCCTGCATACACTAATTCTTTCACACGTGGTGTTTATTACCCTGACAAAGTTTTCAGATCCTCAGTTTTACATTCAACTCAGGACTTGTTCTTACCTTTCTTTTCCAATGTTACTTGGTTCCATGCTATACATGTCTCTGGGACCAATGGTACTAAGAGGTTTGATAACCCTGTCCTACCATTTAATGATGGTGTTTATTTTGCTTCCACTGAGAAGTCTAACATAATAAGAGGCTGGATTTTTGGTACTACTTTAGATTCGAAGACCCAGTCCCTACTTATTGTTAATAACGCTACTAATGTTGTTATTAAAGTCTGTGAATTTCAATTTTGTAATGATCCATTTTTGGGTGTTTATTACCACAAAAACAACAAAAGTTGGATGGAAAGTGAGTTCAGAGTTTATTCTAGTGCGAATAATTGCACTTTTGAATATGTCTCTCAGCCTTTTCTTATGGACCTTGAAGGAAAACAGGGTAATTTCAAAAATCTTAGGGAATTTGTGTTTAAGAATATTGATGGTTATTTTAAAATATATTCTAAGCACACGCCTATTAATTTAGTGCGTGATCTCCCTCAGGGTTTTTCGGCTTTAGAACCATTGGTAGATTTGCCAATAGGTATTAACATCACTAGGTTTCAAACTTTACTTGCTTTACATAGAAGTTATTTGACTCCTGGTGATTCTTCTTCAGGTTGGACAGCTGGTGCTGCAGCTTATTATGTGGGTTATCTTCAACCTAGGACTTTTCTATTAAAATATAATGAAAATGGAACCATTACAGATGCTGTAGACTGTGCACTTGACCCTCTCTCAGAAACAAAGTGTACGTTGAAATCCTTCACTGTAGAAAAAGGAATCTATCAAACTTCTAACTTTAGAGTCCAACCAACAGAATCTATTGTTAGATTTCCTAATATTACAAACTTGTGCCCTTTTGGTGAAGTTTTTAACGCCACCAGATTTGCATCTGTTTATGCTTGGAACAGGAAGAGAATCAGCAACTGTGTTGCTGATTATTCTGTCCTATATAATTCCGCATCATTTTCCACTTTTAAGTGTTATGGAGTGTCTCCTACTAAATTAAATGATCTCTGCTTTACTAATGTCTATGCAGATTCATTTGTAATTAGAGGTGATGAAGTCAGACAAATCGCTCCAGGGCAAACTGGAAAGATTGCTGATTATAATTATAAATTACCAGATGATTTTACAGGCTGCGTTATAGCTTGGAATTCTAACAATCTTGATTCTAAGGTTGGTGGTAATTATAATTACCTGTATAGATTGTTTAGGAAGTCTAATCTCAAACCTTTTGAGAGAGATATTTCAACTGAAATCTATCAGGCCGGTAGCACACCTTGTAATGGTGTTGAAGGTTTTAATTGTTACTTTCCTTTACAATCATATGGTTTCCAACCCACTAATGGTGTTGGTTACCAACCATACAGAGTAGTAGTACTTTCTTTTGAACTTCTACATGCACCAGCAACTGTTTGTGGACCTAAAAAGTCTACTAATTTGGTTAAAAACAAATGTGTCAATTTCAACTTCAATGGTTTAACAGGCACAGGTGTTCTTACTGAGTCTAACAAAAAGTTTCTGCCTTTCCAACAATTTGGCAGAGACATTGCTGACACTACTGATGCTGTCCGTGATCCACAGACACTTGAGATTCTTGACATTACACCATGTTCTTTTGGTGGTGTCAGTGTTATAACACCAGGAACAAATACTTCTAACCAGGTTGCTGTTCTTTATCAGGATGTTAACTGCACAGAAGTCCCTGTTGCTATTCATGCAGATCAACTTACTCCTACTTGGCGTGTTTATTCTACAGGTTCTAATGTTTTTCAAACACGTGCAGGCTGTTTAATAGGGGCTGAACATGTCAACAACTCATATGAGTGTGACATACCCATTGGTGCAGGTATATGCGCTAGTTATCAGACTCAGACTAATTCTCCTCGGCGGGCACGTAGTGTAGCTAGTCAATCCATCATTGCCTACACTATGTCACTTGGTGCAGAAAATTCAGTTGCTTACTCTAATAACTCTATTGCCATACCCACAAATTTTACTATTAGTGTTACCACAGAAATTCTACCAGTGTCTATGACCAAGACATCAGTAGATTGTACAATGTACATTTGTGGTGATTCAACTGAATGCAGCAATCTTTTGTTGCAATATGGCAGTTTTTGTACACAATTAAACCGTGCTTTAACTGGAATAGCTGTTGAACAAGACAAAAACACCCAAGAAGTTTTTGCACAAGTCAAACAAATTTACAAAACACCACCAATTAAAGATTTTGGTGGTTTTAATTTTTCACAAATATTACCAGATCCATCAAAACCAAGCAAGAGGTCATTTATTGAAGATCTACTTTTCAACAAAGTGACACTTGCAGATGCTGGCTTCATCAAACAATATGGTGATTGCCTTGGTGATATTGCTGCTAGAGACCTCATTTGTGCACAAAAGTTTAACGGCCTTACTGTTTTGCCACCTTTGCTCACAGATGAAATGATTGCTCAATACACTTCTGCACTGTTAGCGGGTACAATCACTTCTGGTTGGACCTTTGGTGCAGGTGCTGCATTACAAATACCATTTGCTATGCAAATGGCTTATAGGTTTAATGGTATTGGAGTTACACAGAATGTTCTCTATGAGAACCAAAAATTGATTGCCAACCAATTTAATAGTGCTATTGGCAAAATTCAAGACTCACTTTCTTCCACAGCAAGTGCACTTGGAAAACTTCAAGATGTGGTCAACCAAAATGCACAAGCTTTAAACACGCTTGTTAAACAACTTAGCTCCAATTTTGGTGCAATTTCAAGTGTTTTAAATGATATCCTTTCACGTCTTGACAAAGTTGAGGCTGAAGTGCAAATTGATAGGTTGATCACAGGCAGACTTCAAAGTTTGCAGACATATGTGACTCAACAATTAATTAGAGCTGCAGAAATCAGAGCTTCTGCTAATCTTGCTGCTACTAAAATGTCAGAGTGTGTACTTGGACAATCAAAAAGAGTTGATTTTTGTGGAAAGGGCTATCATCTTATGTCCTTCCCTCAGTCAGCACCTCATGGTGTAGTCTTCTTGCATGTGACTTATGTCCCTGCACAAGAAAAGAACTTCACAACTGCTCCTGCCATTTGTCATGATGGAAAAGCACACTTTCCTCGTGAAGGTGTCTTTGTTTCAAATGGCACACACTGGTTTGTAACACAAAGGAATTTTTATGAACCACAAATCATTACTACAGACAACACATTTGTGTCTGGTAACTGTGATGTTGTAATAGGAATTGTCAACAACACAGTTTATGATCCTTTGCAACCTGAATTAGACTCATTCAAGGAGGAGTTAGATAAATATTTTAAGAATCATACATCACCAGATGTTGATTTAGGTGACATCTCTGGCATTAATGCTTCAGTTGTAAACATTCAAAAAGAAATTGACCGCCTCAATGAGGTTGCCAAGAATTTAAATGAATCTCTCATCGATCTCCAAGAACTTGGAAAGTATGAGCAGTATATAAAATGGCCATGGTACATTTGGCTAGGTTTTATAGCTGGCTTGATTGCCATAGTAATGGTGACAATTATGCTTTGCTGTATGACCAGTTGCTGTAGTTGTCTCAAGGGCTGTTGTTCTTGTGGATCCTGCTGCAAATTTGATGAAGACGACTCTGAGCCAGTGCTCAAAGGAGTCAAATTACATTACACATAA";
+
+  fn mutate(seq: &str, every: usize) -> String {
+    seq
+      .chars()
+      .enumerate()
+      .map(|(i, c)| {
+        if i % every == every / 2 {
+          if c == 'A' {
+            'G'
+          } else {
+            'A'
+          }
+        } else {
+          c
+        }
+      })
+      .collect()
+  }
+
+  #[rstest]
+  fn finds_chain_across_deletion() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(REF)?;
+    let qry_seq = to_nuc_seq(&format!("{}{}", &REF[..1500], &REF[2100..]))?;
+
+    let index = MinimizerIndex::new(&ref_seq, 15, 10)?;
+    let (_, anchors) = index.find_anchors(&qry_seq);
+    let chain = chain_anchors(&anchors, 15, 1000).unwrap();
+
+    assert!(chain
+      .anchors
+      .windows(2)
+      .all(|w| w[0].qry_pos < w[1].qry_pos && w[0].ref_pos < w[1].ref_pos));
+    assert!(chain.anchors.iter().any(|a| a.ref_pos < 1400));
+    assert!(chain.anchors.iter().any(|a| a.ref_pos > 2200));
+    assert!(chain
+      .anchors
+      .iter()
+      .all(|a| a.qry_pos == a.ref_pos || a.qry_pos + 600 == a.ref_pos));
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn aligns_divergent_sequence() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(REF)?;
+    // One difference every 12 nucleotides: too many for fixed spacing seeds with default parameters
+    let qry_seq = to_nuc_seq(&mutate(REF, 12))?;
+
+    let params = AlignPairwiseParams {
+      seed_length: 9,
+      minimizer_window: 4,
+      ..AlignPairwiseParams::default()
+    };
+
+    let index = MinimizerIndex::new(&ref_seq, params.seed_length, params.minimizer_window)?;
+    let (stripes, stats) = seed_chain_alignment(&qry_seq, &ref_seq, &index, &params)?;
+    let stats = stats.unwrap();
+
+    assert_eq!(stripes.len(), ref_seq.len() + 1);
+    assert!(stats.num_chain_anchors >= 2);
+    assert!(find_reverse_complement_chains(&index, &qry_seq, params.max_indel).is_empty());
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn finds_reverse_complement_chain() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(REF)?;
+    let mut inverted = to_nuc_seq(&REF[1000..1600])?;
+    reverse_complement_in_place(&mut inverted);
+    let qry_seq = [&ref_seq[..1000], &inverted, &ref_seq[1600..]].concat();
+
+    let params = AlignPairwiseParams {
+      max_indel: 1000,
+      ..AlignPairwiseParams::default()
+    };
+
+    let index = MinimizerIndex::new(&ref_seq, params.seed_length, params.minimizer_window)?;
+    let inversions = find_reverse_complement_chains(&index, &qry_seq, params.max_indel);
+
+    assert_eq!(inversions.len(), 1);
+    let inversion = &inversions[0];
+    // K-mers at the boundaries of the inverted segment can match by chance, extending the range by a few nucleotides
+    let k = params.seed_length;
+    assert!(inversion.qry_range.begin + k >= 1000 && inversion.qry_range.end <= 1600 + k);
+    assert!(inversion.ref_range.begin + k >= 1000 && inversion.ref_range.end <= 1600 + k);
+    assert!(inversion.qry_range.len() > 400);
+
+    Ok(())
+  }
+}
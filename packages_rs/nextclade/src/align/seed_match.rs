@@ -1,6 +1,7 @@
 use std::cmp;
 
 use crate::io::letter::Letter;
+use crate::io::nuc::Nuc;
 
 pub struct SeedMatchResult {
   pub ref_pos: usize,
@@ -63,6 +64,83 @@ pub fn seed_match<L: Letter<L>>(
   }
 }
 
+/// Maximum k-mer length which fits into 64-bit encoding (2 bits per nucleotide)
+pub const MAX_KMER_LENGTH: usize = 32;
+
+#[inline]
+const fn encode_nuc(nuc: Nuc) -> Option<u64> {
+  match nuc {
+    Nuc::A => Some(0),
+    Nuc::C => Some(1),
+    Nuc::G => Some(2),
+    Nuc::T => Some(3),
+    _ => None,
+  }
+}
+
+/// K-mer encoded into an integer, 2 bits per nucleotide, along with the encoding of its reverse complement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncodedKmer {
+  /// Position in the sequence right after the last nucleotide of the k-mer
+  pub end: usize,
+  pub fwd: u64,
+  pub rev: u64,
+}
+
+impl EncodedKmer {
+  /// The smaller of the encodings of the k-mer and of its reverse complement, which is the same for both strands
+  #[inline]
+  pub fn canonical(&self) -> u64 {
+    self.fwd.min(self.rev)
+  }
+}
+
+/// Encodes every k-mer of a sequence into an integer.
+///
+/// Similarly to seed matching, only k-mers consisting entirely of A, C, G, T are considered: any other character
+/// interrupts the k-mer. Gaps are skipped. Returns no k-mers if `kmer_length` is 0 or exceeds `MAX_KMER_LENGTH`.
+pub fn encode_kmers(seq: &[Nuc], kmer_length: usize) -> Vec<EncodedKmer> {
+  if kmer_length == 0 || kmer_length > MAX_KMER_LENGTH {
+    return vec![];
+  }
+
+  let mask = if kmer_length == MAX_KMER_LENGTH {
+    u64::MAX
+  } else {
+    (1_u64 << (2 * kmer_length)) - 1
+  };
+  let rev_shift = 2 * (kmer_length - 1);
+
+  let mut kmers = Vec::<EncodedKmer>::with_capacity(seq.len().saturating_sub(kmer_length) + 1);
+  let mut fwd = 0_u64;
+  let mut rev = 0_u64;
+  let mut valid_length = 0_usize;
+
+  for (pos, nuc) in seq.iter().enumerate() {
+    if nuc.is_gap() {
+      continue;
+    }
+
+    match encode_nuc(*nuc) {
+      None => {
+        fwd = 0;
+        rev = 0;
+        valid_length = 0;
+      }
+      Some(code) => {
+        fwd = ((fwd << 2) | code) & mask;
+        rev = (rev >> 2) | ((3 - code) << rev_shift);
+        valid_length += 1;
+        if valid_length >= kmer_length {
+          kmers.push(EncodedKmer { end: pos + 1, fwd, rev });
+        }
+      }
+    }
+  }
+
+  kmers
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -102,4 +180,33 @@ mod tests {
 
     Ok(())
   }
+
+  #[rstest]
+  fn encodes_kmers() -> Result<(), Report> {
+    let seq = to_nuc_seq("ACGTNAAC")?;
+    assert_eq!(
+      encode_kmers(&seq, 3),
+      vec![
+        // ACG and its reverse complement CGT
+        EncodedKmer {
+          end: 3,
+          fwd: 0b00_01_10,
+          rev: 0b01_10_11,
+        },
+        // CGT and its reverse complement ACG
+        EncodedKmer {
+          end: 4,
+          fwd: 0b01_10_11,
+          rev: 0b00_01_10,
+        },
+        // AAC and its reverse complement GTT
+        EncodedKmer {
+          end: 8,
+          fwd: 0b00_00_01,
+          rev: 0b10_11_11,
+        },
+      ]
+    );
+    Ok(())
+  }
 }
@@ -10,7 +10,9 @@ use crate::io::gene_map::GeneMap;
 use crate::io::letter::Letter;
 use crate::io::nuc::{from_nuc_seq, Nuc};
 use crate::make_internal_report;
-use crate::translate::complement::reverse_complement_in_place;
+use crate::translate::complement::{complement, reverse_complement_in_place};
+use crate::translate::frame_shifts_translate::FrameShift;
+use crate::translate::genetic_code::GeneticCode;
 use crate::translate::translate_genes::{Translation, TranslationMap};
 use crate::utils::error::keep_ok;
 use crate::utils::range::Range;
@@ -125,6 +127,52 @@ impl PartialOrd for AaDel {
   }
 }
 
+/// Represents an aminoacid position where the query codon contains ambiguous nucleotides, such that it can encode
+/// more than one aminoacid
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AaAmbiguity {
+  pub gene: String,
+  #[serde(rename = "refAA")]
+  pub reff: Aa,
+
+  #[serde(rename = "codon")]
+  pub pos: usize,
+
+  /// Aminoacids which the query codon can encode
+  #[serde(rename = "queryAAs")]
+  pub qry: Vec<Aa>,
+
+  pub query_codon: String,
+  pub codon_nuc_range: Range,
+}
+
+impl ToString for AaAmbiguity {
+  fn to_string(&self) -> String {
+    // NOTE: by convention, in bioinformatics, amino acids are numbered starting from 1, however our arrays are 0-based
+    format!(
+      "{}:{}{}{}",
+      self.gene,
+      from_aa(self.reff),
+      self.pos + 1,
+      self.qry.iter().map(|aa| from_aa(*aa)).join("/")
+    )
+  }
+}
+
+/// Order amino acid ambiguities by gene, then position
+impl Ord for AaAmbiguity {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (&self.gene, self.pos).cmp(&(&other.gene, other.pos))
+  }
+}
+
+impl PartialOrd for AaAmbiguity {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum AaChangeType {
@@ -212,9 +260,12 @@ impl PartialOrd for AaChange {
 pub struct FindAaChangesOutput {
   pub aa_substitutions: Vec<AaSub>,
   pub aa_deletions: Vec<AaDel>,
+  pub aa_ambiguities: Vec<AaAmbiguity>,
 }
 
-/// Finds aminoacid substitutions and deletions in query peptides relative to reference peptides, in all genes
+/// Finds aminoacid substitutions and deletions in query peptides relative to reference peptides, in all genes. Also
+/// finds aminoacid positions where the query codon is ambiguous (see `AaAmbiguity`), using the genetic code of each
+/// gene to resolve the possible aminoacids.
 ///
 /// ## Precondition
 /// Nucleotide sequences and peptides are required to be stripped from insertions
@@ -225,6 +276,7 @@ pub fn find_aa_changes(
   translations: &[Translation],
   alignment_range: &Range,
   gene_map: &GeneMap,
+  genetic_code: &GeneticCode,
 ) -> Result<FindAaChangesOutput, Report> {
   let mut changes = translations
     .iter()
@@ -245,12 +297,16 @@ pub fn find_aa_changes(
           .get(gene_name)
           .ok_or(make_internal_report!("Gene '{gene_name}' not found in gene map"))?;
 
+        let genetic_code = GeneticCode::for_gene(gene, genetic_code)?;
+
         Ok(find_aa_changes_for_gene(
           qry_seq,
           ref_seq,
           &ref_peptide.seq,
           seq,
+          frame_shifts,
           gene,
+          genetic_code,
           alignment_range,
         ))
       },
@@ -260,11 +316,13 @@ pub fn find_aa_changes(
     .fold(FindAaChangesOutput::default(), |mut output, changes| {
       output.aa_substitutions.extend(changes.aa_substitutions);
       output.aa_deletions.extend(changes.aa_deletions);
+      output.aa_ambiguities.extend(changes.aa_ambiguities);
       output
     });
 
   changes.aa_substitutions.sort();
   changes.aa_deletions.sort();
+  changes.aa_ambiguities.sort();
 
   Ok(changes)
 }
@@ -288,7 +346,9 @@ fn find_aa_changes_for_gene(
   ref_seq: &[Nuc],
   ref_peptide: &[Aa],
   qry_peptide: &[Aa],
+  frame_shifts: &[FrameShift],
   gene: &Gene,
+  genetic_code: &GeneticCode,
   alignment_range: &Range,
 ) -> FindAaChangesOutput {
   assert_eq!(ref_peptide.len(), qry_peptide.len());
@@ -296,6 +356,7 @@ fn find_aa_changes_for_gene(
 
   let mut aa_substitutions = Vec::<AaSub>::new();
   let mut aa_deletions = Vec::<AaDel>::new();
+  let mut aa_ambiguities = Vec::<AaAmbiguity>::new();
 
   let num_nucs = qry_seq.len();
   let num_codons = qry_peptide.len();
@@ -303,13 +364,12 @@ fn find_aa_changes_for_gene(
     let ref_aa = ref_peptide[codon];
     let qry_aa = qry_peptide[codon];
 
-    // Find where the codon is in nucleotide sequences
-    let codon_begin = if gene.strand != GeneStrand::Reverse {
-      gene.start + codon * 3
-    } else {
-      gene.end - (codon + 1) * 3
-    };
-    let codon_end = codon_begin + 3;
+    // Find where the codon is in nucleotide sequences. If the codon is split between coding segments, then the range
+    // spans all of its nucleotides.
+    let codon_first_nuc = gene.nuc_rel_to_abs(codon * 3);
+    let codon_last_nuc = gene.nuc_rel_to_abs(codon * 3 + 2);
+    let codon_begin = codon_first_nuc.min(codon_last_nuc);
+    let codon_end = codon_first_nuc.max(codon_last_nuc) + 1;
 
     // If the codon is outside of nucleotide alignment, there is nothing to do
     if !alignment_range.contains(codon_begin) || !alignment_range.contains(codon_end) {
@@ -349,8 +409,13 @@ fn find_aa_changes_for_gene(
         context_nuc_range,
       });
     }
-    // TODO: we might account for ambiguous amino acids in this condition
-    else if qry_aa != ref_aa && qry_aa != Aa::X {
+    // Codons with ambiguous nucleotides which encode the same aminoacid regardless of the resolution of the ambiguity
+    // are already translated into this aminoacid, so the remaining `X` are either truly unknown or ambiguous
+    else if qry_aa == Aa::X {
+      if let Some(aa_ambiguity) = find_aa_ambiguity(qry_seq, ref_aa, codon, frame_shifts, gene, genetic_code) {
+        aa_ambiguities.push(aa_ambiguity);
+      }
+    } else if qry_aa != ref_aa {
       // If not a gap and the state has changed, than it's a substitution
       aa_substitutions.push(AaSub {
         gene: gene.gene_name.clone(),
@@ -368,5 +433,205 @@ fn find_aa_changes_for_gene(
   FindAaChangesOutput {
     aa_substitutions,
     aa_deletions,
+    aa_ambiguities,
+  }
+}
+
+/// Finds aminoacids which an unknown query codon can encode. The codon is reported only if it contains ambiguous
+/// nucleotides which narrow down the set of possible aminoacids. Codons containing `N` or gaps, as well as codons
+/// masked due to a frame shift, are considered unknown.
+fn find_aa_ambiguity(
+  qry_seq: &[Nuc],
+  ref_aa: Aa,
+  codon: usize,
+  frame_shifts: &[FrameShift],
+  gene: &Gene,
+  genetic_code: &GeneticCode,
+) -> Option<AaAmbiguity> {
+  if frame_shifts
+    .iter()
+    .any(|frame_shift| frame_shift.codon_mask.contains(codon))
+  {
+    return None;
+  }
+
+  // Query codon in the direction of translation
+  let qry_codon = (codon * 3..codon * 3 + 3)
+    .map(|nuc_rel| {
+      let nuc = qry_seq[gene.nuc_rel_to_abs(nuc_rel)];
+      if gene.strand == GeneStrand::Reverse {
+        complement(nuc)
+      } else {
+        nuc
+      }
+    })
+    .collect_vec();
+
+  if qry_codon.iter().any(|nuc| nuc.is_gap() || *nuc == Nuc::N) {
+    return None;
+  }
+
+  let possible = genetic_code.decode_possible(&qry_codon);
+  if possible.len() < 2 {
+    return None;
+  }
+
+  let codon_first_nuc = gene.nuc_rel_to_abs(codon * 3);
+  let codon_last_nuc = gene.nuc_rel_to_abs(codon * 3 + 2);
+
+  Some(AaAmbiguity {
+    gene: gene.gene_name.clone(),
+    reff: ref_aa,
+    pos: codon,
+    qry: possible,
+    query_codon: from_nuc_seq(&qry_codon),
+    codon_nuc_range: Range::new(
+      codon_first_nuc.min(codon_last_nuc),
+      codon_first_nuc.max(codon_last_nuc) + 1,
+    ),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::analyze::link_nuc_and_aa_changes::link_nuc_and_aa_changes;
+  use crate::io::aa::to_aa_seq;
+  use crate::io::nuc::to_nuc_seq;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  // Gene `L` is on the reverse strand, so codons are counted from the end of the gene: codon `L:G6` is at nucleotide
+  // positions 31-33 and codon `L:L4` is at positions 37-39 (1-based)
+  const REF: &str = "CGATTCAAATGACGGCAGCAGGCCGGGAGTCCCTGAGAGGCTTGTTCCGGA";
+
+  fn gene_reverse() -> Gene {
+    Gene {
+      gene_name: "L".to_owned(),
+      start: 12,
+      end: 48,
+      strand: GeneStrand::Reverse,
+      frame: 0,
+      cds_segments: vec![Range::new(12, 48)],
+      genetic_code: None,
+    }
+  }
+
+  #[rstest]
+  fn finds_aa_substitution_on_reverse_strand() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(REF)?;
+    let qry_seq = to_nuc_seq("CGATTCAAATGACGGCAGCAGGCCGGGAGTCCCTGAGCGGCTTGTTCCGGA")?;
+    let ref_peptide = to_aa_seq("GTSLSGTPGLLP")?;
+    let qry_peptide = to_aa_seq("GTSRSGTPGLLP")?;
+
+    let changes = find_aa_changes_for_gene(
+      &qry_seq,
+      &ref_seq,
+      &ref_peptide,
+      &qry_peptide,
+      &[],
+      &gene_reverse(),
+      GeneticCode::standard(),
+      &Range::new(0, ref_seq.len()),
+    );
+
+    assert_eq!(
+      changes.aa_substitutions,
+      vec![AaSub {
+        gene: "L".to_owned(),
+        reff: Aa::L,
+        pos: 3,
+        qry: Aa::R,
+        codon_nuc_range: Range::new(36, 39),
+        ref_context: "AGCCTCTCA".to_owned(),
+        query_context: "AGCCGCTCA".to_owned(),
+        context_nuc_range: Range::new(33, 42),
+      }]
+    );
+    assert!(changes.aa_deletions.is_empty());
+
+    let nuc_sub = NucSub {
+      reff: Nuc::A,
+      pos: 37,
+      qry: Nuc::C,
+    };
+    let linked = link_nuc_and_aa_changes(&[nuc_sub.clone()], &[], &changes.aa_substitutions, &[]);
+    assert_eq!(linked.aa_substitutions[0].nuc_substitutions, vec![nuc_sub]);
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn finds_aa_deletion_on_reverse_strand() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(REF)?;
+    let qry_seq = to_nuc_seq("CGATTCAAATGACGGCAGCAGGCCGGGAGT---TGAGAGGCTTGTTCCGGA")?;
+    let ref_peptide = to_aa_seq("GTSLSGTPGLLP")?;
+    let qry_peptide = to_aa_seq("GTSLS-TPGLLP")?;
+
+    let changes = find_aa_changes_for_gene(
+      &qry_seq,
+      &ref_seq,
+      &ref_peptide,
+      &qry_peptide,
+      &[],
+      &gene_reverse(),
+      GeneticCode::standard(),
+      &Range::new(0, ref_seq.len()),
+    );
+
+    assert!(changes.aa_substitutions.is_empty());
+    assert_eq!(
+      changes.aa_deletions,
+      vec![AaDel {
+        gene: "L".to_owned(),
+        reff: Aa::G,
+        pos: 5,
+        codon_nuc_range: Range::new(30, 33),
+        ref_context: "TCAGGGACT".to_owned(),
+        query_context: "TCA---ACT".to_owned(),
+        context_nuc_range: Range::new(27, 36),
+      }]
+    );
+
+    let nuc_del = NucDel { start: 30, length: 3 };
+    let linked = link_nuc_and_aa_changes(&[], &[nuc_del.clone()], &[], &changes.aa_deletions);
+    assert_eq!(linked.aa_deletions[0].nuc_deletions, vec![nuc_del]);
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn finds_aa_ambiguity_on_reverse_strand() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(REF)?;
+    let qry_seq = to_nuc_seq("CGATTCAAATGACGGCAGCAGGCCGGGAGTCCCTGAGARGCTTGTTCCGGA")?;
+    let ref_peptide = to_aa_seq("GTSLSGTPGLLP")?;
+    let qry_peptide = to_aa_seq("GTSXSGTPGLLP")?;
+
+    let changes = find_aa_changes_for_gene(
+      &qry_seq,
+      &ref_seq,
+      &ref_peptide,
+      &qry_peptide,
+      &[],
+      &gene_reverse(),
+      GeneticCode::standard(),
+      &Range::new(0, ref_seq.len()),
+    );
+
+    assert!(changes.aa_substitutions.is_empty());
+    assert_eq!(
+      changes.aa_ambiguities,
+      vec![AaAmbiguity {
+        gene: "L".to_owned(),
+        reff: Aa::L,
+        pos: 3,
+        qry: vec![Aa::F, Aa::L],
+        query_codon: "YTC".to_owned(),
+        codon_nuc_range: Range::new(36, 39),
+      }]
+    );
+    assert_eq!(changes.aa_ambiguities[0].to_string(), "L:L4F/L");
+
+    Ok(())
   }
 }
@@ -0,0 +1,316 @@
+use crate::analyze::letter_ranges::NucRange;
+use crate::io::letter::Letter;
+use crate::io::nuc::{from_nuc, Nuc};
+use crate::tree::tree::{AuspiceTreeNode, CladeDefiningMutations};
+use crate::utils::range::Range;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Position in the query sequence containing an ambiguous nucleotide (such as `R` or `Y`), which might indicate
+/// presence of more than one variant in the sample
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MixedSite {
+  #[serde(rename = "refNuc")]
+  pub reff: Nuc,
+
+  pub pos: usize,
+
+  #[serde(rename = "queryNuc")]
+  pub qry: Nuc,
+
+  /// Canonical nucleotides which the ambiguous nucleotide stands for
+  pub alleles: Vec<Nuc>,
+
+  /// Nucleotide of the nearest reference tree node at this position
+  pub nearest_node_nuc: Nuc,
+
+  /// Clades (other than the clade of the nearest node) for which one of the alleles is a clade-defining mutation.
+  /// Only filled if another allele matches the nearest node.
+  pub other_clades: Vec<String>,
+}
+
+impl MixedSite {
+  /// Whether this site is a possible evidence of co-infection or contamination: one allele matches the nearest node
+  /// and another allele matches a defining mutation of a different clade
+  pub fn is_coinfection_evidence(&self) -> bool {
+    !self.other_clades.is_empty()
+  }
+}
+
+impl ToString for MixedSite {
+  fn to_string(&self) -> String {
+    // NOTE: by convention, in bioinformatics, nucleotides are numbered starting from 1, however our arrays are 0-based
+    format!(
+      "{}{}{}:{}",
+      from_nuc(self.reff),
+      self.pos + 1,
+      from_nuc(self.qry),
+      self.alleles.iter().map(|nuc| from_nuc(*nuc)).join("/")
+    )
+  }
+}
+
+/// Pair of clades which might be present in the sample simultaneously: the clade of the nearest node and another clade,
+/// the defining mutations of which are seen as alleles of mixed sites
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoinfectionCladePair {
+  pub clade: String,
+  pub other_clade: String,
+
+  /// Number of mixed sites where one allele matches the nearest node and another matches the other clade
+  pub supporting_sites: usize,
+
+  /// Number of sequenced positions where a defining mutation of the other clade differs from the nearest node
+  pub informative_sites: usize,
+
+  /// Fraction of informative sites which are supporting sites, between 0 and 1
+  pub score: f64,
+}
+
+impl ToString for CoinfectionCladePair {
+  fn to_string(&self) -> String {
+    format!(
+      "{}+{}:{}/{}",
+      self.clade, self.other_clade, self.supporting_sites, self.informative_sites
+    )
+  }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MixedSites {
+  pub sites: Vec<MixedSite>,
+  pub total_mixed_sites: usize,
+
+  /// Number of mixed sites which are a possible evidence of co-infection or contamination
+  pub total_coinfection_sites: usize,
+
+  /// Score of the best supported clade pair, between 0 and 1
+  pub coinfection_score: f64,
+
+  /// Clade pairs, ordered from the best supported
+  pub coinfection_clade_pairs: Vec<CoinfectionCladePair>,
+}
+
+/// Finds mixed sites (positions with ambiguous nucleotides) and checks whether they might be a result of co-infection
+/// or contamination.
+///
+/// A mixed site is considered a possible evidence of co-infection, if one of the alleles it implies matches the nearest
+/// reference tree node and another allele matches a defining mutation of a different clade. Such sites are counted
+/// for every such clade and then compared to the number of positions which could distinguish this clade from the
+/// nearest node, in order to produce a score.
+pub fn find_mixed_sites(
+  qry_seq: &[Nuc],
+  ref_seq: &[Nuc],
+  non_acgtns: &[NucRange],
+  alignment_range: &Range,
+  node: &AuspiceTreeNode,
+  clade_defining_mutations: &CladeDefiningMutations,
+) -> MixedSites {
+  let clade = node.clade();
+  let node_nuc_at = |pos: usize| node.tmp.mutations.get(pos).unwrap_or(ref_seq[pos]);
+
+  let sites = non_acgtns
+    .iter()
+    .flat_map(|range| range.begin..range.end)
+    .map(|pos| {
+      let qry = qry_seq[pos];
+      let alleles = qry.to_acgt().to_vec();
+      let nearest_node_nuc = node_nuc_at(pos);
+
+      let other_clades = if alleles.contains(&nearest_node_nuc) {
+        find_other_clades(pos, &alleles, nearest_node_nuc, &clade, clade_defining_mutations)
+      } else {
+        vec![]
+      };
+
+      MixedSite {
+        reff: ref_seq[pos],
+        pos,
+        qry,
+        alleles,
+        nearest_node_nuc,
+        other_clades,
+      }
+    })
+    .collect_vec();
+
+  let mut supporting_sites = BTreeMap::<&str, usize>::new();
+  for other_clade in sites.iter().flat_map(|site| &site.other_clades) {
+    *supporting_sites.entry(other_clade).or_default() += 1;
+  }
+
+  let coinfection_clade_pairs = supporting_sites
+    .into_iter()
+    .map(|(other_clade, supporting_sites)| {
+      let informative_sites = count_informative_sites(
+        other_clade,
+        qry_seq,
+        alignment_range,
+        &node_nuc_at,
+        clade_defining_mutations,
+      );
+      let score = supporting_sites as f64 / informative_sites.max(1) as f64;
+      CoinfectionCladePair {
+        clade: clade.clone(),
+        other_clade: other_clade.to_owned(),
+        supporting_sites,
+        informative_sites,
+        score,
+      }
+    })
+    .sorted_by(|a, b| {
+      b.score
+        .total_cmp(&a.score)
+        .then(b.supporting_sites.cmp(&a.supporting_sites))
+    })
+    .collect_vec();
+
+  let total_mixed_sites = sites.len();
+  let total_coinfection_sites = sites.iter().filter(|site| site.is_coinfection_evidence()).count();
+  let coinfection_score = coinfection_clade_pairs.first().map_or(0.0, |pair| pair.score);
+
+  MixedSites {
+    sites,
+    total_mixed_sites,
+    total_coinfection_sites,
+    coinfection_score,
+    coinfection_clade_pairs,
+  }
+}
+
+/// Finds clades, other than the given one, which are defined by one of the alleles not matching the nearest node
+fn find_other_clades(
+  pos: usize,
+  alleles: &[Nuc],
+  nearest_node_nuc: Nuc,
+  clade: &str,
+  clade_defining_mutations: &CladeDefiningMutations,
+) -> Vec<String> {
+  let defining_nucs = match clade_defining_mutations.get(&pos) {
+    None => return vec![],
+    Some(defining_nucs) => defining_nucs,
+  };
+
+  alleles
+    .iter()
+    .filter(|allele| **allele != nearest_node_nuc)
+    .filter_map(|allele| defining_nucs.get(allele))
+    .flatten()
+    .filter(|other_clade| *other_clade != clade)
+    .cloned()
+    .collect::<BTreeSet<String>>()
+    .into_iter()
+    .collect_vec()
+}
+
+/// Counts sequenced positions in the alignment range, where a defining mutation of a given clade differs from
+/// the nearest node, i.e. positions which could have revealed this clade, if it is present in the sample
+fn count_informative_sites(
+  other_clade: &str,
+  qry_seq: &[Nuc],
+  alignment_range: &Range,
+  node_nuc_at: impl Fn(usize) -> Nuc,
+  clade_defining_mutations: &CladeDefiningMutations,
+) -> usize {
+  clade_defining_mutations
+    .range(alignment_range.begin..alignment_range.end)
+    .filter(|(pos, _)| !qry_seq[**pos].is_unknown())
+    .filter(|(pos, defining_nucs)| {
+      defining_nucs
+        .iter()
+        .any(|(nuc, clades)| clades.contains(other_clade) && *nuc != node_nuc_at(**pos))
+    })
+    .count()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::json::json_parse;
+  use crate::io::nuc::to_nuc_seq;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn node(clade: &str, mutations: &[(usize, Nuc)]) -> Result<AuspiceTreeNode, Report> {
+    let mut node: AuspiceTreeNode = json_parse(&format!(
+      r#"{{ "name": "node", "branch_attrs": {{ "mutations": {{}} }}, "node_attrs": {{ "clade_membership": {{ "value": "{clade}" }} }} }}"#
+    ))?;
+    node.tmp.mutations = mutations.iter().copied().collect();
+    Ok(node)
+  }
+
+  fn clade_defining_mutations(muts: &[(usize, Nuc, &str)]) -> CladeDefiningMutations {
+    let mut result = CladeDefiningMutations::new();
+    for (pos, nuc, clade) in muts {
+      result
+        .entry(*pos)
+        .or_default()
+        .entry(*nuc)
+        .or_default()
+        .insert((*clade).to_owned());
+    }
+    result
+  }
+
+  #[rstest]
+  fn finds_coinfection_evidence_in_mixed_sites() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ACGTACGTAC")?;
+    let qry_seq = to_nuc_seq("ARGTAYGTMC")?;
+    let non_acgtns = vec![
+      NucRange {
+        begin: 1,
+        end: 2,
+        letter: Nuc::R,
+      },
+      NucRange {
+        begin: 5,
+        end: 6,
+        letter: Nuc::Y,
+      },
+      NucRange {
+        begin: 8,
+        end: 9,
+        letter: Nuc::M,
+      },
+    ];
+
+    // Nearest node (clade A) carries C2G, clade B is defined by C6T and A9G, clade C by C2A
+    let node = node("A", &[(1, Nuc::G)])?;
+    let defining = clade_defining_mutations(&[(1, Nuc::G, "A"), (5, Nuc::T, "B"), (8, Nuc::G, "B"), (1, Nuc::A, "C")]);
+
+    let actual = find_mixed_sites(
+      &qry_seq,
+      &ref_seq,
+      &non_acgtns,
+      &Range { begin: 0, end: 10 },
+      &node,
+      &defining,
+    );
+
+    assert_eq!(actual.total_mixed_sites, 3);
+    assert_eq!(
+      actual.sites.iter().map(MixedSite::to_string).collect_vec(),
+      vec!["C2R:A/G", "C6Y:C/T", "A9M:A/C"]
+    );
+    assert_eq!(actual.sites[0].other_clades, vec!["C"]);
+    assert_eq!(actual.sites[1].other_clades, vec!["B"]);
+    assert!(actual.sites[2].other_clades.is_empty());
+    assert_eq!(actual.total_coinfection_sites, 2);
+
+    assert_eq!(
+      actual
+        .coinfection_clade_pairs
+        .iter()
+        .map(ToString::to_string)
+        .collect_vec(),
+      vec!["A+C:1/1", "A+B:1/2"]
+    );
+    assert_eq!(actual.coinfection_score, 1.0);
+    Ok(())
+  }
+}
@@ -10,6 +10,7 @@ use crate::io::gene_map::GeneMap;
 use crate::io::letter::Letter;
 use crate::make_internal_report;
 use crate::translate::translate_genes::{Translation, TranslationMap};
+use crate::tree::node_mutations::NodeMutations;
 use crate::tree::tree::AuspiceTreeNode;
 use crate::utils::collections::concat_to_vec;
 use crate::utils::range::Range;
@@ -45,7 +46,7 @@ pub fn find_private_aa_mutations(
     .filter_map(|(gene, _)| match node.tmp.aa_mutations.get(gene) {
       //node.tmp contains mutations accumulated from root
       None => None,
-      Some(node_mut_map) => {
+      Some(node_muts) => {
         let ref_peptide = ref_peptides
           .get(gene)
           .ok_or(make_internal_report!("Reference peptide not found for gene '{gene}'"))
@@ -56,7 +57,7 @@ pub fn find_private_aa_mutations(
         let aa_unknowns = aa_unknowns.iter().filter(|unk| &unk.gene_name == gene).collect_vec();
 
         let private_aa_mutations = find_private_aa_mutations_for_one_gene(
-          node_mut_map,
+          node_muts,
           &aa_substitutions,
           &aa_deletions,
           &aa_unknowns,
@@ -70,7 +71,7 @@ pub fn find_private_aa_mutations(
 }
 
 pub fn find_private_aa_mutations_for_one_gene(
-  node_mut_map: &BTreeMap<usize, Aa>,
+  node_mutations: &NodeMutations<Aa>,
   aa_substitutions: &[&AaSub],
   aa_deletions: &[&AaDel],
   aa_unknowns: &[&GeneAaRange],
@@ -82,11 +83,11 @@ pub fn find_private_aa_mutations_for_one_gene(
 
   // Iterate over sequence substitutions
   let non_reversion_substitutions =
-    process_seq_substitutions(node_mut_map, aa_substitutions, &mut seq_positions_mutated_or_deleted);
+    process_seq_substitutions(node_mutations, aa_substitutions, &mut seq_positions_mutated_or_deleted);
 
   // Iterate over sequence deletions
   let non_reversion_deletions = process_seq_deletions(
-    node_mut_map,
+    node_mutations,
     aa_deletions,
     ref_peptide,
     &mut seq_positions_mutated_or_deleted,
@@ -94,7 +95,7 @@ pub fn find_private_aa_mutations_for_one_gene(
 
   // Iterate over node substitutions and deletions and find reversions
   let reversion_substitutions = find_reversions(
-    node_mut_map,
+    node_mutations,
     aa_unknowns,
     ref_peptide,
     &mut seq_positions_mutated_or_deleted,
@@ -124,7 +125,7 @@ pub fn find_private_aa_mutations_for_one_gene(
 
 /// Iterates over sequence substitutions, compares sequence and node substitutions and finds the private ones.
 fn process_seq_substitutions(
-  node_mut_map: &BTreeMap<usize, Aa>,
+  node_mutations: &NodeMutations<Aa>,
   substitutions: &[&AaSub],
   seq_positions_mutated_or_deleted: &mut BTreeSet<usize>,
 ) -> Vec<AaSubMinimal> {
@@ -142,7 +143,7 @@ fn process_seq_substitutions(
       continue;
     }
 
-    match node_mut_map.get(&pos) {
+    match node_mutations.get(pos) {
       None => {
         // Case 3: Mutation in sequence but not in node, i.e. a newly occurred mutation.
         // Action: Add the sequence mutation itself.
@@ -153,11 +154,11 @@ fn process_seq_substitutions(
         });
       }
       Some(node_qry) => {
-        if &seq_mut.qry != node_qry {
+        if seq_mut.qry != node_qry {
           // Case 2: Mutation in sequence and in node, but the query character is not the same.
           // Action: Add mutation from node query character to sequence query character.
           non_reversion_substitutions.push(AaSubMinimal {
-            reff: *node_qry,
+            reff: node_qry,
             pos,
             qry: seq_mut.qry,
           });
@@ -181,7 +182,7 @@ fn process_seq_substitutions(
 /// two specializations are provided below. This is due to deletions having different data structure for nucleotides
 /// and for amino acids (range vs point).
 fn process_seq_deletions(
-  node_mut_map: &BTreeMap<usize, Aa>,
+  node_mutations: &NodeMutations<Aa>,
   deletions: &[&AaDel],
   ref_seq: &[Aa],
   seq_positions_mutated_or_deleted: &mut BTreeSet<usize>,
@@ -192,7 +193,7 @@ fn process_seq_deletions(
     let pos = del.pos;
     seq_positions_mutated_or_deleted.insert(pos);
 
-    match node_mut_map.get(&pos) {
+    match node_mutations.get(pos) {
       None => {
         // Case 3: Mutation in sequence but not in node, i.e. a newly occurred mutation.
         // Action: Add the sequence mutation itself.
@@ -202,7 +203,7 @@ fn process_seq_deletions(
         if !node_qry.is_gap() {
           // Case 2: Mutation in sequence and in node, but the query character is not the same.
           // Action: Add mutation from node query character to sequence query character.
-          non_reversion_deletions.push(AaDelMinimal { reff: *node_qry, pos });
+          non_reversion_deletions.push(AaDelMinimal { reff: node_qry, pos });
         }
       }
     }
@@ -219,24 +220,22 @@ fn process_seq_deletions(
 
 /// Iterates over node mutations, compares node and sequence mutations and finds reversion mutations.
 fn find_reversions(
-  node_mut_map: &BTreeMap<usize, Aa>,
+  node_mutations: &NodeMutations<Aa>,
   aa_unknowns: &[&GeneAaRange],
   ref_peptide: &[Aa],
   seq_positions_mutated_or_deleted: &mut BTreeSet<usize>,
 ) -> Vec<AaSubMinimal> {
   let mut reversion_substitutions = Vec::<AaSubMinimal>::new();
 
-  for (pos, node_qry) in node_mut_map {
-    let pos = *pos;
+  for (pos, node_qry) in node_mutations.iter_substitutions() {
     let seq_has_no_mut_or_del_here = !seq_positions_mutated_or_deleted.contains(&pos);
     let pos_is_sequenced = is_aa_sequenced(pos, aa_unknowns);
-    let is_not_node_deletion = !node_qry.is_gap();
-    if seq_has_no_mut_or_del_here && pos_is_sequenced && is_not_node_deletion {
+    if seq_has_no_mut_or_del_here && pos_is_sequenced {
       // Case 4: Mutation in node, but not in sequence. This is a so-called reversion. Mutation in sequence reverts
       // the character to ref seq.
       // Action: Add mutation from node query character to character in reference sequence.
       reversion_substitutions.push(AaSubMinimal {
-        reff: *node_qry,
+        reff: node_qry,
         pos,
         qry: ref_peptide[pos],
       });
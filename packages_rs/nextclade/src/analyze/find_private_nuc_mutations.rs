@@ -7,11 +7,12 @@ use crate::gene::genotype::{Genotype, GenotypeLabeled};
 use crate::io::aa::Aa;
 use crate::io::letter::Letter;
 use crate::io::nuc::Nuc;
+use crate::tree::node_mutations::NodeMutations;
 use crate::tree::tree::AuspiceTreeNode;
 use crate::utils::collections::concat_to_vec;
 use crate::utils::range::Range;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeSet;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -67,7 +68,7 @@ pub fn find_private_nuc_mutations(
   ref_seq: &[Nuc],
   virus_properties: &VirusProperties,
 ) -> PrivateNucMutations {
-  let node_mut_map = &node.tmp.mutations;
+  let node_mutations = &node.tmp.mutations;
 
   // Remember which positions we cover while iterating sequence mutations,
   // to be able to skip them when we iterate over node mutations
@@ -75,15 +76,19 @@ pub fn find_private_nuc_mutations(
 
   // Iterate over sequence substitutions
   let non_reversion_substitutions =
-    process_seq_substitutions(node_mut_map, substitutions, &mut seq_positions_mutated_or_deleted);
+    process_seq_substitutions(node_mutations, substitutions, &mut seq_positions_mutated_or_deleted);
 
   // Iterate over sequence deletions
-  let non_reversion_deletions =
-    process_seq_deletions(node_mut_map, deletions, ref_seq, &mut seq_positions_mutated_or_deleted);
+  let non_reversion_deletions = process_seq_deletions(
+    node_mutations,
+    deletions,
+    ref_seq,
+    &mut seq_positions_mutated_or_deleted,
+  );
 
   // Iterate over node substitutions and deletions and find reversions
   let reversion_substitutions = find_reversions(
-    node_mut_map,
+    node_mutations,
     missing,
     alignment_range,
     ref_seq,
@@ -128,7 +133,7 @@ pub fn find_private_nuc_mutations(
 ///
 /// This function is generic and is suitable for both nucleotide and aminoacid substitutions.
 fn process_seq_substitutions(
-  node_mut_map: &BTreeMap<usize, Nuc>,
+  node_mutations: &NodeMutations<Nuc>,
   substitutions: &[NucSub],
   seq_positions_mutated_or_deleted: &mut BTreeSet<usize>,
 ) -> Vec<NucSub> {
@@ -146,7 +151,7 @@ fn process_seq_substitutions(
       continue;
     }
 
-    match node_mut_map.get(&pos) {
+    match node_mutations.get(pos) {
       None => {
         // Case 3: Mutation in sequence but not in node, i.e. a newly occurred mutation.
         // Action: Add the sequence mutation itself.
@@ -157,11 +162,11 @@ fn process_seq_substitutions(
         });
       }
       Some(node_qry) => {
-        if &seq_mut.qry != node_qry {
+        if seq_mut.qry != node_qry {
           // Case 2: Mutation in sequence and in node, but the query character is not the same.
           // Action: Add mutation from node query character to sequence query character.
           non_reversion_substitutions.push(NucSub {
-            reff: *node_qry,
+            reff: node_qry,
             pos,
             qry: seq_mut.qry,
           });
@@ -185,7 +190,7 @@ fn process_seq_substitutions(
 /// two specializations are provided below. This is due to deletions having different data structure for nucleotides
 /// and for amino acids (range vs point).
 fn process_seq_deletions(
-  node_mut_map: &BTreeMap<usize, Nuc>,
+  node_mutations: &NodeMutations<Nuc>,
   deletions: &[NucDel],
   ref_seq: &[Nuc],
   seq_positions_mutated_or_deleted: &mut BTreeSet<usize>,
@@ -200,7 +205,7 @@ fn process_seq_deletions(
     for pos in start..end {
       seq_positions_mutated_or_deleted.insert(pos);
 
-      match node_mut_map.get(&pos) {
+      match node_mutations.get(pos) {
         None => {
           // Case 3: Deletion in sequence but not in node, i.e. this is a newly occurred deletion.
           // Action: Add the sequence deletion itself (take refNuc from reference sequence).
@@ -214,7 +219,7 @@ fn process_seq_deletions(
             {
               // Case 2: Mutation in node but deletion in sequence (mutation to '-'), i.e. the query character is not the
               // same. Action: Add deletion of node query character.
-              non_reversion_deletions.push(NucDelMinimal { reff: *node_qry, pos });
+              non_reversion_deletions.push(NucDelMinimal { reff: node_qry, pos });
             }
           }
         }
@@ -233,7 +238,7 @@ fn process_seq_deletions(
 
 /// Iterates over node mutations, compares node and sequence mutations and finds reversion mutations.
 fn find_reversions(
-  node_mut_map: &BTreeMap<usize, Nuc>,
+  node_mutations: &NodeMutations<Nuc>,
   missing: &[NucRange],
   alignment_range: &Range,
   ref_seq: &[Nuc],
@@ -241,17 +246,15 @@ fn find_reversions(
 ) -> Vec<NucSub> {
   let mut reversion_substitutions = Vec::<NucSub>::new();
 
-  for (pos, node_qry) in node_mut_map {
-    let pos = *pos;
+  for (pos, node_qry) in node_mutations.iter_substitutions() {
     let seq_has_no_mut_or_del_here = !seq_positions_mutated_or_deleted.contains(&pos);
     let pos_is_sequenced = is_nuc_sequenced(pos, missing, alignment_range);
-    let is_not_node_deletion = !node_qry.is_gap();
-    if seq_has_no_mut_or_del_here && pos_is_sequenced && is_not_node_deletion {
+    if seq_has_no_mut_or_del_here && pos_is_sequenced {
       // Case 4: Mutation in node, but not in sequence. This is a so-called reversion. Mutation in sequence reverts
       // the character to ref seq.
       // Action: Add mutation from node query character to character in reference sequence.
       reversion_substitutions.push(NucSub {
-        reff: *node_qry,
+        reff: node_qry,
         pos,
         qry: ref_seq[pos],
       });
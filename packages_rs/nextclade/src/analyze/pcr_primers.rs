@@ -51,6 +51,36 @@ impl PcrPrimer {
       .collect::<Result<Vec<Self>, Report>>()
   }
 
+  /// Parses PCR primers of a segmented genome. Each primer is assigned to every segment it is found in. Returns a list
+  /// of primers for each of the segments, in the same order as the reference sequences of the segments.
+  pub fn from_str_segmented(s: &str, ref_seq_strs: &[&str]) -> Result<Vec<Vec<Self>>, Report> {
+    let raw: Vec<PcrPrimerCsvRow> = parse_csv(s)?;
+    let mut primers = vec![vec![]; ref_seq_strs.len()];
+    for raw_primer in raw {
+      to_nuc_seq(&raw_primer.primer_oligonuc)
+        .wrap_err_with(|| format!("When parsing PCR primer '{}'", raw_primer.name))?;
+
+      let mut is_found = false;
+      for (segment_primers, ref_seq_str) in primers.iter_mut().zip(ref_seq_strs) {
+        if let Ok(primer) = convert_pcr_primer(raw_primer.clone(), ref_seq_str) {
+          segment_primers.push(primer);
+          is_found = true;
+        }
+      }
+
+      if !is_found {
+        return make_error!(
+          "PCR primer not found in any of the reference segments: name: '{}', source: '{}', oligonuc: '{}'. \
+          This might mean that the list of primers is not compatible with the reference sequences used.",
+          raw_primer.name,
+          raw_primer.source,
+          raw_primer.primer_oligonuc
+        );
+      }
+    }
+    Ok(primers)
+  }
+
   pub fn from_path(filepath: impl AsRef<Path>, ref_seq_str: &str) -> Result<Vec<Self>, Report> {
     let filepath = filepath.as_ref();
 
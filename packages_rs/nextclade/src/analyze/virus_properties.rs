@@ -5,6 +5,7 @@ use crate::io::fs::read_file_to_string;
 use crate::io::json::json_parse;
 use crate::io::letter::Letter;
 use crate::io::nuc::Nuc;
+use crate::translate::genetic_code::{GeneticCode, GENETIC_CODE_STANDARD};
 use crate::utils::range::Range;
 use eyre::{Report, WrapErr};
 use serde::{Deserialize, Serialize};
@@ -25,6 +26,10 @@ struct VirusPropertiesRaw {
   pub aa_motifs: Vec<AaMotifsDesc>,
   #[serde(default = "Vec::new")]
   pub placement_mask_ranges: Vec<Range>, // 0-based, end-exclusive
+
+  /// Genetic code (number of NCBI translation table) used to translate genes, unless specified per gene in the gene map
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub genetic_code: Option<u8>,
 }
 
 /// Contains external configuration and data specific for a particular pathogen
@@ -39,6 +44,10 @@ pub struct VirusProperties {
   pub aa_motifs: Vec<AaMotifsDesc>,
   #[serde(default = "Vec::new")]
   pub placement_mask_ranges: Vec<Range>, // 0-based, end-exclusive
+
+  /// Genetic code (number of NCBI translation table) used to translate genes, unless specified per gene in the gene map
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub genetic_code: Option<u8>,
 }
 
 /// Associates a genotype (pos, nuc) to a list of labels
@@ -153,6 +162,10 @@ impl FromStr for VirusProperties {
       }
     }
 
+    if let Some(genetic_code) = raw.genetic_code {
+      GeneticCode::from_id(genetic_code)?;
+    }
+
     Ok(Self {
       schema_version: raw.schema_version,
       alignment_params: raw.alignment_params,
@@ -160,6 +173,7 @@ impl FromStr for VirusProperties {
       phenotype_data: raw.phenotype_data,
       aa_motifs: raw.aa_motifs,
       placement_mask_ranges: raw.placement_mask_ranges,
+      genetic_code: raw.genetic_code,
     })
   }
 }
@@ -171,4 +185,9 @@ impl VirusProperties {
       read_file_to_string(filepath).wrap_err_with(|| format!("When reading virus properties file {filepath:#?}"))?;
     Self::from_str(&data).wrap_err_with(|| format!("When parsing virus properties file {filepath:#?}"))
   }
+
+  /// Genetic code used to translate genes which have no genetic code specified in the gene map
+  pub fn genetic_code(&self) -> Result<&'static GeneticCode, Report> {
+    GeneticCode::from_id(self.genetic_code.unwrap_or(GENETIC_CODE_STANDARD))
+  }
 }
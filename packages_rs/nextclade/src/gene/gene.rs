@@ -21,34 +21,112 @@ impl From<bio_types::strand::Strand> for GeneStrand {
   }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Raw JSON version of the `Gene` struct. Coding segments are optional, for compatibility with the gene maps
+/// serialized before they were introduced.
+#[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+struct GeneRaw {
+  gene_name: String,
+  start: usize,
+  end: usize,
+  strand: GeneStrand,
+  frame: i32,
+  #[serde(default)]
+  cds_segments: Vec<Range>,
+  #[serde(default)]
+  genetic_code: Option<u8>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", from = "GeneRaw")]
 pub struct Gene {
   pub gene_name: String,
   pub start: usize,
   pub end: usize,
   pub strand: GeneStrand,
   pub frame: i32,
+  /// Ranges of coding segments (CDS) in the reference sequence, in the order in which they are concatenated into the
+  /// coding sequence of the gene (i.e. in the order of translation). A contiguous gene has one segment, from `start`
+  /// to `end`. Segments may overlap, for example in case of a ribosomal slippage.
+  pub cds_segments: Vec<Range>,
+  /// Genetic code (number of NCBI translation table), if different from the default genetic code of the dataset
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub genetic_code: Option<u8>,
+}
+
+impl From<GeneRaw> for Gene {
+  fn from(raw: GeneRaw) -> Self {
+    let GeneRaw {
+      gene_name,
+      start,
+      end,
+      strand,
+      frame,
+      mut cds_segments,
+      genetic_code,
+    } = raw;
+
+    // A gene without coding segments is a contiguous gene
+    if cds_segments.is_empty() {
+      cds_segments.push(Range::new(start, end));
+    }
+
+    Self {
+      gene_name,
+      start,
+      end,
+      strand,
+      frame,
+      cds_segments,
+      genetic_code,
+    }
+  }
 }
 
 impl Gene {
+  /// Length of the coding sequence, i.e. total length of all coding segments
   #[inline]
-  pub const fn len(&self) -> usize {
-    self.end - self.start
+  pub fn len(&self) -> usize {
+    self.cds_segments.iter().map(Range::len).sum()
   }
 
   #[inline]
-  pub const fn len_codon(&self) -> usize {
+  pub fn len_codon(&self) -> usize {
     (self.len() - self.len() % 3) / 3
   }
 
   #[inline]
-  pub const fn is_empty(&self) -> bool {
+  pub fn is_empty(&self) -> bool {
     self.len() == 0
   }
 
-  /// Converts relative nucleotide position inside gene (relative to gene start) to absolute position in the
-  /// reference nucleotide sequence
+  #[inline]
+  pub fn is_contiguous(&self) -> bool {
+    self.cds_segments.len() == 1
+  }
+
+  /// Finds the coding segment which contains a given position relative to the coding sequence. Returns index of the
+  /// segment and the position relative to the segment start (in the direction of translation). Positions past the end
+  /// of the coding sequence are attributed to the last segment.
+  pub fn find_cds_segment(&self, nuc_ref_rel: usize) -> (usize, usize) {
+    let last = self.cds_segments.len().saturating_sub(1);
+    let mut offset = nuc_ref_rel;
+    for (index, segment) in self.cds_segments.iter().enumerate() {
+      if offset < segment.len() || index == last {
+        return (index, offset);
+      }
+      offset -= segment.len();
+    }
+    (last, offset)
+  }
+
+  /// Total length of coding segments preceding the segment with a given index
+  pub fn cds_segment_offset(&self, segment_index: usize) -> usize {
+    self.cds_segments[..segment_index].iter().map(Range::len).sum()
+  }
+
+  /// Converts relative nucleotide position inside gene (relative to the beginning of the coding sequence) to absolute
+  /// position in the reference nucleotide sequence
   #[inline]
   pub fn nuc_rel_to_abs(&self, nuc_ref_rel: usize) -> usize {
     debug_assert!(
@@ -56,13 +134,28 @@ impl Gene {
       "Position should be within the gene:\nnuc_ref_rel={nuc_ref_rel:},\ngene.len()={self:#?}"
     );
 
+    let (segment_index, pos) = self.find_cds_segment(nuc_ref_rel);
+    let segment = &self.cds_segments[segment_index];
     if self.strand == GeneStrand::Reverse {
-      self.end - nuc_ref_rel
+      segment.end - 1 - pos
     } else {
-      self.start + nuc_ref_rel
+      segment.begin + pos
     }
   }
 
+  /// Converts absolute position in the reference nucleotide sequence to the position relative to the beginning of the
+  /// coding sequence, given the coding segment the position belongs to
+  #[inline]
+  pub fn nuc_abs_to_rel(&self, segment_index: usize, nuc_ref_abs: usize) -> usize {
+    let segment = &self.cds_segments[segment_index];
+    let pos = if self.strand == GeneStrand::Reverse {
+      (segment.end - 1).saturating_sub(nuc_ref_abs)
+    } else {
+      nuc_ref_abs.saturating_sub(segment.begin)
+    };
+    self.cds_segment_offset(segment_index) + pos
+  }
+
   /// Converts codon index into absolute position in the reference nucleotide sequence
   #[inline]
   pub const fn codon_to_nuc_position(&self, codon: usize) -> usize {
@@ -99,3 +192,22 @@ impl Gene {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::json::json_parse;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  fn reads_gene_without_cds_segments() -> Result<(), Report> {
+    let gene: Gene = json_parse(r#"{ "geneName": "S", "start": 21562, "end": 25384, "strand": "+", "frame": 0 }"#)?;
+
+    assert_eq!(gene.cds_segments, vec![Range::new(21562, 25384)]);
+    assert_eq!(gene.len(), 3822);
+    assert_eq!(gene.genetic_code, None);
+    Ok(())
+  }
+}
@@ -1,4 +1,6 @@
+use crate::align::params::AlignPairwiseParams;
 use crate::align::score_matrix_aa::lookup_aa_scoring_matrix;
+use crate::align::score_matrix_custom::ScoringMatrix;
 use crate::io::letter::{Letter, ScoreMatrixLookup};
 use crate::make_error;
 use color_eyre::{Section, SectionExt};
@@ -62,9 +64,25 @@ impl Default for Aa {
 }
 
 impl ScoreMatrixLookup<Aa> for Aa {
+  #[rustfmt::skip]
+  const ALPHABET: &'static [Aa] = &[
+    Aa::A, Aa::B, Aa::C, Aa::D, Aa::E, Aa::F, Aa::G, Aa::H, Aa::I, Aa::J,
+    Aa::K, Aa::L, Aa::M, Aa::N, Aa::O, Aa::P, Aa::Q, Aa::R, Aa::S, Aa::T,
+    Aa::U, Aa::V, Aa::W, Aa::Y, Aa::Z, Aa::X, Aa::Stop, Aa::Gap,
+  ];
+
   fn lookup_match_score(x: Aa, y: Aa) -> i32 {
     lookup_aa_scoring_matrix(x, y)
   }
+
+  #[inline]
+  fn alphabet_index(x: Aa) -> usize {
+    x as usize
+  }
+
+  fn custom_scoring_matrix(params: &AlignPairwiseParams) -> Option<&ScoringMatrix<Aa>> {
+    params.scoring_matrix_aa.as_ref()
+  }
 }
 
 impl Letter<Aa> for Aa {
@@ -114,6 +114,20 @@ pub fn parse_csv<T: for<'de> Deserialize<'de>, S: AsRef<str>>(data: S) -> Result
     .collect::<Result<Vec<T>, Report>>()
 }
 
+/// Parses tab-separated data from string. Lines starting with `#` are treated as comments.
+pub fn parse_tsv<T: for<'de> Deserialize<'de>, S: AsRef<str>>(data: S) -> Result<Vec<T>, Report> {
+  let reader = CsvReaderBuilder::new()
+    .has_headers(true)
+    .delimiter(b'\t')
+    .comment(Some(b'#'))
+    .from_reader(data.as_ref().as_bytes());
+  reader
+    .into_deserialize::<T>()
+    .into_iter()
+    .map(to_eyre_error)
+    .collect::<Result<Vec<T>, Report>>()
+}
+
 /// Parses CSV file.
 pub fn read_csv_file<T: for<'de> Deserialize<'de>>(filepath: impl AsRef<Path>) -> Result<Vec<T>, Report> {
   let filepath = filepath.as_ref();
@@ -0,0 +1,36 @@
+use crate::io::csv::CsvStructFileWriter;
+use eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetAssignmentCsvEntry<'a> {
+  pub index: usize,
+  pub seq_name: &'a str,
+  pub dataset: &'a str,
+  pub score: String,
+}
+
+/// Writes summary of assignment of sequences to datasets, in multi-dataset mode
+pub struct DatasetAssignmentCsvWriter {
+  writer: CsvStructFileWriter,
+}
+
+impl DatasetAssignmentCsvWriter {
+  pub fn new(filepath: impl AsRef<Path>, delimiter: u8) -> Result<Self, Report> {
+    Ok(Self {
+      writer: CsvStructFileWriter::new(filepath.as_ref(), delimiter)?,
+    })
+  }
+
+  /// Writes one row. Empty dataset name means the sequence was not assigned to any of the datasets.
+  pub fn write(&mut self, index: usize, seq_name: &str, dataset: Option<&str>, score: f64) -> Result<(), Report> {
+    self.writer.write(&DatasetAssignmentCsvEntry {
+      index,
+      seq_name,
+      dataset: dataset.unwrap_or_default(),
+      score: format!("{score:.6}"),
+    })
+  }
+}
@@ -165,6 +165,22 @@ pub fn read_one_fasta_str(contents: &str) -> Result<FastaRecord, Report> {
   Ok(record)
 }
 
+pub fn read_many_fasta_str(contents: &str) -> Result<Vec<FastaRecord>, Report> {
+  let mut reader = FastaReader::from_str(contents)?;
+  let mut fasta_records = Vec::<FastaRecord>::new();
+
+  loop {
+    let mut record = FastaRecord::default();
+    reader.read(&mut record)?;
+    if record.is_empty() {
+      break;
+    }
+    fasta_records.push(record);
+  }
+
+  Ok(fasta_records)
+}
+
 // Writes sequences into given fasta file
 pub struct FastaWriter {
   writer: Box<dyn std::io::Write>,
@@ -0,0 +1,524 @@
+use crate::gene::gene::{Gene, GeneStrand};
+use crate::io::gene_map::{GeneMap, SegmentedGeneMap};
+use crate::make_error;
+use crate::translate::genetic_code::GeneticCode;
+use crate::utils::range::Range;
+use color_eyre::{Section, SectionExt};
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use log::warn;
+
+/// Column at which feature locations and qualifiers start in the feature table of a GenBank flat file (0-based)
+const GENBANK_QUALIFIER_INDENT: usize = 21;
+
+/// Feature from the feature table of a GenBank record
+#[derive(Clone, Debug, Default)]
+pub struct GenBankFeature {
+  pub key: String,
+  pub location: String,
+  /// Qualifiers in the order of appearance, with the enclosing quotes removed
+  pub qualifiers: Vec<(String, String)>,
+}
+
+impl GenBankFeature {
+  /// Returns value of the first qualifier with a given name
+  pub fn qualifier(&self, name: &str) -> Option<&str> {
+    self
+      .qualifiers
+      .iter()
+      .find(|(key, _)| key == name)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GenBankRecord {
+  /// Name of the sequence: accession with version (`VERSION` line), if present, or the locus name (`LOCUS` line)
+  pub name: String,
+  pub features: Vec<GenBankFeature>,
+}
+
+/// Returns true if the content looks like a GenBank flat file
+pub fn is_genbank_str(content: &str) -> bool {
+  content.trim_start().starts_with("LOCUS")
+}
+
+/// Reads records of a GenBank flat file. Only the sequence name and the feature table of each record are read.
+pub fn parse_genbank_records(content: &str) -> Result<Vec<GenBankRecord>, Report> {
+  let mut records = Vec::<GenBankRecord>::new();
+  let mut in_features = false;
+  let mut in_quotes = false;
+
+  for (index, line) in content.lines().enumerate() {
+    let line = line.trim_end();
+    if line.is_empty() {
+      continue;
+    }
+
+    if !line.starts_with(' ') {
+      in_features = false;
+      if line.starts_with("LOCUS") {
+        records.push(GenBankRecord {
+          name: line.split_whitespace().nth(1).unwrap_or_default().to_owned(),
+          features: vec![],
+        });
+      } else if line.starts_with("VERSION") {
+        if let (Some(record), Some(version)) = (records.last_mut(), line.split_whitespace().nth(1)) {
+          record.name = version.to_owned();
+        }
+      } else if line.starts_with("FEATURES") {
+        in_features = true;
+      }
+      continue;
+    }
+
+    if !in_features {
+      continue;
+    }
+
+    let record = match records.last_mut() {
+      Some(record) => record,
+      None => {
+        return make_error!(
+          "GenBank file is invalid: line {}: feature table found before the 'LOCUS' line",
+          index + 1
+        )
+      }
+    };
+
+    let indent = line.len() - line.trim_start().len();
+    let text = line.trim();
+
+    if indent < GENBANK_QUALIFIER_INDENT && !in_quotes {
+      // New feature: key, followed by the location
+      let (key, location) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+      record.features.push(GenBankFeature {
+        key: key.to_owned(),
+        location: location.trim().to_owned(),
+        qualifiers: vec![],
+      });
+      continue;
+    }
+
+    let feature = match record.features.last_mut() {
+      Some(feature) => feature,
+      None => {
+        return make_error!(
+          "GenBank file is invalid: line {}: qualifier found before any feature",
+          index + 1
+        )
+      }
+    };
+
+    if text.starts_with('/') && !in_quotes {
+      let (name, value) = text[1..].split_once('=').unwrap_or((&text[1..], ""));
+      in_quotes = value.matches('"').count() % 2 == 1;
+      feature.qualifiers.push((name.to_owned(), value.to_owned()));
+    } else if let Some((name, value)) = feature.qualifiers.last_mut() {
+      // Continuation of a multi-line qualifier value. Sequences are split without spaces.
+      if name != "translation" {
+        value.push(' ');
+      }
+      value.push_str(text);
+      in_quotes ^= text.matches('"').count() % 2 == 1;
+    } else {
+      // Continuation of a multi-line location
+      feature.location.push_str(text);
+    }
+  }
+
+  for feature in records.iter_mut().flat_map(|record| &mut record.features) {
+    for (_, value) in &mut feature.qualifiers {
+      if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        *value = value[1..value.len() - 1].replace("\"\"", "\"");
+      }
+    }
+  }
+
+  Ok(records)
+}
+
+/// Parses location of a GenBank feature into a list of segments, in the order of translation. The segments are
+/// 0-based, end-exclusive ranges, each with its strand.
+///
+/// Supports ranges (`10..20`), single bases (`10`), partial ranges (`<10..>20`), `join(...)`, `order(...)` and
+/// `complement(...)`, in any combination. References to other sequences and sites between bases (`10^11`) are not
+/// supported.
+pub fn parse_genbank_location(location: &str) -> Result<Vec<(Range, GeneStrand)>, Report> {
+  let location: String = location.chars().filter(|c| !c.is_whitespace()).collect();
+  parse_genbank_location_impl(&location)
+    .wrap_err_with(|| format!("GenBank feature is invalid: unable to parse location '{location}'"))
+}
+
+fn parse_genbank_location_impl(location: &str) -> Result<Vec<(Range, GeneStrand)>, Report> {
+  if let Some(inner) = strip_operator(location, "complement") {
+    let mut segments = parse_genbank_location_impl(inner)?;
+    segments.reverse();
+    for (_, strand) in &mut segments {
+      *strand = match strand {
+        GeneStrand::Forward => GeneStrand::Reverse,
+        GeneStrand::Reverse => GeneStrand::Forward,
+        GeneStrand::Unknown => GeneStrand::Unknown,
+      };
+    }
+    return Ok(segments);
+  }
+
+  if let Some(inner) = strip_operator(location, "join").or_else(|| strip_operator(location, "order")) {
+    return split_top_level(inner)?
+      .into_iter()
+      .map(parse_genbank_location_impl)
+      .flatten_ok()
+      .collect();
+  }
+
+  if location.contains(':') {
+    return make_error!("references to other sequences are not supported");
+  }
+  if location.contains('^') {
+    return make_error!("sites between bases are not supported");
+  }
+
+  let parse_pos = |pos: &str| -> Result<usize, Report> {
+    let pos = pos.trim_start_matches('<').trim_start_matches('>');
+    let pos = pos
+      .parse::<usize>()
+      .wrap_err_with(|| format!("unable to parse position '{pos}'"))?;
+    if pos == 0 {
+      return make_error!("positions are expected to be 1-based, but found position 0");
+    }
+    Ok(pos)
+  };
+
+  let (begin, end) = match location.split_once("..") {
+    Some((begin, end)) => (parse_pos(begin)?, parse_pos(end)?),
+    None => {
+      let pos = parse_pos(location)?;
+      (pos, pos)
+    }
+  };
+
+  if begin > end {
+    return make_error!("range start {begin} is after range end {end}");
+  }
+
+  Ok(vec![(Range::new(begin - 1, end), GeneStrand::Forward)])
+}
+
+/// Returns the arguments of an operator, e.g. `a,b` for `join(a,b)`
+fn strip_operator<'a>(location: &'a str, operator: &str) -> Option<&'a str> {
+  location
+    .strip_prefix(operator)
+    .and_then(|rest| rest.strip_prefix('('))
+    .and_then(|rest| rest.strip_suffix(')'))
+}
+
+/// Splits comma-separated operator arguments, ignoring commas inside of the nested operators
+fn split_top_level(s: &str) -> Result<Vec<&str>, Report> {
+  let mut parts = vec![];
+  let mut depth = 0_usize;
+  let mut part_start = 0;
+  for (i, c) in s.char_indices() {
+    match c {
+      '(' => depth += 1,
+      ')' => {
+        depth = match depth.checked_sub(1) {
+          Some(depth) => depth,
+          None => return make_error!("unbalanced parentheses"),
+        }
+      }
+      ',' if depth == 0 => {
+        parts.push(&s[part_start..i]);
+        part_start = i + 1;
+      }
+      _ => {}
+    }
+  }
+  if depth != 0 {
+    return make_error!("unbalanced parentheses");
+  }
+  parts.push(&s[part_start..]);
+  Ok(parts)
+}
+
+/// Parses genetic code from the `transl_table` qualifier of the feature
+fn parse_genbank_genetic_code(feature: &GenBankFeature) -> Result<Option<u8>, Report> {
+  feature
+    .qualifier("transl_table")
+    .map(|transl_table| -> Result<u8, Report> {
+      let id = transl_table.parse::<u8>().wrap_err_with(|| {
+        format!("GenBank feature is invalid: unable to parse 'transl_table' qualifier: '{transl_table}'")
+      })?;
+      GeneticCode::from_id(id)?;
+      Ok(id)
+    })
+    .transpose()
+}
+
+/// Converts a `CDS` feature of a GenBank record to the internal `Gene` representation. If the `codon_start` qualifier
+/// is present, then the nucleotides before the first codon are excluded from the gene.
+pub fn convert_genbank_feature_to_gene(gene_name: &str, feature: &GenBankFeature) -> Result<Gene, Report> {
+  let segments = parse_genbank_location(&feature.location)?;
+
+  let strand = match segments
+    .iter()
+    .map(|(_, strand)| strand)
+    .dedup()
+    .collect_vec()
+    .as_slice()
+  {
+    [strand] => (*strand).clone(),
+    [] => return make_error!("GenBank feature is invalid: location is empty"),
+    _ => return make_error!("GenBank feature is invalid: coding segments on both strands are not supported"),
+  };
+
+  let mut cds_segments = segments.into_iter().map(|(range, _)| range).collect_vec();
+
+  let codon_start = match feature.qualifier("codon_start") {
+    None => 1,
+    Some(codon_start) => match codon_start.parse::<usize>() {
+      Ok(codon_start @ 1..=3) => codon_start,
+      _ => {
+        return make_error!(
+          "GenBank feature is invalid: 'codon_start' qualifier should be 1, 2 or 3, but found '{codon_start}'"
+        )
+      }
+    },
+  };
+
+  let offset = codon_start - 1;
+  if offset > 0 {
+    let first = &mut cds_segments[0];
+    if first.len() <= offset {
+      return make_error!("GenBank feature is invalid: first coding segment is shorter than 'codon_start'");
+    }
+    if strand == GeneStrand::Reverse {
+      first.end -= offset;
+    } else {
+      first.begin += offset;
+    }
+  }
+
+  let length: usize = cds_segments.iter().map(Range::len).sum();
+  if length % 3 != 0 {
+    if cds_segments.len() == 1 {
+      return make_error!(
+        "GenBank feature is invalid: feature length must be divisible by 3, but the length is {length}"
+      );
+    }
+    return make_error!(
+      "GenBank feature is invalid: total length of coding segments (CDS) must be divisible by 3, but the length is {length}"
+    );
+  }
+
+  let start = cds_segments
+    .iter()
+    .map(|segment| segment.begin)
+    .min()
+    .unwrap_or_default();
+  let end = cds_segments.iter().map(|segment| segment.end).max().unwrap_or_default();
+
+  Ok(Gene {
+    gene_name: gene_name.to_owned(),
+    start,
+    end,
+    strand,
+    frame: (start % 3) as i32,
+    cds_segments,
+    genetic_code: parse_genbank_genetic_code(feature)?,
+  })
+}
+
+/// Converts a GenBank feature to a gene map entry. Only `CDS` features are converted. The gene is named after the
+/// `gene`, `locus_tag` or `product` qualifier, whichever is found first.
+pub fn convert_genbank_feature_to_gene_map_record(feature: &GenBankFeature) -> Option<Result<(String, Gene), Report>> {
+  if feature.key != "CDS" {
+    return None;
+  }
+
+  let gene_name = feature
+    .qualifier("gene")
+    .or_else(|| feature.qualifier("locus_tag"))
+    .or_else(|| feature.qualifier("product"));
+
+  let gene_name = match gene_name {
+    Some(gene_name) => gene_name,
+    None => {
+      warn!(
+        "Genemap record could not be parsed as it contains neither a 'gene', nor 'locus_tag', nor 'product' qualifier ({:?})",
+        feature
+      );
+      return None;
+    }
+  };
+
+  Some(match convert_genbank_feature_to_gene(gene_name, feature) {
+    Ok(gene) => Ok((gene_name.to_owned(), gene)),
+    Err(report) => Err(report)
+      .wrap_err("When parsing a GenBank feature")
+      .with_section(|| format!("{feature:#?}").header("feature:")),
+  })
+}
+
+fn convert_genbank_record_to_gene_map(record: &GenBankRecord) -> Result<GeneMap, Report> {
+  let mut gene_map = GeneMap::new();
+  for result in record
+    .features
+    .iter()
+    .filter_map(convert_genbank_feature_to_gene_map_record)
+  {
+    let (gene_name, gene) = result?;
+    if gene_map.contains_key(&gene_name) {
+      warn!("Gene '{gene_name}' has multiple CDS. Only the first one is used.");
+      continue;
+    }
+    gene_map.insert(gene_name, gene);
+  }
+  Ok(gene_map)
+}
+
+fn read_genbank_str_impl(content: &str) -> Result<GeneMap, Report> {
+  let records = parse_genbank_records(content)?;
+
+  let mut gene_map = GeneMap::new();
+  for record in &records {
+    for (gene_name, gene) in convert_genbank_record_to_gene_map(record)? {
+      if gene_map.contains_key(&gene_name) {
+        warn!("Gene '{gene_name}' has multiple CDS. Only the first one is used.");
+        continue;
+      }
+      gene_map.insert(gene_name, gene);
+    }
+  }
+
+  if gene_map.is_empty() && records.iter().any(|record| !record.features.is_empty()) {
+    warn!("No valid CDS features found in GenBank file. No genes will be used.");
+  }
+
+  Ok(gene_map)
+}
+
+fn read_genbank_str_segmented_impl(content: &str) -> Result<SegmentedGeneMap, Report> {
+  parse_genbank_records(content)?
+    .iter()
+    .map(|record| Ok((record.name.clone(), convert_genbank_record_to_gene_map(record)?)))
+    .collect()
+}
+
+/// Reads genes from the `CDS` features of a GenBank flat file. Genes of all records are merged.
+pub fn read_genbank_str(content: &str) -> Result<GeneMap, Report> {
+  read_genbank_str_impl(content).wrap_err("When reading GenBank file")
+}
+
+/// Reads genes from the `CDS` features of a GenBank flat file, grouped by record. For segmented genomes, each record is
+/// a segment, named after its accession with version (e.g. `CY121680.1`), or after its locus name, if the version is
+/// not present.
+pub fn read_genbank_str_segmented(content: &str) -> Result<SegmentedGeneMap, Report> {
+  read_genbank_str_segmented_impl(content).wrap_err("When reading GenBank file")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::utils::error::report_to_string;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  const GENBANK: &str = r#"LOCUS       TEST                     60 bp    RNA     linear   VRL 01-JAN-2020
+DEFINITION  Test sequence.
+ACCESSION   TEST
+VERSION     TEST.1
+FEATURES             Location/Qualifiers
+     source          1..60
+                     /organism="Test virus"
+     gene            1..30
+                     /gene="A"
+     CDS             join(1..9,
+                     9..20)
+                     /gene="A"
+                     /codon_start=1
+                     /product="protein
+                     A"
+                     /translation="MKLV
+                     W"
+     CDS             complement(<31..>49)
+                     /codon_start=2
+                     /transl_table=2
+                     /product="B"
+ORIGIN
+        1 atgaaactgg tgtggtaaaa aaaaaaaaaa ttttttttta cccccccccc gggggggggg
+//
+"#;
+
+  #[rstest]
+  fn genbank_reads_cds_features() -> Result<(), Report> {
+    let gene_map = read_genbank_str(GENBANK)?;
+
+    assert_eq!(gene_map.keys().collect_vec(), vec!["A", "B"]);
+
+    let a = &gene_map["A"];
+    assert_eq!(a.strand, GeneStrand::Forward);
+    assert_eq!(a.cds_segments, vec![Range::new(0, 9), Range::new(8, 20)]);
+    assert_eq!((a.start, a.end), (0, 20));
+    assert_eq!(a.genetic_code, None);
+
+    let b = &gene_map["B"];
+    assert_eq!(b.strand, GeneStrand::Reverse);
+    assert_eq!(b.cds_segments, vec![Range::new(30, 48)]);
+    assert_eq!(b.genetic_code, Some(2));
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn genbank_reads_qualifiers() -> Result<(), Report> {
+    let records = parse_genbank_records(GENBANK)?;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].name, "TEST.1");
+
+    let cds = &records[0].features[2];
+    assert_eq!(cds.location, "join(1..9,9..20)");
+    assert_eq!(cds.qualifier("product"), Some("protein A"));
+    assert_eq!(cds.qualifier("translation"), Some("MKLVW"));
+    Ok(())
+  }
+
+  #[rstest]
+  fn genbank_groups_genes_by_record() -> Result<(), Report> {
+    let content = format!("{GENBANK}{}", GENBANK.replace("TEST.1", "OTHER.1"));
+    let gene_maps = read_genbank_str_segmented(&content)?;
+    assert_eq!(gene_maps.keys().collect_vec(), vec!["OTHER.1", "TEST.1"]);
+    assert_eq!(gene_maps["OTHER.1"].keys().collect_vec(), vec!["A", "B"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("complement(join(1..3,7..9))", vec![(Range::new(6, 9), GeneStrand::Reverse), (Range::new(0, 3), GeneStrand::Reverse)])]
+  #[case("join(complement(7..9),complement(1..3))", vec![(Range::new(6, 9), GeneStrand::Reverse), (Range::new(0, 3), GeneStrand::Reverse)])]
+  #[case("order(<1..3, 5)", vec![(Range::new(0, 3), GeneStrand::Forward), (Range::new(4, 5), GeneStrand::Forward)])]
+  fn genbank_parses_locations(
+    #[case] location: &str,
+    #[case] expected: Vec<(Range, GeneStrand)>,
+  ) -> Result<(), Report> {
+    assert_eq!(parse_genbank_location(location)?, expected);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("J00194.1:100..202")]
+  #[case("10^11")]
+  #[case("join(1..3,4..6")]
+  #[case("0..3")]
+  fn genbank_rejects_unsupported_locations(#[case] location: &str) {
+    assert!(parse_genbank_location(location).is_err());
+  }
+
+  #[rstest]
+  fn genbank_checks_feature_length() {
+    let result = read_genbank_str(&GENBANK.replace("9..20)", "9..21)"));
+    assert_eq!(
+      report_to_string(&result.unwrap_err()),
+      "When reading GenBank file: When parsing a GenBank feature: GenBank feature is invalid: total length of coding segments (CDS) must be divisible by 3, but the length is 22"
+    );
+  }
+}
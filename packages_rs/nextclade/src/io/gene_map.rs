@@ -1,12 +1,19 @@
 use crate::gene::gene::Gene;
+use crate::io::fs::{extension, read_file_to_string};
+use crate::io::genbank::{is_genbank_str, read_genbank_str, read_genbank_str_segmented};
+use crate::io::gff3::{read_gff3_str, read_gff3_str_segmented};
 use crate::make_error;
-use eyre::Report;
+use eyre::{Report, WrapErr};
 use itertools::Itertools;
 use log::warn;
 use std::collections::BTreeMap;
+use std::path::Path;
 
 pub type GeneMap = BTreeMap<String, Gene>;
 
+/// Gene maps of a segmented genome, keyed by segment name. Each gene map uses coordinates of its own segment.
+pub type SegmentedGeneMap = BTreeMap<String, GeneMap>;
+
 fn get_requested_genes_not_in_genemap(gene_map: &GeneMap, genes: &[String]) -> String {
   genes
     .iter()
@@ -61,3 +68,69 @@ pub fn filter_gene_map(gene_map: Option<GeneMap>, genes: &Option<Vec<String>>) -
     (None, None) => Ok(GeneMap::new()),
   }
 }
+
+/// Formats of genome annotation files
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GeneMapFormat {
+  Gff3,
+  GenBank,
+}
+
+impl GeneMapFormat {
+  /// Guesses format of the genome annotation from the file extension, if the extension is known, or otherwise from the
+  /// file content
+  pub fn guess(filepath: Option<&Path>, content: &str) -> Self {
+    let ext = filepath.and_then(extension).map(|ext| ext.to_lowercase());
+    match ext.as_deref() {
+      Some("gff" | "gff3") => Self::Gff3,
+      Some("gb" | "gbk" | "gbff" | "genbank") => Self::GenBank,
+      _ => {
+        if is_genbank_str(content) {
+          Self::GenBank
+        } else {
+          Self::Gff3
+        }
+      }
+    }
+  }
+}
+
+/// Reads genome annotation in one of the supported formats: GFF3 or GenBank
+pub fn read_gene_map_str(content: &str, format: GeneMapFormat) -> Result<GeneMap, Report> {
+  match format {
+    GeneMapFormat::Gff3 => read_gff3_str(content),
+    GeneMapFormat::GenBank => read_genbank_str(content),
+  }
+}
+
+/// Reads genome annotation of a segmented genome in one of the supported formats: GFF3 or GenBank
+pub fn read_gene_map_str_segmented(content: &str, format: GeneMapFormat) -> Result<SegmentedGeneMap, Report> {
+  match format {
+    GeneMapFormat::Gff3 => read_gff3_str_segmented(content),
+    GeneMapFormat::GenBank => read_genbank_str_segmented(content),
+  }
+}
+
+/// Reads genome annotation file. The format is guessed from the file extension or from the content.
+pub fn read_gene_map_file(filepath: impl AsRef<Path>) -> Result<GeneMap, Report> {
+  let filepath = filepath.as_ref();
+  let content = read_file_to_string(filepath)?;
+  let format = GeneMapFormat::guess(Some(filepath), &content);
+  read_gene_map_str(&content, format).wrap_err_with(|| format!("When reading genome annotation file {filepath:#?}"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(Some("genemap.gff"), "LOCUS", GeneMapFormat::Gff3)]
+  #[case(Some("annotation.GBK"), "", GeneMapFormat::GenBank)]
+  #[case(Some("annotation.txt"), "\nLOCUS       MN908947", GeneMapFormat::GenBank)]
+  #[case(None, "##gff-version 3", GeneMapFormat::Gff3)]
+  fn guesses_gene_map_format(#[case] filepath: Option<&str>, #[case] content: &str, #[case] expected: GeneMapFormat) {
+    assert_eq!(GeneMapFormat::guess(filepath.map(Path::new), content), expected);
+  }
+}
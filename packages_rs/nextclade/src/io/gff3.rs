@@ -1,13 +1,18 @@
 use crate::gene::gene::{Gene, GeneStrand};
-use crate::io::gene_map::GeneMap;
+use crate::io::gene_map::{GeneMap, SegmentedGeneMap};
 use crate::make_error;
+use crate::translate::genetic_code::GeneticCode;
 use crate::utils::error::to_eyre_error;
+use crate::utils::range::Range;
 use bio::io::gff::{GffType, Reader as GffReader, Record as GffRecord};
 use bio_types::strand::Strand;
 use color_eyre::{Section, SectionExt};
 use eyre::{eyre, Report, WrapErr};
+use itertools::Itertools;
 use log::warn;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::iter;
 use std::path::Path;
 
 /// Parses `frame` column of the GFF3 record.
@@ -19,6 +24,76 @@ pub fn parse_gff3_frame(frame: &str, gene_start: usize) -> i32 {
   }
 }
 
+/// Parses genetic code from the `transl_table` attribute of the GFF3 records of a gene. The first record which has
+/// the attribute is used.
+pub fn parse_gff3_genetic_code<'a>(records: impl IntoIterator<Item = &'a GffRecord>) -> Result<Option<u8>, Report> {
+  let transl_table = records
+    .into_iter()
+    .find_map(|record| record.attributes().get("transl_table"));
+
+  transl_table
+    .map(|transl_table| -> Result<u8, Report> {
+      let id = transl_table.parse::<u8>().wrap_err_with(|| {
+        format!("GFF3 record is invalid: unable to parse 'transl_table' attribute: '{transl_table}'")
+      })?;
+      GeneticCode::from_id(id)?;
+      Ok(id)
+    })
+    .transpose()
+}
+
+/// `CDS` records of genes, keyed by `ID` of the gene record
+pub type GffCdsRecordsMap<'a> = HashMap<String, Vec<&'a GffRecord>>;
+
+fn get_gff_parent(record: &GffRecord) -> Option<&str> {
+  // Features can have multiple parents, separated by commas. Only the first one is considered.
+  record
+    .attributes()
+    .get("Parent")
+    .and_then(|parent| parent.split(',').next())
+}
+
+fn is_gff_gene(record: &GffRecord) -> bool {
+  record.feature_type().to_lowercase() == "gene"
+}
+
+/// Finds `CDS` records belonging to each gene. A `CDS` record belongs to a gene if the gene is its `Parent`, either
+/// directly or through intermediate features (e.g. `mRNA`).
+pub fn find_gff_cds_records(records: &[GffRecord]) -> GffCdsRecordsMap {
+  let parents: HashMap<&str, &str> = records
+    .iter()
+    .filter_map(|record| Some((record.attributes().get("ID")?.as_str(), get_gff_parent(record)?)))
+    .collect();
+
+  let gene_ids: HashSet<&str> = records
+    .iter()
+    .filter(|record| is_gff_gene(record))
+    .filter_map(|record| record.attributes().get("ID").map(String::as_str))
+    .collect();
+
+  let mut cds_records = GffCdsRecordsMap::new();
+  for record in records {
+    if record.feature_type().to_lowercase() != "cds" {
+      continue;
+    }
+
+    // Walk up the hierarchy of features until a gene is found. The number of steps is limited to guard against cycles.
+    let mut parent = get_gff_parent(record);
+    for _ in 0..=parents.len() {
+      match parent {
+        Some(id) if gene_ids.contains(id) => {
+          cds_records.entry(id.to_owned()).or_default().push(record);
+          break;
+        }
+        Some(id) => parent = parents.get(id).copied(),
+        None => break,
+      }
+    }
+  }
+
+  cds_records
+}
+
 /// Converts GFF3 record to the internal `Gene` representation
 pub fn convert_gff_record_to_gene(gene_name: &str, record: &GffRecord) -> Result<Gene, Report> {
   let length = record.end() - record.start() + 1;
@@ -27,17 +102,84 @@ pub fn convert_gff_record_to_gene(gene_name: &str, record: &GffRecord) -> Result
   }
 
   let start = (*record.start() - 1) as usize; // Convert to 0-based indices
+  let end = *record.end() as usize;
   Ok(Gene {
     gene_name: gene_name.to_owned(),
     start,
-    end: *record.end() as usize,
+    end,
     strand: record.strand().map_or(GeneStrand::Unknown, Strand::into),
     frame: parse_gff3_frame(record.frame(), start),
+    cds_segments: vec![Range::new(start, end)],
+    genetic_code: parse_gff3_genetic_code([record])?,
+  })
+}
+
+/// Converts GFF3 record of a gene, along with the `CDS` records belonging to it, to the internal `Gene` representation.
+/// The gene consists of the coding segments described by the `CDS` records, concatenated in the order of translation.
+///
+/// Records of a discontinuous CDS share the same `ID`. If the gene has multiple CDS (e.g. alternatively spliced
+/// products), only the first one is used.
+pub fn convert_gff_cds_records_to_gene(
+  gene_name: &str,
+  record: &GffRecord,
+  cds_records: &[&GffRecord],
+) -> Result<Gene, Report> {
+  let cds_id = |cds_record: &GffRecord| cds_record.attributes().get("ID").cloned();
+
+  let first_cds_id = cds_records.first().and_then(|cds_record| cds_id(cds_record));
+  let cds_ids = cds_records.iter().map(|cds_record| cds_id(cds_record)).unique().count();
+  if cds_ids > 1 {
+    warn!("Gene '{gene_name}' has multiple CDS. Only the first one ('{first_cds_id:?}') is used.");
+  }
+
+  let cds_records = cds_records
+    .iter()
+    .copied()
+    .filter(|cds_record| cds_id(cds_record) == first_cds_id)
+    .collect_vec();
+
+  let strand = record.strand().map_or(GeneStrand::Unknown, Strand::into);
+
+  // Coding segments are concatenated in the order of translation
+  let mut cds_segments = cds_records
+    .iter()
+    .map(|cds_record| Range::new((*cds_record.start() - 1) as usize, *cds_record.end() as usize))
+    .sorted_by_key(|segment| segment.begin)
+    .collect_vec();
+  if strand == GeneStrand::Reverse {
+    cds_segments.reverse();
+  }
+
+  let length: usize = cds_segments.iter().map(Range::len).sum();
+  if length % 3 != 0 {
+    return make_error!(
+      "GFF3 records are invalid: total length of coding segments (CDS) must be divisible by 3, but the length is {length}"
+    );
+  }
+
+  let start = cds_segments
+    .iter()
+    .map(|segment| segment.begin)
+    .min()
+    .unwrap_or_default();
+  let end = cds_segments.iter().map(|segment| segment.end).max().unwrap_or_default();
+
+  Ok(Gene {
+    gene_name: gene_name.to_owned(),
+    start,
+    end,
+    strand,
+    frame: parse_gff3_frame(record.frame(), start),
+    cds_segments,
+    genetic_code: parse_gff3_genetic_code(iter::once(record).chain(cds_records.iter().copied()))?,
   })
 }
 
-pub fn convert_gff_record_to_gene_map_record(record: &GffRecord) -> Option<Result<(String, Gene), Report>> {
-  if record.feature_type().to_lowercase() == "gene" {
+pub fn convert_gff_record_to_gene_map_record(
+  record: &GffRecord,
+  cds_records: &GffCdsRecordsMap,
+) -> Option<Result<(String, Gene), Report>> {
+  if is_gff_gene(record) {
     let attributes = record.attributes();
     let gene_name_opt = attributes
       .get("gene_name")
@@ -51,7 +193,13 @@ pub fn convert_gff_record_to_gene_map_record(record: &GffRecord) -> Option<Resul
       return None;
     };
     let gene_name = gene_name_opt.unwrap();
-    return Some(match convert_gff_record_to_gene(gene_name, record) {
+
+    let gene = match attributes.get("ID").and_then(|id| cds_records.get(id)) {
+      Some(gene_cds_records) => convert_gff_cds_records_to_gene(gene_name, record, gene_cds_records),
+      None => convert_gff_record_to_gene(gene_name, record),
+    };
+
+    return Some(match gene {
       Ok(gene) => Ok((gene_name.clone(), gene)),
       Err(report) => Err(report)
         .wrap_err("When parsing a GFF3 record")
@@ -67,6 +215,14 @@ pub fn convert_gff_record_to_gene_map_record(record: &GffRecord) -> Option<Resul
   None
 }
 
+fn convert_gff_records_to_gene_map(records: &[GffRecord]) -> Result<GeneMap, Report> {
+  let cds_records = find_gff_cds_records(records);
+  records
+    .iter()
+    .filter_map(|record| convert_gff_record_to_gene_map_record(record, &cds_records))
+    .collect::<Result<GeneMap, Report>>()
+}
+
 fn read_gff3_file_impl<P: AsRef<Path>>(filename: &P) -> Result<GeneMap, Report> {
   let filename = filename.as_ref();
   let mut reader = GffReader::from_file(filename, GffType::GFF3).map_err(|report| eyre!(report))?;
@@ -76,10 +232,7 @@ fn read_gff3_file_impl<P: AsRef<Path>>(filename: &P) -> Result<GeneMap, Report>
     .map(to_eyre_error)
     .collect::<Result<Vec<GffRecord>, Report>>()?;
 
-  let genemap: GeneMap = records
-    .iter()
-    .filter_map(convert_gff_record_to_gene_map_record)
-    .collect::<Result<GeneMap, Report>>()?;
+  let genemap = convert_gff_records_to_gene_map(&records)?;
 
   if genemap.is_empty() && !records.is_empty() {
     warn!(
@@ -104,16 +257,93 @@ fn read_gff3_str_impl(content: &str) -> Result<GeneMap, Report> {
     .map(to_eyre_error)
     .collect::<Result<Vec<GffRecord>, Report>>()?;
 
-  records
-    .iter()
-    .filter_map(convert_gff_record_to_gene_map_record)
-    .collect::<Result<GeneMap, Report>>()
+  convert_gff_records_to_gene_map(&records)
 }
 
 pub fn read_gff3_str(content: &str) -> Result<GeneMap, Report> {
   read_gff3_str_impl(content).wrap_err("When reading GFF3 file")
 }
 
+/// Groups genes by the `seqid` column of GFF3 records. For segmented genomes, `seqid` is the name of the segment.
+fn convert_gff_records_to_segmented_gene_map(records: &[GffRecord]) -> Result<SegmentedGeneMap, Report> {
+  let cds_records = find_gff_cds_records(records);
+  let mut gene_maps = SegmentedGeneMap::new();
+  for record in records {
+    if let Some(result) = convert_gff_record_to_gene_map_record(record, &cds_records) {
+      let (gene_name, gene) = result?;
+      gene_maps
+        .entry(record.seqname().to_owned())
+        .or_default()
+        .insert(gene_name, gene);
+    }
+  }
+  Ok(gene_maps)
+}
+
+fn read_gff3_file_segmented_impl<P: AsRef<Path>>(filename: &P) -> Result<SegmentedGeneMap, Report> {
+  let filename = filename.as_ref();
+  let mut reader = GffReader::from_file(filename, GffType::GFF3).map_err(|report| eyre!(report))?;
+
+  let records = reader
+    .records()
+    .map(to_eyre_error)
+    .collect::<Result<Vec<GffRecord>, Report>>()?;
+
+  convert_gff_records_to_segmented_gene_map(&records)
+}
+
+pub fn read_gff3_file_segmented<P: AsRef<Path>>(filename: &P) -> Result<SegmentedGeneMap, Report> {
+  let filename = filename.as_ref();
+  read_gff3_file_segmented_impl(&filename).wrap_err_with(|| format!("When reading GFF3 file '{filename:#?}'"))
+}
+
+fn read_gff3_str_segmented_impl(content: &str) -> Result<SegmentedGeneMap, Report> {
+  let mut reader = GffReader::new(content.as_bytes(), GffType::GFF3);
+
+  let records = reader
+    .records()
+    .map(to_eyre_error)
+    .collect::<Result<Vec<GffRecord>, Report>>()?;
+
+  convert_gff_records_to_segmented_gene_map(&records)
+}
+
+pub fn read_gff3_str_segmented(content: &str) -> Result<SegmentedGeneMap, Report> {
+  read_gff3_str_segmented_impl(content).wrap_err("When reading GFF3 file")
+}
+
+/// Writes gene map in GFF3 format, such that reading it back produces the same gene map. Each gene is written as a
+/// `gene` record, followed by the `CDS` records of its coding segments.
+pub fn write_gff3_str(gene_map: &GeneMap, seqid: &str) -> String {
+  let mut lines = vec!["##gff-version 3".to_owned()];
+  for (gene_name, gene) in gene_map {
+    let strand = match gene.strand {
+      GeneStrand::Forward => "+",
+      GeneStrand::Reverse => "-",
+      GeneStrand::Unknown => ".",
+    };
+    let transl_table = gene
+      .genetic_code
+      .map(|genetic_code| format!(";transl_table={genetic_code}"))
+      .unwrap_or_default();
+
+    lines.push(format!(
+      "{seqid}\t.\tgene\t{}\t{}\t.\t{strand}\t.\tID=gene-{gene_name};gene_name={gene_name}",
+      gene.start + 1,
+      gene.end
+    ));
+    lines.extend(gene.cds_segments.iter().map(|segment| {
+      format!(
+        "{seqid}\t.\tCDS\t{}\t{}\t.\t{strand}\t0\tID=cds-{gene_name};Parent=gene-{gene_name}{transl_table}",
+        segment.begin + 1,
+        segment.end
+      )
+    }));
+  }
+  lines.push(String::new());
+  lines.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -142,4 +372,85 @@ EPI1857216	feature	gene	1036	1698	.	+	.	gene_name="HA2"
 
     Ok(())
   }
+
+  #[rstest]
+  fn gff3_groups_genes_by_segment() -> Result<(), Report> {
+    let gene_maps = read_gff3_str_segmented(
+      r#"##gff-version 3
+##sequence-region HA 1 1701
+##sequence-region NA 1 1410
+HA	feature	gene	1	48	.	+	.	gene_name=SigPep
+HA	feature	gene	49	1035	.	+	.	gene_name=HA1
+NA	feature	gene	1	1410	.	+	.	gene_name=NA
+"#,
+    )?;
+
+    assert_eq!(gene_maps.keys().collect_vec(), vec!["HA", "NA"]);
+    assert_eq!(gene_maps["HA"].keys().collect_vec(), vec!["HA1", "SigPep"]);
+    assert_eq!(gene_maps["NA"]["NA"].start, 0);
+    assert_eq!(gene_maps["NA"]["NA"].end, 1410);
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn gff3_joins_coding_segments_of_gene() -> Result<(), Report> {
+    let gene_map = read_gff3_str(
+      r#"##gff-version 3
+##sequence-region MN908947 1 29903
+MN908947	feature	gene	266	21555	.	+	.	ID=gene-ORF1ab;gene_name=ORF1ab
+MN908947	feature	CDS	266	13468	.	+	0	ID=cds-ORF1ab;Parent=gene-ORF1ab
+MN908947	feature	CDS	13468	21555	.	+	0	ID=cds-ORF1ab;Parent=gene-ORF1ab
+"#,
+    )?;
+
+    let gene = &gene_map["ORF1ab"];
+    assert_eq!(gene.start, 265);
+    assert_eq!(gene.end, 21555);
+    assert_eq!(
+      gene.cds_segments,
+      vec![Range::new(265, 13468), Range::new(13467, 21555)]
+    );
+    assert_eq!(gene.len(), 21291);
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn gff3_reads_genetic_code() -> Result<(), Report> {
+    let gene_map = read_gff3_str(
+      r#"##gff-version 3
+##sequence-region NC_012920 1 16569
+NC_012920	feature	gene	3307	4263	.	+	.	ID=gene-ND1;gene_name=ND1
+NC_012920	feature	CDS	3307	4263	.	+	0	ID=cds-ND1;Parent=gene-ND1;transl_table=2
+NC_012920	feature	gene	4470	5510	.	+	.	gene_name=ND2
+"#,
+    )?;
+
+    assert_eq!(gene_map["ND1"].genetic_code, Some(2));
+    assert_eq!(gene_map["ND2"].genetic_code, None);
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn gff3_writes_gene_map() -> Result<(), Report> {
+    let gene_map = read_gff3_str(
+      r#"##gff-version 3
+MN908947	feature	gene	266	21555	.	+	.	ID=gene-ORF1ab;gene_name=ORF1ab
+MN908947	feature	CDS	266	13468	.	+	0	ID=cds-ORF1ab;Parent=gene-ORF1ab
+MN908947	feature	CDS	13468	21555	.	+	0	ID=cds-ORF1ab;Parent=gene-ORF1ab;transl_table=2
+MN908947	feature	gene	28274	29533	.	-	.	gene_name=N
+"#,
+    )?;
+
+    let written = write_gff3_str(&gene_map, "MN908947");
+    let gene_map_read_back = read_gff3_str(&written)?;
+
+    assert_eq!(
+      serde_json::to_value(&gene_map_read_back)?,
+      serde_json::to_value(&gene_map)?
+    );
+    Ok(())
+  }
 }
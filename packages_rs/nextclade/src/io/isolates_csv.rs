@@ -0,0 +1,177 @@
+use crate::io::csv::{CsvVecFileWriter, VecWriter};
+use crate::io::nextclade_csv::format_qc_score;
+use crate::types::outputs::NextcladeOutputs;
+use eyre::{Report, WrapErr};
+use indexmap::IndexMap;
+use itertools::Itertools;
+use log::warn;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// By default, isolate name is everything before the last `|` in the sequence name, e.g. `A/Texas/50/2012|HA`
+pub const ISOLATE_NAME_REGEX_DEFAULT: &str = r"^(?P<isolate>.+)\|[^|]*$";
+
+const ISOLATE_SEGMENT_COLUMNS: &[&str] = &[
+  "seqName",
+  "clade",
+  "qc.overallScore",
+  "qc.overallStatus",
+  "totalSubstitutions",
+  "coverage",
+  "errors",
+];
+
+/// Extracts isolate name from sequence name, using a regular expression.
+///
+/// Isolate name is the capture group named `isolate` or, if there is no such group, the first capture group. If the
+/// sequence name does not match the expression, then the entire sequence name is used as the isolate name.
+#[derive(Clone, Debug)]
+pub struct IsolateNameParser {
+  regex: Regex,
+}
+
+impl IsolateNameParser {
+  pub fn new(isolate_name_regex: &str) -> Result<Self, Report> {
+    let regex = Regex::new(isolate_name_regex)
+      .wrap_err_with(|| format!("When compiling isolate name regex '{isolate_name_regex}'"))?;
+    Ok(Self { regex })
+  }
+
+  pub fn parse<'a>(&self, seq_name: &'a str) -> &'a str {
+    self
+      .regex
+      .captures(seq_name)
+      .and_then(|captures| captures.name("isolate").or_else(|| captures.get(1)))
+      .map_or(seq_name, |capture| capture.as_str())
+  }
+}
+
+/// Joins results of the segments of each isolate, so that each isolate is represented by a single row
+pub struct IsolatesTable {
+  segments: Vec<String>,
+  parser: IsolateNameParser,
+  isolates: IndexMap<String, BTreeMap<usize, Vec<String>>>,
+}
+
+impl IsolatesTable {
+  pub fn new(segments: &[String], isolate_name_regex: &str) -> Result<Self, Report> {
+    Ok(Self {
+      segments: segments.to_vec(),
+      parser: IsolateNameParser::new(isolate_name_regex)?,
+      isolates: IndexMap::new(),
+    })
+  }
+
+  /// Adds results of a sequence which was analyzed using segment with a given index
+  pub fn add_outputs(&mut self, segment_index: usize, outputs: &NextcladeOutputs) {
+    let NextcladeOutputs {
+      seq_name,
+      clade,
+      qc,
+      total_substitutions,
+      coverage,
+      ..
+    } = outputs;
+
+    self.add_impl(
+      segment_index,
+      seq_name,
+      vec![
+        seq_name.clone(),
+        clade.clone(),
+        format_qc_score(qc.overall_score),
+        qc.overall_status.to_string(),
+        total_substitutions.to_string(),
+        coverage.to_string(),
+        String::new(),
+      ],
+    );
+  }
+
+  /// Adds a sequence which was assigned to a segment with a given index, but failed to be analyzed
+  pub fn add_error(&mut self, segment_index: usize, seq_name: &str, error: &str) {
+    let mut row = vec![String::new(); ISOLATE_SEGMENT_COLUMNS.len()];
+    row[0] = seq_name.to_owned();
+    row[ISOLATE_SEGMENT_COLUMNS.len() - 1] = error.to_owned();
+    self.add_impl(segment_index, seq_name, row);
+  }
+
+  fn add_impl(&mut self, segment_index: usize, seq_name: &str, row: Vec<String>) {
+    let isolate = self.parser.parse(seq_name);
+    let segments = self.isolates.entry(isolate.to_owned()).or_default();
+    if let Some(existing) = segments.get(&segment_index) {
+      warn!(
+        "Isolate '{isolate}' has more than one sequence for segment '{}': '{}' and '{seq_name}'. Only the first one will be included into the isolates table.",
+        self.segments[segment_index], existing[0]
+      );
+      return;
+    }
+    segments.insert(segment_index, row);
+  }
+
+  pub fn headers(&self) -> Vec<String> {
+    let segment_headers = self.segments.iter().flat_map(|segment| {
+      ISOLATE_SEGMENT_COLUMNS
+        .iter()
+        .map(move |column| format!("{segment}.{column}"))
+    });
+    ["isolate".to_owned()].into_iter().chain(segment_headers).collect_vec()
+  }
+
+  pub fn rows(&self) -> impl Iterator<Item = Vec<String>> + '_ {
+    self.isolates.iter().map(|(isolate, segments)| {
+      let segment_cells = (0..self.segments.len()).flat_map(|segment_index| {
+        segments
+          .get(&segment_index)
+          .cloned()
+          .unwrap_or_else(|| vec![String::new(); ISOLATE_SEGMENT_COLUMNS.len()])
+      });
+      [isolate.clone()].into_iter().chain(segment_cells).collect_vec()
+    })
+  }
+
+  /// Writes the table into a CSV or TSV file, depending on the delimiter
+  pub fn write(&self, filepath: impl AsRef<Path>, delimiter: u8) -> Result<(), Report> {
+    let filepath = filepath.as_ref();
+    let mut writer = CsvVecFileWriter::new(filepath, delimiter, &self.headers())?;
+    self
+      .rows()
+      .try_for_each(|row| writer.write(row))
+      .wrap_err_with(|| format!("When writing isolates table into {filepath:#?}"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case("A/Texas/50/2012|HA", "A/Texas/50/2012")]
+  #[case("A/Texas/50/2012|EPI_ISL_1|NA", "A/Texas/50/2012|EPI_ISL_1")]
+  #[case("A/Texas/50/2012", "A/Texas/50/2012")]
+  fn parses_isolate_name_with_default_regex(#[case] seq_name: &str, #[case] expected: &str) -> Result<(), Report> {
+    let parser = IsolateNameParser::new(ISOLATE_NAME_REGEX_DEFAULT)?;
+    assert_eq!(parser.parse(seq_name), expected);
+    Ok(())
+  }
+
+  #[rstest]
+  fn joins_segments_of_isolate() -> Result<(), Report> {
+    let mut table = IsolatesTable::new(&["HA".to_owned(), "NA".to_owned()], r"^(\S+) segment")?;
+    table.add_error(1, "A/Texas/50/2012 segment 6", "Unable to align");
+    table.add_error(1, "A/Ohio/1/2020 segment 6", "Unable to align");
+    table.add_error(0, "A/Texas/50/2012 segment 4", "Unable to align");
+
+    let rows = table.rows().collect_vec();
+    assert_eq!(table.headers().len(), 1 + 2 * ISOLATE_SEGMENT_COLUMNS.len());
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0][0], "A/Texas/50/2012");
+    assert_eq!(rows[0][1], "A/Texas/50/2012 segment 4");
+    assert_eq!(rows[0][1 + ISOLATE_SEGMENT_COLUMNS.len()], "A/Texas/50/2012 segment 6");
+    assert_eq!(rows[1][1], "");
+    Ok(())
+  }
+}
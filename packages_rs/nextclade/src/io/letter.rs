@@ -1,10 +1,21 @@
+use crate::align::params::AlignPairwiseParams;
+use crate::align::score_matrix_custom::ScoringMatrix;
 use color_eyre::{Section, SectionExt};
 use eyre::{Report, WrapErr};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Allows to lookup scores for nucleotides and amino acids in a generic way
 pub trait ScoreMatrixLookup<T> {
+  /// All letters, in the order of rows and columns of the scoring matrix
+  const ALPHABET: &'static [T];
+
   fn lookup_match_score(x: T, y: T) -> i32;
+
+  /// Position of the letter in `ALPHABET`
+  fn alphabet_index(x: T) -> usize;
+
+  /// Custom scoring matrix for this kind of letters, if provided in alignment parameters
+  fn custom_scoring_matrix(params: &AlignPairwiseParams) -> Option<&ScoringMatrix<T>>;
 }
 
 /// Generic representation of a character defining nucleotide or amino acid
@@ -2,17 +2,24 @@ pub mod aa;
 pub mod compression;
 pub mod concat;
 pub mod csv;
+pub mod dataset_assignment_csv;
 pub mod errors_csv;
 pub mod fasta;
 pub mod file;
 pub mod fs;
 pub mod gene_map;
+pub mod genbank;
 pub mod gff3;
 pub mod insertions_csv;
+pub mod isolates_csv;
 pub mod json;
 pub mod letter;
 pub mod ndjson;
 pub mod nextclade_csv;
 pub mod nuc;
+pub mod nwk_reader;
+pub mod nwk_writer;
 pub mod parse_pos;
+pub mod protobuf;
 pub mod results_json;
+pub mod usher_mat;
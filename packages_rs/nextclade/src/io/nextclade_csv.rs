@@ -1,6 +1,9 @@
 use crate::align::insertions_strip::{AaIns, Insertion};
+use crate::align::inversions::Inversion;
+use crate::analyze::aa_changes::AaAmbiguity;
 use crate::analyze::aa_sub_full::{AaDelFull, AaSubFull};
 use crate::analyze::find_aa_motifs::AaMotif;
+use crate::analyze::find_mixed_sites::{CoinfectionCladePair, MixedSite};
 use crate::analyze::letter_ranges::{GeneAaRange, NucRange};
 use crate::analyze::nuc_sub::{NucSub, NucSubLabeled};
 use crate::analyze::nuc_sub_full::{NucDelFull, NucSubFull};
@@ -12,6 +15,8 @@ use crate::qc::qc_config::StopCodonLocation;
 use crate::qc::qc_rule_snp_clusters::ClusteredSnp;
 use crate::translate::frame_shifts_translate::FrameShift;
 use crate::translate::translate_genes::Translation;
+use crate::tree::tree_find_breakpoints::RecombinationBreakpoint;
+use crate::tree::tree_placement_posteriors::PlacementCandidate;
 use crate::types::outputs::{
   combine_outputs_and_errors_sorted, NextcladeErrorOutputs, NextcladeOutputOrError, NextcladeOutputs, PeptideWarning,
   PhenotypeValue,
@@ -90,7 +95,11 @@ impl CsvColumnConfig {
     })?;
 
     if output_columns_selection.is_empty() || categories.contains(&CsvColumnCategory::All) {
-      Ok(Self::default())
+      // Columns which are disabled by default can still be requested individually
+      Ok(Self {
+        individual,
+        ..Self::default()
+      })
     } else {
       let include_dynamic = categories.contains(&CsvColumnCategory::Dynamic);
 
@@ -123,10 +132,14 @@ impl Default for CsvColumnConfig {
 }
 
 lazy_static! {
-  // Default configuration and layout of CSV column categories
+  // Default configuration and layout of CSV column categories. Columns marked `false` are not written by default, but
+  // can be requested individually.
   pub static ref CSV_COLUMN_CONFIG_MAP_DEFAULT: CsvColumnConfigMap = indexmap! {
     CsvColumnCategory::General => indexmap! {
+      o!("segment") => false,
       o!("clade") => true,
+      o!("placement.cladeConfidence") => false,
+      o!("placement.candidates") => false,
       o!("qc.overallScore") => true,
       o!("qc.overallStatus") => true,
       o!("totalSubstitutions") => true,
@@ -153,6 +166,7 @@ lazy_static! {
       o!("aaSubstitutions") => true,
       o!("aaDeletions") => true,
       o!("aaInsertions") => true,
+      o!("aaAmbiguities") => false,
     },
     CsvColumnCategory::PrivMuts => indexmap! {
       o!("privateNucMutations.reversionSubstitutions") => true,
@@ -177,6 +191,10 @@ lazy_static! {
       o!("qc.mixedSites.score") => true,
       o!("qc.mixedSites.status") => true,
       o!("qc.mixedSites.totalMixedSites") => true,
+      o!("mixedSites.sites") => false,
+      o!("mixedSites.totalCoinfectionSites") => false,
+      o!("mixedSites.coinfectionScore") => false,
+      o!("mixedSites.coinfectionCladePairs") => false,
       o!("qc.privateMutations.cutoff") => true,
       o!("qc.privateMutations.excess") => true,
       o!("qc.privateMutations.score") => true,
@@ -196,6 +214,15 @@ lazy_static! {
       o!("qc.stopCodons.totalStopCodons") => true,
       o!("qc.stopCodons.score") => true,
       o!("qc.stopCodons.status") => true,
+      o!("qc.recombinants.breakpoints") => true,
+      o!("qc.recombinants.parentalClades") => true,
+      o!("qc.recombinants.totalBreakpoints") => true,
+      o!("qc.recombinants.score") => true,
+      o!("qc.recombinants.status") => true,
+      o!("qc.inversions.inversions") => true,
+      o!("qc.inversions.totalInversions") => true,
+      o!("qc.inversions.score") => true,
+      o!("qc.inversions.status") => true,
     },
     CsvColumnCategory::Primers => indexmap! {
       o!("totalPcrPrimerChanges") => true,
@@ -295,6 +322,7 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
     let NextcladeOutputs {
       index,
       seq_name,
+      segment,
       substitutions,
       total_substitutions,
       deletions,
@@ -305,6 +333,7 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
       total_missing,
       non_acgtns,
       total_non_acgtns,
+      mixed_sites,
       frame_shifts,
       total_frame_shifts,
       aa_substitutions,
@@ -312,6 +341,7 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
       aa_deletions,
       total_aminoacid_deletions,
       aa_insertions,
+      aa_ambiguities,
       total_aminoacid_insertions,
       unknown_aa_ranges,
       total_unknown_aa,
@@ -321,6 +351,7 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
       pcr_primer_changes,
       total_pcr_primer_changes,
       clade,
+      placement,
       private_nuc_mutations,
       // private_aa_mutations,
       missing_genes,
@@ -353,7 +384,20 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
     self.add_entry("index", index)?;
     self.add_entry("seqName", seq_name)?;
 
+    self.add_entry_maybe("segment", segment.as_ref())?;
     self.add_entry("clade", clade)?;
+    self.add_entry_maybe(
+      "placement.cladeConfidence",
+      placement
+        .as_ref()
+        .map(|placement| format_qc_score(placement.clade_confidence)),
+    )?;
+    self.add_entry_maybe(
+      "placement.candidates",
+      placement
+        .as_ref()
+        .map(|placement| format_placement_candidates(&placement.candidates, ARRAY_ITEM_DELIMITER)),
+    )?;
     self.add_entry("qc.overallScore", &format_qc_score(qc.overall_score))?;
     self.add_entry("qc.overallStatus", &qc.overall_status.to_string())?;
     self.add_entry("totalSubstitutions", &total_substitutions.to_string())?;
@@ -414,6 +458,10 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
       "aaInsertions",
       &format_aa_insertions(aa_insertions, ARRAY_ITEM_DELIMITER),
     )?;
+    self.add_entry(
+      "aaAmbiguities",
+      &format_aa_ambiguities(aa_ambiguities, ARRAY_ITEM_DELIMITER),
+    )?;
     self.add_entry(
       "unknownAaRanges",
       &format_unknown_aa_ranges(unknown_aa_ranges, ARRAY_ITEM_DELIMITER),
@@ -460,6 +508,22 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
       "qc.mixedSites.totalMixedSites",
       qc.mixed_sites.as_ref().map(|ms| ms.total_mixed_sites.to_string()),
     )?;
+    self.add_entry(
+      "mixedSites.sites",
+      &format_mixed_sites(&mixed_sites.sites, ARRAY_ITEM_DELIMITER),
+    )?;
+    self.add_entry(
+      "mixedSites.totalCoinfectionSites",
+      &mixed_sites.total_coinfection_sites.to_string(),
+    )?;
+    self.add_entry(
+      "mixedSites.coinfectionScore",
+      &format_qc_score(mixed_sites.coinfection_score),
+    )?;
+    self.add_entry(
+      "mixedSites.coinfectionCladePairs",
+      &format_coinfection_clade_pairs(&mixed_sites.coinfection_clade_pairs, ARRAY_ITEM_DELIMITER),
+    )?;
     self.add_entry_maybe(
       "qc.privateMutations.cutoff",
       qc.private_mutations.as_ref().map(|pm| pm.cutoff.to_string()),
@@ -546,6 +610,48 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
       "qc.stopCodons.status",
       qc.stop_codons.as_ref().map(|sc| sc.status.to_string()),
     )?;
+    self.add_entry_maybe(
+      "qc.recombinants.breakpoints",
+      qc.recombinants
+        .as_ref()
+        .map(|rc| format_recombination_breakpoints(&rc.breakpoints, ARRAY_ITEM_DELIMITER)),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.parentalClades",
+      qc.recombinants
+        .as_ref()
+        .map(|rc| rc.parental_clades.join(ARRAY_ITEM_DELIMITER)),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.totalBreakpoints",
+      qc.recombinants.as_ref().map(|rc| rc.total_breakpoints.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.score",
+      qc.recombinants.as_ref().map(|rc| format_qc_score(rc.score)),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.status",
+      qc.recombinants.as_ref().map(|rc| rc.status.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "qc.inversions.inversions",
+      qc.inversions
+        .as_ref()
+        .map(|inv| format_inversions(&inv.inversions, ARRAY_ITEM_DELIMITER)),
+    )?;
+    self.add_entry_maybe(
+      "qc.inversions.totalInversions",
+      qc.inversions.as_ref().map(|inv| inv.total_inversions.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "qc.inversions.score",
+      qc.inversions.as_ref().map(|inv| format_qc_score(inv.score)),
+    )?;
+    self.add_entry_maybe(
+      "qc.inversions.status",
+      qc.inversions.as_ref().map(|inv| inv.status.to_string()),
+    )?;
     self.add_entry("isReverseComplement", &is_reverse_complement.to_string())?;
     self.add_entry("failedGenes", &format_failed_genes(missing_genes, ARRAY_ITEM_DELIMITER))?;
     self.add_entry(
@@ -718,6 +824,49 @@ pub fn format_aa_deletions(substitutions: &[AaDelFull], delimiter: &str) -> Stri
   substitutions.iter().map(|del| del.del.to_string()).join(delimiter)
 }
 
+#[inline]
+pub fn format_aa_ambiguities(ambiguities: &[AaAmbiguity], delimiter: &str) -> String {
+  ambiguities.iter().map(AaAmbiguity::to_string).join(delimiter)
+}
+
+#[inline]
+pub fn format_mixed_sites(sites: &[MixedSite], delimiter: &str) -> String {
+  sites.iter().map(MixedSite::to_string).join(delimiter)
+}
+
+#[inline]
+pub fn format_coinfection_clade_pairs(pairs: &[CoinfectionCladePair], delimiter: &str) -> String {
+  pairs.iter().map(CoinfectionCladePair::to_string).join(delimiter)
+}
+
+#[inline]
+pub fn format_recombination_breakpoints(breakpoints: &[RecombinationBreakpoint], delimiter: &str) -> String {
+  breakpoints
+    .iter()
+    .map(RecombinationBreakpoint::to_string)
+    .join(delimiter)
+}
+
+#[inline]
+pub fn format_inversions(inversions: &[Inversion], delimiter: &str) -> String {
+  inversions.iter().map(Inversion::to_string).join(delimiter)
+}
+
+#[inline]
+pub fn format_placement_candidates(candidates: &[PlacementCandidate], delimiter: &str) -> String {
+  candidates
+    .iter()
+    .map(|candidate| {
+      format!(
+        "{}:{}:{}",
+        candidate.node_name,
+        candidate.clade,
+        format_qc_score(candidate.posterior)
+      )
+    })
+    .join(delimiter)
+}
+
 #[inline]
 pub fn format_aa_insertion(AaIns { gene, ins, pos }: &AaIns) -> String {
   let ins_str = from_aa_seq(ins);
@@ -1,3 +1,5 @@
+use crate::align::params::AlignPairwiseParams;
+use crate::align::score_matrix_custom::ScoringMatrix;
 use crate::align::score_matrix_nuc::lookup_nuc_scoring_matrix;
 use crate::io::letter::{Letter, ScoreMatrixLookup};
 use crate::make_error;
@@ -44,6 +46,29 @@ impl Nuc {
   pub const fn is_acgtn(self) -> bool {
     matches!(self, Nuc::A | Nuc::C | Nuc::G | Nuc::T | Nuc::N)
   }
+
+  /// Canonical nucleotides (A, C, G, T) which this IUPAC nucleotide code stands for. Gap stands for none.
+  #[inline]
+  pub const fn to_acgt(self) -> &'static [Nuc] {
+    match self {
+      Nuc::A => &[Nuc::A],
+      Nuc::C => &[Nuc::C],
+      Nuc::G => &[Nuc::G],
+      Nuc::T => &[Nuc::T],
+      Nuc::R => &[Nuc::A, Nuc::G],
+      Nuc::Y => &[Nuc::C, Nuc::T],
+      Nuc::S => &[Nuc::C, Nuc::G],
+      Nuc::W => &[Nuc::A, Nuc::T],
+      Nuc::K => &[Nuc::G, Nuc::T],
+      Nuc::M => &[Nuc::A, Nuc::C],
+      Nuc::B => &[Nuc::C, Nuc::G, Nuc::T],
+      Nuc::D => &[Nuc::A, Nuc::G, Nuc::T],
+      Nuc::H => &[Nuc::A, Nuc::C, Nuc::T],
+      Nuc::V => &[Nuc::A, Nuc::C, Nuc::G],
+      Nuc::N => &[Nuc::A, Nuc::C, Nuc::G, Nuc::T],
+      Nuc::Gap => &[],
+    }
+  }
 }
 
 impl Default for Nuc {
@@ -53,9 +78,24 @@ impl Default for Nuc {
 }
 
 impl ScoreMatrixLookup<Nuc> for Nuc {
+  #[rustfmt::skip]
+  const ALPHABET: &'static [Nuc] = &[
+    Nuc::T, Nuc::A, Nuc::W, Nuc::C, Nuc::Y, Nuc::M, Nuc::H, Nuc::G,
+    Nuc::K, Nuc::R, Nuc::D, Nuc::S, Nuc::B, Nuc::V, Nuc::N, Nuc::Gap,
+  ];
+
   fn lookup_match_score(x: Nuc, y: Nuc) -> i32 {
     lookup_nuc_scoring_matrix(x, y)
   }
+
+  #[inline]
+  fn alphabet_index(x: Nuc) -> usize {
+    x as usize
+  }
+
+  fn custom_scoring_matrix(params: &AlignPairwiseParams) -> Option<&ScoringMatrix<Nuc>> {
+    params.scoring_matrix_nuc.as_ref()
+  }
 }
 
 impl Letter<Nuc> for Nuc {
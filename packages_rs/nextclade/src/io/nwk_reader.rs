@@ -0,0 +1,210 @@
+use crate::make_error;
+use eyre::{eyre, Report, WrapErr};
+use std::iter::Peekable;
+use std::mem;
+use std::str::Chars;
+
+/// Tree node as it is written in a Newick file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NwkNode {
+  /// Node name. Empty if the node is unnamed (which is common for internal nodes).
+  pub name: String,
+
+  /// Length of the branch leading to this node, if present
+  pub branch_length: Option<f64>,
+
+  pub children: Vec<NwkNode>,
+}
+
+/// The default (recursive) drop would overflow the stack on very deep trees, so the nodes are dropped one by one
+impl Drop for NwkNode {
+  fn drop(&mut self) {
+    let mut nodes = mem::take(&mut self.children);
+    while let Some(mut node) = nodes.pop() {
+      nodes.append(&mut node.children);
+    }
+  }
+}
+
+/// Parses a tree in Newick format. Comments (in square brackets) are ignored. The parser does not use recursion, so
+/// that very deep trees (e.g. ladder-like trees of large outbreaks) can be read.
+pub fn nwk_parse(s: &str) -> Result<NwkNode, Report> {
+  let mut chars = s.chars().peekable();
+
+  // Internal nodes for which the opening parenthesis is read, but the closing one is not yet
+  let mut open = Vec::<NwkNode>::new();
+
+  // Node which is currently being read
+  let mut current = NwkNode::default();
+
+  loop {
+    skip_whitespace_and_comments(&mut chars)?;
+    match chars.peek() {
+      Some('(') => {
+        chars.next();
+        open.push(current);
+        current = NwkNode::default();
+      }
+      Some(',') => {
+        chars.next();
+        let parent = open
+          .last_mut()
+          .ok_or_else(|| eyre!("Unexpected ',' outside of parentheses"))?;
+        parent.children.push(mem::take(&mut current));
+      }
+      Some(')') => {
+        chars.next();
+        let mut parent = open
+          .pop()
+          .ok_or_else(|| eyre!("Unexpected ')' without matching opening parenthesis"))?;
+        parent.children.push(current);
+        current = parent;
+        read_label(&mut chars, &mut current)?;
+      }
+      Some(';') | None => break,
+      Some(_) => read_label(&mut chars, &mut current)?,
+    }
+  }
+
+  if !open.is_empty() {
+    return make_error!("Unbalanced parentheses: {} parentheses are not closed", open.len());
+  }
+
+  Ok(current)
+}
+
+/// Reads node name and branch length, i.e. everything that follows the closing parenthesis of an internal node, or
+/// everything that constitutes a leaf node
+fn read_label(chars: &mut Peekable<Chars>, node: &mut NwkNode) -> Result<(), Report> {
+  skip_whitespace_and_comments(chars)?;
+
+  // NOTE: by the format specification, underscores in unquoted names stand for spaces. However, most of the tools
+  // (including Nextstrain) write names with underscores unquoted and expect them to be preserved, so we keep them.
+  node.name = if chars.peek() == Some(&'\'') {
+    read_quoted_name(chars)?
+  } else {
+    read_until_special(chars).trim().to_owned()
+  };
+
+  skip_whitespace_and_comments(chars)?;
+  if chars.peek() == Some(&':') {
+    chars.next();
+    skip_whitespace_and_comments(chars)?;
+    let length = read_until_special(chars);
+    let length = length.trim();
+    let length = length
+      .parse::<f64>()
+      .wrap_err_with(|| format!("When parsing branch length of node '{}': '{length}'", node.name))?;
+    node.branch_length = Some(length);
+  }
+
+  Ok(())
+}
+
+fn read_quoted_name(chars: &mut Peekable<Chars>) -> Result<String, Report> {
+  chars.next();
+  let mut name = String::new();
+  loop {
+    match chars.next() {
+      Some('\'') => {
+        // Two consecutive quotes stand for a literal quote
+        if chars.peek() == Some(&'\'') {
+          chars.next();
+          name.push('\'');
+        } else {
+          return Ok(name);
+        }
+      }
+      Some(c) => name.push(c),
+      None => return make_error!("Unterminated quoted node name: '{name}"),
+    }
+  }
+}
+
+fn read_until_special(chars: &mut Peekable<Chars>) -> String {
+  let mut result = String::new();
+  while let Some(&c) = chars.peek() {
+    if matches!(c, '(' | ')' | '[' | ':' | ';' | ',') {
+      break;
+    }
+    result.push(c);
+    chars.next();
+  }
+  result
+}
+
+fn skip_whitespace_and_comments(chars: &mut Peekable<Chars>) -> Result<(), Report> {
+  while let Some(&c) = chars.peek() {
+    if c.is_whitespace() {
+      chars.next();
+    } else if c == '[' {
+      if !chars.by_ref().any(|c| c == ']') {
+        return make_error!("Unterminated comment: expected ']'");
+      }
+    } else {
+      break;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn node(name: &str, branch_length: Option<f64>, children: Vec<NwkNode>) -> NwkNode {
+    NwkNode {
+      name: name.to_owned(),
+      branch_length,
+      children,
+    }
+  }
+
+  #[rstest]
+  fn parses_newick() -> Result<(), Report> {
+    let actual = nwk_parse("((A:0.1,'seq ''1''':0.2)NODE_1:0.05[&comment],C, D_2 :1e-3)root;\n")?;
+    let expected = node(
+      "root",
+      None,
+      vec![
+        node(
+          "NODE_1",
+          Some(0.05),
+          vec![node("A", Some(0.1), vec![]), node("seq '1'", Some(0.2), vec![])],
+        ),
+        node("C", None, vec![]),
+        node("D_2", Some(0.001), vec![]),
+      ],
+    );
+    assert_eq!(actual, expected);
+    Ok(())
+  }
+
+  #[rstest]
+  fn parses_and_drops_very_deep_tree() -> Result<(), Report> {
+    let depth = 1_000_000;
+    let nwk = format!("{}A{};", "(".repeat(depth), ")".repeat(depth));
+    let tree = nwk_parse(&nwk)?;
+
+    let mut actual_depth = 0;
+    let mut node = &tree;
+    while let Some(child) = node.children.first() {
+      actual_depth += 1;
+      node = child;
+    }
+    assert_eq!(actual_depth, depth);
+    assert_eq!(node.name, "A");
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("((A,B);")]
+  #[case("(A,B));")]
+  #[case("(A:x,B);")]
+  #[case("(A,B[comment);")]
+  fn rejects_malformed_newick(#[case] input: &str) {
+    assert!(nwk_parse(input).is_err());
+  }
+}
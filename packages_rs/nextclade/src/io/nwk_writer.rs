@@ -0,0 +1,242 @@
+use crate::io::file::create_file_or_stdout;
+use crate::tree::tree::{AuspiceTree, AuspiceTreeNode};
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use std::io::Write;
+use std::path::Path;
+
+/// Writes the tree into a file in Newick format
+pub fn nwk_write_to_file(filepath: impl AsRef<Path>, tree: &AuspiceTree) -> Result<(), Report> {
+  let filepath = filepath.as_ref();
+  let mut writer = create_file_or_stdout(filepath)?;
+  nwk_write(&mut writer, tree).wrap_err_with(|| format!("When writing Newick tree to file: {filepath:#?}"))
+}
+
+/// Writes the tree into a file in Nexus format, with node annotations
+pub fn nexus_write_to_file(filepath: impl AsRef<Path>, tree: &AuspiceTree) -> Result<(), Report> {
+  let filepath = filepath.as_ref();
+  let mut writer = create_file_or_stdout(filepath)?;
+  nexus_write(&mut writer, tree).wrap_err_with(|| format!("When writing Nexus tree to file: {filepath:#?}"))
+}
+
+pub fn nwk_write(writer: &mut impl Write, tree: &AuspiceTree) -> Result<(), Report> {
+  write_nwk_impl(writer, &tree.tree, false)?;
+  writeln!(writer, ";")?;
+  Ok(())
+}
+
+pub fn nexus_write(writer: &mut impl Write, tree: &AuspiceTree) -> Result<(), Report> {
+  writeln!(writer, "#NEXUS")?;
+  writeln!(writer, "BEGIN TREES;")?;
+  write!(writer, "\tTREE tree1 = [&R] ")?;
+  write_nwk_impl(writer, &tree.tree, true)?;
+  writeln!(writer, ";")?;
+  writeln!(writer, "END;")?;
+  Ok(())
+}
+
+enum NwkVisit<'a> {
+  Enter(&'a AuspiceTreeNode, Option<f64>),
+  Exit(&'a AuspiceTreeNode, Option<f64>),
+  Separator,
+}
+
+/// Writes the tree in Newick format (without the trailing semicolon). The nodes are written as soon as they are
+/// visited, and the traversal uses an explicit stack rather than recursion, so that large and deep trees can be
+/// written without building the entire string in memory.
+fn write_nwk_impl(writer: &mut impl Write, root: &AuspiceTreeNode, annotate: bool) -> Result<(), Report> {
+  let mut stack = vec![NwkVisit::Enter(root, None)];
+
+  while let Some(visit) = stack.pop() {
+    match visit {
+      NwkVisit::Enter(node, parent_div) => {
+        if node.children.is_empty() {
+          write_node(writer, node, parent_div, annotate)?;
+        } else {
+          write!(writer, "(")?;
+          stack.push(NwkVisit::Exit(node, parent_div));
+          for (i, child) in node.children.iter().enumerate().rev() {
+            stack.push(NwkVisit::Enter(child, node.node_attrs.div));
+            if i > 0 {
+              stack.push(NwkVisit::Separator);
+            }
+          }
+        }
+      }
+      NwkVisit::Exit(node, parent_div) => {
+        write!(writer, ")")?;
+        write_node(writer, node, parent_div, annotate)?;
+      }
+      NwkVisit::Separator => {
+        write!(writer, ",")?;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Writes node name, optional annotation and branch length. Branch length is the difference in divergence between
+/// the node and its parent.
+fn write_node(
+  writer: &mut impl Write,
+  node: &AuspiceTreeNode,
+  parent_div: Option<f64>,
+  annotate: bool,
+) -> Result<(), Report> {
+  write!(writer, "{}", quote_nwk_name(&node.name))?;
+
+  if annotate {
+    write!(writer, "{}", format_nexus_annotation(node))?;
+  }
+
+  if let (Some(div), Some(parent_div)) = (node.node_attrs.div, parent_div) {
+    write!(writer, ":{}", format_branch_length(div - parent_div))?;
+  }
+
+  Ok(())
+}
+
+/// Number of decimal places of branch lengths in the output
+const BRANCH_LENGTH_PRECISION: usize = 10;
+
+/// Formats branch length with a fixed precision, such that the floating point artifacts of the subtraction of
+/// divergences (e.g. `0.30000000000000004`) do not end up in the output. Trailing zeros are removed.
+fn format_branch_length(length: f64) -> String {
+  let formatted = format!("{length:.BRANCH_LENGTH_PRECISION$}");
+  let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+  if trimmed == "-0" {
+    "0".to_owned()
+  } else {
+    trimmed.to_owned()
+  }
+}
+
+/// Quotes node name, if it contains characters which have special meaning in Newick format
+fn quote_nwk_name(name: &str) -> String {
+  const SPECIAL_CHARS: &[char] = &[' ', '\t', '\n', '(', ')', '[', ']', '\'', ':', ';', ','];
+  if name.contains(SPECIAL_CHARS) {
+    format!("'{}'", name.replace('\'', "''"))
+  } else {
+    name.to_owned()
+  }
+}
+
+/// Formats node attributes as a comment understood by common tree viewers (e.g. FigTree)
+fn format_nexus_annotation(node: &AuspiceTreeNode) -> String {
+  let mut attrs = vec![("clade", node.clade())];
+
+  if let Some(qc_status) = &node.node_attrs.qc_status {
+    attrs.push(("qc_status", qc_status.value.clone()));
+  }
+
+  let nuc_mutations = node
+    .branch_attrs
+    .mutations
+    .get("nuc")
+    .map(|mutations| mutations.join(","))
+    .unwrap_or_default();
+  if !nuc_mutations.is_empty() {
+    attrs.push(("nuc_mutations", nuc_mutations));
+  }
+
+  let aa_mutations = node
+    .branch_attrs
+    .mutations
+    .iter()
+    .filter(|(gene_name, _)| *gene_name != "nuc")
+    .flat_map(|(gene_name, mutations)| mutations.iter().map(move |mutation| format!("{gene_name}:{mutation}")))
+    .join(",");
+  if !aa_mutations.is_empty() {
+    attrs.push(("aa_mutations", aa_mutations));
+  }
+
+  let attrs = attrs
+    .into_iter()
+    .map(|(key, value)| format!(r#"{key}="{}""#, value.replace('"', "'")))
+    .join(",");
+
+  format!("[&{attrs}]")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::str::FromStr;
+
+  const TREE: &str = r#"{
+    "meta": { "display_defaults": {} },
+    "tree": {
+      "name": "root",
+      "branch_attrs": { "mutations": {} },
+      "node_attrs": { "div": 0.0, "clade_membership": { "value": "A" } },
+      "children": [
+        {
+          "name": "B",
+          "branch_attrs": { "mutations": { "nuc": ["A4C", "A13C"], "S": ["N501Y"] } },
+          "node_attrs": { "div": 2.0, "clade_membership": { "value": "B" } },
+          "children": [
+            {
+              "name": "seq 1_new",
+              "branch_attrs": { "mutations": { "nuc": ["T7G"] } },
+              "node_attrs": { "div": 3.0, "clade_membership": { "value": "B" }, "QC Status": { "value": "good" } }
+            },
+            {
+              "name": "B.1",
+              "branch_attrs": { "mutations": {} },
+              "node_attrs": { "div": 2.5, "clade_membership": { "value": "B" } }
+            }
+          ]
+        },
+        {
+          "name": "C",
+          "branch_attrs": { "mutations": {} },
+          "node_attrs": { "clade_membership": { "value": "C" } }
+        }
+      ]
+    }
+  }"#;
+
+  #[rstest]
+  fn writes_newick() -> Result<(), Report> {
+    let tree = AuspiceTree::from_str(TREE)?;
+    let mut buf = Vec::<u8>::new();
+    nwk_write(&mut buf, &tree)?;
+    assert_eq!(String::from_utf8(buf)?, "(('seq 1_new':1,B.1:0.5)B:2,C)root;\n");
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(1.0, "1")]
+  #[case(0.5, "0.5")]
+  #[case(0.0, "0")]
+  #[case(0.3 - 0.1, "0.2")]
+  #[case(0.1 + 0.2, "0.3")]
+  #[case(-1e-17, "0")]
+  #[case(12.25, "12.25")]
+  fn formats_branch_length_without_float_artifacts(#[case] length: f64, #[case] expected: &str) {
+    assert_eq!(format_branch_length(length), expected);
+  }
+
+  #[rstest]
+  fn writes_nexus_with_annotations() -> Result<(), Report> {
+    let tree = AuspiceTree::from_str(TREE)?;
+    let mut buf = Vec::<u8>::new();
+    nexus_write(&mut buf, &tree)?;
+    assert_eq!(
+      String::from_utf8(buf)?,
+      concat!(
+        "#NEXUS\n",
+        "BEGIN TREES;\n",
+        "\tTREE tree1 = [&R] ",
+        r#"(('seq 1_new'[&clade="B",qc_status="good",nuc_mutations="T7G"]:1,B.1[&clade="B"]:0.5)"#,
+        r#"B[&clade="B",nuc_mutations="A4C,A13C",aa_mutations="S:N501Y"]:2,C[&clade="C"])root[&clade="A"];"#,
+        "\n",
+        "END;\n",
+      )
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,246 @@
+use crate::make_error;
+use eyre::{eyre, Report, WrapErr};
+
+// Minimal reader and writer of the Protocol Buffers wire format, sufficient for the few simple message types we need
+// to exchange with other tools. Messages are decoded field by field, without code generation from .proto files.
+//
+// Libraries such as `prost` generate code from .proto files in a build script, which requires the `protoc` compiler
+// at build time, for every target, including WebAssembly and cross-compiled binaries. For a handful of small messages
+// this hand-written codec is the simpler option.
+//
+// See: https://protobuf.dev/programming-guides/encoding/
+
+const WIRE_TYPE_VARINT: u8 = 0;
+const WIRE_TYPE_FIXED64: u8 = 1;
+const WIRE_TYPE_LEN: u8 = 2;
+const WIRE_TYPE_FIXED32: u8 = 5;
+
+/// Value of a field, as it is encoded on the wire
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProtoValue<'a> {
+  Varint(u64),
+  Fixed64(u64),
+  Len(&'a [u8]),
+  Fixed32(u32),
+}
+
+impl<'a> ProtoValue<'a> {
+  pub fn as_i32(&self) -> Result<i32, Report> {
+    match self {
+      // NOTE: negative int32 values are sign-extended to 64 bits before encoding
+      ProtoValue::Varint(value) => Ok(*value as i64 as i32),
+      _ => make_error!("Expected a varint value, but found: {self:?}"),
+    }
+  }
+
+  pub fn as_bytes(&self) -> Result<&'a [u8], Report> {
+    match self {
+      ProtoValue::Len(bytes) => Ok(bytes),
+      _ => make_error!("Expected a length-delimited value, but found: {self:?}"),
+    }
+  }
+
+  pub fn as_str(&self) -> Result<&'a str, Report> {
+    std::str::from_utf8(self.as_bytes()?).wrap_err("When decoding a string value")
+  }
+
+  /// Reads values of a repeated int32 field. Proto3 encodes them packed (as one length-delimited value), but parsers
+  /// are required to accept unpacked values (one value per field occurrence) too.
+  pub fn as_repeated_i32(&self) -> Result<Vec<i32>, Report> {
+    match self {
+      ProtoValue::Len(bytes) => {
+        let mut reader = ProtoReader::new(bytes);
+        let mut values = vec![];
+        while !reader.is_empty() {
+          values.push(reader.read_varint()? as i64 as i32);
+        }
+        Ok(values)
+      }
+      _ => Ok(vec![self.as_i32()?]),
+    }
+  }
+}
+
+/// Reads fields of a protobuf message one by one
+pub struct ProtoReader<'a> {
+  buf: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+  pub const fn new(buf: &'a [u8]) -> Self {
+    Self { buf, pos: 0 }
+  }
+
+  pub const fn is_empty(&self) -> bool {
+    self.pos >= self.buf.len()
+  }
+
+  /// Reads next field: returns the field number and the value, or `None` if the end of the message is reached
+  pub fn read_field(&mut self) -> Result<Option<(u32, ProtoValue<'a>)>, Report> {
+    if self.is_empty() {
+      return Ok(None);
+    }
+
+    let tag = self.read_varint()?;
+    let field = (tag >> 3) as u32;
+    let wire_type = (tag & 0b111) as u8;
+
+    let value = match wire_type {
+      WIRE_TYPE_VARINT => ProtoValue::Varint(self.read_varint()?),
+      WIRE_TYPE_FIXED64 => ProtoValue::Fixed64(u64::from_le_bytes(self.read_bytes(8)?.try_into()?)),
+      WIRE_TYPE_LEN => {
+        let len = self.read_varint()? as usize;
+        ProtoValue::Len(self.read_bytes(len)?)
+      }
+      WIRE_TYPE_FIXED32 => ProtoValue::Fixed32(u32::from_le_bytes(self.read_bytes(4)?.try_into()?)),
+      _ => {
+        return make_error!(
+          "Unsupported protobuf wire type {wire_type} of field {field} at byte {}",
+          self.pos
+        )
+      }
+    };
+
+    Ok(Some((field, value)))
+  }
+
+  fn read_varint(&mut self) -> Result<u64, Report> {
+    let mut result = 0_u64;
+    for shift in (0..64).step_by(7) {
+      let byte = *self
+        .buf
+        .get(self.pos)
+        .ok_or_else(|| eyre!("Unexpected end of protobuf data when reading a varint"))?;
+      self.pos += 1;
+      result |= u64::from(byte & 0x7F) << shift;
+      if byte & 0x80 == 0 {
+        return Ok(result);
+      }
+    }
+    make_error!("Malformed protobuf varint at byte {}", self.pos)
+  }
+
+  fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Report> {
+    let end = self.pos.saturating_add(len);
+    if end > self.buf.len() {
+      return make_error!(
+        "Unexpected end of protobuf data: expected {len} bytes at byte {}, but the data has length {}",
+        self.pos,
+        self.buf.len()
+      );
+    }
+    let bytes = &self.buf[self.pos..end];
+    self.pos = end;
+    Ok(bytes)
+  }
+}
+
+/// Writes fields of a protobuf message
+#[derive(Clone, Debug, Default)]
+pub struct ProtoWriter {
+  buf: Vec<u8>,
+}
+
+impl ProtoWriter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn into_bytes(self) -> Vec<u8> {
+    self.buf
+  }
+
+  pub fn write_i32(&mut self, field: u32, value: i32) {
+    self.write_tag(field, WIRE_TYPE_VARINT);
+    self.write_varint(value as i64 as u64);
+  }
+
+  pub fn write_bytes(&mut self, field: u32, bytes: &[u8]) {
+    self.write_tag(field, WIRE_TYPE_LEN);
+    self.write_varint(bytes.len() as u64);
+    self.buf.extend_from_slice(bytes);
+  }
+
+  pub fn write_str(&mut self, field: u32, value: &str) {
+    self.write_bytes(field, value.as_bytes());
+  }
+
+  pub fn write_message(&mut self, field: u32, message: ProtoWriter) {
+    self.write_bytes(field, &message.into_bytes());
+  }
+
+  pub fn write_packed_i32(&mut self, field: u32, values: &[i32]) {
+    let mut packed = ProtoWriter::new();
+    for &value in values {
+      packed.write_varint(value as i64 as u64);
+    }
+    self.write_bytes(field, &packed.into_bytes());
+  }
+
+  fn write_tag(&mut self, field: u32, wire_type: u8) {
+    self.write_varint((u64::from(field) << 3) | u64::from(wire_type));
+  }
+
+  fn write_varint(&mut self, mut value: u64) {
+    while value >= 0x80 {
+      self.buf.push((value as u8 & 0x7F) | 0x80);
+      value >>= 7;
+    }
+    self.buf.push(value as u8);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  fn writes_and_reads_fields() -> Result<(), Report> {
+    let mut inner = ProtoWriter::new();
+    inner.write_str(1, "hello");
+
+    let mut writer = ProtoWriter::new();
+    writer.write_i32(1, 150);
+    writer.write_i32(2, -2);
+    writer.write_message(3, inner);
+    writer.write_packed_i32(4, &[1, 300, 0]);
+    let bytes = writer.into_bytes();
+
+    // Example from the protobuf encoding guide: field 1 with value 150 is encoded as `08 96 01`
+    assert_eq!(bytes[..3], [0x08, 0x96, 0x01]);
+
+    let mut reader = ProtoReader::new(&bytes);
+    assert_eq!(
+      reader.read_field()?.map(|(f, v)| (f, v.as_i32().unwrap())),
+      Some((1, 150))
+    );
+    assert_eq!(
+      reader.read_field()?.map(|(f, v)| (f, v.as_i32().unwrap())),
+      Some((2, -2))
+    );
+
+    let (field, value) = reader.read_field()?.unwrap();
+    assert_eq!(field, 3);
+    let mut inner_reader = ProtoReader::new(value.as_bytes()?);
+    assert_eq!(
+      inner_reader.read_field()?.map(|(f, v)| (f, v.as_str().unwrap())),
+      Some((1, "hello"))
+    );
+    assert_eq!(inner_reader.read_field()?, None);
+
+    let (field, value) = reader.read_field()?.unwrap();
+    assert_eq!(field, 4);
+    assert_eq!(value.as_repeated_i32()?, vec![1, 300, 0]);
+    assert_eq!(reader.read_field()?, None);
+    Ok(())
+  }
+
+  #[rstest]
+  fn rejects_truncated_data() {
+    let mut reader = ProtoReader::new(&[0x0A, 0x05, b'a', b'b']);
+    assert!(reader.read_field().is_err());
+  }
+}
@@ -0,0 +1,279 @@
+use crate::io::file::create_file_or_stdout;
+use crate::io::fs::{extension, has_extension, read_file_to_bytes};
+use crate::io::protobuf::{ProtoReader, ProtoWriter};
+use crate::make_error;
+use eyre::{Report, WrapErr};
+use std::io::Write;
+use std::path::Path;
+
+// Mutation-annotated tree (MAT), in the protobuf format used by UShER and matUtils. Message and field numbers follow
+// `parsimony.proto` of UShER:
+//
+//   message mut { int32 position = 1; int32 ref_nuc = 2; int32 par_nuc = 3; repeated int32 mut_nuc = 4; string chromosome = 5; }
+//   message mutation_list { repeated mut mutation = 1; }
+//   message condensed_node { string node_name = 1; repeated string condensed_leaves = 2; }
+//   message node_metadata { repeated string clade_annotations = 1; }
+//   message data { string newick = 1; repeated mutation_list node_mutations = 2; repeated condensed_node condensed_nodes = 3; repeated node_metadata metadata = 4; }
+//
+// See: https://usher-wiki.readthedocs.io/en/latest/matUtils.html
+
+/// Mutation on a branch of a MAT. Position is 1-based. Nucleotides are encoded as numbers: A=0, C=1, G=2, T=3.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsherMutation {
+  pub position: i32,
+  pub ref_nuc: i32,
+  pub par_nuc: i32,
+  pub mut_nuc: Vec<i32>,
+  pub chromosome: String,
+}
+
+/// Leaf of the tree which stands for several identical samples
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsherCondensedNode {
+  pub node_name: String,
+  pub condensed_leaves: Vec<String>,
+}
+
+/// Mutation-annotated tree. Mutations and metadata are listed for every node of the Newick tree, in depth-first
+/// pre-order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsherMat {
+  pub newick: String,
+  pub node_mutations: Vec<Vec<UsherMutation>>,
+  pub condensed_nodes: Vec<UsherCondensedNode>,
+
+  /// Clade annotations of every node. An annotation is non-empty only on the node at which the clade originates.
+  pub metadata: Vec<Vec<String>>,
+}
+
+impl UsherMat {
+  pub fn from_path(filepath: impl AsRef<Path>) -> Result<Self, Report> {
+    let filepath = filepath.as_ref();
+    let data = read_file_to_bytes(filepath).wrap_err_with(|| format!("When reading UShER MAT file {filepath:#?}"))?;
+    Self::from_bytes(&data).wrap_err_with(|| format!("When parsing UShER MAT file {filepath:#?}"))
+  }
+
+  pub fn from_bytes(data: &[u8]) -> Result<Self, Report> {
+    let mut mat = Self::default();
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.read_field()? {
+      match field {
+        1 => mat.newick = value.as_str()?.to_owned(),
+        2 => mat.node_mutations.push(read_mutation_list(value.as_bytes()?)?),
+        3 => mat.condensed_nodes.push(read_condensed_node(value.as_bytes()?)?),
+        4 => mat.metadata.push(read_node_metadata(value.as_bytes()?)?),
+        _ => {}
+      }
+    }
+
+    if mat.newick.is_empty() {
+      return make_error!("The MAT contains no tree");
+    }
+
+    Ok(mat)
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut writer = ProtoWriter::new();
+    writer.write_str(1, &self.newick);
+
+    for mutations in &self.node_mutations {
+      let mut mutation_list = ProtoWriter::new();
+      for mutation in mutations {
+        let mut message = ProtoWriter::new();
+        message.write_i32(1, mutation.position);
+        message.write_i32(2, mutation.ref_nuc);
+        message.write_i32(3, mutation.par_nuc);
+        message.write_packed_i32(4, &mutation.mut_nuc);
+        message.write_str(5, &mutation.chromosome);
+        mutation_list.write_message(1, message);
+      }
+      writer.write_message(2, mutation_list);
+    }
+
+    for condensed_node in &self.condensed_nodes {
+      let mut message = ProtoWriter::new();
+      message.write_str(1, &condensed_node.node_name);
+      for leaf in &condensed_node.condensed_leaves {
+        message.write_str(2, leaf);
+      }
+      writer.write_message(3, message);
+    }
+
+    for clade_annotations in &self.metadata {
+      let mut message = ProtoWriter::new();
+      for annotation in clade_annotations {
+        message.write_str(1, annotation);
+      }
+      writer.write_message(4, message);
+    }
+
+    writer.into_bytes()
+  }
+}
+
+/// Writes MAT into a file. If the file path has a compression extension (e.g. `.pb.gz`), the file is compressed.
+pub fn usher_mat_write_to_file(filepath: impl AsRef<Path>, mat: &UsherMat) -> Result<(), Report> {
+  let filepath = filepath.as_ref();
+  let mut writer = create_file_or_stdout(filepath)?;
+  writer
+    .write_all(&mat.to_bytes())
+    .and_then(|_| writer.flush())
+    .wrap_err_with(|| format!("When writing UShER MAT file {filepath:#?}"))
+}
+
+/// Checks whether the file is an UShER MAT, i.e. whether it has extension `.pb`, possibly followed by a compression
+/// extension (e.g. `.pb.gz`)
+pub fn is_usher_mat_filepath(filepath: impl AsRef<Path>) -> bool {
+  let filepath = filepath.as_ref();
+  let is_compressed = extension(filepath).map_or(false, |ext| {
+    ["gz", "bz2", "xz", "zst", "zstd"].contains(&ext.to_lowercase().as_str())
+  });
+  if is_compressed {
+    filepath.file_stem().map_or(false, |stem| has_extension(stem, "pb"))
+  } else {
+    has_extension(filepath, "pb")
+  }
+}
+
+fn read_mutation_list(data: &[u8]) -> Result<Vec<UsherMutation>, Report> {
+  let mut mutations = vec![];
+  let mut reader = ProtoReader::new(data);
+  while let Some((field, value)) = reader.read_field()? {
+    if field == 1 {
+      mutations.push(read_mutation(value.as_bytes()?)?);
+    }
+  }
+  Ok(mutations)
+}
+
+fn read_mutation(data: &[u8]) -> Result<UsherMutation, Report> {
+  let mut mutation = UsherMutation::default();
+  let mut reader = ProtoReader::new(data);
+  while let Some((field, value)) = reader.read_field()? {
+    match field {
+      1 => mutation.position = value.as_i32()?,
+      2 => mutation.ref_nuc = value.as_i32()?,
+      3 => mutation.par_nuc = value.as_i32()?,
+      4 => mutation.mut_nuc.extend(value.as_repeated_i32()?),
+      5 => mutation.chromosome = value.as_str()?.to_owned(),
+      _ => {}
+    }
+  }
+  Ok(mutation)
+}
+
+fn read_condensed_node(data: &[u8]) -> Result<UsherCondensedNode, Report> {
+  let mut condensed_node = UsherCondensedNode::default();
+  let mut reader = ProtoReader::new(data);
+  while let Some((field, value)) = reader.read_field()? {
+    match field {
+      1 => condensed_node.node_name = value.as_str()?.to_owned(),
+      2 => condensed_node.condensed_leaves.push(value.as_str()?.to_owned()),
+      _ => {}
+    }
+  }
+  Ok(condensed_node)
+}
+
+fn read_node_metadata(data: &[u8]) -> Result<Vec<String>, Report> {
+  let mut clade_annotations = vec![];
+  let mut reader = ProtoReader::new(data);
+  while let Some((field, value)) = reader.read_field()? {
+    if field == 1 {
+      clade_annotations.push(value.as_str()?.to_owned());
+    }
+  }
+  Ok(clade_annotations)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  fn writes_and_reads_mat() -> Result<(), Report> {
+    let mat = UsherMat {
+      newick: "((A:1,B:0)node_2:1,C:2)node_1;".to_owned(),
+      node_mutations: vec![
+        vec![],
+        vec![UsherMutation {
+          position: 241,
+          ref_nuc: 1,
+          par_nuc: 1,
+          mut_nuc: vec![3],
+          chromosome: "MN908947.3".to_owned(),
+        }],
+        vec![],
+        vec![],
+        vec![],
+      ],
+      condensed_nodes: vec![UsherCondensedNode {
+        node_name: "B".to_owned(),
+        condensed_leaves: vec!["B1".to_owned(), "B2".to_owned()],
+      }],
+      metadata: vec![vec!["19A".to_owned()], vec!["20A".to_owned()], vec![], vec![], vec![]],
+    };
+    assert_eq!(UsherMat::from_bytes(&mat.to_bytes())?, mat);
+    Ok(())
+  }
+
+  #[rstest]
+  fn reads_mat_encoded_by_protobuf() -> Result<(), Report> {
+    // MAT with tree `(A,B);`, encoded according to `parsimony.proto`, in the same way as UShER writes it (repeated
+    // numbers are packed)
+    #[rustfmt::skip]
+    let data = [
+      // newick
+      0x0A, 0x06, b'(', b'A', b',', b'B', b')', b';',
+      // node_mutations: root has mutation C241T on chromosome `ref`, leaves have none
+      0x12, 0x11, 0x0A, 0x0F,
+        0x08, 0xF1, 0x01, 0x10, 0x01, 0x18, 0x01, 0x22, 0x01, 0x03, 0x2A, 0x03, b'r', b'e', b'f',
+      0x12, 0x00,
+      0x12, 0x00,
+      // condensed_nodes: leaf `B` stands for samples `B1` and `B2`
+      0x1A, 0x0B, 0x0A, 0x01, b'B', 0x12, 0x02, b'B', b'1', 0x12, 0x02, b'B', b'2',
+      // metadata: root is annotated with clade `20A`
+      0x22, 0x05, 0x0A, 0x03, b'2', b'0', b'A',
+      0x22, 0x00,
+      0x22, 0x00,
+    ];
+
+    let expected = UsherMat {
+      newick: "(A,B);".to_owned(),
+      node_mutations: vec![
+        vec![UsherMutation {
+          position: 241,
+          ref_nuc: 1,
+          par_nuc: 1,
+          mut_nuc: vec![3],
+          chromosome: "ref".to_owned(),
+        }],
+        vec![],
+        vec![],
+      ],
+      condensed_nodes: vec![UsherCondensedNode {
+        node_name: "B".to_owned(),
+        condensed_leaves: vec!["B1".to_owned(), "B2".to_owned()],
+      }],
+      metadata: vec![vec!["20A".to_owned()], vec![], vec![]],
+    };
+
+    assert_eq!(UsherMat::from_bytes(&data)?, expected);
+    assert_eq!(expected.to_bytes(), data);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("tree.pb", true)]
+  #[case("path/to/tree.pb.gz", true)]
+  #[case("tree.PB", true)]
+  #[case("tree.json", false)]
+  #[case("tree.json.gz", false)]
+  #[case("pb", false)]
+  fn detects_mat_filepath(#[case] filepath: &str, #[case] expected: bool) {
+    assert_eq!(is_usher_mat_filepath(filepath), expected);
+  }
+}
@@ -5,6 +5,7 @@ pub mod gene;
 pub mod io;
 pub mod qc;
 pub mod run;
+pub mod sort;
 pub mod translate;
 pub mod tree;
 pub mod types;
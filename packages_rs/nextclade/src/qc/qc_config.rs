@@ -120,6 +120,50 @@ impl Default for QcRulesConfigStopCodons {
   }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct QcRulesConfigRecombinants {
+  pub enabled: bool,
+
+  /// Size of the genome windows, which are placed on the reference tree independently
+  pub window_size: usize,
+
+  /// How much closer the nearest node of a window should be, compared to the nearest node of the whole sequence, for
+  /// the window to be assigned to a different clade
+  pub min_distance_improvement: i64,
+
+  pub score_weight: f64,
+}
+
+impl Default for QcRulesConfigRecombinants {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      window_size: 2000,
+      min_distance_improvement: 2,
+      score_weight: 50.0,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct QcRulesConfigInversions {
+  pub enabled: bool,
+  pub score_weight: f64,
+}
+
+impl Default for QcRulesConfigInversions {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      score_weight: 50.0,
+    }
+  }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
@@ -131,6 +175,8 @@ pub struct QcConfig {
   pub snp_clusters: QcRulesConfigSnpClusters,
   pub frame_shifts: QcRulesConfigFrameShifts,
   pub stop_codons: QcRulesConfigStopCodons,
+  pub recombinants: QcRulesConfigRecombinants,
+  pub inversions: QcRulesConfigInversions,
 }
 
 impl FromStr for QcConfig {
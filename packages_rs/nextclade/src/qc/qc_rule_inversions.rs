@@ -0,0 +1,37 @@
+use crate::align::inversions::Inversion;
+use crate::qc::qc_config::QcRulesConfigInversions;
+use crate::qc::qc_run::{QcRule, QcStatus};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QcResultInversions {
+  pub score: f64,
+  pub status: QcStatus,
+  pub inversions: Vec<Inversion>,
+  pub total_inversions: usize,
+}
+
+impl QcRule for QcResultInversions {
+  fn score(&self) -> f64 {
+    self.score
+  }
+}
+
+pub fn rule_inversions(inversions: &[Inversion], config: &QcRulesConfigInversions) -> Option<QcResultInversions> {
+  if !config.enabled {
+    return None;
+  }
+
+  let total_inversions = inversions.len();
+
+  let score = total_inversions as f64 * config.score_weight;
+  let status = QcStatus::from_score(score);
+
+  Some(QcResultInversions {
+    score,
+    status,
+    inversions: inversions.to_vec(),
+    total_inversions,
+  })
+}
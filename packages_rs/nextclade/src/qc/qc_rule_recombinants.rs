@@ -0,0 +1,45 @@
+use crate::qc::qc_config::QcRulesConfigRecombinants;
+use crate::qc::qc_run::{QcRule, QcStatus};
+use crate::tree::tree_find_breakpoints::{Recombination, RecombinationBreakpoint};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QcResultRecombinants {
+  pub score: f64,
+  pub status: QcStatus,
+  pub breakpoints: Vec<RecombinationBreakpoint>,
+  pub total_breakpoints: usize,
+  pub parental_clades: Vec<String>,
+}
+
+impl QcRule for QcResultRecombinants {
+  fn score(&self) -> f64 {
+    self.score
+  }
+}
+
+pub fn rule_recombinants(
+  recombination: &Option<Recombination>,
+  config: &QcRulesConfigRecombinants,
+) -> Option<QcResultRecombinants> {
+  if !config.enabled {
+    return None;
+  }
+
+  let recombination = recombination.as_ref()?;
+
+  let breakpoints = recombination.breakpoints.clone();
+  let total_breakpoints = breakpoints.len();
+
+  let score = total_breakpoints as f64 * config.score_weight;
+  let status = QcStatus::from_score(score);
+
+  Some(QcResultRecombinants {
+    score,
+    status,
+    breakpoints,
+    total_breakpoints,
+    parental_clades: recombination.parental_clades.clone(),
+  })
+}
@@ -1,14 +1,18 @@
+use crate::align::inversions::Inversion;
 use crate::analyze::find_private_nuc_mutations::PrivateNucMutations;
 use crate::io::nuc::Nuc;
 use crate::qc::qc_config::QcConfig;
 use crate::qc::qc_rule_frame_shifts::{rule_frame_shifts, QcResultFrameShifts};
+use crate::qc::qc_rule_inversions::{rule_inversions, QcResultInversions};
 use crate::qc::qc_rule_missing_data::{rule_missing_data, QcResultMissingData};
 use crate::qc::qc_rule_mixed_sites::{rule_mixed_sites, QcResultMixedSites};
 use crate::qc::qc_rule_private_mutations::{rule_private_mutations, QcResultPrivateMutations};
+use crate::qc::qc_rule_recombinants::{rule_recombinants, QcResultRecombinants};
 use crate::qc::qc_rule_snp_clusters::{rule_snp_clusters, QcResultSnpClusters};
 use crate::qc::qc_rule_stop_codons::{rule_stop_codons, QcResultStopCodons};
 use crate::translate::frame_shifts_translate::FrameShift;
 use crate::translate::translate_genes::Translation;
+use crate::tree::tree_find_breakpoints::Recombination;
 use num::traits::Pow;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -58,6 +62,10 @@ pub struct QcResult {
   pub snp_clusters: Option<QcResultSnpClusters>,
   pub frame_shifts: Option<QcResultFrameShifts>,
   pub stop_codons: Option<QcResultStopCodons>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub recombinants: Option<QcResultRecombinants>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub inversions: Option<QcResultInversions>,
   pub overall_score: f64,
   pub overall_status: QcStatus,
 }
@@ -72,6 +80,8 @@ pub fn qc_run(
   total_missing: usize,
   translations: &[Translation],
   frame_shifts: &[FrameShift],
+  recombination: &Option<Recombination>,
+  inversions: &[Inversion],
   config: &QcConfig,
 ) -> QcResult {
   let mut result = QcResult {
@@ -81,6 +91,8 @@ pub fn qc_run(
     snp_clusters: rule_snp_clusters(private_nuc_mutations, &config.snp_clusters),
     frame_shifts: rule_frame_shifts(frame_shifts, &config.frame_shifts),
     stop_codons: rule_stop_codons(translations, &config.stop_codons),
+    recombinants: rule_recombinants(recombination, &config.recombinants),
+    inversions: rule_inversions(inversions, &config.inversions),
     overall_score: 0.0,
     overall_status: QcStatus::Good,
   };
@@ -91,6 +103,8 @@ pub fn qc_run(
   result.overall_score += add_score(&result.snp_clusters);
   result.overall_score += add_score(&result.frame_shifts);
   result.overall_score += add_score(&result.stop_codons);
+  result.overall_score += add_score(&result.recombinants);
+  result.overall_score += add_score(&result.inversions);
 
   result.overall_status = QcStatus::from_score(result.overall_score);
 
@@ -1,9 +1,11 @@
 use crate::align::align::align_nuc;
 use crate::align::insertions_strip::insertions_strip;
 use crate::align::params::AlignPairwiseParams;
+use crate::align::seed_chain::MinimizerIndex;
 use crate::io::gene_map::GeneMap;
 use crate::io::nuc::Nuc;
 use crate::translate::coord_map::CoordMap;
+use crate::translate::genetic_code::GeneticCode;
 use crate::translate::translate_genes::{translate_genes, Translation, TranslationMap};
 use crate::types::outputs::{NextalignOutputs, PeptideWarning};
 use crate::utils::error::report_to_string;
@@ -18,11 +20,21 @@ pub fn nextalign_run_one(
   ref_seq: &[Nuc],
   ref_peptides: &TranslationMap,
   gene_map: &GeneMap,
+  minimizer_index: Option<&MinimizerIndex>,
   gap_open_close_nuc: &[i32],
   gap_open_close_aa: &[i32],
+  genetic_code: &GeneticCode,
   params: &AlignPairwiseParams,
 ) -> Result<NextalignOutputs, Report> {
-  match align_nuc(index, seq_name, qry_seq, ref_seq, gap_open_close_nuc, params) {
+  match align_nuc(
+    index,
+    seq_name,
+    qry_seq,
+    ref_seq,
+    minimizer_index,
+    gap_open_close_nuc,
+    params,
+  ) {
     Err(report) => Err(report),
 
     Ok(alignment) => {
@@ -35,6 +47,7 @@ pub fn nextalign_run_one(
         gene_map,
         &coord_map,
         gap_open_close_aa,
+        genetic_code,
         params,
       )?;
 
@@ -78,3 +91,54 @@ pub fn nextalign_run_one(
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::align::gap_open::get_gap_open_close_scores_flat;
+  use crate::io::nuc::to_nuc_seq;
+  use crate::translate::complement::reverse_complement_in_place;
+  use std::fs;
+  use std::path::PathBuf;
+
+  fn read_reference() -> Vec<Nuc> {
+    let mut ref_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    ref_path.push("test_data");
+    ref_path.push("reference.fasta");
+    to_nuc_seq(fs::read_to_string(ref_path).unwrap().trim()).unwrap()
+  }
+
+  #[test]
+  fn reports_inverted_regions_in_alignment() -> Result<(), Report> {
+    let ref_seq = read_reference();
+    let mut qry_seq = ref_seq.clone();
+    reverse_complement_in_place(&mut qry_seq[10000..11000]);
+
+    let params = AlignPairwiseParams {
+      detect_inversions: true,
+      ..AlignPairwiseParams::default()
+    };
+    let gene_map = GeneMap::new();
+    let minimizer_index = MinimizerIndex::for_params(&ref_seq, &params)?;
+    let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &params);
+
+    let outputs = nextalign_run_one(
+      0,
+      "inverted",
+      &qry_seq,
+      &ref_seq,
+      &TranslationMap::new(),
+      &gene_map,
+      minimizer_index.as_ref(),
+      &gap_open_close,
+      &gap_open_close,
+      GeneticCode::standard(),
+      &params,
+    )?;
+
+    assert_eq!(outputs.alignment.inversions.len(), 1);
+    assert!(outputs.warnings.is_empty());
+
+    Ok(())
+  }
+}
@@ -1,10 +1,12 @@
 use crate::align::insertions_strip::{get_aa_insertions, NucIns};
 use crate::align::params::AlignPairwiseParams;
+use crate::align::seed_chain::MinimizerIndex;
 use crate::analyze::aa_changes::{find_aa_changes, FindAaChangesOutput};
 use crate::analyze::aa_changes_group::group_adjacent_aa_subs_and_dels;
 use crate::analyze::divergence::calculate_divergence;
 use crate::analyze::find_aa_motifs::find_aa_motifs;
 use crate::analyze::find_aa_motifs_changes::{find_aa_motifs_changes, AaMotifsMap};
+use crate::analyze::find_mixed_sites::find_mixed_sites;
 use crate::analyze::find_private_aa_mutations::find_private_aa_mutations;
 use crate::analyze::find_private_nuc_mutations::find_private_nuc_mutations;
 use crate::analyze::letter_composition::get_letter_composition;
@@ -25,8 +27,11 @@ use crate::run::nextalign_run_one::nextalign_run_one;
 use crate::translate::aa_alignment_ranges::calculate_aa_alignment_ranges_in_place;
 use crate::translate::frame_shifts_flatten::frame_shifts_flatten;
 use crate::translate::translate_genes::{Translation, TranslationMap};
+use crate::tree::params::TreePlacementParams;
 use crate::tree::tree::AuspiceTree;
+use crate::tree::tree_find_breakpoints::tree_find_breakpoints;
 use crate::tree::tree_find_nearest_node::tree_find_nearest_nodes;
+use crate::tree::tree_placement_posteriors::tree_calculate_placement_posteriors;
 use crate::types::outputs::{NextalignOutputs, NextcladeOutputs, PhenotypeValue};
 use crate::utils::range::Range;
 use eyre::Report;
@@ -45,11 +50,15 @@ pub fn nextclade_run_one(
   tree: &AuspiceTree,
   qc_config: &QcConfig,
   virus_properties: &VirusProperties,
+  minimizer_index: Option<&MinimizerIndex>,
   gap_open_close_nuc: &[i32],
   gap_open_close_aa: &[i32],
   params: &AlignPairwiseParams,
   include_nearest_node_info: bool,
+  placement_params: &TreePlacementParams,
 ) -> Result<(Vec<Nuc>, Vec<Translation>, NextcladeOutputs), Report> {
+  let genetic_code = virus_properties.genetic_code()?;
+
   let NextalignOutputs {
     stripped,
     alignment,
@@ -65,8 +74,10 @@ pub fn nextclade_run_one(
     ref_seq,
     ref_peptides,
     gene_map,
+    minimizer_index,
     gap_open_close_nuc,
     gap_open_close_aa,
+    genetic_code,
     params,
   )?;
 
@@ -79,6 +90,8 @@ pub fn nextclade_run_one(
   let alignment_start = alignment_range.begin;
   let alignment_end = alignment_range.end;
   let alignment_score = alignment.alignment_score;
+  let seed_chain = alignment.seed_chain.clone();
+  let inversions = alignment.inversions.clone();
 
   calculate_aa_alignment_ranges_in_place(&alignment_range, gene_map, &coord_map, &mut translations)?;
 
@@ -105,6 +118,7 @@ pub fn nextclade_run_one(
   let FindAaChangesOutput {
     aa_substitutions,
     aa_deletions,
+    aa_ambiguities,
   } = find_aa_changes(
     &stripped.ref_seq,
     &stripped.qry_seq,
@@ -112,6 +126,7 @@ pub fn nextclade_run_one(
     &translations,
     &alignment_range,
     gene_map,
+    genetic_code,
   )?;
 
   let total_aminoacid_substitutions = aa_substitutions.len();
@@ -123,13 +138,18 @@ pub fn nextclade_run_one(
   let unknown_aa_ranges = find_aa_letter_ranges(&translations, Aa::X);
   let total_unknown_aa = unknown_aa_ranges.iter().map(|r| r.length).sum();
 
-  let nearest_node_candidates = tree_find_nearest_nodes(
+  let mut nearest_node_candidates = tree_find_nearest_nodes(
     tree,
     &substitutions,
     &missing,
     &alignment_range,
     &virus_properties.placement_mask_ranges,
   );
+
+  let placement = placement_params
+    .is_probabilistic()
+    .then(|| tree_calculate_placement_posteriors(&mut nearest_node_candidates, placement_params));
+
   let node = nearest_node_candidates[0].node;
   let nearest_node_id = node.tmp.id;
 
@@ -144,9 +164,31 @@ pub fn nextclade_run_one(
 
   let clade = node.clade();
 
+  let recombination = qc_config.recombinants.enabled.then(|| {
+    tree_find_breakpoints(
+      tree,
+      node,
+      &substitutions,
+      &missing,
+      &alignment_range,
+      &virus_properties.placement_mask_ranges,
+      qc_config.recombinants.window_size,
+      qc_config.recombinants.min_distance_improvement,
+    )
+  });
+
   let clade_node_attr_keys = tree.clade_node_attr_descs();
   let clade_node_attrs = node.get_clade_node_attrs(clade_node_attr_keys);
 
+  let mixed_sites = find_mixed_sites(
+    &stripped.qry_seq,
+    ref_seq,
+    &non_acgtns,
+    &alignment_range,
+    node,
+    &tree.tmp.clade_defining_mutations,
+  );
+
   let private_nuc_mutations = find_private_nuc_mutations(
     node,
     &substitutions,
@@ -216,6 +258,8 @@ pub fn nextclade_run_one(
     total_missing,
     &translations,
     &frame_shifts,
+    &recombination,
+    &inversions,
     qc_config,
   );
 
@@ -236,6 +280,7 @@ pub fn nextclade_run_one(
     NextcladeOutputs {
       index,
       seq_name: seq_name.to_owned(),
+      segment: None,
       substitutions,
       total_substitutions,
       deletions,
@@ -246,6 +291,7 @@ pub fn nextclade_run_one(
       total_missing,
       non_acgtns,
       total_non_acgtns,
+      mixed_sites,
       nucleotide_composition,
       frame_shifts,
       total_frame_shifts,
@@ -255,6 +301,7 @@ pub fn nextclade_run_one(
       total_aminoacid_deletions,
       aa_insertions,
       total_aminoacid_insertions,
+      aa_ambiguities,
       unknown_aa_ranges,
       total_unknown_aa,
       aa_changes_groups,
@@ -275,10 +322,14 @@ pub fn nextclade_run_one(
       aa_motifs,
       aa_motifs_changes,
       qc,
+      recombination,
       custom_node_attributes: clade_node_attrs,
       nearest_node_id,
       nearest_nodes,
+      placement,
       is_reverse_complement,
+      seed_chain,
+      inversions,
     },
   ))
 }
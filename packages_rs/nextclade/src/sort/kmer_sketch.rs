@@ -0,0 +1,129 @@
+use crate::align::seed_match::{encode_kmers, EncodedKmer, MAX_KMER_LENGTH};
+use crate::io::nuc::Nuc;
+use crate::make_error;
+use eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Encodes every k-mer of a sequence into an integer (see `encode_kmers`). Each k-mer is represented by the smaller of
+/// the encodings of itself and of its reverse complement, so that reverse-complemented sequences produce the same set of
+/// k-mers.
+pub fn canonical_kmers(seq: &[Nuc], kmer_length: usize) -> Vec<u64> {
+  encode_kmers(seq, kmer_length)
+    .iter()
+    .map(EncodedKmer::canonical)
+    .collect()
+}
+
+/// Set of k-mers of a reference sequence. Allows to quickly estimate similarity of a query sequence to the reference,
+/// without performing an alignment.
+#[derive(Clone, Debug)]
+pub struct KmerSketch {
+  kmer_length: usize,
+  kmers: HashSet<u64>,
+}
+
+impl KmerSketch {
+  pub fn new(ref_seq: &[Nuc], kmer_length: usize) -> Result<Self, Report> {
+    if kmer_length == 0 || kmer_length > MAX_KMER_LENGTH {
+      return make_error!("K-mer length should be between 1 and {MAX_KMER_LENGTH}, but got {kmer_length}");
+    }
+
+    let kmers: HashSet<u64> = canonical_kmers(ref_seq, kmer_length).into_iter().collect();
+    if kmers.is_empty() {
+      return make_error!(
+        "Unable to build k-mer sketch: reference sequence contains no k-mers of length {kmer_length} consisting entirely of A, C, G, T"
+      );
+    }
+
+    Ok(Self { kmer_length, kmers })
+  }
+
+  /// Calculates fraction of the query k-mers found in the sketch. Looks up at most `max_kmers` evenly spaced k-mers.
+  pub fn score(&self, qry_seq: &[Nuc], max_kmers: usize) -> f64 {
+    let qry_kmers = canonical_kmers(qry_seq, self.kmer_length);
+    if qry_kmers.is_empty() || max_kmers == 0 {
+      return 0.0;
+    }
+
+    let step = (qry_kmers.len() + max_kmers - 1) / max_kmers;
+    let (total, found) = qry_kmers
+      .iter()
+      .step_by(step)
+      .fold((0_usize, 0_usize), |(total, found), kmer| {
+        (total + 1, found + usize::from(self.kmers.contains(kmer)))
+      });
+
+    found as f64 / total as f64
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetMatch {
+  pub dataset_index: usize,
+  pub score: f64,
+}
+
+/// Finds the sketch which matches the query best. If several sketches have the same score, the first one wins.
+/// Returns `None` only if the list of sketches is empty.
+pub fn sketch_find_best_match(sketches: &[KmerSketch], qry_seq: &[Nuc], max_kmers: usize) -> Option<DatasetMatch> {
+  sketches
+    .iter()
+    .enumerate()
+    .map(|(dataset_index, sketch)| DatasetMatch {
+      dataset_index,
+      score: sketch.score(qry_seq, max_kmers),
+    })
+    .fold(None, |best: Option<DatasetMatch>, candidate| match best {
+      Some(best) if best.score >= candidate.score => Some(best),
+      _ => Some(candidate),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::to_nuc_seq;
+  use crate::translate::complement::reverse_complement_in_place;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  fn produces_same_kmers_for_reverse_complement() -> Result<(), Report> {
+    let seq = to_nuc_seq("ACGTTGCAAGGCTTAC")?;
+    let mut seq_rev = seq.clone();
+    reverse_complement_in_place(&mut seq_rev);
+
+    let mut kmers = canonical_kmers(&seq, 5);
+    let mut kmers_rev = canonical_kmers(&seq_rev, 5);
+    kmers.sort_unstable();
+    kmers_rev.sort_unstable();
+
+    assert_eq!(kmers, kmers_rev);
+    Ok(())
+  }
+
+  #[rstest]
+  fn interrupts_kmers_at_ambiguous_nucleotides() -> Result<(), Report> {
+    let seq = to_nuc_seq("ACGTNACGTA")?;
+    assert_eq!(canonical_kmers(&seq, 4).len(), 3);
+    Ok(())
+  }
+
+  #[rstest]
+  #[allow(clippy::float_cmp)]
+  fn finds_best_matching_sketch() -> Result<(), Report> {
+    let ref_a = to_nuc_seq("ACGTTGCAAGGCTTACCGATCGGATTACAGGCATTGACCA")?;
+    let ref_b = to_nuc_seq("TTTGGGCCCAAATCGATCGGGCTAGCTAGGACTTTACGAG")?;
+    let sketches = vec![KmerSketch::new(&ref_a, 8)?, KmerSketch::new(&ref_b, 8)?];
+
+    let qry = to_nuc_seq("CGGGCTAGCTAGGACTTTAC")?;
+    let best = sketch_find_best_match(&sketches, &qry, 1000).unwrap();
+
+    assert_eq!(best.dataset_index, 1);
+    assert_eq!(best.score, 1.0);
+    Ok(())
+  }
+}
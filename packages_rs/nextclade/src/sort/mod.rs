@@ -0,0 +1,2 @@
+pub mod kmer_sketch;
+pub mod params;
@@ -0,0 +1,31 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Parameters of matching query sequences to datasets, in multi-dataset mode
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetSketchParams {
+  /// Length of k-mers used to match query sequences to datasets in multi-dataset mode.
+  ///
+  /// Only k-mers consisting entirely of canonical nucleotides (A, C, G, T) are considered. Must be between 1 and 32.
+  #[clap(long, default_value_t = DatasetSketchParams::default().dataset_kmer_length)]
+  pub dataset_kmer_length: usize,
+
+  /// Maximum number of query k-mers to look up in the sketch of each dataset. If a query contains more k-mers, then an evenly spaced subset of them is used.
+  #[clap(long, default_value_t = DatasetSketchParams::default().dataset_max_kmers)]
+  pub dataset_max_kmers: usize,
+
+  /// Minimum score (a fraction of query k-mers found in the dataset reference sequence) required to assign a query sequence to a dataset. Sequences which score lower against every dataset are reported as errors.
+  #[clap(long, default_value_t = DatasetSketchParams::default().dataset_min_score)]
+  pub dataset_min_score: f64,
+}
+
+impl Default for DatasetSketchParams {
+  fn default() -> Self {
+    Self {
+      dataset_kmer_length: 15,
+      dataset_max_kmers: 1000,
+      dataset_min_score: 0.1,
+    }
+  }
+}
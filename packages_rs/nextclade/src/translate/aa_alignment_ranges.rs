@@ -1,3 +1,4 @@
+use crate::gene::gene::GeneStrand;
 use crate::io::gene_map::GeneMap;
 use crate::make_internal_report;
 use crate::translate::coord_map::CoordMap;
@@ -29,12 +30,35 @@ pub fn calculate_aa_alignment_range_in_place(
     )
   })?;
 
-  let gene_range_aln = coord_map.ref_to_aln_range(&Range::new(gene.start, gene.end));
-  let sequenced_gene_range_aln_abs = intersect(&gene_range_aln, alignment_range);
+  // Find the sequenced part of the gene, relative to the aligned gene (concatenation of aligned coding segments)
+  let mut sequenced_gene_range_aln_rel: Option<Range> = None;
+  let mut segment_offset = 0;
+  for segment in &gene.cds_segments {
+    let segment_range_aln = coord_map.ref_to_aln_range(segment);
+    let sequenced_segment_range_aln_abs = intersect(&segment_range_aln, alignment_range);
 
-  let gene_start_aln = coord_map.ref_to_aln_position(gene.start);
-  let sequenced_gene_range_aln_rel = sequenced_gene_range_aln_abs - gene_start_aln;
+    if !sequenced_segment_range_aln_abs.is_empty() {
+      let sequenced_segment_range_aln_rel = if gene.strand == GeneStrand::Reverse {
+        Range::new(
+          segment_offset + segment_range_aln.end - sequenced_segment_range_aln_abs.end,
+          segment_offset + segment_range_aln.end - sequenced_segment_range_aln_abs.begin,
+        )
+      } else {
+        &sequenced_segment_range_aln_abs - segment_range_aln.begin + segment_offset
+      };
 
-  translation.alignment_range = gene.nuc_to_codon_range(&sequenced_gene_range_aln_rel);
+      sequenced_gene_range_aln_rel = Some(match sequenced_gene_range_aln_rel {
+        None => sequenced_segment_range_aln_rel,
+        Some(range) => Range::new(
+          range.begin.min(sequenced_segment_range_aln_rel.begin),
+          range.end.max(sequenced_segment_range_aln_rel.end),
+        ),
+      });
+    }
+
+    segment_offset += segment_range_aln.len();
+  }
+
+  translation.alignment_range = gene.nuc_to_codon_range(&sequenced_gene_range_aln_rel.unwrap_or_default());
   Ok(())
 }
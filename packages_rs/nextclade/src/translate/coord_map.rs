@@ -78,22 +78,41 @@ impl CoordMap {
     self.ref_to_aln_table[reff]
   }
 
+  /// Finds the coding segment of a feature (e.g. gene) which contains a given position relative to the aligned feature,
+  /// i.e. relative to the concatenation of the aligned coding segments. Returns index of the segment and the
+  /// corresponding absolute position in the alignment.
+  fn feature_aln_find_cds_segment(&self, feature: &Gene, aln_pos_rel: usize) -> (usize, usize) {
+    let last = feature.cds_segments.len().saturating_sub(1);
+    let mut offset = aln_pos_rel;
+    for (index, segment) in feature.cds_segments.iter().enumerate() {
+      let segment_aln = self.ref_to_aln_range(segment);
+      if offset < segment_aln.len() || index == last {
+        let aln_pos = if feature.strand == GeneStrand::Reverse {
+          segment_aln.end - 1 - offset // segment end points to the nuc after the segment, hence - 1
+        } else {
+          segment_aln.begin + offset
+        };
+        return (index, aln_pos);
+      }
+      offset -= segment_aln.len();
+    }
+    (last, offset)
+  }
+
   /// Converts relative position inside an aligned feature (e.g. gene) to absolute position in the reference
   pub fn feature_aln_to_ref_position(&self, feature: &Gene, aln_pos_rel: usize) -> usize {
-    let aln_pos = if feature.strand == GeneStrand::Reverse {
-      self.ref_to_aln_position(feature.end - 1) - aln_pos_rel //feature.end points to the nuc after the feature, hence - 1
-    } else {
-      self.ref_to_aln_position(feature.start) + aln_pos_rel
-    };
+    let (_, aln_pos) = self.feature_aln_find_cds_segment(feature, aln_pos_rel);
     self.aln_to_ref_position(aln_pos)
   }
 
   /// Converts relative position inside a feature (e.g. gene) to absolute position in the alignment
   pub fn feature_ref_to_aln_position(&self, feature: &Gene, ref_pos_rel: usize) -> usize {
+    let (segment_index, pos) = feature.find_cds_segment(ref_pos_rel);
+    let segment = &feature.cds_segments[segment_index];
     let ref_pos = if feature.strand == GeneStrand::Reverse {
-      feature.end - 1 - ref_pos_rel // the feature end is one past the last character, hence -1
+      segment.end - 1 - pos // the segment end is one past the last character, hence -1
     } else {
-      feature.start + ref_pos_rel
+      segment.begin + pos
     };
     self.ref_to_aln_position(ref_pos)
   }
@@ -127,18 +146,22 @@ impl CoordMap {
   }
 
   pub fn feature_ref_to_aln_range(&self, feature: &Gene, ref_range: &Range) -> Range {
-    Range {
-      begin: self.feature_ref_to_aln_position(feature, ref_range.begin),
-      end: self.feature_ref_to_aln_position(feature, ref_range.end - 1) + 1,
+    if feature.strand == GeneStrand::Reverse {
+      Range {
+        begin: self.feature_ref_to_aln_position(feature, ref_range.end - 1),
+        end: self.feature_ref_to_aln_position(feature, ref_range.begin) + 1,
+      }
+    } else {
+      Range {
+        begin: self.feature_ref_to_aln_position(feature, ref_range.begin),
+        end: self.feature_ref_to_aln_position(feature, ref_range.end - 1) + 1,
+      }
     }
   }
 
   pub fn feature_aln_to_feature_ref_position(&self, feature: &Gene, aln_position: usize) -> usize {
-    if feature.strand == GeneStrand::Reverse {
-      feature.end - 1 - self.feature_aln_to_ref_position(feature, aln_position)
-    } else {
-      self.feature_aln_to_ref_position(feature, aln_position) - feature.start
-    }
+    let (segment_index, aln_pos) = self.feature_aln_find_cds_segment(feature, aln_position);
+    feature.nuc_abs_to_rel(segment_index, self.aln_to_ref_position(aln_pos))
   }
 
   pub fn feature_aln_to_feature_ref_range(&self, feature: &Gene, aln_range: &Range) -> Range {
@@ -148,23 +171,26 @@ impl CoordMap {
     }
   }
 
-  /// Extracts nucleotide sequence of a gene
+  /// Extracts nucleotide sequence of a gene. For genes consisting of multiple coding segments, the segments are
+  /// concatenated in the order of translation.
   pub fn extract_gene(&self, full_aln_seq: &[Nuc], gene: &Gene) -> Vec<Nuc> {
-    let &Gene { start, end, .. } = gene;
-
-    // Gene map contains gene range in reference coordinates (like in ref sequence)
-    let gene_range_ref = Range { begin: start, end };
-
-    // ...but we are extracting from aligned sequence, so we need to convert it to alignment coordinates (like in aligned sequences)
-    let gene_range_aln = self.ref_to_aln_range(&gene_range_ref);
-    let mut gene_nucs = full_aln_seq[StdRange::from(gene_range_aln)].to_vec();
-
-    // Reverse strands should be reverse-complemented
-    if gene.strand == GeneStrand::Reverse {
-      reverse_complement_in_place(&mut gene_nucs);
-    }
-
-    gene_nucs
+    gene
+      .cds_segments
+      .iter()
+      .flat_map(|segment| {
+        // Gene map contains segment ranges in reference coordinates (like in ref sequence)...
+        // ...but we are extracting from aligned sequence, so we need to convert it to alignment coordinates (like in aligned sequences)
+        let segment_range_aln = self.ref_to_aln_range(segment);
+        let mut segment_nucs = full_aln_seq[StdRange::from(segment_range_aln)].to_vec();
+
+        // Reverse strands should be reverse-complemented
+        if gene.strand == GeneStrand::Reverse {
+          reverse_complement_in_place(&mut segment_nucs);
+        }
+
+        segment_nucs
+      })
+      .collect()
   }
 }
 
@@ -271,6 +297,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Forward,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     // reference: ACT|CCGTGACCG|CGT
     // ref_aln: A--CT|CCGT---GACCG|--CGT
@@ -293,6 +321,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Reverse,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     // reference: ACT|CCGTGACCG|CGT
     // ref_aln: A--CT|CCGT---GACCG|--CGT
@@ -316,6 +346,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Forward,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     //                0..    |7
     // reference: ACT|CCGTGACCG|CGT
@@ -335,6 +367,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Reverse,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     //                 |7      |0
     // reference: ACT|CCGTGACCG|CGT
@@ -354,6 +388,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Forward,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     //               |3    |8
     // reference: ACT|CCGTGACCG|CGT
@@ -373,6 +409,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Reverse,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     //               |3 |5
     // reference: ACT|CCGTGACCG|CGT
@@ -392,6 +430,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Forward,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     //               |   |3 |6
     // reference: ACT|CCGTGACCG|CGT
@@ -414,6 +454,8 @@ mod coord_map_tests {
       end: 12,
       strand: GeneStrand::Reverse,
       frame: 0,
+      cds_segments: vec![Range::new(3, 12)],
+      genetic_code: None,
     };
     //               |   |6 |9
     // reference: ACT|CCGTGACCG|CGT
@@ -427,4 +469,31 @@ mod coord_map_tests {
     );
     Ok(())
   }
+
+  #[rstest]
+  fn extract_gene_multiple_cds_segments() -> Result<(), Report> {
+    let gene = Gene {
+      gene_name: "g1".to_owned(),
+      start: 1,
+      end: 10,
+      strand: GeneStrand::Forward,
+      frame: 0,
+      cds_segments: vec![Range::new(1, 4), Range::new(7, 10)],
+      genetic_code: None,
+    };
+    // reference: A|CTC|CGT|GAC|CGCGT
+    //             |1  |4  |7  |10
+    // ref_aln: A--|CTC|CGT---|GAC|CG--CGT
+    //             |3  |6     |12 |15
+    // qry_aln: ACG|CTC|CGTGCG|G--|CGTGCGT
+
+    let coord_map = CoordMap::new(&to_nuc_seq("A--CTCCGT---GACCG--CGT")?);
+    assert_eq!(
+      from_nuc_seq(&coord_map.extract_gene(&to_nuc_seq("ACGCTCCGTGCGG--CGTGCGT")?, &gene)),
+      "CTCG--"
+    );
+    assert_eq!(coord_map.feature_ref_to_aln_position(&gene, 3), 12);
+    assert_eq!(coord_map.feature_aln_to_feature_ref_position(&gene, 4), 4);
+    Ok(())
+  }
 }
@@ -0,0 +1,255 @@
+use crate::gene::gene::Gene;
+use crate::io::aa::{to_aa, Aa};
+use crate::io::letter::Letter;
+use crate::io::nuc::Nuc;
+use crate::make_error;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+
+/// Identifier of the standard genetic code in the numbering of NCBI translation tables
+pub const GENETIC_CODE_STANDARD: u8 = 1;
+
+// NCBI translation tables: id, name, aminoacids and start codons, in the order of codons TTT, TTC, TTA, TTG, TCT, ...
+// (bases in the order T, C, A, G). Start codons are marked with `M`. Tables with stop codons which can be translated
+// to aminoacids depending on context (27, 28, 31) are not supported.
+//
+// See: https://www.ncbi.nlm.nih.gov/Taxonomy/Utils/wprintgc.cgi
+#[rustfmt::skip]
+const NCBI_TRANSLATION_TABLES: &[(u8, &str, &str, &str)] = &[
+  (1, "Standard", "FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "---M------**--*----M---------------M----------------------------"),
+  (2, "Vertebrate Mitochondrial", "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG", "----------**--------------------MMMM----------**---M------------"),
+  (3, "Yeast Mitochondrial", "FFLLSSSSYY**CCWWTTTTPPPPHHQQRRRRIIMMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "----------**----------------------MM---------------M------------"),
+  (4, "Mold, Protozoan, and Coelenterate Mitochondrial and Mycoplasma/Spiroplasma", "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "--MM------**-------M------------MMMM---------------M------------"),
+  (5, "Invertebrate Mitochondrial", "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG", "---M------**--------------------MMMM---------------M------------"),
+  (6, "Ciliate, Dasycladacean and Hexamita Nuclear", "FFLLSSSSYYQQCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "--------------*--------------------M----------------------------"),
+  (9, "Echinoderm and Flatworm Mitochondrial", "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG", "----------**-----------------------M---------------M------------"),
+  (10, "Euplotid Nuclear", "FFLLSSSSYY**CCCWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "----------**-----------------------M----------------------------"),
+  (11, "Bacterial, Archaeal and Plant Plastid", "FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "---M------**--*----M------------MMMM---------------M------------"),
+  (12, "Alternative Yeast Nuclear", "FFLLSSSSYY**CC*WLLLSPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "----------**--*----M---------------M----------------------------"),
+  (13, "Ascidian Mitochondrial", "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSGGVVVVAAAADDEEGGGG", "---M------**----------------------MM---------------M------------"),
+  (14, "Alternative Flatworm Mitochondrial", "FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG", "-----------*-----------------------M----------------------------"),
+  (16, "Chlorophycean Mitochondrial", "FFLLSSSSYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "----------*---*--------------------M----------------------------"),
+  (21, "Trematode Mitochondrial", "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNNKSSSSVVVVAAAADDEEGGGG", "----------**-----------------------M---------------M------------"),
+  (22, "Scenedesmus obliquus Mitochondrial", "FFLLSS*SYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "------*---*---*--------------------M----------------------------"),
+  (23, "Thraustochytrium Mitochondrial", "FF*LSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "--*-------**--*-----------------M--M---------------M------------"),
+  (24, "Rhabdopleuridae Mitochondrial", "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG", "---M------**-------M---------------M---------------M------------"),
+  (25, "Candidate Division SR1 and Gracilibacteria", "FFLLSSSSYY**CCGWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "---M------**-----------------------M---------------M------------"),
+  (26, "Pachysolen tannophilus Nuclear", "FFLLSSSSYY**CC*WLLLAPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "----------**--*----M---------------M----------------------------"),
+  (29, "Mesodinium Nuclear", "FFLLSSSSYYYYCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "--------------*--------------------M----------------------------"),
+  (30, "Peritrich Nuclear", "FFLLSSSSYYEECC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", "--------------*--------------------M----------------------------"),
+  (33, "Cephalodiscidae Mitochondrial", "FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG", "---M-------*-------M---------------M---------------M------------"),
+];
+
+lazy_static! {
+  static ref GENETIC_CODES: BTreeMap<u8, GeneticCode> = NCBI_TRANSLATION_TABLES
+    .iter()
+    .map(|&(id, name, aminoacids, starts)| {
+      let genetic_code = GeneticCode::new(id, name, aminoacids, starts)
+        .wrap_err_with(|| format!("When parsing genetic code {id} ('{name}')"))
+        .unwrap();
+      (id, genetic_code)
+    })
+    .collect();
+}
+
+/// Genetic code: translation of codons to aminoacids, along with the set of codons that can act as start codons
+#[derive(Clone, Debug)]
+pub struct GeneticCode {
+  pub id: u8,
+  pub name: &'static str,
+  aminoacids: [Aa; 64],
+  starts: [bool; 64],
+}
+
+impl GeneticCode {
+  fn new(id: u8, name: &'static str, aminoacids: &str, starts: &str) -> Result<Self, Report> {
+    if aminoacids.len() != 64 || starts.len() != 64 {
+      return make_error!("Genetic code should contain exactly 64 codons");
+    }
+
+    let mut table = Self {
+      id,
+      name,
+      aminoacids: [Aa::X; 64],
+      starts: [false; 64],
+    };
+
+    for (i, (aa, start)) in aminoacids.chars().zip(starts.chars()).enumerate() {
+      table.aminoacids[i] = to_aa(aa)?;
+      table.starts[i] = start == 'M';
+    }
+
+    Ok(table)
+  }
+
+  /// Finds genetic code by its identifier in the numbering of NCBI translation tables
+  pub fn from_id(id: u8) -> Result<&'static Self, Report> {
+    match GENETIC_CODES.get(&id) {
+      Some(genetic_code) => Ok(genetic_code),
+      None => {
+        let known = GENETIC_CODES.keys().join(", ");
+        make_error!("Unknown genetic code: {id}. Known genetic codes (NCBI translation tables) are: {known}")
+      }
+    }
+  }
+
+  /// Standard genetic code (NCBI translation table 1)
+  pub fn standard() -> &'static Self {
+    &GENETIC_CODES[&GENETIC_CODE_STANDARD]
+  }
+
+  /// Finds genetic code of a gene: the one specified in the gene map, if any, or the default one otherwise
+  pub fn for_gene<'a>(gene: &Gene, default: &'a Self) -> Result<&'a Self, Report> {
+    match gene.genetic_code {
+      Some(id) => Self::from_id(id).wrap_err_with(|| format!("When processing gene '{}'", gene.gene_name)),
+      None => Ok(default),
+    }
+  }
+
+  /// Translates a codon into aminoacid.
+  ///
+  /// A codon containing ambiguous nucleotides is translated into a concrete aminoacid if all of the codons it stands
+  /// for encode the same aminoacid (e.g. `GGN` encodes glycine), and into `X` otherwise.
+  pub fn decode(&self, triplet: &[Nuc]) -> Aa {
+    if triplet.iter().all(Nuc::is_gap) {
+      return Aa::Gap;
+    }
+
+    if let Some(i) = codon_index(triplet) {
+      return self.aminoacids[i];
+    }
+
+    let mut aminoacids = expand_codon(triplet).map(|i| self.aminoacids[i]);
+    match aminoacids.next() {
+      Some(first) if aminoacids.all(|aa| aa == first) => first,
+      _ => Aa::X,
+    }
+  }
+
+  /// Finds all aminoacids which a codon containing ambiguous nucleotides can encode. Returns an empty list if the codon
+  /// contains gaps.
+  pub fn decode_possible(&self, triplet: &[Nuc]) -> Vec<Aa> {
+    expand_codon(triplet)
+      .map(|i| self.aminoacids[i])
+      .sorted()
+      .dedup()
+      .collect_vec()
+  }
+
+  /// Translates the first codon of a coding sequence. Alternative start codons are translated into methionine.
+  pub fn decode_start(&self, triplet: &[Nuc]) -> Aa {
+    if self.is_start(triplet) {
+      Aa::M
+    } else {
+      self.decode(triplet)
+    }
+  }
+
+  /// Checks whether a codon is an alternative start codon, i.e. a start codon which encodes an aminoacid other than
+  /// methionine when not at the start of a coding sequence (e.g. `CTG` in the standard genetic code).
+  pub fn is_alternative_start(&self, triplet: &[Nuc]) -> bool {
+    self.is_start(triplet) && self.decode(triplet) != Aa::M
+  }
+
+  /// Checks whether a codon can act as a start codon. A codon containing ambiguous nucleotides is a start codon if all
+  /// of the codons it stands for are start codons.
+  pub fn is_start(&self, triplet: &[Nuc]) -> bool {
+    let mut codons = expand_codon(triplet).peekable();
+    codons.peek().is_some() && codons.all(|i| self.starts[i])
+  }
+}
+
+/// Indices of all codons which a codon containing ambiguous nucleotides stands for
+fn expand_codon(triplet: &[Nuc]) -> impl Iterator<Item = usize> + '_ {
+  triplet
+    .iter()
+    .map(|nuc| nuc.to_acgt().iter().copied())
+    .multi_cartesian_product()
+    .filter_map(|codon| codon_index(&codon))
+}
+
+/// Index of a codon in translation tables. Only codons consisting of canonical nucleotides have an index.
+fn codon_index(triplet: &[Nuc]) -> Option<usize> {
+  triplet.iter().try_fold(0, |index, nuc| {
+    let base = match nuc {
+      Nuc::T => 0,
+      Nuc::C => 1,
+      Nuc::A => 2,
+      Nuc::G => 3,
+      _ => return None,
+    };
+    Some(index * 4 + base)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::to_nuc_seq;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(1, "TGA", Aa::Stop)]
+  #[case(1, "ATA", Aa::I)]
+  #[case(2, "TGA", Aa::W)]
+  #[case(2, "AGA", Aa::Stop)]
+  #[case(2, "ATA", Aa::M)]
+  #[case(4, "TGA", Aa::W)]
+  #[case(1, "---", Aa::Gap)]
+  #[case(1, "ANA", Aa::X)]
+  #[case(1, "GGN", Aa::G)]
+  #[case(1, "YTA", Aa::L)]
+  #[case(1, "TTR", Aa::L)]
+  #[case(1, "TRA", Aa::Stop)]
+  #[case(1, "A-A", Aa::X)]
+  fn decodes_codons(#[case] id: u8, #[case] codon: &str, #[case] expected: Aa) -> Result<(), Report> {
+    let genetic_code = GeneticCode::from_id(id)?;
+    assert_eq!(genetic_code.decode(&to_nuc_seq(codon)?), expected);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(1, "CTG", Aa::M)]
+  #[case(1, "GTG", Aa::V)]
+  #[case(11, "GTG", Aa::M)]
+  #[case(2, "ATT", Aa::M)]
+  fn decodes_start_codons(#[case] id: u8, #[case] codon: &str, #[case] expected: Aa) -> Result<(), Report> {
+    let genetic_code = GeneticCode::from_id(id)?;
+    assert_eq!(genetic_code.decode_start(&to_nuc_seq(codon)?), expected);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(1, "ATG", false)]
+  #[case(1, "CTG", true)]
+  #[case(1, "GTG", false)]
+  #[case(11, "GTG", true)]
+  #[case(1, "CTN", false)]
+  fn detects_alternative_start_codons(
+    #[case] id: u8,
+    #[case] codon: &str,
+    #[case] expected: bool,
+  ) -> Result<(), Report> {
+    let genetic_code = GeneticCode::from_id(id)?;
+    assert_eq!(genetic_code.is_alternative_start(&to_nuc_seq(codon)?), expected);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("TTY", vec![Aa::F])]
+  #[case("TTN", vec![Aa::F, Aa::L])]
+  #[case("GAS", vec![Aa::D, Aa::E])]
+  #[case("TG-", vec![])]
+  fn decodes_possible_aminoacids(#[case] codon: &str, #[case] expected: Vec<Aa>) -> Result<(), Report> {
+    assert_eq!(GeneticCode::standard().decode_possible(&to_nuc_seq(codon)?), expected);
+    Ok(())
+  }
+
+  #[rstest]
+  fn rejects_unknown_genetic_code() {
+    assert!(GeneticCode::from_id(7).is_err());
+  }
+}
@@ -4,6 +4,7 @@ pub mod coord_map;
 pub mod frame_shifts_detect;
 pub mod frame_shifts_flatten;
 pub mod frame_shifts_translate;
+pub mod genetic_code;
 pub mod translate;
 pub mod translate_genes;
 pub mod translate_genes_ref;
@@ -3,84 +3,22 @@ use crate::io::aa::Aa;
 use crate::io::nuc::Nuc;
 
 use crate::align::params::AlignPairwiseParams;
+use crate::translate::genetic_code::GeneticCode;
 use crate::translate::translate_genes::Translation;
 use crate::utils::range::Range;
 use eyre::Report;
 
-pub const fn decode(triplet: &[Nuc]) -> Aa {
-  match *triplet {
-    [Nuc::Gap, Nuc::Gap, Nuc::Gap] => Aa::Gap,
-    [Nuc::A, Nuc::A, Nuc::A] => Aa::K,
-    [Nuc::A, Nuc::A, Nuc::C] => Aa::N,
-    [Nuc::A, Nuc::A, Nuc::G] => Aa::K,
-    [Nuc::A, Nuc::A, Nuc::T] => Aa::N,
-    [Nuc::A, Nuc::C, Nuc::A] => Aa::T,
-    [Nuc::A, Nuc::C, Nuc::C] => Aa::T,
-    [Nuc::A, Nuc::C, Nuc::G] => Aa::T,
-    [Nuc::A, Nuc::C, Nuc::T] => Aa::T,
-    [Nuc::A, Nuc::G, Nuc::A] => Aa::R,
-    [Nuc::A, Nuc::G, Nuc::C] => Aa::S,
-    [Nuc::A, Nuc::G, Nuc::G] => Aa::R,
-    [Nuc::A, Nuc::G, Nuc::T] => Aa::S,
-    [Nuc::A, Nuc::T, Nuc::A] => Aa::I,
-    [Nuc::A, Nuc::T, Nuc::C] => Aa::I,
-    [Nuc::A, Nuc::T, Nuc::G] => Aa::M,
-    [Nuc::A, Nuc::T, Nuc::T] => Aa::I,
-    [Nuc::C, Nuc::A, Nuc::A] => Aa::Q,
-    [Nuc::C, Nuc::A, Nuc::C] => Aa::H,
-    [Nuc::C, Nuc::A, Nuc::G] => Aa::Q,
-    [Nuc::C, Nuc::A, Nuc::T] => Aa::H,
-    [Nuc::C, Nuc::C, Nuc::A] => Aa::P,
-    [Nuc::C, Nuc::C, Nuc::C] => Aa::P,
-    [Nuc::C, Nuc::C, Nuc::G] => Aa::P,
-    [Nuc::C, Nuc::C, Nuc::T] => Aa::P,
-    [Nuc::C, Nuc::G, Nuc::A] => Aa::R,
-    [Nuc::C, Nuc::G, Nuc::C] => Aa::R,
-    [Nuc::C, Nuc::G, Nuc::G] => Aa::R,
-    [Nuc::C, Nuc::G, Nuc::T] => Aa::R,
-    [Nuc::C, Nuc::T, Nuc::A] => Aa::L,
-    [Nuc::C, Nuc::T, Nuc::C] => Aa::L,
-    [Nuc::C, Nuc::T, Nuc::G] => Aa::L,
-    [Nuc::C, Nuc::T, Nuc::T] => Aa::L,
-    [Nuc::G, Nuc::A, Nuc::A] => Aa::E,
-    [Nuc::G, Nuc::A, Nuc::C] => Aa::D,
-    [Nuc::G, Nuc::A, Nuc::G] => Aa::E,
-    [Nuc::G, Nuc::A, Nuc::T] => Aa::D,
-    [Nuc::G, Nuc::C, Nuc::A] => Aa::A,
-    [Nuc::G, Nuc::C, Nuc::C] => Aa::A,
-    [Nuc::G, Nuc::C, Nuc::G] => Aa::A,
-    [Nuc::G, Nuc::C, Nuc::T] => Aa::A,
-    [Nuc::G, Nuc::G, Nuc::A] => Aa::G,
-    [Nuc::G, Nuc::G, Nuc::C] => Aa::G,
-    [Nuc::G, Nuc::G, Nuc::G] => Aa::G,
-    [Nuc::G, Nuc::G, Nuc::T] => Aa::G,
-    [Nuc::G, Nuc::T, Nuc::A] => Aa::V,
-    [Nuc::G, Nuc::T, Nuc::C] => Aa::V,
-    [Nuc::G, Nuc::T, Nuc::G] => Aa::V,
-    [Nuc::G, Nuc::T, Nuc::T] => Aa::V,
-    [Nuc::T, Nuc::A, Nuc::A] => Aa::Stop,
-    [Nuc::T, Nuc::A, Nuc::C] => Aa::Y,
-    [Nuc::T, Nuc::A, Nuc::G] => Aa::Stop,
-    [Nuc::T, Nuc::A, Nuc::T] => Aa::Y,
-    [Nuc::T, Nuc::C, Nuc::A] => Aa::S,
-    [Nuc::T, Nuc::C, Nuc::C] => Aa::S,
-    [Nuc::T, Nuc::C, Nuc::G] => Aa::S,
-    [Nuc::T, Nuc::C, Nuc::T] => Aa::S,
-    [Nuc::T, Nuc::G, Nuc::A] => Aa::Stop,
-    [Nuc::T, Nuc::G, Nuc::C] => Aa::C,
-    [Nuc::T, Nuc::G, Nuc::G] => Aa::W,
-    [Nuc::T, Nuc::G, Nuc::T] => Aa::C,
-    [Nuc::T, Nuc::T, Nuc::A] => Aa::L,
-    [Nuc::T, Nuc::T, Nuc::C] => Aa::F,
-    [Nuc::T, Nuc::T, Nuc::G] => Aa::L,
-    [Nuc::T, Nuc::T, Nuc::T] => Aa::F,
-    _ => Aa::X,
-  }
-}
-
-/// Translates a nucleotide sequence of a gene into the corresponding aminoacid sequence (peptide)
+/// Translates a nucleotide sequence of a gene into the corresponding aminoacid sequence (peptide), using a given
+/// genetic code. If `has_start_codon` is set, the first codon of the sequence is translated as a start codon, i.e.
+/// alternative start codons of the genetic code are translated into methionine.
 /// NOTE: we accept gene sequence by value here to avoid copying (it should be moved) and then process it in-place
-pub fn translate(gene_nuc_seq: &[Nuc], gene: &Gene, params: &AlignPairwiseParams) -> Result<Translation, Report> {
+pub fn translate(
+  gene_nuc_seq: &[Nuc],
+  gene: &Gene,
+  genetic_code: &GeneticCode,
+  has_start_codon: bool,
+  params: &AlignPairwiseParams,
+) -> Result<Translation, Report> {
   // NOTE: rounds the result to the multiple of 3 (floor) so that translation does not overrun the buffer
   let peptide_length = gene_nuc_seq.len() / 3;
 
@@ -88,7 +26,11 @@ pub fn translate(gene_nuc_seq: &[Nuc], gene: &Gene, params: &AlignPairwiseParams
   for i_aa in 0..peptide_length {
     let i_nuc = i_aa * 3;
     let triplet: &[Nuc] = &gene_nuc_seq[i_nuc..(i_nuc + 3)];
-    let aminoacid = decode(triplet);
+    let aminoacid = if i_aa == 0 && has_start_codon {
+      genetic_code.decode_start(triplet)
+    } else {
+      genetic_code.decode(triplet)
+    };
     peptide.push(aminoacid);
     if params.no_translate_past_stop && aminoacid == Aa::Stop {
       break;
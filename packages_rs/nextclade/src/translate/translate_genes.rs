@@ -11,6 +11,7 @@ use crate::io::nuc::Nuc;
 use crate::translate::coord_map::CoordMap;
 use crate::translate::frame_shifts_detect::frame_shifts_detect;
 use crate::translate::frame_shifts_translate::{frame_shifts_transform_coordinates, FrameShift};
+use crate::translate::genetic_code::GeneticCode;
 use crate::translate::translate::translate;
 use crate::utils::collections::{first, last};
 use crate::utils::range::Range;
@@ -124,6 +125,7 @@ pub fn translate_gene(
   ref_peptide: &Translation,
   gap_open_close_aa: &[i32],
   coord_map: &CoordMap,
+  genetic_code: &GeneticCode,
   params: &AlignPairwiseParams,
 ) -> Result<Translation, Report> {
   let mut ref_gene_seq = coord_map.extract_gene(ref_seq, gene);
@@ -145,13 +147,24 @@ pub fn translate_gene(
     // Set to false for internal genes
     left_terminal_gaps_free: first(&qry_gene_seq)?.is_gap(),
     right_terminal_gaps_free: last(&qry_gene_seq)?.is_gap(),
-    ..*params
+    ..params.clone()
   };
 
   // Make sure subsequent gap stripping does not introduce frame shift
   protect_first_codon_in_place(&mut ref_gene_seq);
   protect_first_codon_in_place(&mut qry_gene_seq);
 
+  // After the first codon is protected, the query contains the start codon of the gene if and only if the first
+  // nucleotide is not a gap. Otherwise the beginning of the gene is not sequenced or is deleted.
+  //
+  // The start codon is translated as such only if the reference gene itself starts with an alternative start codon.
+  // Otherwise alternative start codons in the query would be translated into methionine, hiding mutations of the start
+  // codon (e.g. `ATG` to `CTG` would not be reported as `M1L` in the standard genetic code).
+  let has_start_codon = !first(&qry_gene_seq)?.is_gap()
+    && ref_gene_seq
+      .get(0..3)
+      .map_or(false, |ref_codon| genetic_code.is_alternative_start(ref_codon));
+
   // NOTE: frame shift detection should be performed on unstripped genes
   let nuc_rel_frame_shifts = frame_shifts_detect(&qry_gene_seq, &ref_gene_seq);
   let frame_shifts = frame_shifts_transform_coordinates(&nuc_rel_frame_shifts, &qry_gene_seq, coord_map, gene);
@@ -161,7 +174,7 @@ pub fn translate_gene(
   // Strip all GAP characters to "forget" gaps introduced during alignment
   remove_gaps_in_place(&mut qry_gene_seq);
 
-  let query_peptide = translate(&qry_gene_seq, gene, params)?;
+  let query_peptide = translate(&qry_gene_seq, gene, genetic_code, has_start_codon, params)?;
 
   // Instead of performing seed matching, like we do for nucleotide alignment, here we estimate parameters
   // by counting gaps in the aligned nucleotide sequences;
@@ -189,7 +202,8 @@ pub fn translate_gene(
   })
 }
 
-/// Translates all requested genes
+/// Translates all requested genes. Genes which have no genetic code specified in the gene map are translated using the
+/// default genetic code.
 ///
 /// NOTE: we handle translation errors as warnings, so we return a collection of `Results` as is, to handle elsewhere
 pub fn translate_genes(
@@ -199,6 +213,7 @@ pub fn translate_genes(
   gene_map: &GeneMap,
   coord_map: &CoordMap,
   gap_open_close_aa: &[i32],
+  genetic_code: &GeneticCode,
   params: &AlignPairwiseParams,
 ) -> Result<IndexMap<String, Result<Translation, Report>>, Report> {
   gene_map
@@ -210,18 +225,76 @@ pub fn translate_genes(
           &gene.gene_name
         ))?;
 
-        let res = translate_gene(
-          qry_seq,
-          ref_seq,
-          gene,
-          ref_peptide,
-          gap_open_close_aa,
-          coord_map,
-          params,
-        );
+        let res = GeneticCode::for_gene(gene, genetic_code).and_then(|genetic_code| {
+          translate_gene(
+            qry_seq,
+            ref_seq,
+            gene,
+            ref_peptide,
+            gap_open_close_aa,
+            coord_map,
+            genetic_code,
+            params,
+          )
+        });
 
         Ok((gene_name.clone(), res))
       },
     )
     .collect::<Result<IndexMap<String, Result<Translation, Report>>, Report>>()
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::align::gap_open::get_gap_open_close_scores_flat;
+  use crate::gene::gene::GeneStrand;
+  use crate::io::aa::from_aa_seq;
+  use crate::io::nuc::to_nuc_seq;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case::start_codon_mutation(1, "ATGAAACCCTAA", "CTGAAACCCTAA", "LKP*")]
+  #[case::alternative_start_in_reference(1, "CTGAAACCCTAA", "TTGAAACCCTAA", "MKP*")]
+  #[case::alternative_start_in_other_code(11, "GTGAAACCCTAA", "ATTAAACCCTAA", "MKP*")]
+  #[case::alternative_start_in_query_only(11, "ATGAAACCCTAA", "GTGAAACCCTAA", "VKP*")]
+  fn translates_start_codon(
+    #[case] genetic_code: u8,
+    #[case] rf: &str,
+    #[case] qry: &str,
+    #[case] expected: &str,
+  ) -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(rf)?;
+    let qry_seq = to_nuc_seq(qry)?;
+    let gene = Gene {
+      gene_name: "g".to_owned(),
+      start: 0,
+      end: ref_seq.len(),
+      strand: GeneStrand::Forward,
+      frame: 0,
+      cds_segments: vec![Range::new(0, ref_seq.len())],
+      genetic_code: None,
+    };
+    let genetic_code = GeneticCode::from_id(genetic_code)?;
+    let params = AlignPairwiseParams::default();
+
+    let ref_peptide = translate(&ref_seq, &gene, genetic_code, true, &params)?;
+    let gap_open_close_aa = get_gap_open_close_scores_flat(&ref_seq, &params);
+    let coord_map = CoordMap::new(&ref_seq);
+
+    let translation = translate_gene(
+      &qry_seq,
+      &ref_seq,
+      &gene,
+      &ref_peptide,
+      &gap_open_close_aa,
+      &coord_map,
+      genetic_code,
+      &params,
+    )?;
+
+    assert_eq!(from_aa_seq(&translation.seq), expected);
+    Ok(())
+  }
+}
@@ -3,24 +3,36 @@ use crate::gene::gene::GeneStrand;
 use crate::io::gene_map::GeneMap;
 use crate::io::nuc::Nuc;
 use crate::translate::complement::reverse_complement_in_place;
+use crate::translate::genetic_code::GeneticCode;
 use crate::translate::translate::translate;
 use crate::translate::translate_genes::{Translation, TranslationMap};
 use eyre::Report;
+use itertools::Itertools;
 
-/// Translates genes in reference sequence
+/// Translates genes in reference sequence. Genes which have no genetic code specified in the gene map are translated
+/// using the default genetic code.
 pub fn translate_genes_ref(
   ref_seq: &[Nuc],
   gene_map: &GeneMap,
+  genetic_code: &GeneticCode,
   params: &AlignPairwiseParams,
 ) -> Result<TranslationMap, Report> {
   gene_map
     .iter()
     .map(|(gene_name, gene)| -> Result<(String, Translation), Report> {
-      let mut gene_nuc_seq = ref_seq[gene.start..gene.end].to_vec();
-      if gene.strand == GeneStrand::Reverse {
-        reverse_complement_in_place(&mut gene_nuc_seq);
-      }
-      let peptide = translate(&gene_nuc_seq, gene, params)?;
+      let gene_nuc_seq = gene
+        .cds_segments
+        .iter()
+        .flat_map(|segment| {
+          let mut segment_nucs = ref_seq[segment.begin..segment.end].to_vec();
+          if gene.strand == GeneStrand::Reverse {
+            reverse_complement_in_place(&mut segment_nucs);
+          }
+          segment_nucs
+        })
+        .collect_vec();
+      let genetic_code = GeneticCode::for_gene(gene, genetic_code)?;
+      let peptide = translate(&gene_nuc_seq, gene, genetic_code, true, params)?;
       Ok((gene_name.clone(), peptide))
     })
     .collect::<Result<TranslationMap, Report>>()
@@ -1,4 +1,12 @@
+pub mod node_mutations;
+pub mod params;
 pub mod tree;
+pub mod tree_assign_clades;
 pub mod tree_attach_new_nodes;
+pub mod tree_find_breakpoints;
 pub mod tree_find_nearest_node;
+pub mod tree_from_nwk;
+pub mod tree_placement_index;
+pub mod tree_placement_posteriors;
 pub mod tree_preprocess;
+pub mod tree_usher_mat;
@@ -0,0 +1,289 @@
+use crate::io::letter::Letter;
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter::FromIterator;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Maximum number of layers to walk up when looking up a mutation. When a chain grows longer than that, the new layer
+/// stores a flattened copy of all mutations instead, which bounds the lookup time at the cost of some memory.
+const MAX_CHAIN_LENGTH: usize = 32;
+
+/// Mutations of a reference tree node relative to the reference sequence (or reference peptide).
+///
+/// Storing a full map of mutations on every node makes memory grow as number of nodes times number of mutations
+/// accumulated from the root. Instead, every node only stores the changes on the branch leading to it, on top of the
+/// mutations of its parent, which are shared (not copied) between all its children.
+#[derive(Clone, Debug)]
+pub struct NodeMutations<L> {
+  layer: Option<Arc<Layer<L>>>,
+}
+
+#[derive(Debug)]
+struct Layer<L> {
+  parent: Option<Arc<Layer<L>>>,
+
+  /// Changes relative to the parent layer: new letter, or `None` if the position is reverted back to reference
+  changes: BTreeMap<usize, Option<L>>,
+
+  /// Number of layers in the chain, including this one
+  chain_length: usize,
+
+  /// Total number of mutations
+  len: usize,
+
+  /// Total number of mutations which are not gaps (i.e. substitutions)
+  len_substitutions: usize,
+}
+
+impl<L> Default for NodeMutations<L> {
+  fn default() -> Self {
+    Self { layer: None }
+  }
+}
+
+impl<L: Letter<L>> NodeMutations<L> {
+  /// Creates mutations of a child node, by applying changes on the branch to the mutations of this (parent) node.
+  /// Changes which do not change anything are ignored.
+  pub fn with_changes(&self, changes: BTreeMap<usize, Option<L>>) -> Self {
+    let changes: BTreeMap<usize, Option<L>> = changes
+      .into_iter()
+      .filter(|(pos, letter)| self.get(*pos) != *letter)
+      .collect();
+
+    if changes.is_empty() {
+      return self.clone();
+    }
+
+    let mut len = self.len();
+    let mut len_substitutions = self.len_substitutions();
+    for (pos, new) in &changes {
+      let old = self.get(*pos);
+      len = len + usize::from(new.is_some()) - usize::from(old.is_some());
+      len_substitutions = len_substitutions + usize::from(is_substitution(*new)) - usize::from(is_substitution(old));
+    }
+
+    let chain_length = self.layer.as_ref().map_or(0, |layer| layer.chain_length);
+    let layer = if chain_length < MAX_CHAIN_LENGTH {
+      Layer {
+        parent: self.layer.clone(),
+        changes,
+        chain_length: chain_length + 1,
+        len,
+        len_substitutions,
+      }
+    } else {
+      let mut flattened = self.to_map();
+      apply_changes(&mut flattened, &changes);
+      Layer {
+        parent: None,
+        changes: flattened.into_iter().map(|(pos, letter)| (pos, Some(letter))).collect(),
+        chain_length: 1,
+        len,
+        len_substitutions,
+      }
+    };
+
+    Self {
+      layer: Some(Arc::new(layer)),
+    }
+  }
+
+  /// Returns mutated letter at a given position, or `None` if the position is not mutated
+  pub fn get(&self, pos: usize) -> Option<L> {
+    let mut layer = self.layer.as_deref();
+    while let Some(current) = layer {
+      if let Some(letter) = current.changes.get(&pos) {
+        return *letter;
+      }
+      layer = current.parent.as_deref();
+    }
+    None
+  }
+
+  /// Returns substituted letter at a given position, or `None` if the position is not mutated or is deleted
+  pub fn get_substitution(&self, pos: usize) -> Option<L> {
+    self.get(pos).filter(|letter| !letter.is_gap())
+  }
+
+  pub fn len(&self) -> usize {
+    self.layer.as_ref().map_or(0, |layer| layer.len)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn len_substitutions(&self) -> usize {
+    self.layer.as_ref().map_or(0, |layer| layer.len_substitutions)
+  }
+
+  /// Reconstructs the full map of mutations
+  pub fn to_map(&self) -> BTreeMap<usize, L> {
+    let mut result = BTreeMap::new();
+    for layer in self.layers().into_iter().rev() {
+      apply_changes(&mut result, &layer.changes);
+    }
+    result
+  }
+
+  /// Iterates over substitutions (mutations which are not gaps), in no particular order, without reconstructing the
+  /// full map. A change is only yielded if it is not overridden by one of the layers above it.
+  pub fn iter_substitutions(&self) -> impl Iterator<Item = (usize, L)> + '_ {
+    let layers: Rc<[&Layer<L>]> = self.layers().into();
+    (0..layers.len()).flat_map(move |i| {
+      let layer = layers[i];
+      let layers = Rc::clone(&layers);
+      layer.changes.iter().filter_map(move |(pos, letter)| {
+        let letter = (*letter).filter(|letter| !letter.is_gap())?;
+        let is_overridden = layers[..i].iter().any(|above| above.changes.contains_key(pos));
+        (!is_overridden).then_some((*pos, letter))
+      })
+    })
+  }
+
+  /// Lists layers in the chain, starting from the topmost one (the one of this node)
+  fn layers(&self) -> Vec<&Layer<L>> {
+    let mut layers = vec![];
+    let mut layer = self.layer.as_deref();
+    while let Some(current) = layer {
+      layers.push(current);
+      layer = current.parent.as_deref();
+    }
+    layers
+  }
+
+  /// Reconstructs the full map of substitutions (mutations which are not gaps)
+  pub fn substitutions(&self) -> BTreeMap<usize, L> {
+    self
+      .to_map()
+      .into_iter()
+      .filter(|(_, letter)| !letter.is_gap())
+      .collect()
+  }
+
+  /// Returns positions at which mutations of this node differ from the mutations of the given (usually parent) node
+  pub fn changed_positions(&self, other: &Self) -> Vec<usize> {
+    match (&self.layer, &other.layer) {
+      (None, None) => vec![],
+      (Some(this), Some(that)) if Arc::ptr_eq(this, that) => vec![],
+      (Some(this), other_layer) if ptr_eq_opt(&this.parent, other_layer) => this.changes.keys().copied().collect_vec(),
+      _ => {
+        let this = self.to_map();
+        let other = other.to_map();
+        this
+          .keys()
+          .chain(other.keys())
+          .copied()
+          .collect::<BTreeSet<usize>>()
+          .into_iter()
+          .filter(|pos| this.get(pos) != other.get(pos))
+          .collect_vec()
+      }
+    }
+  }
+}
+
+impl<L: Letter<L>> FromIterator<(usize, L)> for NodeMutations<L> {
+  fn from_iter<I: IntoIterator<Item = (usize, L)>>(iter: I) -> Self {
+    let changes = iter.into_iter().map(|(pos, letter)| (pos, Some(letter))).collect();
+    Self::default().with_changes(changes)
+  }
+}
+
+fn is_substitution<L: Letter<L>>(letter: Option<L>) -> bool {
+  letter.map_or(false, |letter| !letter.is_gap())
+}
+
+fn apply_changes<L: Letter<L>>(map: &mut BTreeMap<usize, L>, changes: &BTreeMap<usize, Option<L>>) {
+  for (pos, letter) in changes {
+    match letter {
+      None => map.remove(pos),
+      Some(letter) => map.insert(*pos, *letter),
+    };
+  }
+}
+
+fn ptr_eq_opt<T>(a: &Option<Arc<T>>, b: &Option<Arc<T>>) -> bool {
+  match (a, b) {
+    (None, None) => true,
+    (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::Nuc;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  fn shares_parent_mutations() {
+    let root: NodeMutations<Nuc> = [(1, Nuc::A), (5, Nuc::Gap)].into_iter().collect();
+    let child = root.with_changes(BTreeMap::from([(1, None), (7, Some(Nuc::T)), (5, Some(Nuc::Gap))]));
+    let grandchild = child.with_changes(BTreeMap::from([(9, Some(Nuc::C))]));
+
+    assert_eq!(root.to_map(), BTreeMap::from([(1, Nuc::A), (5, Nuc::Gap)]));
+    assert_eq!(child.to_map(), BTreeMap::from([(5, Nuc::Gap), (7, Nuc::T)]));
+    assert_eq!(grandchild.substitutions(), BTreeMap::from([(7, Nuc::T), (9, Nuc::C)]));
+    assert_eq!(
+      grandchild.iter_substitutions().collect::<BTreeMap<_, _>>(),
+      grandchild.substitutions()
+    );
+    assert_eq!(grandchild.len(), 3);
+    assert_eq!(grandchild.len_substitutions(), 2);
+    assert_eq!(grandchild.get(1), None);
+    assert_eq!(grandchild.get(5), Some(Nuc::Gap));
+    assert_eq!(grandchild.get_substitution(5), None);
+    assert_eq!(child.changed_positions(&root), vec![1, 7]);
+    assert_eq!(grandchild.changed_positions(&root), vec![1, 7, 9]);
+  }
+
+  #[rstest]
+  fn flattens_long_chains() {
+    let mut mutations = NodeMutations::<Nuc>::default();
+    for pos in 0..(MAX_CHAIN_LENGTH * 2 + 3) {
+      mutations = mutations.with_changes(BTreeMap::from([(pos, Some(Nuc::T)), (pos + 1000, Some(Nuc::C))]));
+    }
+    mutations = mutations.with_changes(BTreeMap::from([(1000, None)]));
+
+    let expected = (0..(MAX_CHAIN_LENGTH * 2 + 3))
+      .map(|pos| (pos, Nuc::T))
+      .chain((1001..(1000 + MAX_CHAIN_LENGTH * 2 + 3)).map(|pos| (pos, Nuc::C)))
+      .collect::<BTreeMap<_, _>>();
+
+    assert!(mutations.layer.as_ref().unwrap().chain_length <= MAX_CHAIN_LENGTH);
+    assert_eq!(mutations.len(), expected.len());
+    assert_eq!(mutations.to_map(), expected);
+  }
+
+  #[rstest]
+  fn stores_less_than_full_maps_on_deep_chains() {
+    // A ladder-like tree, where every node adds one mutation on top of its parent. Storing a full map on every node
+    // would take a number of entries quadratic in the depth of the tree.
+    let depth = 2000;
+    let mut nodes = vec![NodeMutations::<Nuc>::default()];
+    for pos in 0..depth {
+      let node = nodes[pos].with_changes(BTreeMap::from([(pos, Some(Nuc::T))]));
+      nodes.push(node);
+    }
+
+    // Layers shared between nodes are only counted once
+    let mut stored = BTreeMap::new();
+    for node in &nodes {
+      let mut layer = node.layer.as_ref();
+      while let Some(current) = layer {
+        stored.insert(Arc::as_ptr(current), current.changes.len());
+        layer = current.parent.as_ref();
+      }
+    }
+    let num_stored: usize = stored.values().sum();
+    let num_full_maps: usize = nodes.iter().map(NodeMutations::len).sum();
+
+    assert_eq!(num_full_maps, depth * (depth + 1) / 2);
+    assert!(num_stored * 10 < num_full_maps, "{num_stored} vs {num_full_maps}");
+    assert_eq!(nodes[depth].iter_substitutions().count(), depth);
+  }
+}
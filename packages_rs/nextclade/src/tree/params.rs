@@ -0,0 +1,44 @@
+use crate::make_error;
+use clap::Parser;
+use eyre::Report;
+use serde::{Deserialize, Serialize};
+
+/// Parameters of probabilistic placement of query sequences on the reference tree
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreePlacementParams {
+  /// Number of the most probable placements to report, along with their posterior probabilities and the confidence of the clade assignment.
+  ///
+  /// A positive number enables probabilistic placement: distances between the query sequence and the reference tree nodes are converted into likelihoods, which are combined with the node placement priors into posterior probabilities. The query sequence is then placed on the node with the highest posterior probability. If zero, the sequence is placed on the node with the smallest distance and posterior probabilities are not reported.
+  #[clap(long, default_value_t = TreePlacementParams::default().placement_candidates)]
+  pub placement_candidates: usize,
+
+  /// Probability of a difference between the query sequence and a tree node at any given site, which is used to convert placement distances into likelihoods. Must be greater than 0 and less than 0.5.
+  ///
+  /// Lower values make the posterior probabilities more concentrated on the nearest nodes.
+  #[clap(long, default_value_t = TreePlacementParams::default().placement_mismatch_rate)]
+  pub placement_mismatch_rate: f64,
+}
+
+impl Default for TreePlacementParams {
+  fn default() -> Self {
+    Self {
+      placement_candidates: 0,
+      placement_mismatch_rate: 0.01,
+    }
+  }
+}
+
+impl TreePlacementParams {
+  pub const fn is_probabilistic(&self) -> bool {
+    self.placement_candidates > 0
+  }
+
+  pub fn validate(&self) -> Result<(), Report> {
+    let rate = self.placement_mismatch_rate;
+    if !(rate > 0.0 && rate < 0.5) {
+      return make_error!("Placement mismatch rate should be greater than 0 and less than 0.5, but found {rate}");
+    }
+    Ok(())
+  }
+}
@@ -1,12 +1,21 @@
 use crate::io::aa::Aa;
+use crate::io::fasta::read_many_fasta;
 use crate::io::fs::read_file_to_string;
+use crate::io::gene_map::GeneMap;
 use crate::io::json::json_parse;
-use crate::io::nuc::Nuc;
+use crate::io::nuc::{to_nuc_seq, Nuc};
+use crate::io::nwk_reader::NwkNode;
+use crate::io::usher_mat::UsherMat;
+use crate::translate::genetic_code::GeneticCode;
+use crate::tree::node_mutations::NodeMutations;
+use crate::tree::tree_from_nwk::{tree_from_nwk_and_alignment, tree_from_nwk_and_node_data, NodeDataJson};
+use crate::tree::tree_placement_index::TreePlacementIndex;
+use crate::tree::tree_usher_mat::tree_from_usher_mat;
 use eyre::{Report, WrapErr};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::any::Any;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use std::slice::Iter;
 use std::str::FromStr;
@@ -123,10 +132,14 @@ pub struct TreeNodeAttrs {
 #[derive(Clone, Default, Debug)]
 pub struct TreeNodeTempData {
   pub id: usize,
-  pub substitutions: BTreeMap<usize, Nuc>,
-  pub mutations: BTreeMap<usize, Nuc>,
-  pub aa_substitutions: BTreeMap<String, BTreeMap<usize, Aa>>,
-  pub aa_mutations: BTreeMap<String, BTreeMap<usize, Aa>>,
+
+  /// Nucleotide mutations (substitutions and deletions) accumulated from root. Substitutions are the mutations which
+  /// are not gaps.
+  pub mutations: NodeMutations<Nuc>,
+
+  /// Aminoacid mutations accumulated from root, per gene
+  pub aa_mutations: BTreeMap<String, NodeMutations<Aa>>,
+
   pub is_ref_node: bool,
 }
 
@@ -289,10 +302,15 @@ impl DivergenceUnits {
   }
 }
 
+/// Nucleotides introduced on the branches where clade changes, per position: position -> nucleotide -> clades
+pub type CladeDefiningMutations = BTreeMap<usize, BTreeMap<Nuc, BTreeSet<String>>>;
+
 #[derive(Debug, Clone, Default)]
 pub struct TreeTempData {
   pub max_divergence: f64,
   pub divergence_units: DivergenceUnits,
+  pub clade_defining_mutations: CladeDefiningMutations,
+  pub placement_index: TreePlacementIndex,
 }
 
 #[derive(Clone, Serialize, Deserialize, Validate, Debug)]
@@ -329,6 +347,53 @@ impl AuspiceTree {
     Self::from_str(&data).wrap_err_with(|| format!("When parsing Auspice Tree JSON file {filepath:#?}"))
   }
 
+  /// Builds the tree from a Newick tree and a set of augur node data JSON files (see `tree_from_nwk_and_node_data`)
+  pub fn from_nwk_and_node_data_paths<P: AsRef<Path>>(
+    nwk: &NwkNode,
+    node_data_filepaths: &[P],
+  ) -> Result<Self, Report> {
+    let node_data = node_data_filepaths
+      .iter()
+      .map(|filepath| {
+        let filepath = filepath.as_ref();
+        NodeDataJson::from_str(&read_file_to_string(filepath)?)
+          .wrap_err_with(|| format!("When reading node data JSON file {filepath:#?}"))
+      })
+      .collect::<Result<Vec<NodeDataJson>, Report>>()?;
+    tree_from_nwk_and_node_data(nwk, &node_data)
+  }
+
+  /// Builds the tree from a Newick tree and a FASTA file with aligned sequences of its tips, reconstructing mutations
+  /// by parsimony (see `tree_from_nwk_and_alignment`)
+  pub fn from_nwk_and_alignment_path(
+    nwk: &NwkNode,
+    alignment_filepath: impl AsRef<Path>,
+    ref_seq: &[Nuc],
+    gene_map: &GeneMap,
+    genetic_code: &GeneticCode,
+  ) -> Result<Self, Report> {
+    let tip_seqs = read_many_fasta(&[alignment_filepath])?
+      .into_iter()
+      .map(|record| -> Result<(String, Vec<Nuc>), Report> {
+        let seq = to_nuc_seq(&record.seq)
+          .wrap_err_with(|| format!("When reading sequence '{}' of the tree alignment", record.seq_name))?;
+        Ok((record.seq_name, seq))
+      })
+      .collect::<Result<BTreeMap<String, Vec<Nuc>>, Report>>()?;
+    tree_from_nwk_and_alignment(nwk, ref_seq, &tip_seqs, gene_map, genetic_code)
+  }
+
+  /// Builds the tree from an UShER mutation-annotated tree protobuf file (see `tree_from_usher_mat`)
+  pub fn from_usher_mat_path(
+    filepath: impl AsRef<Path>,
+    ref_seq: &[Nuc],
+    gene_map: &GeneMap,
+    genetic_code: &GeneticCode,
+  ) -> Result<Self, Report> {
+    let mat = UsherMat::from_path(filepath)?;
+    tree_from_usher_mat(&mat, ref_seq, gene_map, genetic_code)
+  }
+
   pub fn to_string_pretty(&self) -> Result<String, Report> {
     let mut tree_str = serde_json::to_string_pretty(self)?;
     tree_str += "\n";
@@ -0,0 +1,336 @@
+use crate::analyze::aa_sub::AaSubMinimal;
+use crate::analyze::nuc_sub::NucSub;
+use crate::io::aa::Aa;
+use crate::io::csv::parse_tsv;
+use crate::io::fs::read_file_to_string;
+use crate::io::letter::Letter;
+use crate::io::nuc::Nuc;
+use crate::make_error;
+use crate::translate::translate_genes::TranslationMap;
+use crate::tree::tree::{AuspiceTree, AuspiceTreeNode, TreeNodeAttr, AUSPICE_UNKNOWN_VALUE};
+use eyre::{eyre, Report, WrapErr};
+use indexmap::IndexMap;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Row of a clade definitions table, in the format of `augur clades`: each clade is defined by a set of rows, one per
+/// nucleotide (gene `nuc`) or aminoacid allele. Sites are 1-based.
+///
+/// See: https://docs.nextstrain.org/projects/augur/en/stable/usage/cli/clades.html
+#[derive(Clone, Debug, Deserialize)]
+struct CladeDefinitionRow {
+  clade: String,
+  gene: String,
+  site: usize,
+  alt: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CladeDefiningAllele {
+  Nuc { pos: usize, nuc: Nuc },
+  Aa { gene: String, pos: usize, aa: Aa },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CladeDefinition {
+  pub clade: String,
+  pub alleles: Vec<CladeDefiningAllele>,
+}
+
+/// Clade definitions, in the order in which they are listed in the table
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CladeDefinitions {
+  pub clades: Vec<CladeDefinition>,
+}
+
+impl FromStr for CladeDefinitions {
+  type Err = Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let rows: Vec<CladeDefinitionRow> = parse_tsv(s).wrap_err("When parsing clade definitions table")?;
+
+    let mut clades = IndexMap::<String, Vec<CladeDefiningAllele>>::new();
+    for CladeDefinitionRow { clade, gene, site, alt } in rows {
+      if site == 0 {
+        return make_error!("Clade '{clade}': sites are expected to be 1-based, but found site 0");
+      }
+      let pos = site - 1;
+
+      let allele = if gene == "nuc" {
+        CladeDefiningAllele::Nuc {
+          pos,
+          nuc: Nuc::from_string(&alt).wrap_err_with(|| format!("When parsing allele of clade '{clade}'"))?,
+        }
+      } else if gene == "clade" {
+        return make_error!(
+          "Clade '{clade}': definitions of clades relative to other clades (gene 'clade') are not supported. Please list all defining alleles of the clade instead."
+        );
+      } else {
+        CladeDefiningAllele::Aa {
+          gene,
+          pos,
+          aa: Aa::from_string(&alt).wrap_err_with(|| format!("When parsing allele of clade '{clade}'"))?,
+        }
+      };
+
+      clades.entry(clade).or_default().push(allele);
+    }
+
+    Ok(Self {
+      clades: clades
+        .into_iter()
+        .map(|(clade, alleles)| CladeDefinition { clade, alleles })
+        .collect_vec(),
+    })
+  }
+}
+
+impl CladeDefinitions {
+  pub fn from_path(filepath: impl AsRef<Path>) -> Result<Self, Report> {
+    let filepath = filepath.as_ref();
+    let data =
+      read_file_to_string(filepath).wrap_err_with(|| format!("When reading clade definitions file {filepath:#?}"))?;
+    Self::from_str(&data).wrap_err_with(|| format!("When parsing clade definitions file {filepath:#?}"))
+  }
+}
+
+/// Alleles of a node at the sites used in clade definitions
+#[derive(Clone, Debug, Default)]
+struct NodeAlleles {
+  nuc: BTreeMap<usize, Nuc>,
+  aa: BTreeMap<(String, usize), Aa>,
+}
+
+impl NodeAlleles {
+  fn matches(&self, allele: &CladeDefiningAllele) -> bool {
+    match allele {
+      CladeDefiningAllele::Nuc { pos, nuc } => self.nuc.get(pos) == Some(nuc),
+      CladeDefiningAllele::Aa { gene, pos, aa } => self.aa.get(&(gene.clone(), *pos)) == Some(aa),
+    }
+  }
+
+  /// Applies mutations on the branch leading to the node. Only the sites which are present in the map are updated.
+  fn apply_mutations(&mut self, node: &AuspiceTreeNode) -> Result<(), Report> {
+    for (gene_name, mutations) in &node.branch_attrs.mutations {
+      for mutation in mutations {
+        if gene_name == "nuc" {
+          let sub = NucSub::from_str(mutation)?;
+          if let Some(nuc) = self.nuc.get_mut(&sub.pos) {
+            *nuc = sub.qry;
+          }
+        } else {
+          let sub = AaSubMinimal::from_str(mutation)?;
+          if let Some(aa) = self.aa.get_mut(&(gene_name.clone(), sub.pos)) {
+            *aa = sub.qry;
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Assigns clades to the nodes of the tree, given clade definitions, in the same way as `augur clades` does: a clade is
+/// assigned to the node at which all of its defining alleles are first present, and is inherited by the descendants of
+/// this node, unless they belong to another clade. If several clades originate at the same node, the last one listed
+/// in the definitions wins. The nodes at which clades originate receive branch label `clade`.
+///
+/// Clades which were assigned to the nodes previously are replaced.
+pub fn tree_assign_clades_in_place(
+  tree: &mut AuspiceTree,
+  clade_definitions: &CladeDefinitions,
+  ref_seq: &[Nuc],
+  ref_peptides: &TranslationMap,
+) -> Result<(), Report> {
+  let mut alleles = NodeAlleles::default();
+  for definition in &clade_definitions.clades {
+    for allele in &definition.alleles {
+      match allele {
+        CladeDefiningAllele::Nuc { pos, .. } => {
+          let nuc = ref_seq.get(*pos).ok_or_else(|| {
+            eyre!(
+              "Clade '{}': site {} is outside of the reference sequence of length {}",
+              definition.clade,
+              pos + 1,
+              ref_seq.len()
+            )
+          })?;
+          alleles.nuc.insert(*pos, *nuc);
+        }
+        CladeDefiningAllele::Aa { gene, pos, .. } => {
+          let ref_peptide = ref_peptides.get(gene).ok_or_else(|| {
+            eyre!(
+              "Clade '{}': gene '{gene}' is not found in the genome annotation",
+              definition.clade
+            )
+          })?;
+          let aa = ref_peptide.seq.get(*pos).ok_or_else(|| {
+            eyre!(
+              "Clade '{}': codon {} is outside of gene '{gene}' of length {}",
+              definition.clade,
+              pos + 1,
+              ref_peptide.seq.len()
+            )
+          })?;
+          alleles.aa.insert((gene.clone(), *pos), *aa);
+        }
+      }
+    }
+  }
+
+  // The traversal uses an explicit stack rather than recursion, so that deep trees do not overflow the call stack
+  let root_matches = vec![false; clade_definitions.clades.len()];
+  let mut stack = vec![(&mut tree.tree, alleles, root_matches, AUSPICE_UNKNOWN_VALUE.to_owned())];
+  while let Some((node, mut alleles, parent_matches, parent_clade)) = stack.pop() {
+    let (matches, clade) = assign_clade(node, clade_definitions, &mut alleles, &parent_matches, &parent_clade)?;
+    for child in node.children.iter_mut().rev() {
+      stack.push((child, alleles.clone(), matches.clone(), clade.clone()));
+    }
+  }
+
+  Ok(())
+}
+
+/// Assigns clade to a single node, given alleles and clade matches of its parent. Returns clade matches and clade of
+/// the node, to be passed on to its children.
+fn assign_clade(
+  node: &mut AuspiceTreeNode,
+  clade_definitions: &CladeDefinitions,
+  alleles: &mut NodeAlleles,
+  parent_matches: &[bool],
+  parent_clade: &str,
+) -> Result<(Vec<bool>, String), Report> {
+  alleles
+    .apply_mutations(node)
+    .wrap_err_with(|| format!("When reading mutations of node '{}'", node.name))?;
+
+  let matches = clade_definitions
+    .clades
+    .iter()
+    .map(|definition| definition.alleles.iter().all(|allele| alleles.matches(allele)))
+    .collect_vec();
+
+  let new_clade = clade_definitions
+    .clades
+    .iter()
+    .zip(&matches)
+    .zip(parent_matches)
+    .filter(|((_, &matches), &parent_matches)| matches && !parent_matches)
+    .map(|((definition, _), _)| definition.clade.as_str())
+    .last();
+
+  set_clade_branch_label(node, new_clade);
+
+  let clade = new_clade.unwrap_or(parent_clade).to_owned();
+  node.node_attrs.clade_membership = TreeNodeAttr::new(&clade);
+
+  Ok((matches, clade))
+}
+
+/// Sets branch label `clade` of the node, or removes it, if no clade originates at the node
+fn set_clade_branch_label(node: &mut AuspiceTreeNode, clade: Option<&str>) {
+  let other = &mut node.branch_attrs.other;
+  match clade {
+    Some(clade) => {
+      if !other.is_object() {
+        *other = json!({});
+      }
+      let labels = &mut other["labels"];
+      if !labels.is_object() {
+        *labels = json!({});
+      }
+      labels["clade"] = json!(clade);
+    }
+    None => {
+      if let Some(labels) = other.get_mut("labels").and_then(serde_json::Value::as_object_mut) {
+        labels.remove("clade");
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::to_nuc_seq;
+  use crate::translate::translate_genes::Translation;
+  use crate::utils::range::Range;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  const TREE: &str = r#"{
+    "meta": { "display_defaults": {} },
+    "tree": {
+      "name": "root",
+      "branch_attrs": { "mutations": {} },
+      "node_attrs": { "clade_membership": { "value": "old" } },
+      "children": [
+        {
+          "name": "A",
+          "branch_attrs": { "mutations": { "nuc": ["A4C"] } },
+          "node_attrs": { "clade_membership": { "value": "old" } },
+          "children": [
+            {
+              "name": "A.1",
+              "branch_attrs": { "mutations": { "nuc": ["G7T"], "g": ["V2F"] } },
+              "node_attrs": { "clade_membership": { "value": "old" } }
+            },
+            {
+              "name": "A.2",
+              "branch_attrs": { "mutations": {}, "labels": { "clade": "old" } },
+              "node_attrs": { "clade_membership": { "value": "old" } }
+            }
+          ]
+        },
+        {
+          "name": "B",
+          "branch_attrs": { "mutations": { "nuc": ["G7T"] } },
+          "node_attrs": { "clade_membership": { "value": "old" } }
+        }
+      ]
+    }
+  }"#;
+
+  #[rstest]
+  fn assigns_clades() -> Result<(), Report> {
+    let mut tree = AuspiceTree::from_str(TREE)?;
+    let ref_seq = to_nuc_seq("ACGAACGAA")?;
+    let ref_peptides = TranslationMap::from([(
+      "g".to_owned(),
+      Translation {
+        gene_name: "g".to_owned(),
+        seq: vec![Aa::M, Aa::V, Aa::E],
+        insertions: vec![],
+        frame_shifts: vec![],
+        alignment_range: Range::new(0, 3),
+      },
+    )]);
+
+    let clade_definitions =
+      CladeDefinitions::from_str("clade\tgene\tsite\talt\n# comment\nX\tnuc\t4\tC\nX.1\tnuc\t4\tC\nX.1\tg\t2\tF\n")?;
+
+    tree_assign_clades_in_place(&mut tree, &clade_definitions, &ref_seq, &ref_peptides)?;
+
+    let a = &tree.tree.children[0];
+    assert_eq!(tree.tree.clade(), AUSPICE_UNKNOWN_VALUE);
+    assert_eq!(a.clade(), "X");
+    assert_eq!(a.branch_attrs.other["labels"]["clade"], "X");
+    assert_eq!(a.children[1].branch_attrs.other["labels"].get("clade"), None);
+    assert_eq!(a.children[0].clade(), "X.1");
+    assert_eq!(a.children[1].clade(), "X");
+    assert_eq!(tree.tree.children[1].clade(), AUSPICE_UNKNOWN_VALUE);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("clade\tgene\tsite\talt\nX\tnuc\t0\tC\n")]
+  #[case("clade\tgene\tsite\talt\nX\tclade\t1\tY\n")]
+  #[case("clade\tgene\tsite\talt\nX\tnuc\tfoo\tC\n")]
+  fn rejects_invalid_clade_definitions(#[case] input: &str) {
+    assert!(CladeDefinitions::from_str(input).is_err());
+  }
+}
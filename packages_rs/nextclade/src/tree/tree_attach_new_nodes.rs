@@ -4,51 +4,105 @@ use crate::analyze::find_private_aa_mutations::PrivateAaMutations;
 use crate::analyze::find_private_nuc_mutations::PrivateNucMutations;
 use crate::analyze::nuc_del::NucDelMinimal;
 use crate::analyze::nuc_sub::NucSub;
+use crate::io::aa::Aa;
 use crate::io::nextclade_csv::{
   format_failed_genes, format_missings, format_non_acgtns, format_nuc_deletions, format_pcr_primer_changes,
 };
+use crate::io::nuc::Nuc;
 use crate::tree::tree::{
   AuspiceTree, AuspiceTreeNode, TreeBranchAttrs, TreeNodeAttr, TreeNodeAttrs, TreeNodeTempData, AUSPICE_UNKNOWN_VALUE,
 };
+use crate::tree::tree_placement_index::TreePlacementIndex;
 use crate::types::outputs::NextcladeOutputs;
 use crate::utils::collections::concat_to_vec;
+use eyre::Report;
 use itertools::{chain, Itertools};
 use serde_json::json;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
 
 pub fn tree_attach_new_nodes_in_place(tree: &mut AuspiceTree, results: &[NextcladeOutputs]) {
-  tree_attach_new_nodes_impl_in_place_recursive(&mut tree.tree, results);
+  let root_div = tree.tree.node_attrs.div.unwrap_or(0.0);
+  let root_clade = tree.tree.clade();
+
+  // Nodes inserted when splitting branches receive ids following the ids of the reference nodes
+  let mut next_id = tree
+    .iter_depth_first_preorder()
+    .map(|(_, node)| node.tmp.id + 1)
+    .max()
+    .unwrap_or(0);
+
+  tree_attach_new_nodes_impl_in_place_recursive(&mut tree.tree, root_div, &root_clade, &mut next_id, results);
+
+  // The tree has changed, so the placement index is no longer valid
+  tree.tmp.placement_index = TreePlacementIndex::default();
 }
 
-fn tree_attach_new_nodes_impl_in_place_recursive(node: &mut AuspiceTreeNode, results: &[NextcladeOutputs]) {
+fn tree_attach_new_nodes_impl_in_place_recursive(
+  node: &mut AuspiceTreeNode,
+  parent_div: f64,
+  parent_clade: &str,
+  next_id: &mut usize,
+  results: &[NextcladeOutputs],
+) {
   // Attach only to a reference node.
   // If it's not a reference node, we can stop here, because there can be no reference nodes down the tree.
   if !node.tmp.is_ref_node {
     return;
   }
 
+  let node_div = node.node_attrs.div.unwrap_or(0.0);
+  let node_clade = node.clade();
   for child in &mut node.children {
-    tree_attach_new_nodes_impl_in_place_recursive(child, results);
+    tree_attach_new_nodes_impl_in_place_recursive(child, node_div, &node_clade, next_id, results);
   }
 
-  // Look for a query sample result for which this node was decided to be nearest
+  // Look for a query sample result for which this node was decided to be nearest.
+  // Attaching a query might split the branch above the node, in which case the node moves one level down the tree.
+  // The new parent is then on the same branch as the old one, so it is in the same clade.
+  let mut node = node;
+  let mut parent_div = parent_div;
   for result in results {
     if node.tmp.id == result.nearest_node_id {
-      attach_new_node(node, result);
+      (node, parent_div) = attach_new_node(node, parent_div, parent_clade, next_id, result);
     }
   }
 }
 
-/// Attaches a new node to the reference tree
-fn attach_new_node(node: &mut AuspiceTreeNode, result: &NextcladeOutputs) {
+/// Attaches a new node to the reference tree.
+///
+/// If the query sequence shares only some of the mutations on the branch leading to the nearest node, then the branch
+/// is split: a new internal node carrying the shared mutations is inserted on the branch and the query is attached to
+/// it. Otherwise the query is attached as a child of the nearest node.
+///
+/// Returns the nearest node (which is moved one level down if the branch is split) and the divergence of its parent.
+fn attach_new_node<'node>(
+  node: &'node mut AuspiceTreeNode,
+  parent_div: f64,
+  parent_clade: &str,
+  next_id: &mut usize,
+  result: &NextcladeOutputs,
+) -> (&'node mut AuspiceTreeNode, f64) {
   debug_assert!(node.is_ref_node());
   debug_assert_eq!(node.tmp.id, result.nearest_node_id);
 
+  if let Some(split) = find_branch_split(node, result) {
+    let split_id = *next_id;
+    *next_id += 1;
+    return split_branch_and_attach(node, parent_div, parent_clade, split_id, result, split);
+  }
+
   if node.is_leaf() {
     add_aux_node(node);
   }
 
-  add_child(node, result);
+  add_child(
+    node,
+    result,
+    convert_mutations_to_node_branch_attrs(result),
+    result.divergence,
+  );
+  (node, parent_div)
 }
 
 fn add_aux_node(node: &mut AuspiceTreeNode) {
@@ -63,9 +117,12 @@ fn add_aux_node(node: &mut AuspiceTreeNode) {
   node.name = format!("{}_parent", node.name);
 }
 
-fn add_child(node: &mut AuspiceTreeNode, result: &NextcladeOutputs) {
-  let mutations = convert_mutations_to_node_branch_attrs(result);
-
+fn add_child(
+  node: &mut AuspiceTreeNode,
+  result: &NextcladeOutputs,
+  mutations: BTreeMap<String, Vec<String>>,
+  divergence: f64,
+) {
   let alignment = format!(
     "start: {}, end: {} (score: {})",
     result.alignment_start, result.alignment_end, result.alignment_score
@@ -108,7 +165,7 @@ fn add_child(node: &mut AuspiceTreeNode, result: &NextcladeOutputs) {
         other: serde_json::Value::default(),
       },
       node_attrs: TreeNodeAttrs {
-        div: Some(result.divergence),
+        div: Some(divergence),
         clade_membership: TreeNodeAttr::new(&result.clade),
         node_type: Some(TreeNodeAttr::new("New")),
         region: Some(TreeNodeAttr::new(AUSPICE_UNKNOWN_VALUE)),
@@ -157,6 +214,21 @@ fn convert_mutations_to_node_branch_attrs(result: &NextcladeOutputs) -> BTreeMap
 }
 
 fn convert_nuc_mutations_to_node_branch_attrs(private_nuc_mutations: &PrivateNucMutations) -> Vec<String> {
+  private_nuc_subs(private_nuc_mutations)
+    .iter()
+    .map(NucSub::to_string)
+    .collect_vec()
+}
+
+fn convert_aa_mutations_to_node_branch_attrs(private_aa_mutations: &PrivateAaMutations) -> Vec<String> {
+  private_aa_subs(private_aa_mutations)
+    .iter()
+    .map(AaSubMinimal::to_string_without_gene)
+    .collect_vec()
+}
+
+/// Private nucleotide substitutions and deletions (as substitutions to gap), sorted
+fn private_nuc_subs(private_nuc_mutations: &PrivateNucMutations) -> Vec<NucSub> {
   let dels_as_subs = private_nuc_mutations
     .private_deletions
     .iter()
@@ -165,11 +237,11 @@ fn convert_nuc_mutations_to_node_branch_attrs(private_nuc_mutations: &PrivateNuc
 
   let mut subs = concat_to_vec(&private_nuc_mutations.private_substitutions, &dels_as_subs);
   subs.sort();
-
-  subs.iter().map(NucSub::to_string).collect_vec()
+  subs
 }
 
-fn convert_aa_mutations_to_node_branch_attrs(private_aa_mutations: &PrivateAaMutations) -> Vec<String> {
+/// Private aminoacid substitutions and deletions (as substitutions to gap), sorted
+fn private_aa_subs(private_aa_mutations: &PrivateAaMutations) -> Vec<AaSubMinimal> {
   let dels_as_subs = private_aa_mutations
     .private_deletions
     .iter()
@@ -178,6 +250,406 @@ fn convert_aa_mutations_to_node_branch_attrs(private_aa_mutations: &PrivateAaMut
 
   let mut subs = concat_to_vec(&private_aa_mutations.private_substitutions, &dels_as_subs);
   subs.sort();
+  subs
+}
+
+/// Substitution on a tree branch, either nucleotide or aminoacid
+trait BranchMutation: Clone {
+  type Letter: Copy + Eq;
+
+  fn new(reff: Self::Letter, pos: usize, qry: Self::Letter) -> Self;
+  fn reff(&self) -> Self::Letter;
+  fn pos(&self) -> usize;
+  fn qry(&self) -> Self::Letter;
+}
+
+impl BranchMutation for NucSub {
+  type Letter = Nuc;
+
+  fn new(reff: Nuc, pos: usize, qry: Nuc) -> Self {
+    Self { reff, pos, qry }
+  }
+
+  fn reff(&self) -> Nuc {
+    self.reff
+  }
+
+  fn pos(&self) -> usize {
+    self.pos
+  }
+
+  fn qry(&self) -> Nuc {
+    self.qry
+  }
+}
+
+impl BranchMutation for AaSubMinimal {
+  type Letter = Aa;
+
+  fn new(reff: Aa, pos: usize, qry: Aa) -> Self {
+    Self { reff, pos, qry }
+  }
+
+  fn reff(&self) -> Aa {
+    self.reff
+  }
+
+  fn pos(&self) -> usize {
+    self.pos
+  }
+
+  fn qry(&self) -> Aa {
+    self.qry
+  }
+}
+
+/// Mutations of a branch and of a query, re-partitioned for attaching the query in the middle of the branch
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BranchSplit<T> {
+  /// Mutations of the branch shared with the query. These are moved to the new internal node.
+  shared: Vec<T>,
+
+  /// Mutations of the branch not shared with the query. These stay on the branch of the original node.
+  unshared: Vec<T>,
+
+  /// Private mutations of the query relative to the new internal node
+  private: Vec<T>,
+}
+
+/// Splits mutations of a branch into the ones shared with the query and the ones which are not.
+///
+/// Private mutations of the query are relative to the node at the end of the branch, so a branch mutation is not shared
+/// if the query has a private mutation at the same position (either a reversion or a different character). Private
+/// mutations at these positions are rewritten relative to the state before the branch mutation, and reversions to
+/// that state disappear.
+fn split_branch_mutations<T: BranchMutation>(branch: &[T], private: &[T]) -> BranchSplit<T> {
+  let private_positions = private.iter().map(BranchMutation::pos).collect::<BTreeSet<_>>();
+
+  let (unshared, shared): (Vec<T>, Vec<T>) = branch
+    .iter()
+    .cloned()
+    .partition(|m| private_positions.contains(&m.pos()));
+
+  let unshared_by_pos = unshared.iter().map(|m| (m.pos(), m)).collect::<BTreeMap<_, _>>();
+
+  let private = private
+    .iter()
+    .filter_map(|m| match unshared_by_pos.get(&m.pos()) {
+      None => Some(m.clone()),
+      Some(branch_mut) => (m.qry() != branch_mut.reff()).then(|| T::new(branch_mut.reff(), m.pos(), m.qry())),
+    })
+    .collect_vec();
+
+  BranchSplit {
+    shared,
+    unshared,
+    private,
+  }
+}
+
+struct NodeSplit {
+  nuc: BranchSplit<NucSub>,
+  aa: BTreeMap<String, BranchSplit<AaSubMinimal>>,
+}
+
+/// Decides whether the branch leading to the nearest node should be split, that is whether the query shares some, but
+/// not all nucleotide mutations of this branch
+fn find_branch_split(node: &AuspiceTreeNode, result: &NextcladeOutputs) -> Option<NodeSplit> {
+  let branch_nuc = parse_branch_mutations::<NucSub>(node.branch_attrs.mutations.get("nuc")?).ok()?;
+  let nuc = split_branch_mutations(&branch_nuc, &private_nuc_subs(&result.private_nuc_mutations));
+  if nuc.shared.is_empty() || nuc.unshared.is_empty() {
+    return None;
+  }
+
+  let aa = node
+    .branch_attrs
+    .mutations
+    .iter()
+    .filter(|(gene_name, _)| *gene_name != "nuc")
+    .map(|(gene_name, mutations)| {
+      let branch_aa = parse_branch_mutations::<AaSubMinimal>(mutations)?;
+      let private_aa = result
+        .private_aa_mutations
+        .get(gene_name)
+        .map(private_aa_subs)
+        .unwrap_or_default();
+      Ok((gene_name.clone(), split_branch_mutations(&branch_aa, &private_aa)))
+    })
+    .collect::<Result<BTreeMap<_, _>, Report>>()
+    .ok()?;
+
+  Some(NodeSplit { nuc, aa })
+}
+
+fn parse_branch_mutations<T: FromStr<Err = Report>>(mutations: &[String]) -> Result<Vec<T>, Report> {
+  mutations.iter().map(|m| T::from_str(m)).collect()
+}
+
+/// Inserts a new internal node carrying the shared mutations on the branch leading to the nearest node, and attaches
+/// the query to it. Returns the nearest node, now a child of the new internal node, and the divergence of the new
+/// internal node.
+///
+/// The new internal node is above any clade change on the branch, so it belongs to the clade of the parent. It does not
+/// inherit other attributes of the nearest node, which describe the sample of the nearest node.
+fn split_branch_and_attach<'node>(
+  node: &'node mut AuspiceTreeNode,
+  parent_div: f64,
+  parent_clade: &str,
+  split_id: usize,
+  result: &NextcladeOutputs,
+  split: NodeSplit,
+) -> (&'node mut AuspiceTreeNode, f64) {
+  let NodeSplit { nuc, aa } = split;
+
+  // Position the internal node on the branch proportionally to the number of shared nucleotide mutations
+  let node_div = node.node_attrs.div.unwrap_or(0.0);
+  let n_branch_muts = nuc.shared.len() + nuc.unshared.len();
+  let split_div = parent_div + (node_div - parent_div) * nuc.shared.len() as f64 / n_branch_muts as f64;
+
+  // Divergence of the query is proportional to the number of its private substitutions. Some of these might have
+  // disappeared, because they were reversions of the unshared branch mutations.
+  let n_private_subs_before = result.private_nuc_mutations.private_substitutions.len();
+  let n_private_subs_after = nuc.private.iter().filter(|sub| !sub.is_del()).count();
+  let query_div = if n_private_subs_before == 0 {
+    split_div
+  } else {
+    split_div + (result.divergence - node_div) * n_private_subs_after as f64 / n_private_subs_before as f64
+  };
+
+  let mut shared_mutations =
+    BTreeMap::from([("nuc".to_owned(), nuc.shared.iter().map(NucSub::to_string).collect_vec())]);
+  let mut unshared_mutations = BTreeMap::from([(
+    "nuc".to_owned(),
+    nuc.unshared.iter().map(NucSub::to_string).collect_vec(),
+  )]);
+  let mut query_mutations = BTreeMap::from([(
+    "nuc".to_owned(),
+    nuc.private.iter().map(NucSub::to_string).collect_vec(),
+  )]);
+
+  for (gene_name, gene_split) in &aa {
+    if !gene_split.shared.is_empty() {
+      shared_mutations.insert(
+        gene_name.clone(),
+        gene_split
+          .shared
+          .iter()
+          .map(AaSubMinimal::to_string_without_gene)
+          .collect_vec(),
+      );
+    }
+    if !gene_split.unshared.is_empty() {
+      unshared_mutations.insert(
+        gene_name.clone(),
+        gene_split
+          .unshared
+          .iter()
+          .map(AaSubMinimal::to_string_without_gene)
+          .collect_vec(),
+      );
+    }
+  }
+
+  for (gene_name, private_aa_mutations) in &result.private_aa_mutations {
+    let private_aa = aa.get(gene_name).map_or_else(
+      || private_aa_subs(private_aa_mutations),
+      |gene_split| gene_split.private.clone(),
+    );
+    query_mutations.insert(
+      gene_name.clone(),
+      private_aa
+        .iter()
+        .map(AaSubMinimal::to_string_without_gene)
+        .collect_vec(),
+    );
+  }
+
+  // Branch labels (e.g. clade labels) stay on the original branch
+  let split_node = AuspiceTreeNode {
+    name: format!("{}_split", result.seq_name),
+    branch_attrs: TreeBranchAttrs {
+      mutations: shared_mutations,
+      other: serde_json::Value::default(),
+    },
+    node_attrs: TreeNodeAttrs {
+      div: Some(split_div),
+      clade_membership: TreeNodeAttr::new(parent_clade),
+      node_type: Some(TreeNodeAttr::new("New")),
+      region: None,
+      country: None,
+      division: None,
+      placement_prior: None,
+      alignment: None,
+      missing: None,
+      gaps: None,
+      non_acgtns: None,
+      has_pcr_primer_changes: None,
+      pcr_primer_changes: None,
+      qc_status: None,
+      missing_genes: None,
+      other: serde_json::Value::default(),
+    },
+    children: vec![],
+    tmp: TreeNodeTempData {
+      id: split_id,
+      ..TreeNodeTempData::default()
+    },
+    other: serde_json::Value::default(),
+  };
+
+  node.branch_attrs.mutations = unshared_mutations;
+  let original_node = std::mem::replace(node, split_node);
+  node.children.push(original_node);
+  add_child(node, result, query_mutations, query_div);
 
-  subs.iter().map(AaSubMinimal::to_string_without_gene).collect_vec()
+  let original_node_index = node.children.len() - 1;
+  (&mut node.children[original_node_index], split_div)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn subs(mutations: &[&str]) -> Result<Vec<NucSub>, Report> {
+    mutations.iter().map(|m| NucSub::from_str(m)).collect()
+  }
+
+  fn nuc_mutations(node: &AuspiceTreeNode) -> Vec<String> {
+    node.branch_attrs.mutations["nuc"].clone()
+  }
+
+  /// Reference tree: root (clade A) with a single leaf (clade B), with 2 mutations on the branch leading to the leaf
+  fn reference_tree() -> Result<AuspiceTree, Report> {
+    let mut tree = AuspiceTree::from_str(
+      r#"{
+        "meta": { "display_defaults": {} },
+        "tree": {
+          "name": "root",
+          "branch_attrs": { "mutations": {} },
+          "node_attrs": { "div": 0.0, "clade_membership": { "value": "A" } },
+          "children": [
+            {
+              "name": "leaf",
+              "branch_attrs": { "mutations": { "nuc": ["A10C", "G20T"] }, "labels": { "clade": "B" } },
+              "node_attrs": { "div": 2.0, "clade_membership": { "value": "B" }, "country": { "value": "Spain" } }
+            }
+          ]
+        }
+      }"#,
+    )?;
+
+    tree.tree.tmp.id = 0;
+    tree.tree.tmp.is_ref_node = true;
+    tree.tree.children[0].tmp.id = 1;
+    tree.tree.children[0].tmp.is_ref_node = true;
+    Ok(tree)
+  }
+
+  /// Result of analysis of a query sequence, with a given nearest node and private nucleotide substitutions
+  fn query_result(
+    nearest_node_id: usize,
+    private_substitutions: &[&str],
+    divergence: f64,
+  ) -> Result<NextcladeOutputs, Report> {
+    let mut result = NextcladeOutputs::from_str(
+      r#"{
+        "index": 0, "seqName": "query", "substitutions": [], "totalSubstitutions": 0, "deletions": [],
+        "totalDeletions": 0, "insertions": [], "totalInsertions": 0, "missing": [], "totalMissing": 0, "nonACGTNs": [],
+        "totalNonACGTNs": 0, "nucleotideComposition": {}, "frameShifts": [], "totalFrameShifts": 0,
+        "aaSubstitutions": [], "totalAminoacidSubstitutions": 0, "aaDeletions": [], "totalAminoacidDeletions": 0,
+        "aaInsertions": [], "totalAminoacidInsertions": 0, "unknownAaRanges": [], "totalUnknownAa": 0,
+        "aaChangesGroups": [], "alignmentStart": 0, "alignmentEnd": 30, "alignmentScore": 90, "aaAlignmentRanges": {},
+        "pcrPrimerChanges": [], "totalPcrPrimerChanges": 0, "clade": "A",
+        "privateNucMutations": {
+          "privateSubstitutions": [], "privateDeletions": [], "reversionSubstitutions": [], "labeledSubstitutions": [],
+          "unlabeledSubstitutions": [], "totalPrivateSubstitutions": 0, "totalPrivateDeletions": 0,
+          "totalReversionSubstitutions": 0, "totalLabeledSubstitutions": 0, "totalUnlabeledSubstitutions": 0
+        },
+        "privateAaMutations": {}, "warnings": [], "missingGenes": [], "divergence": 0.0, "coverage": 1.0,
+        "qc": { "overallScore": 0.0, "overallStatus": "good" }, "customNodeAttributes": {}, "nearestNodeId": 0,
+        "isReverseComplement": false, "phenotypeValues": null, "aaMotifs": {}, "aaMotifsChanges": {}
+      }"#,
+    )?;
+
+    result.nearest_node_id = nearest_node_id;
+    result.private_nuc_mutations.private_substitutions = subs(private_substitutions)?;
+    result.divergence = divergence;
+    Ok(result)
+  }
+
+  #[rstest]
+  fn splits_branch_when_attaching_query_sharing_some_mutations() -> Result<(), Report> {
+    // Query has the first mutation of the branch leading to the leaf, but not the second one
+    let mut tree = reference_tree()?;
+    let result = query_result(1, &["T20G"], 3.0)?;
+
+    tree_attach_new_nodes_in_place(&mut tree, &[result]);
+
+    let root = &tree.tree;
+    assert_eq!(root.children.len(), 1);
+
+    let split = &root.children[0];
+    assert_eq!(split.name, "query_split");
+    assert_eq!(split.tmp.id, 2);
+    assert_eq!(split.clade(), "A");
+    assert_eq!(split.node_attrs.div, Some(1.0));
+    assert!(split.node_attrs.country.is_none());
+    assert_eq!(split.branch_attrs.other, serde_json::Value::default());
+    assert_eq!(nuc_mutations(split), vec!["A10C"]);
+    assert_eq!(split.children.len(), 2);
+
+    // The reversion of the unshared branch mutation disappears from the mutations of the query
+    let query = &split.children[0];
+    assert_eq!(query.name, "query_new");
+    assert_eq!(query.node_attrs.div, Some(1.0));
+    assert_eq!(nuc_mutations(query), Vec::<String>::new());
+
+    // The leaf keeps its attributes, the branch labels, and the unshared mutation
+    let leaf = &split.children[1];
+    assert_eq!(leaf.name, "leaf");
+    assert_eq!(leaf.tmp.id, 1);
+    assert_eq!(leaf.clade(), "B");
+    assert_eq!(leaf.node_attrs.div, Some(2.0));
+    assert_eq!(
+      leaf.node_attrs.country.as_ref().map(|country| country.value.as_str()),
+      Some("Spain")
+    );
+    assert_eq!(leaf.branch_attrs.other["labels"]["clade"], "B");
+    assert_eq!(nuc_mutations(leaf), vec!["G20T"]);
+    Ok(())
+  }
+
+  #[rstest]
+  fn splits_branch_mutations() -> Result<(), Report> {
+    // Query has the first branch mutation, reverts the second and has a different character at the third
+    let branch = subs(&["A10C", "G20T", "C30A"])?;
+    let private = subs(&["T20G", "A30G", "A40T"])?;
+
+    let actual = split_branch_mutations(&branch, &private);
+
+    assert_eq!(
+      actual,
+      BranchSplit {
+        shared: subs(&["A10C"])?,
+        unshared: subs(&["G20T", "C30A"])?,
+        private: subs(&["C30G", "A40T"])?,
+      }
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn shares_all_branch_mutations_without_private_mutations_at_same_positions() -> Result<(), Report> {
+    let branch = subs(&["A10C", "G20T"])?;
+    let private = subs(&["A40T"])?;
+
+    let actual = split_branch_mutations(&branch, &private);
+
+    assert_eq!(actual.shared, branch);
+    assert!(actual.unshared.is_empty());
+    assert_eq!(actual.private, private);
+    Ok(())
+  }
 }
@@ -0,0 +1,288 @@
+use crate::analyze::letter_ranges::NucRange;
+use crate::analyze::nuc_sub::NucSub;
+use crate::tree::tree::{AuspiceTree, AuspiceTreeNode};
+use crate::tree::tree_find_nearest_node::{tree_calculate_node_distance, tree_find_nearest_node};
+use crate::utils::range::Range;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Contiguous part of the query sequence, which is placed near the same clade
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecombinationSegment {
+  pub clade: String,
+  pub nearest_node_name: String,
+  pub range: Range,
+}
+
+/// Approximate location of a switch between clades of the neighbouring segments
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecombinationBreakpoint {
+  pub left_clade: String,
+  pub right_clade: String,
+
+  /// Range of nucleotide positions within which the breakpoint is located
+  pub range: Range,
+}
+
+impl ToString for RecombinationBreakpoint {
+  fn to_string(&self) -> String {
+    // NOTE: by convention, in bioinformatics, nucleotides are numbered starting from 1, however our arrays are 0-based
+    format!(
+      "{}/{}:{}-{}",
+      self.left_clade,
+      self.right_clade,
+      self.range.begin + 1,
+      self.range.end
+    )
+  }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recombination {
+  pub segments: Vec<RecombinationSegment>,
+  pub breakpoints: Vec<RecombinationBreakpoint>,
+
+  /// Clades of the segments, in the order of appearance. Empty if no breakpoints are found.
+  pub parental_clades: Vec<String>,
+}
+
+impl Recombination {
+  pub fn is_recombinant(&self) -> bool {
+    !self.breakpoints.is_empty()
+  }
+}
+
+/// Scans the query sequence for recombination breakpoints.
+///
+/// The alignment range is split into contiguous windows of a given size and each window is placed on the reference
+/// tree independently, using the same distance metric as the placement of the whole sequence. A window is assigned to
+/// a different clade only if the nearest node in this window is closer than the nearest node of the whole sequence by
+/// at least `min_distance_improvement`, which prevents windows with few mutations from being assigned at random.
+/// Consecutive windows of the same clade are then merged into segments, and breakpoints are reported between the
+/// segments of different clades.
+///
+/// Every window is compared against every node of the tree, so the cost is proportional to the number of windows times
+/// the number of nodes (with the placement index, the number of mutation changes on the branches), i.e. roughly
+/// `aln_range.len() / window_size` placements of the whole sequence. Smaller windows make the scan proportionally
+/// slower on large trees.
+pub fn tree_find_breakpoints(
+  tree: &AuspiceTree,
+  nearest_node: &AuspiceTreeNode,
+  qry_nuc_subs: &[NucSub],
+  qry_missing: &[NucRange],
+  aln_range: &Range,
+  masked_ranges: &[Range],
+  window_size: usize,
+  min_distance_improvement: i64,
+) -> Recombination {
+  let window_nodes = split_into_windows(aln_range, window_size)
+    .into_iter()
+    .map(|window| {
+      let window_subs = qry_nuc_subs
+        .iter()
+        .filter(|sub| window.contains(sub.pos))
+        .cloned()
+        .collect_vec();
+
+      let best = tree_find_nearest_node(tree, &window_subs, qry_missing, &window, masked_ranges);
+      let nearest_distance =
+        tree_calculate_node_distance(nearest_node, &window_subs, qry_missing, &window, masked_ranges);
+
+      let is_improvement = nearest_distance - best.distance >= min_distance_improvement;
+      let node = if is_improvement && best.node.clade() != nearest_node.clade() {
+        best.node
+      } else {
+        nearest_node
+      };
+
+      (window, node)
+    })
+    .collect_vec();
+
+  // Merge consecutive windows of the same clade into segments
+  let mut segments = Vec::<(RecombinationSegment, &AuspiceTreeNode)>::new();
+  for (window, node) in window_nodes {
+    match segments.last_mut() {
+      Some((segment, _)) if segment.clade == node.clade() => {
+        segment.range.end = window.end;
+      }
+      _ => segments.push((
+        RecombinationSegment {
+          clade: node.clade(),
+          nearest_node_name: node.name.clone(),
+          range: window,
+        },
+        node,
+      )),
+    }
+  }
+
+  let breakpoints = segments
+    .iter()
+    .tuple_windows()
+    .map(|((left, left_node), (right, right_node))| RecombinationBreakpoint {
+      left_clade: left.clade.clone(),
+      right_clade: right.clade.clone(),
+      range: find_breakpoint_range(left, left_node, right, right_node, qry_nuc_subs),
+    })
+    .collect_vec();
+
+  let segments = segments.into_iter().map(|(segment, _)| segment).collect_vec();
+
+  let parental_clades = if breakpoints.is_empty() {
+    vec![]
+  } else {
+    segments
+      .iter()
+      .map(|segment| segment.clade.clone())
+      .unique()
+      .collect_vec()
+  };
+
+  Recombination {
+    segments,
+    breakpoints,
+    parental_clades,
+  }
+}
+
+/// Splits a range into windows of a given size. The last window also receives the remainder.
+fn split_into_windows(range: &Range, window_size: usize) -> Vec<Range> {
+  if range.is_empty() {
+    return vec![];
+  }
+
+  let window_size = window_size.max(1);
+  let n_windows = (range.len() / window_size).max(1);
+  (0..n_windows)
+    .map(|i| {
+      let begin = range.begin + i * window_size;
+      let end = if i == n_windows - 1 {
+        range.end
+      } else {
+        begin + window_size
+      };
+      Range::new(begin, end)
+    })
+    .collect_vec()
+}
+
+/// Narrows down the breakpoint location between two segments: the breakpoint is after the last query substitution
+/// of the left segment, which is shared with the left node only, and before the first query substitution of the right
+/// segment, which is shared with the right node only.
+fn find_breakpoint_range(
+  left: &RecombinationSegment,
+  left_node: &AuspiceTreeNode,
+  right: &RecombinationSegment,
+  right_node: &AuspiceTreeNode,
+  qry_nuc_subs: &[NucSub],
+) -> Range {
+  let supports = |node: &AuspiceTreeNode, other: &AuspiceTreeNode, sub: &NucSub| {
+    node.tmp.mutations.get_substitution(sub.pos) == Some(sub.qry)
+      && other.tmp.mutations.get_substitution(sub.pos) != Some(sub.qry)
+  };
+
+  let begin = qry_nuc_subs
+    .iter()
+    .filter(|sub| left.range.contains(sub.pos) && supports(left_node, right_node, sub))
+    .map(|sub| sub.pos + 1)
+    .max()
+    .unwrap_or(left.range.begin);
+
+  let end = qry_nuc_subs
+    .iter()
+    .filter(|sub| right.range.contains(sub.pos) && supports(right_node, left_node, sub))
+    .map(|sub| sub.pos)
+    .min()
+    .unwrap_or(right.range.end);
+
+  Range::new(begin, end)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::{to_nuc_seq, Nuc};
+  use crate::tree::tree_preprocess::tree_preprocess_in_place;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::collections::BTreeMap;
+  use std::str::FromStr;
+
+  const TREE: &str = r#"{
+    "meta": { "display_defaults": {} },
+    "tree": {
+      "name": "root",
+      "branch_attrs": { "mutations": {} },
+      "node_attrs": { "clade_membership": { "value": "root" } },
+      "children": [
+        {
+          "name": "A",
+          "branch_attrs": { "mutations": { "nuc": ["A11C", "A21C"] } },
+          "node_attrs": { "clade_membership": { "value": "A" } }
+        },
+        {
+          "name": "B",
+          "branch_attrs": { "mutations": { "nuc": ["A71G", "A81G"] } },
+          "node_attrs": { "clade_membership": { "value": "B" } }
+        }
+      ]
+    }
+  }"#;
+
+  fn sub(pos: usize, qry: Nuc) -> NucSub {
+    NucSub { reff: Nuc::A, pos, qry }
+  }
+
+  #[rstest]
+  fn splits_range_into_windows() {
+    assert_eq!(
+      split_into_windows(&Range::new(10, 65), 20),
+      vec![Range::new(10, 30), Range::new(30, 65)]
+    );
+    assert_eq!(split_into_windows(&Range::new(10, 15), 20), vec![Range::new(10, 15)]);
+    assert!(split_into_windows(&Range::new(10, 10), 20).is_empty());
+  }
+
+  #[rstest]
+  fn finds_breakpoint_between_clades() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(&"A".repeat(100))?;
+    let mut tree = AuspiceTree::from_str(TREE)?;
+    tree_preprocess_in_place(&mut tree, &ref_seq, &BTreeMap::new())?;
+
+    let qry_nuc_subs = vec![sub(10, Nuc::C), sub(20, Nuc::C), sub(70, Nuc::G), sub(80, Nuc::G)];
+    let nearest_node = &tree.tree.children[0];
+
+    let actual = tree_find_breakpoints(&tree, nearest_node, &qry_nuc_subs, &[], &Range::new(0, 100), &[], 50, 2);
+
+    assert!(actual.is_recombinant());
+    assert_eq!(actual.parental_clades, vec!["A", "B"]);
+    assert_eq!(
+      actual.breakpoints.iter().map(ToString::to_string).collect_vec(),
+      vec!["A/B:22-70"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn finds_no_breakpoints_in_non_recombinant() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(&"A".repeat(100))?;
+    let mut tree = AuspiceTree::from_str(TREE)?;
+    tree_preprocess_in_place(&mut tree, &ref_seq, &BTreeMap::new())?;
+
+    let qry_nuc_subs = vec![sub(10, Nuc::C), sub(20, Nuc::C), sub(70, Nuc::T)];
+    let nearest_node = &tree.tree.children[0];
+
+    let actual = tree_find_breakpoints(&tree, nearest_node, &qry_nuc_subs, &[], &Range::new(0, 100), &[], 50, 2);
+
+    assert!(!actual.is_recombinant());
+    assert!(actual.parental_clades.is_empty());
+    assert_eq!(actual.segments.len(), 1);
+    assert_eq!(actual.segments[0].range, Range::new(0, 100));
+    Ok(())
+  }
+}
@@ -4,7 +4,8 @@ use crate::analyze::nuc_sub::NucSub;
 use crate::io::nuc::Nuc;
 use crate::tree::tree::{AuspiceTree, AuspiceTreeNode, TreeNodeAttr};
 use crate::utils::range::Range;
-use itertools::Itertools;
+use itertools::{Either, Itertools};
+use std::cmp::Ordering;
 
 /// Distance and placement prior for a ref tree node
 pub struct TreePlacementInfo<'node> {
@@ -13,23 +14,110 @@ pub struct TreePlacementInfo<'node> {
   pub prior: f64, // prior in non-log scale
 }
 
-/// For a given query sample, finds nearest node on the reference tree (according to the distance metric)
+/// For a given query sample, finds nearest node on the reference tree (according to the distance metric).
+///
+/// Uses the placement index of the tree, if it is available, and falls back to the exhaustive search otherwise.
 pub fn tree_find_nearest_nodes<'node>(
   tree: &'node AuspiceTree,
   qry_nuc_subs: &[NucSub],
   qry_missing: &[NucRange],
   aln_range: &Range,
   masked_ranges: &[Range],
+) -> Vec<TreePlacementInfo<'node>> {
+  let index = &tree.tmp.placement_index;
+  if index.is_empty() {
+    return tree_find_nearest_nodes_exhaustive(tree, qry_nuc_subs, qry_missing, aln_range, masked_ranges);
+  }
+
+  let nodes_with_distances =
+    tree_calculate_distances_indexed(tree, qry_nuc_subs, qry_missing, aln_range, masked_ranges);
+
+  sort_by_placement_score(tree, nodes_with_distances)
+}
+
+/// Same as `tree_find_nearest_nodes()`, but only returns the best placement, without sorting all the nodes of the tree.
+/// This still calculates distance to every node, so the cost is linear in the number of nodes.
+pub fn tree_find_nearest_node<'node>(
+  tree: &'node AuspiceTree,
+  qry_nuc_subs: &[NucSub],
+  qry_missing: &[NucRange],
+  aln_range: &Range,
+  masked_ranges: &[Range],
+) -> TreePlacementInfo<'node> {
+  let nodes_with_distances = if tree.tmp.placement_index.is_empty() {
+    Either::Left(tree.iter_depth_first_preorder().map(|(_, node)| {
+      (
+        node,
+        tree_calculate_node_distance(node, qry_nuc_subs, qry_missing, aln_range, masked_ranges),
+      )
+    }))
+  } else {
+    Either::Right(tree_calculate_distances_indexed(
+      tree,
+      qry_nuc_subs,
+      qry_missing,
+      aln_range,
+      masked_ranges,
+    ))
+  };
+
+  nodes_with_distances
+    .map(|(node, distance)| {
+      let prior = get_prior(node);
+      TreePlacementInfo { node, distance, prior }
+    })
+    .min_by(compare_placement_score)
+    .unwrap_or(TreePlacementInfo {
+      node: &tree.tree,
+      distance: 0,
+      prior: 1.0,
+    })
+}
+
+fn tree_calculate_distances_indexed<'node>(
+  tree: &'node AuspiceTree,
+  qry_nuc_subs: &[NucSub],
+  qry_missing: &[NucRange],
+  aln_range: &Range,
+  masked_ranges: &[Range],
+) -> impl Iterator<Item = (&'node AuspiceTreeNode, i64)> {
+  let distances = tree
+    .tmp
+    .placement_index
+    .calculate_distances(qry_nuc_subs, qry_missing, aln_range, masked_ranges);
+  debug_assert_eq!(distances.len(), tree.iter_depth_first_preorder().count());
+
+  tree.iter_depth_first_preorder().map(|(_, node)| node).zip(distances)
+}
+
+/// For a given query sample, finds nearest node on the reference tree by calculating distance metric between the
+/// sample and each node
+pub fn tree_find_nearest_nodes_exhaustive<'node>(
+  tree: &'node AuspiceTree,
+  qry_nuc_subs: &[NucSub],
+  qry_missing: &[NucRange],
+  aln_range: &Range,
+  masked_ranges: &[Range],
 ) -> Vec<TreePlacementInfo<'node>> {
   // Iterate over tree nodes and calculate distance metric between the sample and each node
-  let nodes_by_placement_score = tree
-    .iter_depth_first_preorder()
-    .map(|(_, node)| {
-      let distance = tree_calculate_node_distance(node, qry_nuc_subs, qry_missing, aln_range, masked_ranges);
+  let nodes_with_distances = tree.iter_depth_first_preorder().map(|(_, node)| {
+    let distance = tree_calculate_node_distance(node, qry_nuc_subs, qry_missing, aln_range, masked_ranges);
+    (node, distance)
+  });
+
+  sort_by_placement_score(tree, nodes_with_distances)
+}
+
+fn sort_by_placement_score<'node>(
+  tree: &'node AuspiceTree,
+  nodes_with_distances: impl Iterator<Item = (&'node AuspiceTreeNode, i64)>,
+) -> Vec<TreePlacementInfo<'node>> {
+  let nodes_by_placement_score = nodes_with_distances
+    .map(|(node, distance)| {
       let prior = get_prior(node);
       TreePlacementInfo { node, distance, prior }
     })
-    .sorted_by(|a, b| a.distance.cmp(&b.distance).then(b.prior.total_cmp(&a.prior)))
+    .sorted_by(compare_placement_score)
     .collect_vec();
 
   if nodes_by_placement_score.is_empty() {
@@ -44,6 +132,11 @@ pub fn tree_find_nearest_nodes<'node>(
   }
 }
 
+/// Orders placements by distance (smaller first), then by prior (larger first)
+fn compare_placement_score(a: &TreePlacementInfo, b: &TreePlacementInfo) -> Ordering {
+  a.distance.cmp(&b.distance).then(b.prior.total_cmp(&a.prior))
+}
+
 /// Gets non-log scale prior from node attributes
 fn get_prior(node: &AuspiceTreeNode) -> f64 {
   10.0_f64.powf(
@@ -57,7 +150,7 @@ fn get_prior(node: &AuspiceTreeNode) -> f64 {
 }
 
 /// Calculates distance metric between a given query sample and a tree node
-fn tree_calculate_node_distance(
+pub fn tree_calculate_node_distance(
   node: &AuspiceTreeNode,
   qry_nuc_subs: &[NucSub],
   qry_missing: &[NucRange],
@@ -67,30 +160,15 @@ fn tree_calculate_node_distance(
   let mut shared_differences = 0_i64;
   let mut shared_sites = 0_i64;
 
-  // Mask effectively turns query mutations into missing
-  // Rest of logic is the same once qry_nuc_subs and qry_missing are mutated
-  // Remove from qry_nuc_subs all mutations that are masked
-  let masked_qry_nuc_subs = qry_nuc_subs
-    .iter()
-    .filter(|sub| !masked_ranges.iter().any(|range| range.contains(sub.pos)))
-    .collect_vec();
+  let (masked_qry_nuc_subs, masked_qry_missing) = mask_query(qry_nuc_subs, qry_missing, masked_ranges);
 
-  // Add all masked ranges to qry_missing
-  let masked_qry_missing = masked_ranges
-    .iter()
-    .map(|range| NucRange {
-      begin: range.begin,
-      end: range.end,
-      letter: Nuc::N,
-    })
-    .chain(qry_missing.iter().cloned())
-    .collect_vec();
+  let node_mutations = &node.tmp.mutations;
 
   for qmut in &masked_qry_nuc_subs {
-    let node_mut = node.tmp.substitutions.get(&qmut.pos);
+    let node_mut = node_mutations.get_substitution(qmut.pos);
     if let Some(node_mut) = node_mut {
       // position is also mutated in node
-      if qmut.qry == *node_mut {
+      if qmut.qry == node_mut {
         shared_differences += 1; // the exact mutation is shared between node and seq
       } else {
         shared_sites += 1; // the same position is mutated, but the states are different
@@ -101,19 +179,46 @@ fn tree_calculate_node_distance(
   // determine the number of sites that are mutated in the node but missing in seq.
   // for these we can't tell whether the node agrees with seq
   let mut undetermined_sites = 0_i64;
-  for pos in node.tmp.substitutions.keys() {
-    if !is_nuc_sequenced(*pos, &masked_qry_missing, aln_range) {
+  for (pos, _) in node_mutations.iter_substitutions() {
+    if !is_nuc_sequenced(pos, &masked_qry_missing, aln_range) {
       undetermined_sites += 1;
     }
   }
 
-  let total_node_muts = node.tmp.substitutions.len() as i64;
+  let total_node_muts = node_mutations.len_substitutions() as i64;
   let total_seq_muts = masked_qry_nuc_subs.len() as i64;
 
   // calculate distance from set overlaps.
   total_node_muts + total_seq_muts - 2 * shared_differences - shared_sites - undetermined_sites
 }
 
+/// Mask effectively turns query mutations into missing.
+/// Rest of logic is the same once qry_nuc_subs and qry_missing are mutated.
+pub(crate) fn mask_query<'a>(
+  qry_nuc_subs: &'a [NucSub],
+  qry_missing: &[NucRange],
+  masked_ranges: &[Range],
+) -> (Vec<&'a NucSub>, Vec<NucRange>) {
+  // Remove from qry_nuc_subs all mutations that are masked
+  let masked_qry_nuc_subs = qry_nuc_subs
+    .iter()
+    .filter(|sub| !masked_ranges.iter().any(|range| range.contains(sub.pos)))
+    .collect_vec();
+
+  // Add all masked ranges to qry_missing
+  let masked_qry_missing = masked_ranges
+    .iter()
+    .map(|range| NucRange {
+      begin: range.begin,
+      end: range.end,
+      letter: Nuc::N,
+    })
+    .chain(qry_missing.iter().cloned())
+    .collect_vec();
+
+  (masked_qry_nuc_subs, masked_qry_missing)
+}
+
 #[cfg(test)]
 mod tests {
   use std::collections::BTreeMap;
@@ -210,7 +315,7 @@ mod tests {
 
   fn node_with_simple_nuc_subs() -> AuspiceTreeNode {
     let mut node = default_node();
-    node.tmp.substitutions = simple_node_nuc_subs();
+    node.tmp.mutations = simple_node_nuc_subs().into_iter().collect();
     node
   }
 
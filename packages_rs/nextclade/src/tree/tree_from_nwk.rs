@@ -0,0 +1,705 @@
+use crate::analyze::aa_sub::AaSubMinimal;
+use crate::analyze::nuc_sub::NucSub;
+use crate::gene::gene::{Gene, GeneStrand};
+use crate::io::aa::Aa;
+use crate::io::gene_map::GeneMap;
+use crate::io::json::json_parse;
+use crate::io::nuc::Nuc;
+use crate::io::nwk_reader::NwkNode;
+use crate::make_error;
+use crate::translate::complement::complement;
+use crate::translate::genetic_code::GeneticCode;
+use crate::tree::tree::{
+  AuspiceColoring, AuspiceDisplayDefaults, AuspiceTree, AuspiceTreeMeta, AuspiceTreeNode, TreeBranchAttrs,
+  TreeNodeAttr, TreeNodeAttrs, TreeNodeTempData, TreeTempData, AUSPICE_UNKNOWN_VALUE,
+};
+use eyre::{eyre, Report, WrapErr};
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::str::FromStr;
+
+/// Node data JSON, as produced by augur commands (`augur ancestral`, `augur translate`, `augur clades`, `augur refine`
+/// etc.) and consumed by `augur export`.
+///
+/// See: https://docs.nextstrain.org/projects/augur/en/stable/usage/cli/export.html
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NodeDataJson {
+  #[serde(default)]
+  pub nodes: BTreeMap<String, NodeDataEntry>,
+
+  #[serde(default)]
+  pub branches: BTreeMap<String, BranchDataEntry>,
+}
+
+impl FromStr for NodeDataJson {
+  type Err = Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    json_parse(s).wrap_err("When parsing node data JSON")
+  }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NodeDataEntry {
+  /// Nucleotide mutations on the branch leading to the node (`augur ancestral`)
+  #[serde(default)]
+  pub muts: Vec<String>,
+
+  /// Aminoacid mutations on the branch leading to the node, per gene (`augur translate`)
+  #[serde(default)]
+  pub aa_muts: BTreeMap<String, Vec<String>>,
+
+  /// Clade of the node (`augur clades`)
+  pub clade_membership: Option<String>,
+
+  /// Length of the branch in units of divergence (`augur refine`)
+  pub mutation_length: Option<f64>,
+
+  pub branch_length: Option<f64>,
+
+  #[serde(flatten)]
+  pub other: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BranchDataEntry {
+  /// Branch labels, e.g. `clade` (`augur clades`)
+  #[serde(default)]
+  pub labels: BTreeMap<String, String>,
+}
+
+/// Builds reference tree from a tree in Newick format and a set of node data JSON files, similarly to what
+/// `augur export v2` does. Data of the same node from multiple files is merged.
+///
+/// Divergence is accumulated from `mutation_length` or `branch_length` of the node data, or from the branch lengths in
+/// the Newick tree. If some of the branches have no length, the number of nucleotide mutations is used instead.
+pub fn tree_from_nwk_and_node_data(nwk: &NwkNode, node_data: &[NodeDataJson]) -> Result<AuspiceTree, Report> {
+  let flat = flatten_nwk(nwk)?;
+
+  let n_found = flat
+    .iter()
+    .filter(|node| node_data.iter().any(|data| data.nodes.contains_key(&node.name)))
+    .count();
+  if !node_data.is_empty() && n_found == 0 {
+    return make_error!(
+      "None of the nodes of the Newick tree are found in the node data. Check that node data files are produced for this tree and that the internal nodes of the tree are named."
+    );
+  }
+
+  let infos = flat
+    .iter()
+    .map(|node| {
+      let mut info = NodeInfo::default();
+      let mut length = None;
+
+      for data in node_data {
+        if let Some(entry) = data.nodes.get(&node.name) {
+          if !entry.muts.is_empty() {
+            info
+              .mutations
+              .entry("nuc".to_owned())
+              .or_default()
+              .extend(entry.muts.iter().cloned());
+          }
+          for (gene_name, aa_muts) in &entry.aa_muts {
+            if !aa_muts.is_empty() {
+              info
+                .mutations
+                .entry(gene_name.clone())
+                .or_default()
+                .extend(aa_muts.iter().cloned());
+            }
+          }
+          info.clade = entry.clade_membership.clone().or(info.clade);
+          length = entry.mutation_length.or(entry.branch_length).or(length);
+          // Other textual attributes (e.g. from `augur traits`) become node attributes, except for the full
+          // sequences, which `augur ancestral` may add
+          for (key, value) in &entry.other {
+            match value {
+              serde_json::Value::String(value) if key != "sequence" => {
+                info.attrs.insert(key.clone(), value.clone());
+              }
+              _ => {}
+            }
+          }
+        }
+
+        if let Some(entry) = data.branches.get(&node.name) {
+          info.labels.extend(entry.labels.clone());
+        }
+      }
+
+      info.length = length.or(node.branch_length);
+      info
+    })
+    .collect_vec();
+
+  Ok(build_auspice_tree(flat, infos))
+}
+
+/// Builds reference tree from a tree in Newick format and an alignment of the sequences of its tips. Mutations on
+/// branches are reconstructed using maximum parsimony (Fitch algorithm). Aminoacid mutations are deduced from the
+/// reconstructed nucleotide sequences of the nodes.
+///
+/// Divergence is accumulated from the branch lengths in the Newick tree. If some of the branches have no length, the
+/// number of reconstructed nucleotide mutations is used instead.
+pub fn tree_from_nwk_and_alignment(
+  nwk: &NwkNode,
+  ref_seq: &[Nuc],
+  tip_seqs: &BTreeMap<String, Vec<Nuc>>,
+  gene_map: &GeneMap,
+  genetic_code: &GeneticCode,
+) -> Result<AuspiceTree, Report> {
+  let flat = flatten_nwk(nwk)?;
+
+  let tips = flat
+    .iter()
+    .enumerate()
+    .filter(|(_, node)| node.children.is_empty())
+    .map(|(index, node)| -> Result<(usize, &[Nuc]), Report> {
+      let seq = tip_seqs.get(&node.name).ok_or_else(|| {
+        eyre!(
+          "Sequence of the tree tip '{}' is not found in the alignment. The alignment should contain sequences of all tips of the tree",
+          node.name
+        )
+      })?;
+      if seq.len() != ref_seq.len() {
+        return make_error!(
+          "Sequence of the tree tip '{}' has length {}, but the reference sequence has length {}. The tip sequences should be aligned to the reference sequence",
+          node.name,
+          seq.len(),
+          ref_seq.len()
+        );
+      }
+      Ok((index, seq))
+    })
+    .collect::<Result<Vec<_>, Report>>()?;
+
+  let states = reconstruct_ancestral_states(&flat, ref_seq, &tips);
+
+  let mut infos = vec![NodeInfo::default(); flat.len()];
+  for (index, node) in flat.iter().enumerate() {
+    let node_nuc = |pos: usize| states.get(pos, index);
+    let parent_nuc = |pos: usize| node.parent.map_or(ref_seq[pos], |parent| states.get(pos, parent));
+
+    let changed = states
+      .positions
+      .iter()
+      .copied()
+      .filter(|&pos| node_nuc(pos) != parent_nuc(pos))
+      .collect_vec();
+
+    let info = &mut infos[index];
+
+    if !changed.is_empty() {
+      let nuc_muts = changed
+        .iter()
+        .map(|&pos| {
+          NucSub {
+            reff: parent_nuc(pos),
+            pos,
+            qry: node_nuc(pos),
+          }
+          .to_string()
+        })
+        .collect_vec();
+      info.mutations.insert("nuc".to_owned(), nuc_muts);
+    }
+
+    for (gene_name, gene) in gene_map {
+      let genetic_code = GeneticCode::for_gene(gene, genetic_code)?;
+      let aa_muts = find_aa_muts(gene, genetic_code, &changed, &node_nuc, &parent_nuc);
+      if !aa_muts.is_empty() {
+        info.mutations.insert(gene_name.clone(), aa_muts);
+      }
+    }
+
+    info.length = node.branch_length;
+  }
+
+  Ok(build_auspice_tree(flat, infos))
+}
+
+/// Node of the tree in flat representation, in which nodes are stored in depth-first pre-order
+#[derive(Clone, Debug)]
+pub(crate) struct FlatNode {
+  pub name: String,
+  pub branch_length: Option<f64>,
+  pub parent: Option<usize>,
+  pub children: Vec<usize>,
+}
+
+/// Data to be attached to a node when building the Auspice tree
+#[derive(Clone, Debug, Default)]
+pub(crate) struct NodeInfo {
+  pub mutations: BTreeMap<String, Vec<String>>,
+  pub clade: Option<String>,
+  pub labels: BTreeMap<String, String>,
+  pub attrs: BTreeMap<String, String>,
+  pub length: Option<f64>,
+}
+
+/// Converts the tree into the flat representation. Unnamed nodes receive names `NODE_<number>`, numbered in pre-order,
+/// in the same way as `augur refine` names them.
+pub(crate) fn flatten_nwk(root: &NwkNode) -> Result<Vec<FlatNode>, Report> {
+  let mut flat = Vec::<FlatNode>::new();
+  let mut names = HashSet::<String>::new();
+  let mut stack = vec![(root, None)];
+  let mut n_unnamed = 0;
+
+  while let Some((node, parent)) = stack.pop() {
+    let index = flat.len();
+
+    let name = if node.name.is_empty() {
+      let name = format!("NODE_{n_unnamed:07}");
+      n_unnamed += 1;
+      name
+    } else {
+      node.name.clone()
+    };
+
+    if !names.insert(name.clone()) {
+      return make_error!("Newick tree contains more than one node named '{name}'. Node names should be unique");
+    }
+
+    flat.push(FlatNode {
+      name,
+      branch_length: node.branch_length,
+      parent,
+      children: vec![],
+    });
+
+    if let Some(parent) = parent {
+      flat[parent].children.push(index);
+    }
+
+    for child in node.children.iter().rev() {
+      stack.push((child, Some(index)));
+    }
+  }
+
+  Ok(flat)
+}
+
+/// Assembles the Auspice tree from the flat representation of a tree and the data of its nodes
+pub(crate) fn build_auspice_tree(flat: Vec<FlatNode>, infos: Vec<NodeInfo>) -> AuspiceTree {
+  // Use branch lengths only if every branch has one, otherwise divergences of different parts of the tree would not
+  // be comparable
+  let use_lengths = flat
+    .iter()
+    .zip(&infos)
+    .all(|(node, info)| node.parent.is_none() || info.length.is_some());
+
+  let mut divs = vec![0.0; flat.len()];
+  for (index, (node, info)) in flat.iter().zip(&infos).enumerate() {
+    if let Some(parent) = node.parent {
+      let length = if use_lengths {
+        info.length.unwrap_or_default()
+      } else {
+        info.mutations.get("nuc").map_or(0, Vec::len) as f64
+      };
+      divs[index] = divs[parent] + length;
+    }
+  }
+
+  // Nodes are assembled from leaves to root (reverse pre-order), so that children are always ready before the parent
+  let mut built: Vec<Option<AuspiceTreeNode>> = vec![None; flat.len()];
+  for (index, (node, info)) in flat.iter().zip(infos).enumerate().rev() {
+    let children = node
+      .children
+      .iter()
+      .filter_map(|&child| built[child].take())
+      .collect_vec();
+
+    let other: serde_json::Value = info
+      .attrs
+      .into_iter()
+      .map(|(key, value)| (key, json!({ "value": value })))
+      .collect();
+
+    let branch_other = if info.labels.is_empty() {
+      serde_json::Value::default()
+    } else {
+      json!({ "labels": info.labels })
+    };
+
+    built[index] = Some(AuspiceTreeNode {
+      name: node.name.clone(),
+      branch_attrs: TreeBranchAttrs {
+        mutations: info.mutations,
+        other: branch_other,
+      },
+      node_attrs: TreeNodeAttrs {
+        div: Some(divs[index]),
+        clade_membership: TreeNodeAttr::new(info.clade.as_deref().unwrap_or(AUSPICE_UNKNOWN_VALUE)),
+        node_type: None,
+        region: None,
+        country: None,
+        division: None,
+        placement_prior: None,
+        alignment: None,
+        missing: None,
+        gaps: None,
+        non_acgtns: None,
+        has_pcr_primer_changes: None,
+        pcr_primer_changes: None,
+        qc_status: None,
+        missing_genes: None,
+        other,
+      },
+      children,
+      tmp: TreeNodeTempData::default(),
+      other: serde_json::Value::default(),
+    });
+  }
+
+  let tree = built[0].take().expect("Tree root is expected to be built");
+
+  AuspiceTree {
+    meta: AuspiceTreeMeta {
+      extensions: None,
+      colorings: vec![AuspiceColoring {
+        type_: "categorical".to_owned(),
+        key: "clade_membership".to_owned(),
+        title: "Clade".to_owned(),
+        scale: vec![],
+      }],
+      panels: vec!["tree".to_owned()],
+      filters: vec![],
+      display_defaults: AuspiceDisplayDefaults {
+        branch_label: None,
+        color_by: None,
+        distance_measure: None,
+      },
+      geo_resolutions: None,
+      other: serde_json::Value::default(),
+    },
+    tree,
+    tmp: TreeTempData::default(),
+    other: json!({ "version": "v2" }),
+  }
+}
+
+/// Bit sets of nucleotides used in the Fitch algorithm
+const NUC_STATES: [Nuc; 5] = [Nuc::A, Nuc::C, Nuc::G, Nuc::T, Nuc::Gap];
+
+const ANY_STATE: u8 = 0b1_1111;
+
+fn nuc_to_state(nuc: Nuc) -> u8 {
+  match nuc {
+    Nuc::N => ANY_STATE,
+    Nuc::Gap => 1 << 4,
+    _ => nuc
+      .to_acgt()
+      .iter()
+      .map(|nuc| 1 << NUC_STATES.iter().position(|state| state == nuc).unwrap_or_default())
+      .fold(0, |acc, bit| acc | bit),
+  }
+}
+
+fn state_to_nuc(state: u8) -> Nuc {
+  NUC_STATES[state.trailing_zeros() as usize]
+}
+
+/// Reconstructed nucleotides of every node, at the positions where at least one tip differs from reference
+struct AncestralStates<'a> {
+  ref_seq: &'a [Nuc],
+
+  /// Variable positions, sorted
+  positions: Vec<usize>,
+
+  /// For every variable position, nucleotides of every node
+  nucs: Vec<Vec<Nuc>>,
+}
+
+impl<'a> AncestralStates<'a> {
+  fn get(&self, pos: usize, node: usize) -> Nuc {
+    match self.positions.binary_search(&pos) {
+      Ok(site) => self.nucs[site][node],
+      Err(_) => self.ref_seq[pos],
+    }
+  }
+}
+
+/// Reconstructs nucleotides of internal nodes using Fitch algorithm. Only the positions at which at least one of the
+/// tips differs from reference are considered. When several nucleotides are equally parsimonious, the one of the parent
+/// node (or of the reference sequence, for the root) is preferred.
+fn reconstruct_ancestral_states<'a>(
+  flat: &[FlatNode],
+  ref_seq: &'a [Nuc],
+  tips: &[(usize, &[Nuc])],
+) -> AncestralStates<'a> {
+  let positions = tips
+    .iter()
+    .flat_map(|(_, seq)| {
+      seq
+        .iter()
+        .enumerate()
+        .filter(|(pos, nuc)| **nuc != ref_seq[*pos] && nuc_to_state(**nuc) & nuc_to_state(ref_seq[*pos]) == 0)
+        .map(|(pos, _)| pos)
+    })
+    .collect::<BTreeSet<usize>>()
+    .into_iter()
+    .collect_vec();
+
+  let nucs = positions
+    .iter()
+    .map(|&pos| {
+      let mut sets = vec![ANY_STATE; flat.len()];
+      for &(index, seq) in tips {
+        sets[index] = nuc_to_state(seq[pos]);
+      }
+
+      // Bottom-up pass: a set of the most parsimonious states of each internal node is a set of states present in the
+      // greatest number of its children
+      for (index, node) in flat.iter().enumerate().rev() {
+        if node.children.is_empty() {
+          continue;
+        }
+        let mut counts = [0_usize; NUC_STATES.len()];
+        for &child in &node.children {
+          for (bit, count) in counts.iter_mut().enumerate() {
+            if sets[child] & (1 << bit) != 0 {
+              *count += 1;
+            }
+          }
+        }
+        let max_count = counts.iter().max().copied().unwrap_or_default();
+        sets[index] = counts
+          .iter()
+          .enumerate()
+          .filter(|(_, count)| **count == max_count)
+          .fold(0, |acc, (bit, _)| acc | (1 << bit));
+      }
+
+      // Top-down pass: choose a state from each set, preferring the state of the parent
+      let mut states = vec![0_u8; flat.len()];
+      for (index, node) in flat.iter().enumerate() {
+        let preferred = node
+          .parent
+          .map_or_else(|| nuc_to_state(ref_seq[pos]), |parent| states[parent]);
+        let set = sets[index];
+        states[index] = if set & preferred != 0 && preferred.count_ones() == 1 {
+          preferred
+        } else {
+          1 << set.trailing_zeros()
+        };
+      }
+
+      states.into_iter().map(state_to_nuc).collect_vec()
+    })
+    .collect_vec();
+
+  AncestralStates {
+    ref_seq,
+    positions,
+    nucs,
+  }
+}
+
+/// Finds aminoacid mutations in a gene, given positions of nucleotide mutations on a branch and nucleotides of the
+/// node and of its parent
+pub(crate) fn find_aa_muts(
+  gene: &Gene,
+  genetic_code: &GeneticCode,
+  changed: &[usize],
+  node_nuc: &impl Fn(usize) -> Nuc,
+  parent_nuc: &impl Fn(usize) -> Nuc,
+) -> Vec<String> {
+  let codons = changed
+    .iter()
+    .flat_map(|&pos| {
+      gene
+        .cds_segments
+        .iter()
+        .enumerate()
+        .filter(move |(_, segment)| segment.contains(pos))
+        .map(move |(segment_index, _)| gene.nuc_abs_to_rel(segment_index, pos) / 3)
+    })
+    .filter(|&codon| codon < gene.len_codon())
+    .collect::<BTreeSet<usize>>();
+
+  let get_triplet = |codon: usize, get_nuc: &dyn Fn(usize) -> Nuc| -> Vec<Nuc> {
+    (0..3)
+      .map(|i| {
+        let nuc = get_nuc(gene.nuc_rel_to_abs(codon * 3 + i));
+        if gene.strand == GeneStrand::Reverse {
+          complement(nuc)
+        } else {
+          nuc
+        }
+      })
+      .collect_vec()
+  };
+
+  codons
+    .into_iter()
+    .filter_map(|codon| {
+      let parent_triplet = get_triplet(codon, parent_nuc);
+      let node_triplet = get_triplet(codon, node_nuc);
+
+      // Same as in translation of query sequences: the start codon is translated as such only if the parent starts with
+      // an alternative start codon, so that mutations of the start codon are not hidden
+      let (reff, qry) = if codon == 0 && genetic_code.is_alternative_start(&parent_triplet) {
+        (
+          genetic_code.decode_start(&parent_triplet),
+          genetic_code.decode_start(&node_triplet),
+        )
+      } else {
+        (genetic_code.decode(&parent_triplet), genetic_code.decode(&node_triplet))
+      };
+
+      (reff != qry).then(|| AaSubMinimal { reff, pos: codon, qry }.to_string_without_gene())
+    })
+    .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::to_nuc_seq;
+  use crate::io::nwk_reader::nwk_parse;
+  use crate::utils::range::Range;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn mutations(node: &AuspiceTreeNode) -> BTreeMap<String, Vec<String>> {
+    node.branch_attrs.mutations.clone()
+  }
+
+  #[rstest]
+  fn names_unnamed_nodes_in_preorder() -> Result<(), Report> {
+    let nwk = nwk_parse("(A,(B,C),(D,E)F);")?;
+    let names = flatten_nwk(&nwk)?.into_iter().map(|node| node.name).collect_vec();
+    assert_eq!(
+      names,
+      vec!["NODE_0000000", "A", "NODE_0000001", "B", "C", "F", "D", "E"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn builds_tree_from_node_data() -> Result<(), Report> {
+    let nwk = nwk_parse("((A:0.1,B:0.2)NODE_1:0.05,C:0.3)root;")?;
+
+    let ancestral = NodeDataJson::from_str(
+      r#"{ "nodes": { "NODE_1": { "muts": ["A4C"] }, "A": { "muts": ["C4T", "G7A"] }, "C": { "muts": [] } } }"#,
+    )?;
+    let translate = NodeDataJson::from_str(r#"{ "nodes": { "A": { "aa_muts": { "S": ["N2Y"], "E": [] } } } }"#)?;
+    let clades = NodeDataJson::from_str(
+      r#"{
+        "nodes": { "root": { "clade_membership": "X" }, "NODE_1": { "clade_membership": "Y" }, "A": { "clade_membership": "Y", "host": "bat" } },
+        "branches": { "NODE_1": { "labels": { "clade": "Y" } } }
+      }"#,
+    )?;
+
+    let tree = tree_from_nwk_and_node_data(&nwk, &[ancestral, translate, clades])?;
+
+    let node_1 = &tree.tree.children[0];
+    let a = &node_1.children[0];
+    let c = &tree.tree.children[1];
+
+    assert_eq!(node_1.name, "NODE_1");
+    assert_eq!(node_1.clade(), "Y");
+    assert_eq!(node_1.branch_attrs.other["labels"]["clade"], "Y");
+    assert_eq!(
+      mutations(node_1),
+      BTreeMap::from([("nuc".to_owned(), vec!["A4C".to_owned()])])
+    );
+    assert_eq!(
+      mutations(a),
+      BTreeMap::from([
+        ("S".to_owned(), vec!["N2Y".to_owned()]),
+        ("nuc".to_owned(), vec!["C4T".to_owned(), "G7A".to_owned()]),
+      ])
+    );
+    assert_eq!(a.node_attrs.other["host"]["value"], "bat");
+    assert_eq!(a.node_attrs.div, Some(0.15000000000000002));
+    assert_eq!(c.clade(), AUSPICE_UNKNOWN_VALUE);
+    assert_eq!(c.node_attrs.div, Some(0.3));
+    Ok(())
+  }
+
+  #[rstest]
+  fn builds_tree_from_alignment_using_parsimony() -> Result<(), Report> {
+    let nwk = nwk_parse("(((A,B),C),D);")?;
+    let ref_seq = to_nuc_seq("ATGAAACCCGGG")?;
+    let tip_seqs = BTreeMap::from([
+      ("A".to_owned(), to_nuc_seq("ATGTAACCCGGA")?),
+      ("B".to_owned(), to_nuc_seq("ATGTAACCNGGG")?),
+      ("C".to_owned(), to_nuc_seq("ATGTAACCCGGG")?),
+      ("D".to_owned(), to_nuc_seq("ATGAAACCCGGG")?),
+    ]);
+    let gene_map = GeneMap::from([(
+      "g".to_owned(),
+      Gene {
+        gene_name: "g".to_owned(),
+        start: 0,
+        end: 12,
+        strand: GeneStrand::Forward,
+        frame: 0,
+        cds_segments: vec![Range::new(0, 12)],
+        genetic_code: None,
+      },
+    )]);
+
+    let tree = tree_from_nwk_and_alignment(&nwk, &ref_seq, &tip_seqs, &gene_map, GeneticCode::standard())?;
+
+    let node_ab_c = &tree.tree.children[0];
+    let node_ab = &node_ab_c.children[0];
+    let a = &node_ab.children[0];
+    let b = &node_ab.children[1];
+
+    assert_eq!(node_ab_c.name, "NODE_0000001");
+    assert_eq!(
+      mutations(node_ab_c),
+      BTreeMap::from([
+        ("g".to_owned(), vec!["K2*".to_owned()]),
+        ("nuc".to_owned(), vec!["A4T".to_owned()]),
+      ])
+    );
+    assert_eq!(mutations(node_ab), BTreeMap::new());
+    assert_eq!(
+      mutations(a),
+      BTreeMap::from([("nuc".to_owned(), vec!["G12A".to_owned()])])
+    );
+    assert_eq!(mutations(b), BTreeMap::new());
+    assert_eq!(a.node_attrs.div, Some(2.0));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case::start_codon_mutation(1, "ATGAAA", "CTGAAA", vec!["M1L"])]
+  #[case::alternative_start_codons(11, "GTGAAA", "TTGAAA", vec![])]
+  fn finds_start_codon_mutations(
+    #[case] genetic_code: u8,
+    #[case] parent: &str,
+    #[case] node: &str,
+    #[case] expected: Vec<&str>,
+  ) -> Result<(), Report> {
+    let parent = to_nuc_seq(parent)?;
+    let node = to_nuc_seq(node)?;
+    let gene = Gene {
+      gene_name: "g".to_owned(),
+      start: 0,
+      end: 6,
+      strand: GeneStrand::Forward,
+      frame: 0,
+      cds_segments: vec![Range::new(0, 6)],
+      genetic_code: None,
+    };
+
+    let aa_muts = find_aa_muts(
+      &gene,
+      GeneticCode::from_id(genetic_code)?,
+      &[0],
+      &|pos| node[pos],
+      &|pos| parent[pos],
+    );
+
+    assert_eq!(aa_muts, expected);
+    Ok(())
+  }
+}
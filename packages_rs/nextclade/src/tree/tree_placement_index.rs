@@ -0,0 +1,213 @@
+use crate::analyze::is_sequenced::is_nuc_sequenced;
+use crate::analyze::letter_ranges::NucRange;
+use crate::analyze::nuc_sub::NucSub;
+use crate::io::nuc::Nuc;
+use crate::tree::node_mutations::NodeMutations;
+use crate::tree::tree::AuspiceTreeNode;
+use crate::tree::tree_find_nearest_node::mask_query;
+use crate::utils::range::Range;
+use std::collections::BTreeMap;
+
+/// Change of a node substitution along a branch. `None` means the position is not substituted relative to reference.
+#[derive(Clone, Debug)]
+struct BranchChange {
+  pos: usize,
+  parent: Option<Nuc>,
+  child: Option<Nuc>,
+}
+
+#[derive(Clone, Debug)]
+struct TreePlacementIndexEntry {
+  /// Index of the parent entry. `None` for the root.
+  parent: Option<usize>,
+
+  /// Positions at which substitutions of this node differ from the substitutions of the parent
+  changes: Vec<BranchChange>,
+}
+
+/// Index of the reference tree for fast search of the nearest node.
+///
+/// Distance metric between a query and a node is a sum of contributions of the individual node substitutions, so the
+/// distance to a node only differs from the distance to its parent by the contributions of the positions which change
+/// along the branch between them. The index stores these changes for every node, in depth-first pre-order, so that
+/// distances to all nodes are calculated in a single pass, in time proportional to the number of branch mutations
+/// rather than to the number of nodes times the number of substitutions in each node.
+///
+/// Must be rebuilt if the tree changes.
+#[derive(Clone, Debug, Default)]
+pub struct TreePlacementIndex {
+  entries: Vec<TreePlacementIndexEntry>,
+}
+
+impl TreePlacementIndex {
+  /// Builds the index. Expects node mutations to be already calculated (see `tree_preprocess_in_place`).
+  pub fn new(root: &AuspiceTreeNode) -> Self {
+    let mut entries = vec![];
+    build_index_recursive(root, None, &NodeMutations::default(), &mut entries);
+    Self { entries }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Calculates distance metric between a given query sample and every node of the tree, in depth-first pre-order.
+  /// The results are identical to calling `tree_calculate_node_distance` for every node.
+  pub fn calculate_distances(
+    &self,
+    qry_nuc_subs: &[NucSub],
+    qry_missing: &[NucRange],
+    aln_range: &Range,
+    masked_ranges: &[Range],
+  ) -> Vec<i64> {
+    let (masked_qry_nuc_subs, masked_qry_missing) = mask_query(qry_nuc_subs, qry_missing, masked_ranges);
+    let qry_subs_map = masked_qry_nuc_subs
+      .iter()
+      .map(|sub| (sub.pos, sub.qry))
+      .collect::<BTreeMap<usize, Nuc>>();
+    let total_seq_muts = masked_qry_nuc_subs.len() as i64;
+
+    // Contribution of a single node substitution into the distance
+    let contribution = |pos: usize, node_nuc: Option<Nuc>| -> i64 {
+      match (node_nuc, qry_subs_map.get(&pos)) {
+        (None, _) => 0,
+        // the exact mutation is shared between node and seq: counted once in node and once in seq, minus 2
+        (Some(node_nuc), Some(qry_nuc)) if node_nuc == *qry_nuc => -1,
+        // the same position is mutated, but the states are different: counted twice, minus 1
+        (Some(_), Some(_)) => 0,
+        // mutated in node, but missing in seq: undetermined
+        (Some(_), None) if !is_nuc_sequenced(pos, &masked_qry_missing, aln_range) => 0,
+        (Some(_), None) => 1,
+      }
+    };
+
+    let mut distances = Vec::<i64>::with_capacity(self.entries.len());
+    for entry in &self.entries {
+      let parent_distance = entry.parent.map_or(total_seq_muts, |parent| distances[parent]);
+      let delta: i64 = entry
+        .changes
+        .iter()
+        .map(|change| contribution(change.pos, change.child) - contribution(change.pos, change.parent))
+        .sum();
+      distances.push(parent_distance + delta);
+    }
+    distances
+  }
+}
+
+fn build_index_recursive(
+  node: &AuspiceTreeNode,
+  parent: Option<usize>,
+  parent_mutations: &NodeMutations<Nuc>,
+  entries: &mut Vec<TreePlacementIndexEntry>,
+) {
+  let node_mutations = &node.tmp.mutations;
+  let changes = node_mutations
+    .changed_positions(parent_mutations)
+    .into_iter()
+    .filter_map(|pos| {
+      let parent_nuc = parent_mutations.get_substitution(pos);
+      let child_nuc = node_mutations.get_substitution(pos);
+      (parent_nuc != child_nuc).then_some(BranchChange {
+        pos,
+        parent: parent_nuc,
+        child: child_nuc,
+      })
+    })
+    .collect();
+
+  let index = entries.len();
+  entries.push(TreePlacementIndexEntry { parent, changes });
+
+  for child in &node.children {
+    build_index_recursive(child, Some(index), node_mutations, entries);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::nuc::to_nuc_seq;
+  use crate::tree::tree::AuspiceTree;
+  use crate::tree::tree_find_nearest_node::tree_calculate_node_distance;
+  use crate::tree::tree_preprocess::tree_preprocess_in_place;
+  use eyre::Report;
+  use itertools::Itertools;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::str::FromStr;
+
+  const TREE: &str = r#"{
+    "meta": { "display_defaults": {} },
+    "tree": {
+      "name": "root",
+      "branch_attrs": { "mutations": {} },
+      "node_attrs": { "clade_membership": { "value": "root" } },
+      "children": [
+        {
+          "name": "A",
+          "branch_attrs": { "mutations": { "nuc": ["A4C", "A13C", "A16T"] } },
+          "node_attrs": { "clade_membership": { "value": "A" } },
+          "children": [
+            {
+              "name": "A.1",
+              "branch_attrs": { "mutations": { "nuc": ["C4A", "A24G", "A36G"] } },
+              "node_attrs": { "clade_membership": { "value": "A.1" } }
+            },
+            {
+              "name": "A.2",
+              "branch_attrs": { "mutations": { "nuc": ["C13T", "A8C"] } },
+              "node_attrs": { "clade_membership": { "value": "A.2" } }
+            }
+          ]
+        },
+        {
+          "name": "B",
+          "branch_attrs": { "mutations": { "nuc": ["A10-", "A11-", "A24G"] } },
+          "node_attrs": { "clade_membership": { "value": "B" } }
+        }
+      ]
+    }
+  }"#;
+
+  fn sub(pos: usize, qry: Nuc) -> NucSub {
+    NucSub { reff: Nuc::A, pos, qry }
+  }
+
+  #[rstest]
+  #[case(vec![], vec![], Range::new(0, 40), vec![])]
+  #[case(vec![sub(3, Nuc::C), sub(12, Nuc::C)], vec![], Range::new(0, 40), vec![])]
+  #[case(vec![sub(3, Nuc::C), sub(7, Nuc::C), sub(12, Nuc::T), sub(23, Nuc::G)], vec![], Range::new(0, 40), vec![])]
+  #[case(
+    vec![sub(3, Nuc::C), sub(23, Nuc::T)],
+    vec![NucRange { begin: 14, end: 20, letter: Nuc::N }],
+    Range::new(2, 30),
+    vec![Range::new(12, 13)]
+  )]
+  fn calculates_same_distances_as_exhaustive_search(
+    #[case] qry_nuc_subs: Vec<NucSub>,
+    #[case] qry_missing: Vec<NucRange>,
+    #[case] aln_range: Range,
+    #[case] masked_ranges: Vec<Range>,
+  ) -> Result<(), Report> {
+    let ref_seq = to_nuc_seq(&"A".repeat(40))?;
+    let mut tree = AuspiceTree::from_str(TREE)?;
+    tree_preprocess_in_place(&mut tree, &ref_seq, &BTreeMap::new())?;
+
+    let index = TreePlacementIndex::new(&tree.tree);
+    let actual = index.calculate_distances(&qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+
+    let expected = tree
+      .iter_depth_first_preorder()
+      .map(|(_, node)| tree_calculate_node_distance(node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges))
+      .collect_vec();
+
+    assert_eq!(index.len(), 5);
+    assert_eq!(actual, expected);
+    Ok(())
+  }
+}
@@ -0,0 +1,200 @@
+use crate::tree::params::TreePlacementParams;
+use crate::tree::tree_find_nearest_node::TreePlacementInfo;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacementCandidate {
+  pub node_name: String,
+  pub clade: String,
+  pub distance: i64,
+  pub posterior: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CladePosterior {
+  pub clade: String,
+  pub posterior: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacementPosteriors {
+  /// The most probable placements, in the order of decreasing posterior probability
+  pub candidates: Vec<PlacementCandidate>,
+
+  /// The most probable clades, in the order of decreasing posterior probability. Posterior probability of a clade is
+  /// the sum of posterior probabilities of all nodes of this clade.
+  pub clades: Vec<CladePosterior>,
+
+  /// Posterior probability of the clade of the most probable placement
+  pub clade_confidence: f64,
+}
+
+/// Converts placement distances and priors into posterior probabilities and reorders the candidates in place, from the
+/// most probable to the least probable.
+///
+/// Every site compared between the query and a node is assumed to differ independently, with the probability
+/// `placement_mismatch_rate`, so that the likelihood of a node at distance `d` is proportional to `(ε / (1 - ε))^d`.
+/// Missing and masked sites are already excluded from the distance, so they do not contribute to the likelihood. The
+/// calculation is performed in log space to avoid underflow for distant nodes.
+pub fn tree_calculate_placement_posteriors(
+  candidates: &mut Vec<TreePlacementInfo>,
+  params: &TreePlacementParams,
+) -> PlacementPosteriors {
+  if candidates.is_empty() {
+    return PlacementPosteriors::default();
+  }
+
+  let rate = params.placement_mismatch_rate;
+  let log_odds = (rate / (1.0 - rate)).ln();
+
+  let log_weights = candidates
+    .iter()
+    .map(|candidate| candidate.distance as f64 * log_odds + candidate.prior.ln())
+    .collect_vec();
+  let max_log_weight = log_weights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+  let weights = log_weights.iter().map(|w| (w - max_log_weight).exp()).collect_vec();
+  let total_weight: f64 = weights.iter().sum();
+
+  // Stable sort preserves the order by distance for the candidates with equal posteriors
+  let mut sorted = candidates
+    .drain(..)
+    .zip(weights.into_iter().map(|w| w / total_weight))
+    .collect_vec();
+  sorted.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+  let (sorted, posteriors): (Vec<TreePlacementInfo>, Vec<f64>) = sorted.into_iter().unzip();
+  *candidates = sorted;
+
+  let mut clade_posteriors = BTreeMap::<String, f64>::new();
+  for (candidate, posterior) in candidates.iter().zip(&posteriors) {
+    *clade_posteriors.entry(candidate.node.clade()).or_default() += posterior;
+  }
+
+  let clade_confidence = clade_posteriors
+    .get(&candidates[0].node.clade())
+    .copied()
+    .unwrap_or_default();
+
+  let clades = clade_posteriors
+    .into_iter()
+    .map(|(clade, posterior)| CladePosterior { clade, posterior })
+    .sorted_by(|a, b| b.posterior.total_cmp(&a.posterior))
+    .take(params.placement_candidates)
+    .collect_vec();
+
+  let candidates = candidates
+    .iter()
+    .zip(posteriors)
+    .take(params.placement_candidates)
+    .map(|(candidate, posterior)| PlacementCandidate {
+      node_name: candidate.node.name.clone(),
+      clade: candidate.node.clade(),
+      distance: candidate.distance,
+      posterior,
+    })
+    .collect_vec();
+
+  PlacementPosteriors {
+    candidates,
+    clades,
+    clade_confidence,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::json::json_parse;
+  use crate::tree::tree::AuspiceTreeNode;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  const EPSILON: f64 = 1e-12;
+
+  fn node(name: &str, clade: &str) -> Result<AuspiceTreeNode, Report> {
+    json_parse(&format!(
+      r#"{{ "name": "{name}", "branch_attrs": {{ "mutations": {{}} }}, "node_attrs": {{ "clade_membership": {{ "value": "{clade}" }} }} }}"#
+    ))
+  }
+
+  #[rstest]
+  fn calculates_placement_posteriors() -> Result<(), Report> {
+    let a1 = node("a1", "A")?;
+    let a2 = node("a2", "A")?;
+    let b = node("b", "B")?;
+
+    let mut candidates = vec![
+      TreePlacementInfo {
+        node: &a1,
+        distance: 0,
+        prior: 1.0,
+      },
+      TreePlacementInfo {
+        node: &b,
+        distance: 1,
+        prior: 1.0,
+      },
+      TreePlacementInfo {
+        node: &a2,
+        distance: 1,
+        prior: 1.0,
+      },
+    ];
+
+    let params = TreePlacementParams {
+      placement_candidates: 2,
+      placement_mismatch_rate: 0.01,
+    };
+
+    let actual = tree_calculate_placement_posteriors(&mut candidates, &params);
+
+    assert_eq!(
+      actual.candidates.iter().map(|c| c.node_name.as_str()).collect_vec(),
+      vec!["a1", "b"]
+    );
+    assert!((actual.candidates[0].posterior - 99.0 / 101.0).abs() < EPSILON);
+    assert!((actual.candidates[1].posterior - 1.0 / 101.0).abs() < EPSILON);
+    assert!((actual.clade_confidence - 100.0 / 101.0).abs() < EPSILON);
+    assert_eq!(
+      actual.clades.iter().map(|c| c.clade.as_str()).collect_vec(),
+      vec!["A", "B"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn prefers_node_with_higher_prior() -> Result<(), Report> {
+    let a = node("a", "A")?;
+    let b = node("b", "B")?;
+
+    let mut candidates = vec![
+      TreePlacementInfo {
+        node: &a,
+        distance: 0,
+        prior: 0.001,
+      },
+      TreePlacementInfo {
+        node: &b,
+        distance: 1,
+        prior: 1.0,
+      },
+    ];
+
+    let params = TreePlacementParams {
+      placement_candidates: 1,
+      placement_mismatch_rate: 0.01,
+    };
+
+    let actual = tree_calculate_placement_posteriors(&mut candidates, &params);
+
+    assert_eq!(candidates[0].node.name, "b");
+    assert_eq!(actual.candidates.len(), 1);
+    assert_eq!(actual.candidates[0].clade, "B");
+    Ok(())
+  }
+}
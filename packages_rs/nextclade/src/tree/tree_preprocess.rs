@@ -1,13 +1,15 @@
 use crate::analyze::aa_sub::AaSubMinimal;
 use crate::analyze::nuc_sub::NucSub;
 use crate::io::aa::Aa;
-use crate::io::letter::Letter;
 use crate::io::nuc::Nuc;
 use crate::make_error;
 use crate::translate::translate_genes::Translation;
+use crate::tree::node_mutations::NodeMutations;
 use crate::tree::tree::{
-  AuspiceColoring, AuspiceTree, AuspiceTreeNode, DivergenceUnits, TreeNodeAttr, AUSPICE_UNKNOWN_VALUE,
+  AuspiceColoring, AuspiceTree, AuspiceTreeNode, CladeDefiningMutations, DivergenceUnits, TreeNodeAttr,
+  AUSPICE_UNKNOWN_VALUE,
 };
+use crate::tree::tree_placement_index::TreePlacementIndex;
 use crate::utils::collections::concat_to_vec;
 use eyre::{Report, WrapErr};
 use itertools::Itertools;
@@ -20,14 +22,14 @@ pub fn tree_preprocess_in_place(
   ref_seq: &[Nuc],
   ref_peptides: &BTreeMap<String, Translation>,
 ) -> Result<(), Report> {
-  let mut parent_nuc_muts = BTreeMap::<usize, Nuc>::new();
-  let mut parent_aa_muts = BTreeMap::<String, BTreeMap<usize, Aa>>::new();
+  let parent_nuc_muts = NodeMutations::<Nuc>::default();
+  let parent_aa_muts = BTreeMap::<String, NodeMutations<Aa>>::new();
   let mut id = 0_usize;
   tree_preprocess_in_place_impl_recursive(
     &mut id,
     &mut tree.tree,
-    &mut parent_nuc_muts,
-    &mut parent_aa_muts,
+    &parent_nuc_muts,
+    &parent_aa_muts,
     ref_seq,
     ref_peptides,
   )?;
@@ -37,6 +39,13 @@ pub fn tree_preprocess_in_place(
   // TODO: Use auspice extension field to pass info on divergence units, rather than guess
   tree.tmp.divergence_units = DivergenceUnits::guess_from_max_divergence(tree.tmp.max_divergence);
 
+  let mut clade_defining_mutations = CladeDefiningMutations::new();
+  let root_nuc_muts = NodeMutations::<Nuc>::default();
+  find_clade_defining_mutations_recursive(&tree.tree, None, &root_nuc_muts, ref_seq, &mut clade_defining_mutations);
+  tree.tmp.clade_defining_mutations = clade_defining_mutations;
+
+  tree.tmp.placement_index = TreePlacementIndex::new(&tree.tree);
+
   tree_add_metadata(tree);
 
   Ok(())
@@ -45,33 +54,25 @@ pub fn tree_preprocess_in_place(
 fn tree_preprocess_in_place_impl_recursive(
   id: &mut usize,
   node: &mut AuspiceTreeNode,
-  parent_nuc_muts: &mut BTreeMap<usize, Nuc>,
-  parent_aa_muts: &mut BTreeMap<String, BTreeMap<usize, Aa>>,
+  parent_nuc_muts: &NodeMutations<Nuc>,
+  parent_aa_muts: &BTreeMap<String, NodeMutations<Aa>>,
   ref_seq: &[Nuc],
   ref_peptides: &BTreeMap<String, Translation>,
 ) -> Result<(), Report> {
-  let mut nuc_muts: BTreeMap<usize, Nuc> = map_nuc_muts(node, ref_seq, parent_nuc_muts)?;
-  let nuc_subs: BTreeMap<usize, Nuc> = nuc_muts.clone().into_iter().filter(|(_, nuc)| !nuc.is_gap()).collect();
-
-  let mut aa_muts: BTreeMap<String, BTreeMap<usize, Aa>> = map_aa_muts(node, ref_peptides, parent_aa_muts)?;
-  let aa_subs: BTreeMap<String, BTreeMap<usize, Aa>> = aa_muts
-    .clone()
-    .into_iter()
-    .map(|(gene, aa_muts)| (gene, aa_muts.into_iter().filter(|(_, aa)| !aa.is_gap()).collect()))
-    .collect();
+  let nuc_muts = map_nuc_muts(node, ref_seq, parent_nuc_muts)?;
+  let aa_muts = map_aa_muts(node, ref_peptides, parent_aa_muts)?;
 
+  // Mutations of the parent are shared, rather than copied, so cloning is cheap here
   node.tmp.id = *id;
   node.tmp.mutations = nuc_muts.clone();
-  node.tmp.substitutions = nuc_subs;
   node.tmp.aa_mutations = aa_muts.clone();
-  node.tmp.aa_substitutions = aa_subs;
   node.tmp.is_ref_node = true;
 
   node.node_attrs.node_type = Some(TreeNodeAttr::new("Reference"));
 
   for child in &mut node.children {
     *id += 1;
-    tree_preprocess_in_place_impl_recursive(id, child, &mut nuc_muts, &mut aa_muts, ref_seq, ref_peptides)?;
+    tree_preprocess_in_place_impl_recursive(id, child, &nuc_muts, &aa_muts, ref_seq, ref_peptides)?;
   }
 
   Ok(())
@@ -80,11 +81,11 @@ fn tree_preprocess_in_place_impl_recursive(
 fn map_nuc_muts(
   node: &AuspiceTreeNode,
   ref_seq: &[Nuc],
-  parent_nuc_muts: &BTreeMap<usize, Nuc>,
-) -> Result<BTreeMap<usize, Nuc>, Report> {
-  let mut nuc_muts = parent_nuc_muts.clone();
+  parent_nuc_muts: &NodeMutations<Nuc>,
+) -> Result<NodeMutations<Nuc>, Report> {
+  let mut changes = BTreeMap::<usize, Option<Nuc>>::new();
   match node.branch_attrs.mutations.get("nuc") {
-    None => Ok(nuc_muts),
+    None => Ok(parent_nuc_muts.clone()),
     Some(mutations) => {
       for mutation_str in mutations {
         let mutation = NucSub::from_str(mutation_str)
@@ -99,12 +100,12 @@ fn map_nuc_muts(
         // If mutation reverts nucleotide back to what reference had, remove it from the map
         let ref_nuc = ref_seq[mutation.pos];
         if ref_nuc == mutation.qry {
-          nuc_muts.remove(&mutation.pos);
+          changes.insert(mutation.pos, None);
         } else {
-          nuc_muts.insert(mutation.pos, mutation.qry);
+          changes.insert(mutation.pos, Some(mutation.qry));
         }
       }
-      Ok(nuc_muts)
+      Ok(parent_nuc_muts.with_changes(changes))
     }
   }
 }
@@ -115,8 +116,8 @@ fn map_nuc_muts(
 fn map_aa_muts(
   node: &AuspiceTreeNode,
   ref_peptides: &BTreeMap<String, Translation>,
-  parent_aa_muts: &BTreeMap<String, BTreeMap<usize, Aa>>,
-) -> Result<BTreeMap<String, BTreeMap<usize, Aa>>, Report> {
+  parent_aa_muts: &BTreeMap<String, NodeMutations<Aa>>,
+) -> Result<BTreeMap<String, NodeMutations<Aa>>, Report> {
   ref_peptides
     .iter()
     //We iterate over all genes that we have ref_peptides for
@@ -126,7 +127,7 @@ fn map_aa_muts(
         map_aa_muts_for_one_gene(gene_name, node, &ref_peptide.seq, aa_muts),
       ),
       // Initialize aa_muts, default dictionary style
-      None => (gene_name.clone(), Ok(BTreeMap::new())),
+      None => (gene_name.clone(), Ok(NodeMutations::default())),
     })
     .map(|(name, muts)| -> Result<_, Report>  {
       Ok((name, muts?))
@@ -138,12 +139,12 @@ fn map_aa_muts_for_one_gene(
   gene_name: &str,
   node: &AuspiceTreeNode,
   ref_peptide: &[Aa],
-  parent_aa_muts: &BTreeMap<usize, Aa>,
-) -> Result<BTreeMap<usize, Aa>, Report> {
-  let mut aa_muts = parent_aa_muts.clone();
+  parent_aa_muts: &NodeMutations<Aa>,
+) -> Result<NodeMutations<Aa>, Report> {
+  let mut changes = BTreeMap::<usize, Option<Aa>>::new();
 
   match node.branch_attrs.mutations.get(gene_name) {
-    None => Ok(aa_muts),
+    None => Ok(parent_aa_muts.clone()),
     Some(mutations) => {
       for mutation_str in mutations {
         let mutation = AaSubMinimal::from_str(mutation_str)?;
@@ -162,16 +163,51 @@ fn map_aa_muts_for_one_gene(
         // If mutation reverts amino acid back to what reference had, remove it from the map
         let ref_nuc = ref_peptide[mutation.pos];
         if ref_nuc == mutation.qry {
-          aa_muts.remove(&mutation.pos);
+          changes.insert(mutation.pos, None);
         } else {
-          aa_muts.insert(mutation.pos, mutation.qry);
+          changes.insert(mutation.pos, Some(mutation.qry));
         }
       }
-      Ok(aa_muts)
+      Ok(parent_aa_muts.with_changes(changes))
     }
   }
 }
 
+/// Collects nucleotide mutations on the branches leading to the nodes where clade differs from the clade of the parent.
+///
+/// A branch can also revert a mutation of the parent, in which case the reference nucleotide becomes clade-defining.
+fn find_clade_defining_mutations_recursive(
+  node: &AuspiceTreeNode,
+  parent_clade: Option<&str>,
+  parent_nuc_muts: &NodeMutations<Nuc>,
+  ref_seq: &[Nuc],
+  clade_defining_mutations: &mut CladeDefiningMutations,
+) {
+  let clade = node.clade();
+  let nuc_muts = &node.tmp.mutations;
+
+  if parent_clade != Some(clade.as_str()) {
+    // Mutations added on the branch, as well as reversions back to the reference nucleotide
+    let changed = nuc_muts
+      .changed_positions(parent_nuc_muts)
+      .into_iter()
+      .map(|pos| (pos, nuc_muts.get(pos).unwrap_or(ref_seq[pos])));
+
+    for (pos, nuc) in changed {
+      clade_defining_mutations
+        .entry(pos)
+        .or_default()
+        .entry(nuc)
+        .or_default()
+        .insert(clade.clone());
+    }
+  }
+
+  for child in &node.children {
+    find_clade_defining_mutations_recursive(child, Some(&clade), nuc_muts, ref_seq, clade_defining_mutations);
+  }
+}
+
 fn get_max_divergence_recursively(node: &AuspiceTreeNode) -> f64 {
   let div = node.node_attrs.div.unwrap_or(-f64::infinity());
 
@@ -0,0 +1,507 @@
+use crate::analyze::nuc_sub::NucSub;
+use crate::io::gene_map::GeneMap;
+use crate::io::nuc::Nuc;
+use crate::io::nwk_reader::nwk_parse;
+use crate::io::nwk_writer::nwk_write;
+use crate::io::usher_mat::{UsherCondensedNode, UsherMat, UsherMutation};
+use crate::make_error;
+use crate::translate::genetic_code::GeneticCode;
+use crate::tree::tree::{
+  AuspiceColoring, AuspiceMetaExtensions, AuspiceMetaExtensionsNextclade, AuspiceTree, CladeNodeAttrKeyDesc,
+  AUSPICE_UNKNOWN_VALUE,
+};
+use crate::tree::tree_from_nwk::{build_auspice_tree, find_aa_muts, flatten_nwk, FlatNode, NodeInfo};
+use eyre::{eyre, Report, WrapErr};
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Nucleotides in the order of their numeric codes in MAT
+const USHER_NUCS: [Nuc; 4] = [Nuc::A, Nuc::C, Nuc::G, Nuc::T];
+
+fn nuc_from_usher(code: i32) -> Result<Nuc, Report> {
+  usize::try_from(code)
+    .ok()
+    .and_then(|code| USHER_NUCS.get(code).copied())
+    .ok_or_else(|| eyre!("Unknown nucleotide code in UShER MAT: {code}. Expected one of: 0, 1, 2, 3"))
+}
+
+fn nuc_to_usher(nuc: Nuc) -> Option<i32> {
+  USHER_NUCS.iter().position(|&n| n == nuc).map(|code| code as i32)
+}
+
+/// Name of the node attribute holding the clade annotation with the given index. The first annotation becomes the
+/// clade of the node, the others become clade-like node attributes (e.g. Pango lineage, when the tree is annotated by
+/// `matUtils annotate` with Nextstrain clades and Pango lineages).
+fn clade_annotation_attr_name(index: usize) -> String {
+  format!("clade_annotation_{}", index + 1)
+}
+
+/// Builds reference tree from an UShER mutation-annotated tree (MAT).
+///
+/// Nucleotide mutations are taken from the MAT. Aminoacid mutations are deduced from nucleotide sequences of the
+/// nodes, which are reconstructed from the reference sequence along the way. Clade annotations, which MAT only stores
+/// on the nodes at which the clades originate, are propagated to the descendant nodes. Condensed nodes (groups of
+/// identical samples) are expanded into their member leaves, in the same way as UShER does when loading a MAT.
+pub fn tree_from_usher_mat(
+  mat: &UsherMat,
+  ref_seq: &[Nuc],
+  gene_map: &GeneMap,
+  genetic_code: &GeneticCode,
+) -> Result<AuspiceTree, Report> {
+  let nwk = nwk_parse(&mat.newick).wrap_err("When parsing tree of UShER MAT")?;
+  let mut flat = flatten_nwk(&nwk)?;
+
+  if mat.node_mutations.len() != flat.len() {
+    return make_error!(
+      "UShER MAT is inconsistent: tree has {} nodes, but mutations are listed for {} nodes",
+      flat.len(),
+      mat.node_mutations.len()
+    );
+  }
+
+  if !mat.metadata.is_empty() && mat.metadata.len() != flat.len() {
+    return make_error!(
+      "UShER MAT is inconsistent: tree has {} nodes, but clade annotations are listed for {} nodes",
+      flat.len(),
+      mat.metadata.len()
+    );
+  }
+
+  let n_annotations = mat.metadata.iter().map(Vec::len).max().unwrap_or_default();
+
+  // Sequence of the parent of the current node. Nodes are visited in pre-order, so when moving to the next node, the
+  // mutations of the nodes which are not its ancestors are reverted, and the mutations of the node itself are applied.
+  let mut seq = ref_seq.to_vec();
+  let mut path: Vec<(usize, Vec<(usize, Nuc)>)> = vec![];
+
+  let mut clades: Vec<Vec<Option<String>>> = vec![];
+  let mut infos = vec![NodeInfo::default(); flat.len()];
+  for (index, node) in flat.iter().enumerate() {
+    while path.last().map(|(ancestor, _)| *ancestor) != node.parent {
+      let (_, reverted) = path.pop().expect("Node is expected to have its parent on the path");
+      for (pos, nuc) in reverted.into_iter().rev() {
+        seq[pos] = nuc;
+      }
+    }
+
+    let subs = mat.node_mutations[index]
+      .iter()
+      .map(|mutation| nuc_sub_from_usher(mutation, ref_seq.len()))
+      .collect::<Result<Vec<NucSub>, Report>>()
+      .wrap_err_with(|| format!("When reading mutations of node '{}' of UShER MAT", node.name))?;
+
+    let info = &mut infos[index];
+
+    if !subs.is_empty() {
+      let node_nucs: BTreeMap<usize, Nuc> = subs.iter().map(|sub| (sub.pos, sub.qry)).collect();
+      let changed = node_nucs.keys().copied().collect_vec();
+      let node_nuc = |pos: usize| node_nucs.get(&pos).copied().unwrap_or(seq[pos]);
+      let parent_nuc = |pos: usize| seq[pos];
+
+      for (gene_name, gene) in gene_map {
+        let genetic_code = GeneticCode::for_gene(gene, genetic_code)?;
+        let aa_muts = find_aa_muts(gene, genetic_code, &changed, &node_nuc, &parent_nuc);
+        if !aa_muts.is_empty() {
+          info.mutations.insert(gene_name.clone(), aa_muts);
+        }
+      }
+
+      info
+        .mutations
+        .insert("nuc".to_owned(), subs.iter().map(NucSub::to_string).collect_vec());
+    }
+
+    let mut applied = vec![];
+    for sub in &subs {
+      applied.push((sub.pos, seq[sub.pos]));
+      seq[sub.pos] = sub.qry;
+    }
+    path.push((index, applied));
+
+    let annotations = mat.metadata.get(index).map(Vec::as_slice).unwrap_or_default();
+    let node_clades = (0..n_annotations)
+      .map(|i| match annotations.get(i) {
+        Some(annotation) if !annotation.is_empty() => Some(annotation.clone()),
+        _ => node.parent.and_then(|parent| clades[parent][i].clone()),
+      })
+      .collect_vec();
+
+    info.clade = node_clades.first().cloned().flatten();
+    for (i, clade) in node_clades.iter().enumerate().skip(1) {
+      if let Some(clade) = clade {
+        info.attrs.insert(clade_annotation_attr_name(i), clade.clone());
+      }
+    }
+    clades.push(node_clades);
+
+    info.length = node.branch_length;
+  }
+
+  expand_condensed_nodes(&mut flat, &mut infos, &mat.condensed_nodes)?;
+
+  let mut tree = build_auspice_tree(flat, infos);
+
+  if n_annotations > 1 {
+    let attr_descs = (1..n_annotations)
+      .map(|i| CladeNodeAttrKeyDesc {
+        name: clade_annotation_attr_name(i),
+        display_name: format!("Clade annotation {}", i + 1),
+        description: format!("Clade annotation #{} of the UShER mutation-annotated tree", i + 1),
+        hide_in_web: false,
+      })
+      .collect_vec();
+
+    tree
+      .meta
+      .colorings
+      .extend(attr_descs.iter().map(|desc| AuspiceColoring {
+        type_: "categorical".to_owned(),
+        key: desc.name.clone(),
+        title: desc.display_name.clone(),
+        scale: vec![],
+      }));
+
+    tree.meta.extensions = Some(AuspiceMetaExtensions {
+      nextclade: Some(AuspiceMetaExtensionsNextclade {
+        clade_node_attrs: Some(attr_descs),
+      }),
+    });
+  }
+
+  Ok(tree)
+}
+
+/// Replaces each condensed leaf with its member leaves. The first member takes the place of the condensed leaf, the
+/// others are added as its siblings, with the same branch length, mutations and clade annotations.
+fn expand_condensed_nodes(
+  flat: &mut Vec<FlatNode>,
+  infos: &mut Vec<NodeInfo>,
+  condensed_nodes: &[UsherCondensedNode],
+) -> Result<(), Report> {
+  let mut indices: HashMap<String, usize> = flat
+    .iter()
+    .enumerate()
+    .map(|(index, node)| (node.name.clone(), index))
+    .collect();
+
+  for UsherCondensedNode {
+    node_name,
+    condensed_leaves,
+  } in condensed_nodes
+  {
+    let index = indices
+      .remove(node_name)
+      .ok_or_else(|| eyre!("UShER MAT is inconsistent: condensed node '{node_name}' is not found in the tree"))?;
+
+    let parent = match (flat[index].parent, flat[index].children.is_empty()) {
+      (Some(parent), true) => parent,
+      _ => return make_error!("UShER MAT is inconsistent: condensed node '{node_name}' is expected to be a leaf"),
+    };
+
+    let (first_leaf, other_leaves) = match condensed_leaves.split_first() {
+      Some(leaves) => leaves,
+      None => return make_error!("UShER MAT is inconsistent: condensed node '{node_name}' contains no leaves"),
+    };
+
+    if let Some(leaf) = condensed_leaves.iter().find(|&leaf| indices.contains_key(leaf)) {
+      return make_error!("UShER MAT contains more than one node named '{leaf}'. Node names should be unique");
+    }
+
+    flat[index].name = first_leaf.clone();
+    indices.insert(first_leaf.clone(), index);
+
+    let mut siblings = vec![];
+    for leaf in other_leaves {
+      let leaf_index = flat.len();
+      flat.push(FlatNode {
+        name: leaf.clone(),
+        ..flat[index].clone()
+      });
+      infos.push(infos[index].clone());
+      indices.insert(leaf.clone(), leaf_index);
+      siblings.push(leaf_index);
+    }
+
+    let children = &mut flat[parent].children;
+    let position = children
+      .iter()
+      .position(|&child| child == index)
+      .unwrap_or(children.len())
+      + 1;
+    children.splice(position..position, siblings);
+  }
+
+  Ok(())
+}
+
+fn nuc_sub_from_usher(mutation: &UsherMutation, ref_seq_len: usize) -> Result<NucSub, Report> {
+  let pos = usize::try_from(mutation.position)
+    .ok()
+    .filter(|pos| (1..=ref_seq_len).contains(pos))
+    .ok_or_else(|| {
+      eyre!(
+        "Mutation position {} is outside of the reference sequence of length {ref_seq_len}",
+        mutation.position
+      )
+    })?;
+
+  // NOTE: more than one nucleotide is listed for ambiguous mutations. The first one is the most likely.
+  let qry = match mutation.mut_nuc.first() {
+    Some(&code) => nuc_from_usher(code)?,
+    None => return make_error!("Mutation at position {pos} has no nucleotide"),
+  };
+
+  Ok(NucSub {
+    reff: nuc_from_usher(mutation.par_nuc)?,
+    pos: pos - 1,
+    qry,
+  })
+}
+
+/// Converts the tree into an UShER mutation-annotated tree (MAT), e.g. in order to continue working with the tree
+/// containing placed sequences in UShER and matUtils.
+///
+/// MAT can only contain substitutions between nucleotides A, C, G and T, so other nucleotide mutations (e.g. deletions)
+/// are omitted. Clades and clade-like node attributes are written as clade annotations, on the nodes at which their
+/// values change.
+pub fn tree_to_usher_mat(tree: &AuspiceTree, ref_seq: &[Nuc], ref_name: &str) -> Result<UsherMat, Report> {
+  let mut newick = vec![];
+  nwk_write(&mut newick, tree)?;
+  let newick = String::from_utf8(newick)?.trim_end().to_owned();
+
+  let attr_keys = tree
+    .clade_node_attr_descs()
+    .iter()
+    .map(|desc| desc.name.clone())
+    .collect_vec();
+
+  let mut node_mutations = vec![];
+  let mut metadata = vec![];
+
+  // Annotations of the ancestors of the current node, by depth
+  let mut ancestor_annotations: Vec<Vec<String>> = vec![];
+
+  for (depth, node) in tree.iter_depth_first_preorder() {
+    let mutations = node
+      .branch_attrs
+      .mutations
+      .get("nuc")
+      .map(Vec::as_slice)
+      .unwrap_or_default()
+      .iter()
+      .map(|mutation| NucSub::from_str(mutation))
+      .filter_map_ok(|sub| {
+        let ref_nuc = ref_seq.get(sub.pos).copied().and_then(nuc_to_usher)?;
+        Some(UsherMutation {
+          position: (sub.pos + 1) as i32,
+          ref_nuc,
+          par_nuc: nuc_to_usher(sub.reff)?,
+          mut_nuc: vec![nuc_to_usher(sub.qry)?],
+          chromosome: ref_name.to_owned(),
+        })
+      })
+      .collect::<Result<Vec<UsherMutation>, Report>>()
+      .wrap_err_with(|| format!("When converting mutations of node '{}' to UShER MAT", node.name))?;
+    node_mutations.push(mutations);
+
+    let clade = node.clade();
+    let clade = if clade == AUSPICE_UNKNOWN_VALUE {
+      String::new()
+    } else {
+      clade
+    };
+    let clade_attrs = node.get_clade_node_attrs(tree.clade_node_attr_descs());
+    let annotations = std::iter::once(clade)
+      .chain(
+        attr_keys
+          .iter()
+          .map(|key| clade_attrs.get(key).cloned().unwrap_or_default()),
+      )
+      .collect_vec();
+
+    ancestor_annotations.truncate(depth);
+    let changed = annotations
+      .iter()
+      .enumerate()
+      .map(|(i, annotation)| match ancestor_annotations.last() {
+        Some(parent_annotations) if &parent_annotations[i] == annotation => String::new(),
+        _ => annotation.clone(),
+      })
+      .collect_vec();
+    metadata.push(changed);
+    ancestor_annotations.push(annotations);
+  }
+
+  Ok(UsherMat {
+    newick,
+    node_mutations,
+    condensed_nodes: vec![],
+    metadata,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gene::gene::{Gene, GeneStrand};
+  use crate::io::nuc::to_nuc_seq;
+  use crate::tree::tree::AUSPICE_UNKNOWN_VALUE;
+  use crate::utils::range::Range;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn mutation(ref_nuc: i32, par_nuc: i32, position: i32, mut_nuc: i32) -> UsherMutation {
+    UsherMutation {
+      position,
+      ref_nuc,
+      par_nuc,
+      mut_nuc: vec![mut_nuc],
+      chromosome: "ref".to_owned(),
+    }
+  }
+
+  fn annotations(values: &[&str]) -> Vec<String> {
+    values.iter().map(|&value| value.to_owned()).collect_vec()
+  }
+
+  fn mat() -> UsherMat {
+    UsherMat {
+      newick: "((A:1,B:1):1,C:0);".to_owned(),
+      node_mutations: vec![
+        vec![],
+        // A4T
+        vec![mutation(0, 0, 4, 3)],
+        // A12G
+        vec![mutation(0, 0, 12, 2)],
+        // T4C
+        vec![mutation(0, 3, 4, 1)],
+        vec![],
+      ],
+      condensed_nodes: vec![],
+      metadata: vec![
+        annotations(&["", ""]),
+        annotations(&["X", "X.1"]),
+        annotations(&["", "X.2"]),
+        annotations(&["", ""]),
+        annotations(&["", ""]),
+      ],
+    }
+  }
+
+  fn gene_map() -> GeneMap {
+    GeneMap::from([(
+      "g".to_owned(),
+      Gene {
+        gene_name: "g".to_owned(),
+        start: 0,
+        end: 12,
+        strand: GeneStrand::Forward,
+        frame: 0,
+        cds_segments: vec![Range::new(0, 12)],
+        genetic_code: None,
+      },
+    )])
+  }
+
+  #[rstest]
+  fn builds_tree_from_usher_mat() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ATGAAACCCGGA")?;
+    let tree = tree_from_usher_mat(&mat(), &ref_seq, &gene_map(), GeneticCode::standard())?;
+
+    let node_ab = &tree.tree.children[0];
+    let a = &node_ab.children[0];
+    let b = &node_ab.children[1];
+    let c = &tree.tree.children[1];
+
+    assert_eq!(node_ab.name, "NODE_0000001");
+    assert_eq!(
+      node_ab.branch_attrs.mutations,
+      BTreeMap::from([
+        ("g".to_owned(), vec!["K2*".to_owned()]),
+        ("nuc".to_owned(), vec!["A4T".to_owned()]),
+      ])
+    );
+    assert_eq!(
+      a.branch_attrs.mutations,
+      BTreeMap::from([("nuc".to_owned(), vec!["A12G".to_owned()])])
+    );
+    assert_eq!(
+      b.branch_attrs.mutations,
+      BTreeMap::from([
+        ("g".to_owned(), vec!["*2Q".to_owned()]),
+        ("nuc".to_owned(), vec!["T4C".to_owned()]),
+      ])
+    );
+
+    assert_eq!(node_ab.clade(), "X");
+    assert_eq!(a.clade(), "X");
+    assert_eq!(b.clade(), "X");
+    assert_eq!(c.clade(), AUSPICE_UNKNOWN_VALUE);
+    assert_eq!(a.node_attrs.other["clade_annotation_2"]["value"], "X.2");
+    assert_eq!(b.node_attrs.other["clade_annotation_2"]["value"], "X.1");
+    assert_eq!(tree.clade_node_attr_descs()[0].name, "clade_annotation_2");
+
+    assert_eq!(b.node_attrs.div, Some(2.0));
+    Ok(())
+  }
+
+  #[rstest]
+  fn writes_tree_to_usher_mat() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ATGAAACCCGGA")?;
+    let tree = tree_from_usher_mat(&mat(), &ref_seq, &gene_map(), GeneticCode::standard())?;
+    let actual = tree_to_usher_mat(&tree, &ref_seq, "ref")?;
+
+    assert_eq!(actual.newick, "((A:1,B:1)NODE_0000001:1,C:0)NODE_0000000;".to_owned());
+    assert_eq!(actual.node_mutations, mat().node_mutations);
+    assert_eq!(actual.metadata, mat().metadata);
+    Ok(())
+  }
+
+  #[rstest]
+  fn expands_condensed_nodes() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ATGAAACCCGGA")?;
+    let mut mat = mat();
+    mat.condensed_nodes = vec![UsherCondensedNode {
+      node_name: "A".to_owned(),
+      condensed_leaves: vec!["A1".to_owned(), "A2".to_owned(), "A3".to_owned()],
+    }];
+
+    let tree = tree_from_usher_mat(&mat, &ref_seq, &gene_map(), GeneticCode::standard())?;
+
+    let node_ab = &tree.tree.children[0];
+    let names = node_ab.children.iter().map(|node| node.name.as_str()).collect_vec();
+    assert_eq!(names, vec!["A1", "A2", "A3", "B"]);
+    for leaf in &node_ab.children[..3] {
+      assert_eq!(
+        leaf.branch_attrs.mutations,
+        BTreeMap::from([("nuc".to_owned(), vec!["A12G".to_owned()])])
+      );
+      assert_eq!(leaf.node_attrs.div, Some(2.0));
+      assert_eq!(leaf.clade(), "X");
+      assert_eq!(leaf.node_attrs.other["clade_annotation_2"]["value"], "X.2");
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  fn rejects_condensed_node_not_in_tree() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ATGAAACCCGGA")?;
+    let mut mat = mat();
+    mat.condensed_nodes = vec![UsherCondensedNode {
+      node_name: "D".to_owned(),
+      condensed_leaves: vec!["D1".to_owned(), "D2".to_owned()],
+    }];
+    assert!(tree_from_usher_mat(&mat, &ref_seq, &gene_map(), GeneticCode::standard()).is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  fn rejects_inconsistent_usher_mat() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ATGAAACCCGGA")?;
+    let mut mat = mat();
+    mat.node_mutations.pop();
+    assert!(tree_from_usher_mat(&mat, &ref_seq, &gene_map(), GeneticCode::standard()).is_err());
+    Ok(())
+  }
+}
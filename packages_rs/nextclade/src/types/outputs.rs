@@ -1,8 +1,12 @@
 use crate::align::backtrace::AlignmentOutput;
 use crate::align::insertions_strip::{AaIns, Insertion, StripInsertionsResult};
+use crate::align::inversions::Inversion;
+use crate::align::seed_chain::SeedChainStats;
+use crate::analyze::aa_changes::AaAmbiguity;
 use crate::analyze::aa_changes_group::AaChangeGroup;
 use crate::analyze::aa_sub_full::{AaDelFull, AaSubFull};
 use crate::analyze::find_aa_motifs_changes::{AaMotifsChangesMap, AaMotifsMap};
+use crate::analyze::find_mixed_sites::MixedSites;
 use crate::analyze::find_private_aa_mutations::PrivateAaMutations;
 use crate::analyze::find_private_nuc_mutations::PrivateNucMutations;
 use crate::analyze::letter_ranges::{GeneAaRange, NucRange};
@@ -14,6 +18,8 @@ use crate::qc::qc_run::QcResult;
 use crate::translate::coord_map::CoordMap;
 use crate::translate::frame_shifts_translate::FrameShift;
 use crate::translate::translate_genes::Translation;
+use crate::tree::tree_find_breakpoints::Recombination;
+use crate::tree::tree_placement_posteriors::PlacementPosteriors;
 use crate::utils::range::Range;
 use eyre::Report;
 use serde::{Deserialize, Serialize};
@@ -51,6 +57,8 @@ pub struct PhenotypeValue {
 pub struct NextcladeOutputs {
   pub index: usize,
   pub seq_name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub segment: Option<String>,
   pub substitutions: Vec<NucSubFull>,
   pub total_substitutions: usize,
   pub deletions: Vec<NucDelFull>,
@@ -63,6 +71,8 @@ pub struct NextcladeOutputs {
   pub non_acgtns: Vec<NucRange>,
   #[serde(rename = "totalNonACGTNs")]
   pub total_non_acgtns: usize,
+  #[serde(default)]
+  pub mixed_sites: MixedSites,
   pub nucleotide_composition: BTreeMap<Nuc, usize>,
   pub frame_shifts: Vec<FrameShift>,
   pub total_frame_shifts: usize,
@@ -72,6 +82,8 @@ pub struct NextcladeOutputs {
   pub total_aminoacid_deletions: usize,
   pub aa_insertions: Vec<AaIns>,
   pub total_aminoacid_insertions: usize,
+  #[serde(default)]
+  pub aa_ambiguities: Vec<AaAmbiguity>,
   pub unknown_aa_ranges: Vec<GeneAaRange>,
   pub total_unknown_aa: usize,
   pub aa_changes_groups: Vec<AaChangeGroup>,
@@ -89,11 +101,19 @@ pub struct NextcladeOutputs {
   pub divergence: f64,
   pub coverage: f64,
   pub qc: QcResult,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub recombination: Option<Recombination>,
   pub custom_node_attributes: BTreeMap<String, String>,
   pub nearest_node_id: usize,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub nearest_nodes: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub placement: Option<PlacementPosteriors>,
   pub is_reverse_complement: bool,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub seed_chain: Option<SeedChainStats>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub inversions: Vec<Inversion>,
   pub phenotype_values: Option<Vec<PhenotypeValue>>,
   pub aa_motifs: AaMotifsMap,
   pub aa_motifs_changes: AaMotifsChangesMap,
@@ -0,0 +1,25 @@
+  $ mkdir -p "$TESTDIR/tmp"
+  $ pushd "$TESTDIR/tmp" > /dev/null
+  $ export NEXTCLADE="../../../.out/nextclade-x86_64-unknown-linux-gnu"
+
+Genome with gene `N` on the forward strand and gene `L` on the reverse strand. Codons of `L` are counted from the end
+of the gene, and nucleotide context of its aminoacid changes is reverse-complemented.
+
+  $ ${NEXTCLADE} run \
+  > --jobs=1 \
+  > --in-order \
+  > --min-length=30 \
+  > --input-dataset="$TESTDIR/data/reverse-strand/" \
+  > --output-tsv='out/nextclade.tsv' \
+  > --output-translations='out/gene.{gene}.fasta' \
+  > "$TESTDIR/data/reverse-strand/sequences.fasta"
+
+  $ awk -F'\t' 'NR == 1 { for (i = 1; i <= NF; i++) col[$i] = i; next } { print $col["seqName"] "|" $col["substitutions"] "|" $col["deletions"] "|" $col["aaSubstitutions"] "|" $col["aaDeletions"] }' 'out/nextclade.tsv'
+  substitutions|T5A,A38C||L:L4R,N:F2Y|
+  deletion||31-33||L:G6-
+
+  $ cat 'out/gene.L.fasta'
+  >substitutions
+  GTSRSGTPGLLP
+  >deletion
+  GTSLS-TPGLLP